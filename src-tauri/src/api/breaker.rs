@@ -0,0 +1,205 @@
+// Circuit breaker
+// Fails calls fast while a service keeps failing, instead of piling up timeouts
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Consecutive failures that open a circuit
+pub const FAILURE_THRESHOLD: u32 = 5;
+/// How long an open circuit rejects calls before letting one through to test recovery
+pub const OPEN_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Circuit {
+    consecutive_failures: u32,
+    // Set while open; once the cooldown has passed the circuit is half-open
+    opened_at: Option<Instant>,
+}
+
+impl Circuit {
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        let opened_at = self.opened_at?;
+        OPEN_COOLDOWN
+            .checked_sub(now.duration_since(opened_at))
+            .filter(|remaining| !remaining.is_zero())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitStatus {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// Snapshot of one circuit, for the UI and debugging
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitState {
+    pub key: String,
+    pub status: CircuitStatus,
+    pub consecutive_failures: u32,
+    /// Seconds until an open circuit lets a call through again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
+
+/// Circuits keyed by service base URL
+#[derive(Default)]
+pub struct CircuitBreaker {
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a call to `key` may go ahead. While the circuit is open this
+    /// fails with the seconds left in the cooldown; once it has passed, calls
+    /// go through and the next outcome decides whether it closes or reopens.
+    pub fn check(&self, key: &str) -> Result<(), u64> {
+        let circuits = self.circuits.lock().unwrap();
+        match circuits.get(key).and_then(|c| c.remaining(Instant::now())) {
+            Some(remaining) => Err(ceil_secs(remaining)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self, key: &str) {
+        let mut circuits = self.circuits.lock().unwrap();
+        if let Some(circuit) = circuits.remove(key) {
+            if circuit.opened_at.is_some() {
+                println!("Circuit for {} closed", key);
+            }
+        }
+    }
+
+    pub fn record_failure(&self, key: &str) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(key.to_string()).or_default();
+        circuit.consecutive_failures += 1;
+
+        // A failed trial call while half-open reopens straight away
+        if circuit.opened_at.is_some() || circuit.consecutive_failures >= FAILURE_THRESHOLD {
+            println!(
+                "Circuit for {} open after {} consecutive failures",
+                key, circuit.consecutive_failures
+            );
+            circuit.opened_at = Some(Instant::now());
+        }
+    }
+
+    /// Close every circuit
+    pub fn clear(&self) {
+        self.circuits.lock().unwrap().clear();
+    }
+
+    /// Current state of every circuit with recent failures, sorted by key
+    pub fn snapshot(&self) -> Vec<CircuitState> {
+        let circuits = self.circuits.lock().unwrap();
+        let now = Instant::now();
+        let mut states: Vec<CircuitState> = circuits
+            .iter()
+            .map(|(key, circuit)| {
+                let remaining = circuit.remaining(now);
+                let status = match (circuit.opened_at, remaining) {
+                    (None, _) => CircuitStatus::Closed,
+                    (Some(_), Some(_)) => CircuitStatus::Open,
+                    (Some(_), None) => CircuitStatus::HalfOpen,
+                };
+                CircuitState {
+                    key: key.clone(),
+                    status,
+                    consecutive_failures: circuit.consecutive_failures,
+                    retry_after: remaining.map(ceil_secs),
+                }
+            })
+            .collect();
+        states.sort_by(|a, b| a.key.cmp(&b.key));
+        states
+    }
+}
+
+// Round up so the UI never shows "0 seconds" while still blocked
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "https://api.example.com";
+
+    fn status(breaker: &CircuitBreaker) -> Option<CircuitStatus> {
+        breaker.snapshot().first().map(|state| state.status)
+    }
+
+    /// Move an open circuit to the end of its cooldown
+    fn expire_cooldown(breaker: &CircuitBreaker) {
+        let mut circuits = breaker.circuits.lock().unwrap();
+        let circuit = circuits.get_mut(KEY).unwrap();
+        circuit.opened_at = Instant::now().checked_sub(OPEN_COOLDOWN);
+    }
+
+    #[test]
+    fn circuit_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new();
+        for _ in 1..FAILURE_THRESHOLD {
+            breaker.record_failure(KEY);
+        }
+        assert_eq!(breaker.check(KEY), Ok(()));
+        assert_eq!(status(&breaker), Some(CircuitStatus::Closed));
+
+        breaker.record_failure(KEY);
+
+        assert_eq!(breaker.check(KEY), Err(OPEN_COOLDOWN.as_secs()));
+        assert_eq!(status(&breaker), Some(CircuitStatus::Open));
+        assert_eq!(breaker.check("https://other.example.com"), Ok(()));
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new();
+        for _ in 1..FAILURE_THRESHOLD {
+            breaker.record_failure(KEY);
+        }
+
+        breaker.record_success(KEY);
+        breaker.record_failure(KEY);
+
+        assert_eq!(breaker.check(KEY), Ok(()));
+        assert_eq!(breaker.snapshot()[0].consecutive_failures, 1);
+    }
+
+    #[test]
+    fn half_open_circuit_closes_on_success_and_reopens_on_failure() {
+        let breaker = CircuitBreaker::new();
+        for _ in 0..FAILURE_THRESHOLD {
+            breaker.record_failure(KEY);
+        }
+        expire_cooldown(&breaker);
+        assert_eq!(status(&breaker), Some(CircuitStatus::HalfOpen));
+        assert_eq!(breaker.check(KEY), Ok(()));
+
+        // One failed trial call is enough to reopen it
+        breaker.record_failure(KEY);
+        assert_eq!(status(&breaker), Some(CircuitStatus::Open));
+        assert!(breaker.check(KEY).is_err());
+
+        expire_cooldown(&breaker);
+        breaker.record_success(KEY);
+        assert_eq!(status(&breaker), None);
+        assert_eq!(breaker.check(KEY), Ok(()));
+    }
+
+    #[test]
+    fn seconds_left_are_rounded_up() {
+        assert_eq!(ceil_secs(Duration::from_millis(1)), 1);
+        assert_eq!(ceil_secs(Duration::from_secs(2)), 2);
+        assert_eq!(ceil_secs(Duration::from_millis(2001)), 3);
+    }
+}
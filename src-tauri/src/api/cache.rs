@@ -0,0 +1,162 @@
+// Descriptor cache
+// Fetched descriptors kept on disk with their ETag and Last-Modified, so
+// rediscovery can ask the server whether anything changed
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::storage::Storage;
+
+use super::discovery::SocketAgentDescriptor;
+
+const DESCRIPTOR_CACHE_STORAGE_KEY: &str = "descriptor_cache";
+
+/// Cached descriptors kept; the least recently fetched are evicted first
+const MAX_DESCRIPTOR_CACHE_ENTRIES: usize = 100;
+
+/// A descriptor as last fetched, before validation, with the validators
+/// the server sent for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedDescriptor {
+    pub descriptor: SocketAgentDescriptor,
+    /// Base URL discovery ended up at after redirects
+    pub base_url: String,
+    /// Path the descriptor was found at; `None` for entries saved before
+    /// other paths were tried, which all came from `/.well-known/socket-agent`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    pub cached_at: u64,
+}
+
+impl CachedDescriptor {
+    pub fn new(
+        descriptor: SocketAgentDescriptor,
+        base_url: String,
+        discovery_path: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Self {
+        Self {
+            descriptor,
+            base_url,
+            discovery_path: Some(discovery_path),
+            etag,
+            last_modified,
+            cached_at: unix_now(),
+        }
+    }
+}
+
+/// Cached descriptor for the site at `url`, if any
+pub fn load_cached_descriptor(storage: &Storage, url: &str) -> Option<CachedDescriptor> {
+    load_cache(storage).remove(url.trim_end_matches('/'))
+}
+
+/// Store a freshly fetched descriptor, evicting the oldest entries past the cap
+pub fn save_cached_descriptor(storage: &Storage, url: &str, entry: CachedDescriptor) -> Result<()> {
+    let mut cache = load_cache(storage);
+    cache.insert(url.trim_end_matches('/').to_string(), entry);
+
+    while cache.len() > MAX_DESCRIPTOR_CACHE_ENTRIES {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.cached_at)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => cache.remove(&key),
+            None => break,
+        };
+    }
+
+    storage.set(
+        DESCRIPTOR_CACHE_STORAGE_KEY.to_string(),
+        serde_json::to_value(cache)?,
+    )
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn load_cache(storage: &Storage) -> HashMap<String, CachedDescriptor> {
+    storage
+        .get(DESCRIPTOR_CACHE_STORAGE_KEY)
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::discovery::discover_socket_agent;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answer one request with 304 and a new ETag, returning the URL and
+    /// the request received
+    async fn serve_not_modified() -> (String, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(String::new()));
+        let request = received.clone();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let read = socket.read(&mut buf).await.unwrap_or(0);
+            *request.lock().unwrap() = String::from_utf8_lossy(&buf[..read]).to_string();
+            let head = "HTTP/1.1 304 Not Modified\r\nETag: \"v2\"\r\nConnection: close\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+        });
+        (format!("http://{}", addr), received)
+    }
+
+    #[tokio::test]
+    async fn a_304_reuses_the_cached_descriptor_and_refreshes_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let (base_url, request) = serve_not_modified().await;
+        let descriptor: SocketAgentDescriptor = serde_json::from_value(serde_json::json!({
+            "name": "Cached",
+            "baseUrl": base_url,
+            "endpoints": [{ "path": "/items", "method": "GET" }],
+        }))
+        .unwrap();
+        let mut entry = CachedDescriptor::new(
+            descriptor,
+            base_url.clone(),
+            "/.well-known/socket-agent".to_string(),
+            Some("\"v1\"".to_string()),
+            None,
+        );
+        entry.cached_at = 1;
+        save_cached_descriptor(&storage, &base_url, entry).unwrap();
+        let cached = load_cached_descriptor(&storage, &base_url);
+
+        let discovery =
+            discover_socket_agent(&base_url, None, Duration::from_secs(5), cached.as_ref())
+                .await
+                .unwrap();
+
+        let request = request.lock().unwrap().to_lowercase();
+        assert!(request.contains("if-none-match: \"v1\""), "{}", request);
+        assert_eq!(discovery.descriptor.name, "Cached");
+        save_cached_descriptor(&storage, &base_url, discovery.cache_entry.unwrap()).unwrap();
+        let refreshed = load_cached_descriptor(&storage, &base_url).unwrap();
+        assert_eq!(refreshed.descriptor.name, "Cached");
+        assert_eq!(refreshed.etag.as_deref(), Some("\"v2\""));
+        assert!(refreshed.cached_at > 1);
+    }
+}
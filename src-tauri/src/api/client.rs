@@ -2,32 +2,355 @@
 // Makes HTTP calls to Socket Agent APIs
 
 use anyhow::{anyhow, Context, Result};
-use reqwest::{Client, Method};
+use reqwest::{Client, Method, RequestBuilder, Response, Url};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
-use super::discovery::{get_endpoint, SocketAgentDescriptor};
+use crate::config::{client_builder, host_interval, log_enabled, max_response_bytes, LogLevel};
+use crate::response::ApiResult;
 
-#[derive(Serialize)]
+use super::breaker::CircuitBreaker;
+use super::cookies::CookieJar;
+use super::credentials::ApiCredential;
+use super::discovery::{
+    get_default_params, get_endpoint, get_pagination, get_rate_limit, missing_required_params,
+    synthetic_id, ArrayStyle, BodyEncoding, Endpoint, PaginationStyle, SocketAgentDescriptor,
+};
+use super::error::ApiError;
+use super::ratelimit::{HostThrottle, RateLimiter};
+use super::schema::validate_against_schema;
+
+/// Pages fetched by `call_api_paginated` when the caller doesn't say
+pub const DEFAULT_MAX_PAGES: u32 = 10;
+/// Hard cap on pages fetched in one paginated call
+pub const MAX_PAGES_LIMIT: u32 = 100;
+/// Calls from one batch that may be in flight at once
+pub const BATCH_CONCURRENCY: usize = 4;
+/// Bytes written between `download-progress` reports
+const DOWNLOAD_PROGRESS_BYTES: u64 = 256 * 1024;
+/// Replaces the API timeout for downloads, which covers the whole body
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Body of a successful call, returned in an `ApiResult`. A failed call's
+/// `ApiError` comes back as the error's details.
+#[derive(Debug, Serialize)]
 pub struct ApiCallResponse {
-    pub success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub data: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub status_code: Option<u16>,
+    pub body: Value,
+    /// Status of the response the body came from
+    pub status_code: u16,
+    /// Where the body didn't match the endpoint's response schema
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl ApiCallResponse {
+    pub fn new(body: Value, status_code: u16) -> Self {
+        Self {
+            body,
+            status_code,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn from_result(result: Result<Self, ApiError>) -> ApiResult<Self> {
+        match result {
+            Ok(response) => ApiResult::ok(response),
+            Err(e) => ApiResult::err_from(&e),
+        }
+    }
+}
+
+/// One call in a `call_api_batch` request
+#[derive(Debug, Deserialize)]
+pub struct BatchCall {
+    pub endpoint_id: String,
+    #[serde(default)]
+    pub params: HashMap<String, Value>,
+    #[serde(default)]
+    pub body_encoding: Option<BodyEncoding>,
+}
+
+/// File written by `call_api_download`
+#[derive(Debug, Serialize)]
+pub struct DownloadedFile {
+    pub path: String,
+    pub bytes: u64,
+    /// Status of the response, reported as the call's `status_code`
+    #[serde(skip)]
+    pub status: u16,
+}
+
+/// Result of a raw HTTP request
+#[derive(Serialize)]
+pub struct RawHttpResult {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    /// Parsed JSON, text, or a serialized `BinaryBody` when the body isn't UTF-8
+    pub body: Value,
+}
+
+/// Everything about an API that applies to each call made to it
+#[derive(Clone)]
+pub struct CallContext {
+    pub descriptor: Option<SocketAgentDescriptor>,
+    pub credential: Option<ApiCredential>,
+    /// Session cookies, when cookies are enabled
+    pub cookie_jar: Option<Arc<CookieJar>>,
+    pub timeout: Duration,
+    /// Enforces the descriptor's advertised rate limits across calls
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Keeps a minimum interval between requests to the same host
+    pub throttle: Arc<HostThrottle>,
+    /// Fails calls fast while the API keeps failing
+    pub breaker: Arc<CircuitBreaker>,
+    pub clients: Arc<ApiClients>,
+}
+
+/// How long an idle pooled connection is kept open for reuse
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Idle connections kept per host
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Pooled HTTP clients reused across API calls, so back-to-back calls to the
+/// same host skip the TCP and TLS handshakes. Timeouts are set per request.
+pub struct ApiClients {
+    // Used when the call has no cookie jar
+    shared: RwLock<Client>,
+    // Cookie jars are bound at build time, so each base URL with cookies
+    // gets its own client; rebuilt when the jar is replaced
+    with_cookies: Mutex<HashMap<String, (Arc<CookieJar>, Client)>>,
+}
+
+impl ApiClients {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            shared: RwLock::new(build_client(None)?),
+            with_cookies: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Client for a call to `base_url`, using `cookie_jar` if given
+    pub fn client(&self, base_url: &str, cookie_jar: Option<&Arc<CookieJar>>) -> Result<Client, ApiError> {
+        let Some(jar) = cookie_jar else {
+            return Ok(self.shared.read().unwrap().clone());
+        };
+
+        let key = base_url.trim_end_matches('/').to_string();
+        let mut clients = self.with_cookies.lock().unwrap();
+        if let Some((cached_jar, client)) = clients.get(&key) {
+            if Arc::ptr_eq(cached_jar, jar) {
+                return Ok(client.clone());
+            }
+        }
+
+        let client = build_client(Some(jar.clone())).map_err(|e| ApiError::Network {
+            message: format!("{:#}", e),
+        })?;
+        clients.insert(key, (jar.clone(), client.clone()));
+        Ok(client)
+    }
+
+    /// Drop every pooled connection, e.g. after the proxy setting changes
+    pub fn reset(&self) -> Result<()> {
+        *self.shared.write().unwrap() = build_client(None)?;
+        self.with_cookies.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+fn build_client(cookie_jar: Option<Arc<CookieJar>>) -> Result<Client> {
+    let mut builder = client_builder()?
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .tcp_keepalive(TCP_KEEPALIVE);
+    if let Some(jar) = cookie_jar {
+        builder = builder.cookie_provider(jar);
+    }
+    Ok(builder.build()?)
+}
+
+/// Non-JSON response body, base64 encoded so it survives the trip to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct BinaryBody {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
+    pub content_type: Option<String>,
+    pub size: usize,
+    pub base64: String,
+}
+
+impl BinaryBody {
+    fn new(content_type: Option<String>, bytes: &[u8]) -> Self {
+        Self {
+            content_type,
+            size: bytes.len(),
+            base64: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes),
+        }
+    }
+}
+
+/// Successful response body, its status and its declared content type
+struct ResponseBody {
+    status: u16,
+    content_type: Option<String>,
+    bytes: Vec<u8>,
 }
 
-/// Make an API call to a Socket Agent endpoint
+/// Make an API call to a Socket Agent endpoint. JSON responses are parsed;
+/// anything else (images, PDFs, ...) comes back as a serialized `BinaryBody`.
+/// `body_encoding` overrides the encoding the endpoint declares for its body.
 pub async fn call_api(
     base_url: &str,
     endpoint_id: &str,
     params: HashMap<String, Value>,
-    descriptor: Option<&SocketAgentDescriptor>,
-) -> Result<Value> {
+    body_encoding: Option<BodyEncoding>,
+    context: &CallContext,
+) -> Result<ApiCallResponse, ApiError> {
+    let body = send_api_request(base_url, endpoint_id, params, body_encoding, context).await?;
+
+    let declared_json = body
+        .content_type
+        .as_deref()
+        .map(|content_type| content_type.to_ascii_lowercase().contains("json"));
+
+    let data = match declared_json {
+        Some(true) => serde_json::from_slice(&body.bytes).map_err(|e| ApiError::Parse {
+            message: e.to_string(),
+        })?,
+        Some(false) => return binary_value(body),
+        // Without a content type, treat it as JSON only if it parses as JSON
+        None => match serde_json::from_slice(&body.bytes) {
+            Ok(data) => data,
+            Err(_) => return binary_value(body),
+        },
+    };
+
+    if log_enabled(LogLevel::Debug) {
+        println!("API response data: {:?}", data);
+    }
+
+    Ok(ApiCallResponse::new(data, body.status))
+}
+
+/// Mismatches between `data` and the response schema the endpoint declares;
+/// empty when it declares none
+pub fn response_warnings(context: &CallContext, endpoint_id: &str, data: &Value) -> Vec<String> {
+    let schema = context
+        .descriptor
+        .as_ref()
+        .and_then(|descriptor| get_endpoint(descriptor, endpoint_id))
+        .and_then(|endpoint| endpoint.response_schema);
+
+    match schema {
+        Some(schema) => {
+            let warnings = validate_against_schema(&schema, data);
+            if !warnings.is_empty() {
+                println!(
+                    "Response from {} doesn't match its schema: {}",
+                    endpoint_id,
+                    warnings.join("; ")
+                );
+            }
+            warnings
+        }
+        None => Vec::new(),
+    }
+}
+
+fn binary_value(body: ResponseBody) -> Result<ApiCallResponse, ApiError> {
+    let binary = BinaryBody::new(body.content_type, &body.bytes);
+    println!(
+        "API response is binary: {} bytes of {}",
+        binary.size,
+        binary.content_type.as_deref().unwrap_or("unknown type")
+    );
+    let value = serde_json::to_value(binary).map_err(|e| ApiError::Parse {
+        message: e.to_string(),
+    })?;
+    Ok(ApiCallResponse::new(value, body.status))
+}
+
+/// Make an API call and return the raw body bytes as a serialized
+/// `BinaryBody`, whatever the content type
+pub async fn call_api_binary(
+    base_url: &str,
+    endpoint_id: &str,
+    params: HashMap<String, Value>,
+    context: &CallContext,
+) -> Result<ApiCallResponse, ApiError> {
+    let body = send_api_request(base_url, endpoint_id, params, None, context).await?;
+    binary_value(body)
+}
+
+/// Send the request for an endpoint call, returning the body of a
+/// successful response
+async fn send_api_request(
+    base_url: &str,
+    endpoint_id: &str,
+    params: HashMap<String, Value>,
+    body_encoding: Option<BodyEncoding>,
+    context: &CallContext,
+) -> Result<ResponseBody, ApiError> {
+    let request = build_api_request(base_url, endpoint_id, params, body_encoding, context).await?;
+
+    // Send request, counting transport and server failures against the circuit
+    let result: Result<ResponseBody, ApiError> = async {
+        let response = request.send().await?;
+
+        let status = response.status();
+        println!("API response: {}", status);
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let body = read_body_limited(response, max_response_bytes()).await?;
+
+        // Handle error responses
+        if !status.is_success() {
+            let error_text = String::from_utf8_lossy(&body).into_owned();
+            return Err(ApiError::from_status(status.as_u16(), error_text));
+        }
+
+        Ok(ResponseBody {
+            status: status.as_u16(),
+            content_type,
+            bytes: body,
+        })
+    }
+    .await;
+
+    record_outcome(base_url, endpoint_id, context, &result);
+    result
+}
+
+/// Resolve an endpoint call against the descriptor and build its request,
+/// after the parameter, circuit breaker and rate limit checks. The body is
+/// sent as `body_encoding`, or as the endpoint declares when that's `None`.
+pub(crate) async fn build_api_request(
+    base_url: &str,
+    endpoint_id: &str,
+    mut params: HashMap<String, Value>,
+    body_encoding: Option<BodyEncoding>,
+    context: &CallContext,
+) -> Result<RequestBuilder, ApiError> {
+    let descriptor = context.descriptor.as_ref();
+
+    // Fill in descriptor context defaults the caller didn't override
+    if let Some(desc) = descriptor {
+        for (key, value) in get_default_params(desc) {
+            params.entry(key).or_insert(value);
+        }
+    }
+
     let mut method = "GET";
     let mut path = endpoint_id;
 
@@ -38,6 +361,42 @@ pub async fn call_api(
         None
     };
 
+    // Endpoints from a merged descriptor go to the API they came from
+    let breaker_key = breaker_key(base_url, endpoint.as_ref());
+    let origin = endpoint.as_ref().and_then(|ep| ep.base_url.clone());
+    let base_url = origin.as_deref().unwrap_or(base_url);
+
+    // Reject missing required params and values outside a parameter's
+    // declared enum before sending anything
+    if let Some(ep) = &endpoint {
+        let names = missing_required_params(ep, &params);
+        if !names.is_empty() {
+            return Err(ApiError::MissingParameters { names });
+        }
+        validate_params(ep, &params)?;
+    }
+
+    // Don't wait on an API that keeps failing
+    context
+        .breaker
+        .check(&breaker_key)
+        .map_err(|retry_after| ApiError::CircuitOpen { retry_after })?;
+
+    // Stay within the rate limit the descriptor advertises for this endpoint
+    if let (Some(desc), Some(ep)) = (descriptor, &endpoint) {
+        if let Some(limit) = get_rate_limit(desc, ep) {
+            let key = format!("{} {}", base_url.trim_end_matches('/'), synthetic_id(ep));
+            context.rate_limiter.acquire(&key, limit).await?;
+        }
+    }
+
+    // Don't burst a single host, e.g. when a dashboard refreshes many calls at once
+    let host = Url::parse(base_url)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or_else(|| breaker_key.clone());
+    context.throttle.wait(&host, host_interval()).await;
+
     if let Some(ep) = &endpoint {
         if let Some(m) = &ep.method {
             method = m.as_str();
@@ -47,89 +406,1365 @@ pub async fn call_api(
 
     // Substitute path parameters and separate query/body params
     let mut final_path = path.to_string();
-    let mut query_params: HashMap<String, String> = HashMap::new();
+    let mut query_params: Vec<(String, String)> = Vec::new();
     let mut body_params: HashMap<String, Value> = HashMap::new();
 
     for (key, value) in params {
         let placeholder = format!("{{{}}}", key);
         if final_path.contains(&placeholder) {
             // Path parameter
-            let value_str = value.as_str().unwrap_or_else(|| value.to_string().trim_matches('"'));
-            final_path = final_path.replace(&placeholder, value_str);
+            final_path = final_path.replace(&placeholder, &param_to_string(&value));
         } else if method == "GET" || method == "DELETE" {
             // Query parameter
-            let value_str = value.as_str().unwrap_or_else(|| value.to_string().trim_matches('"'));
-            query_params.insert(key, value_str.to_string());
+            let style = endpoint
+                .as_ref()
+                .and_then(|ep| ep.parameters.iter().find(|p| p.name == key))
+                .map(|p| p.array_style())
+                .unwrap_or_default();
+            push_query_param(&mut query_params, key, &value, style);
         } else {
             // Body parameter
             body_params.insert(key, value);
         }
     }
 
+    // Nest body params the way the endpoint's request schema expects
+    let body = if body_params.is_empty() {
+        None
+    } else {
+        let schema = endpoint.as_ref().and_then(|ep| ep.request_schema.as_ref());
+        Some(build_body(body_params, schema)?)
+    };
+
     // Build final URL
     let url = format!("{}{}", base_url.trim_end_matches('/'), final_path);
 
     println!("Making API call: {} {}", method, url);
-    if !query_params.is_empty() {
-        println!("Query params: {:?}", query_params);
-    }
-    if !body_params.is_empty() {
-        println!("Body params: {:?}", body_params);
+    if log_enabled(LogLevel::Debug) {
+        if !query_params.is_empty() {
+            println!("Query params: {:?}", query_params);
+        }
+        if let Some(body) = &body {
+            println!("Body: {}", body);
+        }
     }
 
-    // Create HTTP client
-    let client = Client::builder()
-        .timeout(Duration::from_secs(15))
-        .build()?;
+    let client = context
+        .clients
+        .client(base_url, context.cookie_jar.as_ref())?;
 
     // Parse method
-    let http_method = Method::from_bytes(method.as_bytes())
-        .context("Invalid HTTP method")?;
+    let http_method =
+        Method::from_bytes(method.as_bytes()).map_err(|_| ApiError::InvalidDescriptor {
+            message: format!("Invalid HTTP method: {}", method),
+        })?;
 
     // Build request
     let mut request = client
         .request(http_method, &url)
+        .timeout(context.timeout)
         .header("Accept", "application/json")
         .header("User-Agent", "Socket-Browser/0.1.0");
 
+    // Attach the user's credential in the descriptor's auth scheme
+    if let Some(credential) = &context.credential {
+        request = credential.apply(request, descriptor);
+    }
+
     // Add query parameters
     if !query_params.is_empty() {
         request = request.query(&query_params);
     }
 
     // Add body for non-GET/DELETE requests
-    if method != "GET" && method != "DELETE" && !body_params.is_empty() {
-        request = request
-            .header("Content-Type", "application/json")
-            .json(&body_params);
+    let body_encoding = body_encoding
+        .or_else(|| endpoint.as_ref().map(Endpoint::body_encoding))
+        .unwrap_or_default();
+    if let Some(body) = body.filter(|_| method != "GET" && method != "DELETE") {
+        request = match body_encoding {
+            BodyEncoding::Json => request
+                .header("Content-Type", "application/json")
+                .json(&body),
+            // Sets the form Content-Type itself
+            BodyEncoding::Form => request.form(&form_fields(&body)),
+        };
+    }
+
+    Ok(request)
+}
+
+/// The circuit a call to `endpoint` goes through: that of the API it came
+/// from in a merged descriptor, or of `base_url`
+fn breaker_key(base_url: &str, endpoint: Option<&Endpoint>) -> String {
+    endpoint
+        .and_then(|ep| ep.base_url.as_deref())
+        .unwrap_or(base_url)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Count transport and server failures against the circuit the call to
+/// `endpoint_id` was checked against
+pub(crate) fn record_outcome<T>(
+    base_url: &str,
+    endpoint_id: &str,
+    context: &CallContext,
+    result: &Result<T, ApiError>,
+) {
+    let endpoint = context
+        .descriptor
+        .as_ref()
+        .and_then(|desc| get_endpoint(desc, endpoint_id));
+    let breaker_key = breaker_key(base_url, endpoint.as_ref());
+    match result {
+        Err(e) if e.is_retriable() => context.breaker.record_failure(&breaker_key),
+        _ => context.breaker.record_success(&breaker_key),
     }
+}
 
-    // Send request
-    let response = request.send().await.context("Failed to send request")?;
+/// Where a download named `file_name` goes in `dir`. The name must be a
+/// plain file name, not a path, and mustn't name an existing file.
+pub fn download_path(dir: &Path, file_name: &str) -> Result<PathBuf, ApiError> {
+    let invalid = |message: &str| ApiError::InvalidParameter {
+        name: "file_name".to_string(),
+        message: message.to_string(),
+    };
+    let mut components = Path::new(file_name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(name)), None) if name == file_name => {}
+        _ => return Err(invalid("must be a file name, not a path")),
+    }
+
+    let path = dir.join(file_name);
+    if path.exists() {
+        return Err(invalid("a file with that name already exists"));
+    }
+    Ok(path)
+}
+
+/// Make an API call and stream the response body into a new file at `path`,
+/// without buffering it or applying the response size limit. An existing
+/// file is never overwritten. `on_progress` gets the bytes written so far
+/// and the total when the server sent a length. A partly written file is
+/// removed on failure.
+pub async fn call_api_download(
+    base_url: &str,
+    endpoint_id: &str,
+    params: HashMap<String, Value>,
+    context: &CallContext,
+    path: &Path,
+    mut on_progress: impl FnMut(u64, Option<u64>),
+) -> Result<DownloadedFile, ApiError> {
+    let request = build_api_request(base_url, endpoint_id, params, None, context)
+        .await?
+        .timeout(DOWNLOAD_TIMEOUT);
 
-    let status = response.status();
-    println!("API response: {}", status);
+    let result: Result<Response, ApiError> = async {
+        let response = request.send().await?;
 
-    // Handle error responses
-    if !status.is_success() {
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        let status = response.status();
+        println!("API response: {}", status);
 
-        if status.as_u16() >= 400 && status.as_u16() < 500 {
-            return Err(anyhow!("Client error ({}): {}", status.as_u16(), error_text));
-        } else if status.as_u16() >= 500 {
-            return Err(anyhow!("Server error ({}): {}", status.as_u16(), error_text));
-        } else {
-            return Err(anyhow!("HTTP {}: {}", status.as_u16(), error_text));
+        if !status.is_success() {
+            let body = read_body_limited(response, max_response_bytes()).await?;
+            let error_text = String::from_utf8_lossy(&body).into_owned();
+            return Err(ApiError::from_status(status.as_u16(), error_text));
         }
+        Ok(response)
     }
+    .await;
+    record_outcome(base_url, endpoint_id, context, &result);
+    let response = result?;
+    let status = response.status().as_u16();
 
-    // Parse response
-    let data: Value = response
-        .json()
+    let total = response.content_length();
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
         .await
-        .context("Failed to parse API response")?;
+        .map_err(|e| ApiError::from_io(path, e))?;
+    let result = write_download(response, file, path, total, &mut on_progress).await;
+    if result.is_err() {
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            println!(
+                "Failed to remove partial download {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+    let bytes = result?;
 
-    println!("API response data: {:?}", data);
+    println!("Downloaded {} bytes to {}", bytes, path.display());
+    Ok(DownloadedFile {
+        path: path.display().to_string(),
+        bytes,
+        status,
+    })
+}
+
+async fn write_download(
+    mut response: Response,
+    mut file: tokio::fs::File,
+    path: &Path,
+    total: Option<u64>,
+    on_progress: &mut impl FnMut(u64, Option<u64>),
+) -> Result<u64, ApiError> {
+    let write_error = |e: std::io::Error| ApiError::from_io(path, e);
+
+    let mut written: u64 = 0;
+    let mut reported: u64 = 0;
+    on_progress(0, total);
+
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await.map_err(write_error)?;
+        written += chunk.len() as u64;
+        if written - reported >= DOWNLOAD_PROGRESS_BYTES {
+            on_progress(written, total);
+            reported = written;
+        }
+    }
+    file.flush().await.map_err(write_error)?;
+    file.sync_all().await.map_err(write_error)?;
 
-    Ok(data)
+    if reported != written {
+        on_progress(written, total);
+    }
+    Ok(written)
+}
+
+/// Run several calls concurrently, at most `BATCH_CONCURRENCY` at a time.
+/// Each call comes with the base URL and context of the API it goes to, so
+/// one batch can span the APIs of a merged descriptor. Each call gets its
+/// own response, in input order; a failing call doesn't affect the others.
+pub async fn call_api_batch(
+    calls: Vec<(String, BatchCall, Arc<CallContext>)>,
+) -> Vec<ApiResult<ApiCallResponse>> {
+    let permits = Arc::new(Semaphore::new(BATCH_CONCURRENCY));
+
+    let mut tasks = JoinSet::new();
+    let count = calls.len();
+    for (index, (base_url, call, context)) in calls.into_iter().enumerate() {
+        let permits = permits.clone();
+
+        tasks.spawn(async move {
+            let _permit = permits.acquire().await;
+            let result = call_api(
+                &base_url,
+                &call.endpoint_id,
+                call.params,
+                call.body_encoding,
+                &context,
+            )
+            .await;
+            (index, result)
+        });
+    }
+
+    let mut results: Vec<Option<ApiResult<ApiCallResponse>>> = (0..count).map(|_| None).collect();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((index, result)) => results[index] = Some(ApiCallResponse::from_result(result)),
+            Err(e) => println!("Batch call task failed: {}", e),
+        }
+    }
+
+    results
+        .into_iter()
+        .map(|result| {
+            result.unwrap_or_else(|| {
+                ApiCallResponse::from_result(Err(ApiError::Network {
+                    message: "Call was aborted".to_string(),
+                }))
+            })
+        })
+        .collect()
+}
+
+/// Follow the pages of a list endpoint and concatenate their items into one array,
+/// with the status of the last page. The pagination style comes from the
+/// descriptor context (cursor by default).
+pub async fn call_api_paginated(
+    base_url: &str,
+    endpoint_id: &str,
+    mut params: HashMap<String, Value>,
+    max_pages: u32,
+    context: &CallContext,
+) -> Result<ApiCallResponse, ApiError> {
+    let pagination = context
+        .descriptor
+        .as_ref()
+        .map(get_pagination)
+        .unwrap_or_default();
+    let max_pages = max_pages.clamp(1, MAX_PAGES_LIMIT);
+
+    let limit = params
+        .get(&pagination.limit_param)
+        .and_then(|v| v.as_u64())
+        .unwrap_or(pagination.page_size);
+    let mut offset = params
+        .get(&pagination.offset_param)
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+    let mut page_number = params
+        .get(&pagination.page_param)
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1);
+
+    let mut items: Vec<Value> = Vec::new();
+    let mut status = 0;
+
+    for page in 1..=max_pages {
+        match pagination.style {
+            PaginationStyle::Cursor => {}
+            PaginationStyle::Offset => {
+                params.insert(pagination.offset_param.clone(), Value::from(offset));
+                params.insert(pagination.limit_param.clone(), Value::from(limit));
+            }
+            PaginationStyle::Page => {
+                params.insert(pagination.page_param.clone(), Value::from(page_number));
+                params.insert(pagination.limit_param.clone(), Value::from(limit));
+            }
+        }
+
+        let response = call_api(base_url, endpoint_id, params.clone(), None, context).await?;
+        status = response.status_code;
+        let data = response.body;
+        let page_items = page_items(&data, &pagination.items_field);
+        let count = page_items.len() as u64;
+        items.extend(page_items);
+
+        println!("Fetched page {} ({} items)", page, count);
+
+        match pagination.style {
+            PaginationStyle::Cursor => match next_cursor(&data, &pagination.next_field) {
+                Some(cursor) => {
+                    params.insert(pagination.cursor_param.clone(), cursor);
+                }
+                None => break,
+            },
+            PaginationStyle::Offset => {
+                if count < limit {
+                    break;
+                }
+                offset += count;
+            }
+            PaginationStyle::Page => {
+                if count < limit {
+                    break;
+                }
+                page_number += 1;
+            }
+        }
+
+        if page == max_pages {
+            println!("Stopped after reaching the {} page limit", max_pages);
+        }
+    }
+
+    Ok(ApiCallResponse::new(Value::Array(items), status))
+}
+
+/// Read a response body, aborting once it grows past `limit` bytes
+pub(crate) async fn read_body_limited(
+    mut response: Response,
+    limit: usize,
+) -> Result<Vec<u8>, ApiError> {
+    if response.content_length().is_some_and(|len| len > limit as u64) {
+        return Err(ApiError::TooLarge { limit });
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(ApiError::TooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+/// Check supplied values against each parameter's `enum`. Values are also
+/// compared as strings since form inputs send numbers as text.
+fn validate_params(endpoint: &Endpoint, params: &HashMap<String, Value>) -> Result<(), ApiError> {
+    for param in &endpoint.parameters {
+        let (value, allowed) = match (params.get(&param.name), param.allowed_values()) {
+            (Some(value), Some(allowed)) => (value, allowed),
+            _ => continue,
+        };
+
+        // Each item of an array value has to be allowed
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let text = param_to_string(value);
+            if !allowed
+                .iter()
+                .any(|option| option == value || param_to_string(option) == text)
+            {
+                let options: Vec<String> = allowed.iter().map(param_to_string).collect();
+                return Err(ApiError::InvalidParameter {
+                    name: param.name.clone(),
+                    message: format!("'{}' is not one of: {}", text, options.join(", ")),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Add `value` to the query string, spreading an array in `style`
+fn push_query_param(
+    query: &mut Vec<(String, String)>,
+    key: String,
+    value: &Value,
+    style: ArrayStyle,
+) {
+    let Value::Array(items) = value else {
+        query.push((key, param_to_string(value)));
+        return;
+    };
+
+    let key = match style {
+        ArrayStyle::Repeat => key,
+        ArrayStyle::Brackets => format!("{}[]", key),
+        // An empty list is left out rather than sent as `key=`
+        ArrayStyle::Comma => {
+            if !items.is_empty() {
+                let joined: Vec<String> = items.iter().map(param_to_string).collect();
+                query.push((key, joined.join(",")));
+            }
+            return;
+        }
+    };
+    for item in items {
+        query.push((key.clone(), param_to_string(item)));
+    }
+}
+
+/// JSON body for `params`. Dotted keys (`user.email`) become nested objects,
+/// and a plain key the schema only declares inside one object property
+/// (`email` under `user`) is moved there. A key that would nest inside a
+/// param that isn't an object (`user` given as a string) is rejected.
+fn build_body(params: HashMap<String, Value>, schema: Option<&Value>) -> Result<Value, ApiError> {
+    let mut params: Vec<(String, Vec<String>, Value)> = params
+        .into_iter()
+        .map(|(key, value)| {
+            let mut path: Vec<String> = if key.split('.').any(|segment| segment.is_empty()) {
+                vec![key.clone()]
+            } else {
+                key.split('.').map(String::from).collect()
+            };
+            if let [name] = path.as_slice() {
+                if let Some(parent) = schema.and_then(|s| schema_parent(s, name)) {
+                    path.insert(0, parent);
+                }
+            }
+            (key, path, value)
+        })
+        .collect();
+    // Shallower paths first, so `user.email` lands inside a `user` object
+    // rather than being overwritten by it
+    params.sort_by(|a, b| a.1.len().cmp(&b.1.len()).then_with(|| a.0.cmp(&b.0)));
+
+    let mut body = Map::new();
+    for (key, path, value) in params {
+        insert_nested(&mut body, &path, value).map_err(|parent| ApiError::InvalidParameter {
+            name: key,
+            message: format!(
+                "'{}' is not an object, so nothing can be nested in it",
+                parent
+            ),
+        })?;
+    }
+    Ok(Value::Object(body))
+}
+
+/// Form fields for a body built by `build_body`. Nested objects become
+/// `user[email]` keys, arrays repeat their key and nulls are left out.
+fn form_fields(body: &Value) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    if let Value::Object(object) = body {
+        for (key, value) in object {
+            push_form_field(&mut fields, key.clone(), value);
+        }
+    }
+    fields
+}
+
+fn push_form_field(fields: &mut Vec<(String, String)>, key: String, value: &Value) {
+    match value {
+        Value::Null => {}
+        Value::Object(object) => {
+            for (name, value) in object {
+                push_form_field(fields, format!("{}[{}]", key, name), value);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                push_form_field(fields, key.clone(), item);
+            }
+        }
+        value => fields.push((key, param_to_string(value))),
+    }
+}
+
+/// The single object property of `schema` that declares `key`, unless `key`
+/// is a top-level property itself
+fn schema_parent(schema: &Value, key: &str) -> Option<String> {
+    let properties = schema.get("properties")?.as_object()?;
+    if properties.contains_key(key) {
+        return None;
+    }
+
+    let mut parents = properties.iter().filter(|(_, property)| {
+        property
+            .get("properties")
+            .and_then(|p| p.get(key))
+            .is_some()
+    });
+    match (parents.next(), parents.next()) {
+        (Some((name, _)), None) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+/// Set `value` at `path` in `object`, creating objects along the way. Fails
+/// with the dotted path of a value in the way that isn't an object.
+fn insert_nested(
+    object: &mut Map<String, Value>,
+    path: &[String],
+    value: Value,
+) -> Result<(), String> {
+    match path {
+        [] => Ok(()),
+        [key] => {
+            object.insert(key.clone(), value);
+            Ok(())
+        }
+        [key, rest @ ..] => {
+            let child = object
+                .entry(key.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            match child {
+                Value::Object(child) => {
+                    insert_nested(child, rest, value).map_err(|name| format!("{}.{}", key, name))
+                }
+                _ => Err(key.clone()),
+            }
+        }
+    }
+}
+
+/// Render a param value for a path segment or query string
+pub(crate) fn param_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Items of a single page: the response itself if it's an array, else `items_field`
+fn page_items(data: &Value, items_field: &str) -> Vec<Value> {
+    match data {
+        Value::Array(items) => items.clone(),
+        _ => data
+            .get(items_field)
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default(),
+    }
+}
+
+/// Next cursor from a page, or `None` when there are no more pages
+fn next_cursor(data: &Value, next_field: &str) -> Option<Value> {
+    match data.get(next_field)? {
+        Value::String(s) if s.is_empty() => None,
+        v @ (Value::String(_) | Value::Number(_)) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// Send an arbitrary HTTP request, not tied to any descriptor, through the
+/// same circuit breaker, host throttle, timeout and size limit as API calls.
+/// Non-2xx responses are returned as-is rather than turned into errors.
+pub async fn raw_request(
+    method: &str,
+    url: &str,
+    headers: HashMap<String, String>,
+    body: Option<Value>,
+    context: &CallContext,
+) -> Result<RawHttpResult> {
+    let parsed_url = reqwest::Url::parse(url).context("Invalid URL")?;
+    if parsed_url.scheme() != "http" && parsed_url.scheme() != "https" {
+        return Err(anyhow!("Only http and https URLs are supported"));
+    }
+
+    let http_method = Method::from_bytes(method.to_uppercase().as_bytes())
+        .context("Invalid HTTP method")?;
+
+    let origin = parsed_url.origin().ascii_serialization();
+    context
+        .breaker
+        .check(&origin)
+        .map_err(|retry_after| ApiError::CircuitOpen { retry_after })?;
+    if let Some(host) = parsed_url.host_str() {
+        context.throttle.wait(host, host_interval()).await;
+    }
+
+    println!("Making raw HTTP call: {} {}", http_method, parsed_url);
+
+    let client = context.clients.client(&origin, None)?;
+
+    let mut request = client
+        .request(http_method, parsed_url)
+        .timeout(context.timeout)
+        .header("User-Agent", "Socket-Browser/0.1.0");
+
+    for (name, value) in &headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+
+    if let Some(body) = &body {
+        request = request.json(body);
+    }
+
+    // Server errors count against the circuit like failed API calls
+    let result: Result<RawHttpResult, ApiError> = async {
+        let response = request.send().await?;
+
+        let status = response.status().as_u16();
+        let response_headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let bytes = read_body_limited(response, max_response_bytes()).await?;
+        let body = match serde_json::from_slice(&bytes) {
+            Ok(json) => json,
+            Err(_) => match String::from_utf8(bytes) {
+                Ok(text) => Value::String(text),
+                // Binary bodies are passed on base64 encoded, not mangled into text
+                Err(e) => serde_json::to_value(BinaryBody::new(content_type, e.as_bytes()))
+                    .map_err(|e| ApiError::Parse {
+                        message: e.to_string(),
+                    })?,
+            },
+        };
+
+        println!("Raw HTTP response: {}", status);
+
+        Ok(RawHttpResult {
+            status,
+            headers: response_headers,
+            body,
+        })
+    }
+    .await;
+
+    let failed = match &result {
+        Ok(raw) => raw.status >= 500,
+        Err(e) => e.is_retriable(),
+    };
+    if failed {
+        context.breaker.record_failure(&origin);
+    } else {
+        context.breaker.record_success(&origin);
+    }
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::merge::{merge_descriptors, route_endpoint};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// Answer one request with `status` and `body`, returning the server's
+    /// base URL
+    async fn serve_once(status: u16, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let head = format!(
+                "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&body).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn test_context() -> CallContext {
+        CallContext {
+            descriptor: None,
+            credential: None,
+            cookie_jar: None,
+            timeout: Duration::from_secs(5),
+            rate_limiter: Arc::new(RateLimiter::new()),
+            throttle: Arc::new(HostThrottle::new()),
+            breaker: Arc::new(CircuitBreaker::new()),
+            clients: Arc::new(ApiClients::new().unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn download_writes_the_whole_body() {
+        let body: Vec<u8> = (0..DOWNLOAD_PROGRESS_BYTES as usize * 3 + 17)
+            .map(|i| i as u8)
+            .collect();
+        let base_url = serve_once(200, body.clone()).await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = download_path(dir.path(), "data.bin").unwrap();
+
+        let mut progress = Vec::new();
+        let file = call_api_download(
+            &base_url,
+            "/data",
+            HashMap::new(),
+            &test_context(),
+            &path,
+            |bytes, total| progress.push((bytes, total)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(file.bytes, body.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        let total = Some(body.len() as u64);
+        assert_eq!(progress.first(), Some(&(0, total)));
+        assert_eq!(progress.last(), Some(&(body.len() as u64, total)));
+    }
+
+    #[tokio::test]
+    async fn download_never_overwrites_a_file() {
+        let base_url = serve_once(200, b"new".to_vec()).await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, b"old").unwrap();
+
+        let result = call_api_download(
+            &base_url,
+            "/data",
+            HashMap::new(),
+            &test_context(),
+            &path,
+            |_, _| {},
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiError::Write { .. })));
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+    }
+
+    #[test]
+    fn body_nests_by_path_depth() {
+        let params = HashMap::from([
+            ("user.address.city".to_string(), json!("Oslo")),
+            ("user.name".to_string(), json!("Ada")),
+            ("user".to_string(), json!({ "id": 7 })),
+        ]);
+
+        let body = build_body(params, None).unwrap();
+
+        assert_eq!(
+            body,
+            json!({ "user": { "id": 7, "name": "Ada", "address": { "city": "Oslo" } } })
+        );
+    }
+
+    #[test]
+    fn body_refuses_to_nest_inside_a_scalar() {
+        let params = HashMap::from([
+            ("user".to_string(), json!("ada")),
+            ("user.email".to_string(), json!("ada@example.com")),
+        ]);
+
+        let error = build_body(params, None).unwrap_err();
+
+        assert!(
+            matches!(&error, ApiError::InvalidParameter { name, .. } if name == "user.email"),
+            "{:?}",
+            error
+        );
+    }
+
+    /// Answer one request with each of `bodies` in turn, returning the base
+    /// URL and the request lines received
+    async fn serve_each(bodies: Vec<&'static str>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for body in bodies {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 4096];
+                let read = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let line = request.lines().next().unwrap_or_default().to_string();
+                received.lock().unwrap().push(line);
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(body.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    /// Like `serve_each`, with extra header lines per response, recording
+    /// each request in full
+    async fn serve_each_with_headers(
+        responses: Vec<(&'static str, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for (headers, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 4096];
+                let read = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                received.lock().unwrap().push(request);
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    headers,
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(body.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    #[tokio::test]
+    async fn cookies_an_api_sets_are_sent_back_to_it() {
+        let (base_url, requests) = serve_each_with_headers(vec![
+            ("Set-Cookie: session=abc123; Path=/\r\n", "{}"),
+            ("", "{}"),
+        ])
+        .await;
+        let context = CallContext {
+            cookie_jar: Some(Arc::new(CookieJar::default())),
+            ..test_context()
+        };
+
+        call_api(&base_url, "/login", HashMap::new(), None, &context)
+            .await
+            .unwrap();
+        call_api(&base_url, "/me", HashMap::new(), None, &context)
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap().join("\n").to_lowercase();
+        let (first, second) = requests.split_once("get /me").unwrap();
+        assert!(!first.contains("cookie:"), "{}", first);
+        assert!(second.contains("cookie: session=abc123"), "{}", second);
+    }
+
+    /// Serve `{}` over keep-alive connections, counting the connections made
+    async fn serve_keep_alive() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut request = [0u8; 4096];
+                    while socket.read(&mut request).await.unwrap_or(0) > 0 {
+                        let response = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}";
+                        if socket.write_all(response.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        (format!("http://{}", addr), connections)
+    }
+
+    #[tokio::test]
+    async fn calls_reuse_pooled_connections_per_origin() {
+        let (first, first_connections) = serve_keep_alive().await;
+        let (second, second_connections) = serve_keep_alive().await;
+        let context = test_context();
+
+        for base_url in [&first, &second, &first, &second, &first] {
+            call_api(base_url, "/items", HashMap::new(), None, &context)
+                .await
+                .unwrap();
+        }
+        assert_eq!(first_connections.load(Ordering::SeqCst), 1);
+        assert_eq!(second_connections.load(Ordering::SeqCst), 1);
+
+        // What a proxy change does, so later calls connect through the new proxy
+        context.clients.reset().unwrap();
+        call_api(&first, "/items", HashMap::new(), None, &context)
+            .await
+            .unwrap();
+        assert_eq!(first_connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn page_numbers_are_walked_until_a_short_page() {
+        let (base_url, requests) = serve_each(vec!["[1, 2]", "[3]"]).await;
+        let descriptor = serde_json::from_value(json!({
+            "name": "Pages",
+            "baseUrl": base_url,
+            "endpoints": [{ "path": "/items", "method": "GET" }],
+            "context": { "pagination": { "pageParam": "p", "pageSize": 2 } },
+        }))
+        .unwrap();
+        let context = CallContext {
+            descriptor: Some(descriptor),
+            ..test_context()
+        };
+
+        let items = call_api_paginated(&base_url, "/items", HashMap::new(), 5, &context)
+            .await
+            .unwrap();
+
+        assert_eq!(items.body, json!([1, 2, 3]));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("p=1"), "{}", requests[0]);
+        assert!(requests[1].contains("p=2"), "{}", requests[1]);
+        assert!(requests[1].contains("limit=2"), "{}", requests[1]);
+    }
+
+    #[tokio::test]
+    async fn context_defaults_fill_in_params_the_caller_left_out() {
+        let (base_url, requests) = serve_each(vec!["{}"]).await;
+        let descriptor = serde_json::from_value(json!({
+            "name": "Prices",
+            "baseUrl": base_url,
+            "endpoints": [{ "path": "/prices", "method": "GET" }],
+            "context": { "defaultCurrency": "USD", "defaultLanguage": "en" },
+        }))
+        .unwrap();
+        let context = CallContext {
+            descriptor: Some(descriptor),
+            ..test_context()
+        };
+        let params = HashMap::from([("language".to_string(), json!("de"))]);
+
+        call_api(&base_url, "/prices", params, None, &context)
+            .await
+            .unwrap();
+
+        let request = &requests.lock().unwrap()[0];
+        assert!(request.contains("currency=USD"), "{}", request);
+        assert!(request.contains("language=de"), "{}", request);
+        assert!(!request.contains("language=en"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn calls_missing_required_params_are_rejected_unsent() {
+        let (base_url, requests) = serve_each(vec!["{}"]).await;
+        let descriptor = serde_json::from_value(json!({
+            "name": "Posts",
+            "baseUrl": base_url,
+            "endpoints": [{
+                "path": "/users/{id}/posts",
+                "method": "POST",
+                "parameters": [{ "name": "title", "in": "body", "required": true }],
+                "requestSchema": { "type": "object", "required": ["body"] },
+            }],
+        }))
+        .unwrap();
+        let context = CallContext {
+            descriptor: Some(descriptor),
+            ..test_context()
+        };
+        let params = HashMap::from([("title".to_string(), json!(" "))]);
+
+        let error = call_api(&base_url, "/users/{id}/posts", params, None, &context)
+            .await
+            .unwrap_err();
+
+        match &error {
+            ApiError::MissingParameters { names } => assert_eq!(names, &["title", "id", "body"]),
+            other => panic!("expected missing parameters, got {:?}", other),
+        }
+        assert_eq!(
+            error.to_string(),
+            "Missing required parameters: title, id, body"
+        );
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn calls_past_the_advertised_rate_limit_are_rejected_unsent() {
+        let (base_url, requests) = serve_each(vec!["[]"]).await;
+        let descriptor = serde_json::from_value(json!({
+            "name": "Limited",
+            "baseUrl": base_url,
+            "endpoints": [{ "path": "/items", "method": "GET" }],
+            "context": { "rateLimits": { "*": { "requests": 1, "window": 3600 } } },
+        }))
+        .unwrap();
+        let context = CallContext {
+            descriptor: Some(descriptor),
+            ..test_context()
+        };
+
+        call_api(&base_url, "/items", HashMap::new(), None, &context)
+            .await
+            .unwrap();
+        let rejected = call_api(&base_url, "/items", HashMap::new(), None, &context).await;
+
+        match rejected {
+            Err(ApiError::RateLimited { retry_after }) => assert!(retry_after > 3000),
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn bodies_that_are_not_utf8_come_back_as_blobs() {
+        let bytes = vec![0x89, b'P', b'N', b'G', 0xff, 0x00];
+        let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &bytes);
+        let context = test_context();
+
+        let base_url = serve_once(200, bytes.clone()).await;
+        let call = call_api(&base_url, "/logo", HashMap::new(), None, &context)
+            .await
+            .unwrap();
+        let url = format!("{}/logo", serve_once(200, bytes).await);
+        let raw = raw_request("GET", &url, HashMap::new(), None, &context)
+            .await
+            .unwrap();
+
+        for body in [call.body, raw.body] {
+            assert_eq!(body["size"], 6);
+            assert_eq!(body["base64"], encoded);
+        }
+    }
+
+    fn endpoint_with_enums() -> Endpoint {
+        serde_json::from_value(json!({
+            "path": "/items",
+            "method": "GET",
+            "parameters": [
+                { "name": "sort", "enum": ["name", "date"] },
+                { "name": "limit", "schema": { "type": "integer", "enum": [10, 50] } },
+                { "name": "tags", "enum": ["red", "blue"] },
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn invalid_param(params: Value) -> Option<(String, String)> {
+        let params = serde_json::from_value(params).unwrap();
+        match validate_params(&endpoint_with_enums(), &params) {
+            Ok(()) => None,
+            Err(ApiError::InvalidParameter { name, message }) => Some((name, message)),
+            Err(other) => panic!("expected an invalid parameter, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn values_outside_the_enum_are_rejected() {
+        assert_eq!(
+            invalid_param(json!({ "sort": "price" })),
+            Some((
+                "sort".to_string(),
+                "'price' is not one of: name, date".to_string()
+            ))
+        );
+        assert_eq!(invalid_param(json!({ "sort": "date", "other": "x" })), None);
+    }
+
+    #[test]
+    fn numbers_sent_as_strings_match_numeric_enums() {
+        assert_eq!(invalid_param(json!({ "limit": "50" })), None);
+        assert_eq!(invalid_param(json!({ "limit": 50 })), None);
+        assert_eq!(
+            invalid_param(json!({ "limit": "20" })).map(|(name, _)| name),
+            Some("limit".to_string())
+        );
+    }
+
+    #[test]
+    fn every_array_item_has_to_be_allowed() {
+        assert_eq!(invalid_param(json!({ "tags": ["red", "blue"] })), None);
+        assert_eq!(
+            invalid_param(json!({ "tags": ["red", "green"] })),
+            Some((
+                "tags".to_string(),
+                "'green' is not one of: red, blue".to_string()
+            ))
+        );
+    }
+
+    fn field(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn nested_objects_become_bracketed_form_keys() {
+        let fields = form_fields(&json!({ "user": { "profile": { "email": "a@b.c" } } }));
+
+        assert_eq!(fields, vec![field("user[profile][email]", "a@b.c")]);
+    }
+
+    #[test]
+    fn arrays_repeat_their_form_key() {
+        let fields = form_fields(&json!({ "tags": ["red", 2, true] }));
+
+        assert_eq!(
+            fields,
+            vec![
+                field("tags", "red"),
+                field("tags", "2"),
+                field("tags", "true")
+            ]
+        );
+    }
+
+    #[test]
+    fn nulls_are_left_out_of_forms() {
+        let fields =
+            form_fields(&json!({ "note": null, "user": { "email": null, "name": "Ann" } }));
+
+        assert_eq!(fields, vec![field("user[name]", "Ann")]);
+        assert!(form_fields(&json!(null)).is_empty());
+    }
+
+    #[test]
+    fn array_query_params_follow_their_style() {
+        let tags = json!(["a", "b"]);
+        let encode = |style, value: &Value| {
+            let mut query = Vec::new();
+            push_query_param(&mut query, "tags".to_string(), value, style);
+            query
+        };
+
+        let repeat = vec![field("tags", "a"), field("tags", "b")];
+        assert_eq!(encode(ArrayStyle::Repeat, &tags), repeat);
+        let brackets = vec![field("tags[]", "a"), field("tags[]", "b")];
+        assert_eq!(encode(ArrayStyle::Brackets, &tags), brackets);
+        assert_eq!(encode(ArrayStyle::Comma, &tags), vec![field("tags", "a,b")]);
+        assert!(encode(ArrayStyle::Comma, &json!([])).is_empty());
+        // Single values go out as they are, whatever the style
+        let single = encode(ArrayStyle::Brackets, &json!(3));
+        assert_eq!(single, [field("tags", "3")]);
+    }
+
+    #[tokio::test]
+    async fn array_params_repeat_their_query_key() {
+        let (base_url, requests) = serve_each(vec!["[]"]).await;
+        let descriptor = serde_json::from_value(json!({
+            "name": "Items",
+            "baseUrl": base_url,
+            "endpoints": [{
+                "path": "/items",
+                "method": "GET",
+                "parameters": [
+                    { "name": "tags", "in": "query" },
+                    { "name": "ids", "in": "query", "style": "form", "explode": false },
+                ],
+            }],
+        }))
+        .unwrap();
+        let context = CallContext {
+            descriptor: Some(descriptor),
+            ..test_context()
+        };
+        let params = HashMap::from([
+            ("tags".to_string(), json!(["a", "b"])),
+            ("ids".to_string(), json!([1, 2])),
+        ]);
+
+        call_api(&base_url, "/items", params, None, &context)
+            .await
+            .unwrap();
+
+        let request = &requests.lock().unwrap()[0];
+        assert!(request.contains("tags=a&tags=b"), "{}", request);
+        assert!(request.contains("ids=1%2C2"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn calls_report_the_status_they_got() {
+        let base_url = serve_once(201, br#"{"id":7}"#.to_vec()).await;
+
+        let response = call_api(&base_url, "/items", HashMap::new(), None, &test_context())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status_code, 201);
+        assert_eq!(response.body, json!({ "id": 7 }));
+        let result = serde_json::to_value(ApiCallResponse::from_result(Ok(response))).unwrap();
+        assert_eq!(result["data"]["status_code"], 201);
+    }
+
+    fn descriptor(name: &str, base_url: &str) -> SocketAgentDescriptor {
+        serde_json::from_value(json!({
+            "name": name,
+            "baseUrl": base_url,
+            "endpoints": [{ "path": "/items", "method": "GET", "operationId": "listItems" }],
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn batch_calls_go_to_the_api_of_their_endpoint() {
+        let weather = serve_once(200, br#"{"api":"weather"}"#.to_vec()).await;
+        let news = serve_once(503, b"down".to_vec()).await;
+        let sources = [descriptor("Weather", &weather), descriptor("News", &news)];
+        let merged = merge_descriptors(sources.to_vec()).unwrap();
+
+        // Route each call the way the batch command does, with the context
+        // of the API it goes to
+        let breaker = Arc::new(CircuitBreaker::new());
+        let calls = ["news.listItems", "weather.listItems"]
+            .into_iter()
+            .map(|id| {
+                let (base_url, endpoint_id) = route_endpoint(&merged, id).unwrap();
+                let source = sources
+                    .iter()
+                    .find(|source| source.base_url.as_deref() == Some(base_url.as_str()));
+                let context = CallContext {
+                    descriptor: source.cloned(),
+                    breaker: breaker.clone(),
+                    ..test_context()
+                };
+                let call = BatchCall {
+                    endpoint_id,
+                    params: HashMap::new(),
+                    body_encoding: None,
+                };
+                (base_url, call, Arc::new(context))
+            })
+            .collect();
+        let results = call_api_batch(calls).await;
+
+        let failed = results[0].error.as_ref().unwrap();
+        assert_eq!(failed.code.as_deref(), Some("server_error"));
+        assert_eq!(failed.details.as_ref().unwrap()["status"], 503);
+        assert_eq!(
+            results[1].data.as_ref().unwrap().body,
+            json!({ "api": "weather" })
+        );
+
+        // The failure counts against the API that failed, the circuit the
+        // next call to it is checked against
+        let circuits = breaker.snapshot();
+        assert_eq!(circuits.len(), 1);
+        assert_eq!(circuits[0].key, news);
+        assert_eq!(circuits[0].consecutive_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn merged_calls_record_outcomes_under_their_api() {
+        let news = serve_once(503, b"down".to_vec()).await;
+        let merged = merge_descriptors(vec![
+            descriptor("Weather", "https://weather.example.com"),
+            descriptor("News", &news),
+        ])
+        .unwrap();
+        let merged_url = merged.base_url.clone().unwrap();
+        let context = CallContext {
+            descriptor: Some(merged),
+            ..test_context()
+        };
+
+        let result = call_api(
+            &merged_url,
+            "news.listItems",
+            HashMap::new(),
+            None,
+            &context,
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(ApiError::ServerError { status: 503, .. })
+        ));
+        let circuits = context.breaker.snapshot();
+        assert_eq!(circuits.len(), 1);
+        assert_eq!(circuits[0].key, news);
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_slow_call_closes_its_connection() {
+        // Read the request, never answer, and report when the client hangs up
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (closed_tx, closed_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            while socket.read(&mut buf).await.is_ok_and(|read| read > 0) {}
+            let _ = closed_tx.send(());
+        });
+        let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+        let context = test_context();
+
+        // Cancel the way call_api_cancel does: by dropping the call future
+        let call = call_api(&base_url, "/slow", HashMap::new(), None, &context);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let _ = cancel_tx.send(());
+        });
+        let cancelled = tokio::select! {
+            _ = call => false,
+            _ = cancel_rx => true,
+        };
+
+        assert!(cancelled);
+        tokio::time::timeout(Duration::from_secs(2), closed_rx)
+            .await
+            .expect("connection left open after cancel")
+            .unwrap();
+    }
+
+    #[test]
+    fn download_path_takes_only_new_file_names() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            download_path(dir.path(), "report.csv").unwrap(),
+            dir.path().join("report.csv")
+        );
+        for name in ["", "..", "../report.csv", "a/report.csv", "/etc/passwd"] {
+            assert!(download_path(dir.path(), name).is_err(), "{}", name);
+        }
+
+        std::fs::write(dir.path().join("report.csv"), b"").unwrap();
+        assert!(download_path(dir.path(), "report.csv").is_err());
+    }
 }
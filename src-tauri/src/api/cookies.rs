@@ -0,0 +1,326 @@
+// Cookie sessions
+// Opt-in per-API cookie jars so sessions set via Set-Cookie carry across calls
+
+use anyhow::Result;
+use cookie_store::{Cookie, RawCookie};
+use reqwest::cookie::CookieStore;
+use reqwest::header::HeaderValue;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::storage::{KeyRotation, Storage};
+
+const COOKIE_POLICY_STORAGE_KEY: &str = "cookie_policy";
+const COOKIES_STORAGE_KEY: &str = "api_cookies";
+const COOKIES_KEY_STORAGE_KEY: &str = "api_cookies_key";
+
+/// Whether API calls keep cookies, and whether they survive restarts
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CookiePolicy {
+    pub enabled: bool,
+    pub persist: bool,
+}
+
+impl CookiePolicy {
+    pub fn load(storage: &Storage) -> Self {
+        storage
+            .get(COOKIE_POLICY_STORAGE_KEY)
+            .ok()
+            .flatten()
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, storage: &Storage) -> Result<()> {
+        storage.set(
+            COOKIE_POLICY_STORAGE_KEY.to_string(),
+            serde_json::to_value(self)?,
+        )
+    }
+}
+
+/// Cookies of one API. Unlike reqwest's `Jar`, it keeps each cookie's
+/// Set-Cookie attributes, so saved cookies expire and stay scoped to their
+/// domain and path after a restart.
+#[derive(Default)]
+pub struct CookieJar(RwLock<cookie_store::CookieStore>);
+
+impl CookieJar {
+    /// The unexpired cookies as JSON, with their attributes
+    fn to_json(&self) -> Result<Option<Vec<u8>>> {
+        let store = self.0.read().unwrap();
+        let cookies: Vec<&Cookie> = store.iter_unexpired().collect();
+        if cookies.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::to_vec(&cookies)?))
+    }
+
+    /// A jar holding the cookies from `to_json` that haven't expired since
+    fn from_json(json: &[u8]) -> Result<Self> {
+        let cookies: Vec<Cookie<'static>> = serde_json::from_slice(json)?;
+        let store = cookie_store::CookieStore::from_cookies(
+            cookies.into_iter().map(Ok::<_, Infallible>),
+            false,
+        )
+        .unwrap_or_default();
+        Ok(Self(RwLock::new(store)))
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers.filter_map(|value| {
+            let value = value.to_str().ok()?.to_string();
+            RawCookie::parse(value).ok()
+        });
+        self.0.write().unwrap().store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let header = self
+            .0
+            .read()
+            .unwrap()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if header.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&header).ok()
+    }
+}
+
+/// One cookie jar per API base URL
+pub struct CookieJars {
+    policy: Mutex<CookiePolicy>,
+    jars: Mutex<HashMap<String, Arc<CookieJar>>>,
+}
+
+impl CookieJars {
+    /// Load the policy and, if persisted, the saved cookies. Saved cookies
+    /// that don't decrypt, including ones saved before cookies were
+    /// encrypted, are dropped.
+    pub fn load(storage: &Storage) -> Self {
+        let policy = CookiePolicy::load(storage);
+        let mut jars = HashMap::new();
+
+        if policy.enabled && policy.persist {
+            if let Ok(Some(Value::Object(saved))) = storage.get(COOKIES_STORAGE_KEY) {
+                for (base_url, sealed) in saved {
+                    let jar = sealed
+                        .as_str()
+                        .and_then(|sealed| storage.unseal(COOKIES_KEY_STORAGE_KEY, sealed).ok())
+                        .and_then(|json| CookieJar::from_json(&json).ok());
+                    if let Some(jar) = jar {
+                        jars.insert(base_url, Arc::new(jar));
+                    }
+                }
+            }
+        }
+
+        Self {
+            policy: Mutex::new(policy),
+            jars: Mutex::new(jars),
+        }
+    }
+
+    pub fn policy(&self) -> CookiePolicy {
+        *self.policy.lock().unwrap()
+    }
+
+    /// Change the policy, dropping cookies that are no longer allowed to be kept
+    pub fn set_policy(&self, policy: CookiePolicy, storage: &Storage) -> Result<()> {
+        policy.save(storage)?;
+        if !policy.enabled {
+            self.jars.lock().unwrap().clear();
+        }
+        if !policy.enabled || !policy.persist {
+            storage.remove(COOKIES_STORAGE_KEY)?;
+        }
+        *self.policy.lock().unwrap() = policy;
+        Ok(())
+    }
+
+    /// Jar for an API, or `None` when cookies are disabled
+    pub fn jar(&self, base_url: &str) -> Option<Arc<CookieJar>> {
+        if !self.policy().enabled {
+            return None;
+        }
+
+        let mut jars = self.jars.lock().unwrap();
+        let jar = jars.entry(normalize(base_url)).or_default();
+        Some(jar.clone())
+    }
+
+    /// Drop every jar and return to the default policy. Saved cookies are
+    /// left to the caller, which is clearing storage anyway
+    pub fn reset(&self) {
+        self.jars.lock().unwrap().clear();
+        *self.policy.lock().unwrap() = CookiePolicy::default();
+    }
+
+    /// Forget the cookies of one API, including any saved copy
+    pub fn clear(&self, base_url: &str, storage: &Storage) -> Result<()> {
+        let base_url = normalize(base_url);
+        self.jars.lock().unwrap().remove(&base_url);
+
+        if let Some(Value::Object(mut saved)) = storage.get(COOKIES_STORAGE_KEY)? {
+            if saved.remove(&base_url).is_some() {
+                storage.set(COOKIES_STORAGE_KEY.to_string(), Value::Object(saved))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Save an API's unexpired cookies, encrypted, if the policy asks for
+    /// persistence
+    pub fn persist(&self, base_url: &str, storage: &Storage) -> Result<()> {
+        let policy = self.policy();
+        if !policy.enabled || !policy.persist {
+            return Ok(());
+        }
+
+        let base_url = normalize(base_url);
+        let cookies = {
+            let jars = self.jars.lock().unwrap();
+            let Some(jar) = jars.get(&base_url) else {
+                return Ok(());
+            };
+            jar.to_json()?
+        };
+
+        let mut saved = match storage.get(COOKIES_STORAGE_KEY)? {
+            Some(Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        match cookies {
+            Some(cookies) => {
+                let sealed = storage.seal(COOKIES_KEY_STORAGE_KEY, &cookies)?;
+                saved.insert(base_url, Value::String(sealed))
+            }
+            None => saved.remove(&base_url),
+        };
+        storage.set(COOKIES_STORAGE_KEY.to_string(), Value::Object(saved))
+    }
+}
+
+/// Entries that move the saved cookies to a fresh local key, the key
+/// included, for saving with the other sealed stores. Cookies that don't
+/// decrypt are dropped, as they are on load.
+pub fn reseal_cookies(storage: &Storage) -> Result<Vec<(String, Value)>> {
+    let rotation = KeyRotation::new(storage, COOKIES_KEY_STORAGE_KEY)?;
+    let mut entries = vec![rotation.key_entry()];
+    if let Some(Value::Object(saved)) = storage.get(COOKIES_STORAGE_KEY)? {
+        let resealed = saved
+            .into_iter()
+            .filter_map(|(base_url, sealed)| {
+                let sealed = rotation.reseal(sealed.as_str()?).ok()?;
+                Some((base_url, Value::String(sealed)))
+            })
+            .collect();
+        entries.push((COOKIES_STORAGE_KEY.to_string(), Value::Object(resealed)));
+    }
+    Ok(entries)
+}
+
+fn normalize(base_url: &str) -> String {
+    base_url.trim().trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_URL: &str = "https://api.example.com";
+
+    fn persisting_jars(storage: &Storage) -> CookieJars {
+        let policy = CookiePolicy {
+            enabled: true,
+            persist: true,
+        };
+        policy.save(storage).unwrap();
+        CookieJars::load(storage)
+    }
+
+    fn cookie_header(jars: &CookieJars, url: &str) -> Option<String> {
+        let jar = jars.jar(BASE_URL).unwrap();
+        jar.cookies(&Url::parse(url).unwrap())
+            .map(|header| header.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn saved_cookies_keep_their_attributes_and_are_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let jars = persisting_jars(&storage);
+
+        let set_cookies = [
+            HeaderValue::from_static("session=secret-session; Path=/api; Max-Age=3600"),
+            HeaderValue::from_static("gone=secret-gone; Max-Age=0"),
+        ];
+        let url = Url::parse(BASE_URL).unwrap();
+        jars.jar(BASE_URL)
+            .unwrap()
+            .set_cookies(&mut set_cookies.iter(), &url);
+        jars.persist(BASE_URL, &storage).unwrap();
+
+        let file = std::fs::read_to_string(dir.path().join("storage.json")).unwrap();
+        assert!(!file.contains("secret-"));
+
+        let reloaded = CookieJars::load(&storage);
+        assert_eq!(
+            cookie_header(&reloaded, "https://api.example.com/api/items").as_deref(),
+            Some("session=secret-session")
+        );
+        assert_eq!(
+            cookie_header(&reloaded, "https://api.example.com/other"),
+            None
+        );
+    }
+
+    #[test]
+    fn cookies_saved_in_plain_text_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let legacy = serde_json::json!({ BASE_URL: "session=abc" });
+        storage
+            .set(COOKIES_STORAGE_KEY.to_string(), legacy)
+            .unwrap();
+
+        let jars = persisting_jars(&storage);
+
+        assert_eq!(cookie_header(&jars, BASE_URL), None);
+    }
+
+    #[test]
+    fn saved_cookies_survive_a_key_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let jars = persisting_jars(&storage);
+        let url = Url::parse(BASE_URL).unwrap();
+        let set_cookie = [HeaderValue::from_static("session=secret; Max-Age=3600")];
+        jars.jar(BASE_URL)
+            .unwrap()
+            .set_cookies(&mut set_cookie.iter(), &url);
+        jars.persist(BASE_URL, &storage).unwrap();
+        let old_key = storage.get(COOKIES_KEY_STORAGE_KEY).unwrap();
+
+        let entries = reseal_cookies(&storage).unwrap();
+        storage.set_many(entries).unwrap();
+
+        assert_ne!(storage.get(COOKIES_KEY_STORAGE_KEY).unwrap(), old_key);
+        let reloaded = CookieJars::load(&storage);
+        assert_eq!(
+            cookie_header(&reloaded, BASE_URL).as_deref(),
+            Some("session=secret")
+        );
+    }
+}
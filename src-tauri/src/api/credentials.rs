@@ -0,0 +1,243 @@
+// API credentials
+// Per-host credentials applied according to the descriptor's auth scheme
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::storage::{decrypt, encrypt, KeyRotation, Storage};
+
+use super::discovery::{get_auth_scheme, AuthScheme, SocketAgentDescriptor};
+
+const CREDENTIALS_STORAGE_KEY: &str = "api_credentials";
+const CREDENTIALS_KEY_STORAGE_KEY: &str = "api_credentials_key";
+
+/// A credential the user supplied for one API host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiCredential {
+    /// Scheme name as given to `set_api_credentials` (see `AuthScheme::from_name`)
+    pub scheme: String,
+    pub value: String,
+}
+
+impl ApiCredential {
+    /// The scheme to present this credential with. The descriptor's scheme wins
+    /// when it is the same kind, since it carries the real header or param name.
+    pub fn resolve_scheme(&self, descriptor: Option<&SocketAgentDescriptor>) -> Option<AuthScheme> {
+        let requested = AuthScheme::from_name(&self.scheme)?;
+        match descriptor.and_then(get_auth_scheme) {
+            Some(declared) if declared.same_kind(&requested) => Some(declared),
+            _ => Some(requested),
+        }
+    }
+
+    /// Attach the credential to a request
+    pub fn apply(
+        &self,
+        request: RequestBuilder,
+        descriptor: Option<&SocketAgentDescriptor>,
+    ) -> RequestBuilder {
+        match self.resolve_scheme(descriptor) {
+            Some(AuthScheme::Basic) => match self.value.split_once(':') {
+                Some((user, password)) => request.basic_auth(user, Some(password)),
+                None => request.basic_auth(&self.value, None::<&str>),
+            },
+            Some(AuthScheme::Bearer) => request.bearer_auth(&self.value),
+            Some(AuthScheme::ApiKeyHeader(name)) => request.header(name, &self.value),
+            Some(AuthScheme::ApiKeyQuery(name)) => request.query(&[(name, &self.value)]),
+            None => request,
+        }
+    }
+}
+
+/// Host (with port, if any) that credentials are stored under
+pub fn credential_host(url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?;
+    Some(match parsed.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+/// Store (or, with an empty value, remove) the credential for a host
+pub fn save_credentials(storage: &Storage, host: &str, scheme: &str, value: &str) -> Result<()> {
+    if AuthScheme::from_name(scheme).is_none() {
+        return Err(anyhow!(
+            "Unknown auth scheme '{}' (expected basic, bearer, apiKey or apiKeyQuery)",
+            scheme
+        ));
+    }
+    if scheme == "basic" && !value.is_empty() && !value.contains(':') {
+        return Err(anyhow!("Basic credentials must be in user:password form"));
+    }
+
+    let host = host.trim().to_lowercase();
+    let mut all = match storage.get(CREDENTIALS_STORAGE_KEY)? {
+        Some(Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+
+    if value.is_empty() {
+        all.remove(&host);
+    } else {
+        let credential = ApiCredential {
+            scheme: scheme.to_string(),
+            value: value.to_string(),
+        };
+        let key = credentials_key(storage)?;
+        let encrypted = encrypt(&serde_json::to_vec(&credential)?, &key)?;
+        all.insert(host, Value::String(encrypted));
+    }
+
+    storage.set(CREDENTIALS_STORAGE_KEY.to_string(), Value::Object(all))
+}
+
+/// Load the credential stored for a host, if any
+pub fn load_credentials(storage: &Storage, host: &str) -> Result<Option<ApiCredential>> {
+    let encrypted = match storage
+        .get(CREDENTIALS_STORAGE_KEY)?
+        .and_then(|all| all.get(host.to_lowercase()).cloned())
+    {
+        Some(Value::String(encrypted)) => encrypted,
+        _ => return Ok(None),
+    };
+
+    let key = credentials_key(storage)?;
+    let plaintext = decrypt(&encrypted, &key)?;
+    let credential = serde_json::from_slice(&plaintext).context("Invalid stored credential")?;
+    Ok(Some(credential))
+}
+
+/// Entries that move every stored credential to a fresh local key, the key
+/// included, for saving with the other sealed stores. Nothing is written,
+/// and it fails unless every credential decrypts with the old key.
+pub fn reseal_credentials(storage: &Storage) -> Result<Vec<(String, Value)>> {
+    let all = match storage.get(CREDENTIALS_STORAGE_KEY)? {
+        Some(Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+
+    let rotation = KeyRotation::new(storage, CREDENTIALS_KEY_STORAGE_KEY)?;
+    let mut rotated = serde_json::Map::new();
+    for (host, encrypted) in all {
+        let encrypted = encrypted
+            .as_str()
+            .ok_or_else(|| anyhow!("Invalid stored credential for {}", host))?;
+        let resealed = rotation
+            .reseal(encrypted)
+            .with_context(|| format!("Failed to decrypt credential for {}", host))?;
+        rotated.insert(host, Value::String(resealed));
+    }
+
+    Ok(vec![
+        rotation.key_entry(),
+        (CREDENTIALS_STORAGE_KEY.to_string(), Value::Object(rotated)),
+    ])
+}
+
+/// Local key the credentials are encrypted with, generated on first use
+fn credentials_key(storage: &Storage) -> Result<[u8; 32]> {
+    storage.local_key(CREDENTIALS_KEY_STORAGE_KEY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_storage() -> (tempfile::TempDir, Storage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        (dir, storage)
+    }
+
+    /// A request to the API with `scheme`/`value` applied
+    fn applied(scheme: &str, value: &str) -> reqwest::Request {
+        let credential = ApiCredential {
+            scheme: scheme.to_string(),
+            value: value.to_string(),
+        };
+        let request = reqwest::Client::new().get("https://api.example.com/items?page=2");
+        credential.apply(request, None).build().unwrap()
+    }
+
+    fn header(request: &reqwest::Request, name: &str) -> Option<String> {
+        request
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn bearer_tokens_go_in_the_authorization_header() {
+        let request = applied("bearer", "token-1");
+
+        assert_eq!(
+            header(&request, "authorization").as_deref(),
+            Some("Bearer token-1")
+        );
+        assert_eq!(request.url().query(), Some("page=2"));
+    }
+
+    #[test]
+    fn basic_credentials_are_encoded_as_user_and_password() {
+        let request = applied("basic", "bob:pw");
+
+        // base64 of "bob:pw"
+        assert_eq!(
+            header(&request, "authorization").as_deref(),
+            Some("Basic Ym9iOnB3")
+        );
+    }
+
+    #[test]
+    fn header_api_keys_go_in_their_header() {
+        let request = applied("apiKey", "key-1");
+
+        assert_eq!(header(&request, "x-api-key").as_deref(), Some("key-1"));
+        assert_eq!(header(&request, "authorization"), None);
+        assert_eq!(request.url().query(), Some("page=2"));
+    }
+
+    #[test]
+    fn query_api_keys_are_added_to_the_query() {
+        let request = applied("apiKeyQuery", "key 1");
+
+        assert_eq!(request.url().query(), Some("page=2&api_key=key+1"));
+        assert_eq!(header(&request, "x-api-key"), None);
+        assert_eq!(header(&request, "authorization"), None);
+    }
+
+    #[test]
+    fn credentials_survive_a_key_rotation() {
+        let (_dir, storage) = temp_storage();
+        save_credentials(&storage, "api.example.com", "bearer", "token-1").unwrap();
+        save_credentials(&storage, "other.example.com:8443", "basic", "bob:pw").unwrap();
+        let old_key = storage.get(CREDENTIALS_KEY_STORAGE_KEY).unwrap();
+        let old_sealed = storage.get(CREDENTIALS_STORAGE_KEY).unwrap();
+
+        let entries = reseal_credentials(&storage).unwrap();
+        storage.set_many(entries).unwrap();
+
+        assert_ne!(storage.get(CREDENTIALS_KEY_STORAGE_KEY).unwrap(), old_key);
+        assert_ne!(storage.get(CREDENTIALS_STORAGE_KEY).unwrap(), old_sealed);
+        let bearer = load_credentials(&storage, "api.example.com").unwrap();
+        assert_eq!(bearer.unwrap().value, "token-1");
+        let basic = load_credentials(&storage, "other.example.com:8443").unwrap();
+        assert_eq!(basic.unwrap().value, "bob:pw");
+    }
+
+    #[test]
+    fn a_credential_that_wont_decrypt_stops_the_rotation() {
+        let (_dir, storage) = temp_storage();
+        save_credentials(&storage, "api.example.com", "bearer", "token-1").unwrap();
+        let mut all = storage.get(CREDENTIALS_STORAGE_KEY).unwrap().unwrap();
+        all["broken.example.com"] = Value::from("not sealed");
+        storage
+            .set(CREDENTIALS_STORAGE_KEY.to_string(), all)
+            .unwrap();
+
+        assert!(reseal_credentials(&storage).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+// Descriptor diffing
+// Detects endpoint changes between two versions of a Socket Agent descriptor
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::storage::Storage;
+
+use super::discovery::{endpoint_key, Endpoint, SocketAgentDescriptor};
+
+const LAST_SEEN_STORAGE_KEY: &str = "last_seen_descriptors";
+
+#[derive(Debug, Serialize)]
+pub struct EndpointChange {
+    pub key: String,
+    pub old: Endpoint,
+    pub new: Endpoint,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DescriptorDiff {
+    pub added: Vec<Endpoint>,
+    pub removed: Vec<Endpoint>,
+    pub modified: Vec<EndpointChange>,
+}
+
+impl DescriptorDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Report endpoints added, removed or modified between two descriptors
+pub fn diff_descriptors(old: &SocketAgentDescriptor, new: &SocketAgentDescriptor) -> DescriptorDiff {
+    let mut diff = DescriptorDiff::default();
+
+    for new_ep in &new.endpoints {
+        let key = endpoint_key(new_ep);
+        match old.endpoints.iter().find(|ep| endpoint_key(ep) == key) {
+            None => diff.added.push(new_ep.clone()),
+            Some(old_ep) if old_ep != new_ep => diff.modified.push(EndpointChange {
+                key,
+                old: old_ep.clone(),
+                new: new_ep.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for old_ep in &old.endpoints {
+        let key = endpoint_key(old_ep);
+        if !new.endpoints.iter().any(|ep| endpoint_key(ep) == key) {
+            diff.removed.push(old_ep.clone());
+        }
+    }
+
+    diff
+}
+
+/// Descriptor last seen for a base URL, if any
+pub fn load_last_seen(storage: &Storage, base_url: &str) -> Option<SocketAgentDescriptor> {
+    let all = storage.get(LAST_SEEN_STORAGE_KEY).ok()??;
+    let descriptor = all.get(base_url.trim_end_matches('/'))?.clone();
+    serde_json::from_value(descriptor).ok()
+}
+
+/// Remember a descriptor as the last seen for its base URL
+pub fn save_last_seen(
+    storage: &Storage,
+    base_url: &str,
+    descriptor: &SocketAgentDescriptor,
+) -> Result<()> {
+    let mut all = match storage.get(LAST_SEEN_STORAGE_KEY)? {
+        Some(Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    all.insert(
+        base_url.trim_end_matches('/').to_string(),
+        serde_json::to_value(descriptor)?,
+    );
+    storage.set(LAST_SEEN_STORAGE_KEY.to_string(), Value::Object(all))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn descriptor(endpoints: Value) -> SocketAgentDescriptor {
+        serde_json::from_value(json!({ "name": "Test API", "endpoints": endpoints })).unwrap()
+    }
+
+    #[test]
+    fn endpoints_are_matched_by_id() {
+        let old = descriptor(json!([
+            { "path": "/items", "method": "GET", "operationId": "listItems" },
+            { "path": "/items", "method": "POST", "summary": "Create" },
+            { "path": "/old", "method": "GET" },
+        ]));
+        let new = descriptor(json!([
+            { "path": "/v2/items", "method": "GET", "operationId": "listItems" },
+            { "path": "/items", "method": "POST", "summary": "Create" },
+            { "path": "/new", "method": "GET" },
+        ]));
+
+        let diff = diff_descriptors(&old, &new);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].path, "/new");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].path, "/old");
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].key, "listItems");
+        assert_eq!(diff.modified[0].old.path, "/items");
+        assert_eq!(diff.modified[0].new.path, "/v2/items");
+    }
+
+    #[test]
+    fn identical_descriptors_have_no_changes() {
+        let endpoints = json!([{ "path": "/items", "method": "GET" }]);
+
+        let diff = diff_descriptors(&descriptor(endpoints.clone()), &descriptor(endpoints));
+
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn last_seen_descriptor_is_kept_per_base_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let first = descriptor(json!([{ "path": "/a", "method": "GET" }]));
+        let second = descriptor(json!([{ "path": "/b", "method": "GET" }]));
+
+        assert!(load_last_seen(&storage, "https://api.example.com").is_none());
+        save_last_seen(&storage, "https://api.example.com/", &first).unwrap();
+        save_last_seen(&storage, "https://other.example.com", &second).unwrap();
+
+        let seen = load_last_seen(&storage, "https://api.example.com").unwrap();
+        assert!(diff_descriptors(&seen, &first).is_empty());
+        let seen = load_last_seen(&storage, "https://other.example.com").unwrap();
+        assert!(diff_descriptors(&seen, &second).is_empty());
+    }
+}
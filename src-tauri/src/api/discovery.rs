@@ -2,12 +2,50 @@
 // Discovers Socket Agent API descriptors from URLs
 
 use anyhow::{anyhow, Context, Result};
-use reqwest::Client;
+use reqwest::header::{
+    CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+};
+use reqwest::{redirect, StatusCode, Url};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
 use std::time::Duration;
 
+use crate::config::{client_builder, max_response_bytes};
+
+use super::cache::CachedDescriptor;
+use super::client::read_body_limited;
+use super::diff::DescriptorDiff;
+use super::reachability::{reachability_warnings, ReachabilityWarning};
+
+/// Descriptor format versions this client understands, as (major, minor)
+pub const SUPPORTED_DESCRIPTOR_VERSIONS: RangeInclusive<(u64, u64)> = (1, 0)..=(1, 0);
+/// Characters of the raw descriptor quoted around a parse error
+const PARSE_SNIPPET_CHARS: usize = 80;
+/// How long `probe_socket_agent` waits; it runs while the user is typing
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Redirects followed while fetching a descriptor, as reqwest does by default
+const MAX_DISCOVERY_REDIRECTS: usize = 10;
+/// Where Socket Agent APIs serve their descriptor
+pub const DISCOVERY_PATH: &str = "/.well-known/socket-agent";
+/// Other places some APIs serve it, tried in order when that path 404s
+const FALLBACK_DISCOVERY_PATHS: [&str; 3] = [
+    "/socket-agent.json",
+    "/.well-known/socket-agent.json",
+    "/socket-agent",
+];
+pub const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+/// Group `group_endpoints_by_tag` puts endpoints without tags in
+pub const UNTAGGED_GROUP: &str = "untagged";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SocketAgentDescriptor {
+    /// Descriptor format version, e.g. `"1.0"`; a bare number is accepted too
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<Value>,
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
@@ -18,7 +56,7 @@ pub struct SocketAgentDescriptor {
     pub context: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Endpoint {
     pub path: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -29,48 +67,499 @@ pub struct Endpoint {
     pub summary: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(default, alias = "params", skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<EndpointParam>,
+    /// Section names for navigation, e.g. `["users", "admin"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// API the endpoint belongs to when it differs from the descriptor's,
+    /// as in merged descriptors
+    #[serde(rename = "baseUrl", skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// JSON schema of the request body, used to nest body params under the
+    /// keys the endpoint expects
+    #[serde(rename = "requestSchema", skip_serializing_if = "Option::is_none")]
+    pub request_schema: Option<Value>,
+    /// Media type the body is sent as, e.g.
+    /// `application/x-www-form-urlencoded`; JSON when absent
+    #[serde(rename = "requestContentType", skip_serializing_if = "Option::is_none")]
+    pub request_content_type: Option<String>,
+    /// JSON schema of a successful response, checked when a call asks for it
+    #[serde(rename = "responseSchema", skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<Value>,
+    /// Sample parameter sets for "try it" forms
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<EndpointExample>,
+}
+
+/// A sample call as declared in the descriptor:
+/// `{"name": "By city", "params": {"city": "Paris"}}`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EndpointExample {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, alias = "parameters")]
+    pub params: serde_json::Map<String, Value>,
+}
+
+/// A parameter as declared in the descriptor. `enum`, `default` and `type`
+/// may sit on the parameter itself or under an OpenAPI-style `schema`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EndpointParam {
+    pub name: String,
+    #[serde(rename = "in", skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub param_type: Option<String>,
+    #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub enum_values: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+    /// How an array value is serialized: OpenAPI's `form`, `spaceDelimited`
+    /// etc., or `repeat`, `comma` or `brackets`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// OpenAPI `explode`; `false` with `form` style means comma separated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explode: Option<bool>,
+}
+
+/// How an array-valued query parameter is put in the URL
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ArrayStyle {
+    /// `tags=a&tags=b`
+    #[default]
+    Repeat,
+    /// `tags=a,b`
+    Comma,
+    /// `tags[]=a&tags[]=b`
+    Brackets,
+}
+
+/// How body params are serialized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyEncoding {
+    /// `application/json`
+    #[default]
+    Json,
+    /// `application/x-www-form-urlencoded`
+    Form,
+}
+
+impl Endpoint {
+    /// Body encoding the endpoint declares; JSON when it doesn't say
+    pub fn body_encoding(&self) -> BodyEncoding {
+        let is_form = self
+            .request_content_type
+            .as_deref()
+            .is_some_and(|content_type| {
+                content_type
+                    .to_ascii_lowercase()
+                    .starts_with(FORM_CONTENT_TYPE)
+            });
+        if is_form {
+            BodyEncoding::Form
+        } else {
+            BodyEncoding::Json
+        }
+    }
+}
+
+impl EndpointParam {
+    /// Values the parameter is restricted to, if any
+    pub fn allowed_values(&self) -> Option<Vec<Value>> {
+        self.enum_values
+            .clone()
+            .or_else(|| self.schema.as_ref()?.get("enum")?.as_array().cloned())
+    }
+
+    pub fn default_value(&self) -> Option<Value> {
+        self.default
+            .clone()
+            .or_else(|| self.schema.as_ref()?.get("default").cloned())
+    }
+
+    /// Array serialization the parameter declares; repeated keys when it
+    /// doesn't say
+    pub fn array_style(&self) -> ArrayStyle {
+        match (self.style.as_deref(), self.explode) {
+            (Some("comma"), _) | (Some("form") | None, Some(false)) => ArrayStyle::Comma,
+            (Some("brackets"), _) => ArrayStyle::Brackets,
+            _ => ArrayStyle::Repeat,
+        }
+    }
+
+    pub fn type_name(&self) -> Option<String> {
+        self.param_type.clone().or_else(|| {
+            self.schema
+                .as_ref()?
+                .get("type")?
+                .as_str()
+                .map(String::from)
+        })
+    }
 }
 
+/// Parameter entry returned by `endpoint_params`, with `schema` resolved
+#[derive(Debug, Serialize)]
+pub struct ParamInfo {
+    pub name: String,
+    #[serde(rename = "in", skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub param_type: Option<String>,
+    #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub enum_values: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+}
+
+/// Endpoint entry returned by `list_endpoints`
+#[derive(Debug, Serialize)]
+pub struct EndpointSummary {
+    /// operationId, or a synthetic `METHOD:path` id; either works as an endpoint_id
+    pub id: String,
+    #[serde(rename = "operationId", skip_serializing_if = "Option::is_none")]
+    pub operation_id: Option<String>,
+    pub method: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub requires_auth: bool,
+    /// Path params the endpoint expects
+    pub params: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// What a discovery command found, returned in an `ApiResult`
 #[derive(Serialize)]
 pub struct DiscoveryResponse {
-    pub success: bool,
+    pub descriptor: SocketAgentDescriptor,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub descriptor: Option<SocketAgentDescriptor>,
+    pub diff: Option<DescriptorDiff>,
+    /// Path the descriptor was found at, e.g. `/.well-known/socket-agent`
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
+    pub discovery_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    /// Why generated pages may not be able to call the API
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reachability: Vec<ReachabilityWarning>,
+}
+
+/// How a list endpoint hands out its pages
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaginationStyle {
+    /// Response carries a `next` cursor that is sent back as a query param
+    Cursor,
+    /// Client walks `offset`/`limit` params until a short page comes back
+    Offset,
+    /// Client walks page numbers, from 1, until a short page comes back
+    Page,
+}
+
+/// Pagination settings read from `context.pagination` in the descriptor
+#[derive(Debug, Clone)]
+pub struct PaginationConfig {
+    pub style: PaginationStyle,
+    /// Response field holding the page's items (ignored if the response is an array)
+    pub items_field: String,
+    /// Response field holding the next cursor
+    pub next_field: String,
+    /// Request param the cursor is sent back in
+    pub cursor_param: String,
+    pub offset_param: String,
+    pub page_param: String,
+    /// Page size param, sent with offset and page numbers
+    pub limit_param: String,
+    pub page_size: u64,
 }
 
-/// Discover Socket Agent API descriptor from a given URL
-pub async fn discover_socket_agent(base_url: &str) -> Result<SocketAgentDescriptor> {
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        Self {
+            style: PaginationStyle::Cursor,
+            items_field: "items".to_string(),
+            next_field: "next".to_string(),
+            cursor_param: "cursor".to_string(),
+            offset_param: "offset".to_string(),
+            page_param: "page".to_string(),
+            limit_param: "limit".to_string(),
+            page_size: 50,
+        }
+    }
+}
+
+/// How an API expects credentials to be presented
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthScheme {
+    /// `Authorization: Basic` with a `user:password` credential
+    Basic,
+    /// `Authorization: Bearer` token
+    Bearer,
+    /// API key sent in the named header
+    ApiKeyHeader(String),
+    /// API key sent as the named query param
+    ApiKeyQuery(String),
+}
+
+impl AuthScheme {
+    /// Parse a scheme name as accepted from the user: `basic`, `bearer`,
+    /// `apiKey` (header) or `apiKeyQuery`, with default key names
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "basic" => Some(Self::Basic),
+            "bearer" => Some(Self::Bearer),
+            "apiKey" | "apiKeyHeader" => Some(Self::ApiKeyHeader("X-API-Key".to_string())),
+            "apiKeyQuery" => Some(Self::ApiKeyQuery("api_key".to_string())),
+            _ => None,
+        }
+    }
+
+    /// Whether two schemes present credentials the same way, ignoring key names
+    pub fn same_kind(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// Result of discovering a Socket Agent site
+#[derive(Debug)]
+pub struct Discovery {
+    pub descriptor: SocketAgentDescriptor,
+    /// Path the descriptor was found at
+    pub discovery_path: String,
+    /// Set when discovery was redirected to a different host
+    pub warning: Option<String>,
+    /// Scheme problems that would keep generated pages from the API
+    pub reachability: Vec<ReachabilityWarning>,
+    /// Set when the server sent cache validators, for the caller to save with
+    /// `save_cached_descriptor`; after a 304 this refreshes the cached copy
+    pub cache_entry: Option<CachedDescriptor>,
+}
+
+/// Descriptor as fetched, before it has been validated
+#[derive(Debug)]
+pub struct FetchedDescriptor {
+    pub descriptor: SocketAgentDescriptor,
+    /// Base URL discovery ended up at after redirects
+    pub base_url: String,
+    /// Path the descriptor was found at
+    pub discovery_path: String,
+    pub warning: Option<String>,
+    /// Size of the descriptor body; zero when the cached copy was reused
+    pub bytes: usize,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The server answered 304 and the cached copy was reused
+    pub not_modified: bool,
+}
+
+impl FetchedDescriptor {
+    /// Check required fields and fill in `baseUrl`
+    pub fn validate(self) -> Result<Discovery> {
+        let cache_entry = self.cache_entry();
+        let mut descriptor = self.descriptor;
+        validate_descriptor(&mut descriptor, Some(&self.base_url))?;
+
+        // A newer format still parses; fields this client doesn't know are ignored
+        let warning = match (self.warning, version_warning(&descriptor)) {
+            (Some(redirect), Some(version)) => Some(format!("{}; {}", redirect, version)),
+            (redirect, version) => redirect.or(version),
+        };
+        let reachability = reachability_warnings(&descriptor, Some(&self.base_url));
+
+        println!(
+            "Discovered API: {} with {} endpoints",
+            descriptor.name,
+            descriptor.endpoints.len()
+        );
+
+        Ok(Discovery {
+            descriptor,
+            discovery_path: self.discovery_path,
+            warning,
+            reachability,
+            cache_entry,
+        })
+    }
+
+    /// Disk cache entry for what was fetched or revalidated; `None` when the
+    /// server sent nothing to revalidate with
+    fn cache_entry(&self) -> Option<CachedDescriptor> {
+        if self.etag.is_none() && self.last_modified.is_none() {
+            return None;
+        }
+        Some(CachedDescriptor::new(
+            self.descriptor.clone(),
+            self.base_url.clone(),
+            self.discovery_path.clone(),
+            self.etag.clone(),
+            self.last_modified.clone(),
+        ))
+    }
+}
+
+/// Discover Socket Agent API descriptor from a given URL, revalidating
+/// `cached` rather than downloading it again when given. See
+/// `fetch_descriptor` for the paths tried when `discovery_path` isn't given.
+pub async fn discover_socket_agent(
+    base_url: &str,
+    discovery_path: Option<&str>,
+    timeout: Duration,
+    cached: Option<&CachedDescriptor>,
+) -> Result<Discovery> {
+    fetch_descriptor(base_url, discovery_path, timeout, cached)
+        .await?
+        .validate()
+}
+
+/// Fetch the descriptor without validating it. Tries `discovery_path` when
+/// given; otherwise the path that worked last time, then
+/// `/.well-known/socket-agent`, then the other places some APIs serve it.
+/// With `cached`, the request is conditional and a 304 reuses the cached copy;
+/// servers that ignore conditional headers just send the full descriptor.
+pub async fn fetch_descriptor(
+    base_url: &str,
+    discovery_path: Option<&str>,
+    timeout: Duration,
+    cached: Option<&CachedDescriptor>,
+) -> Result<FetchedDescriptor> {
     // Normalize URL
     let url = base_url.trim_end_matches('/');
 
+    let paths = match discovery_path.map(str::trim) {
+        Some("") => return Err(anyhow!("Discovery path is empty")),
+        Some(path) if path.starts_with('/') => vec![path.to_string()],
+        Some(path) => vec![format!("/{}", path)],
+        None => {
+            let mut paths: Vec<String> = cached
+                .and_then(|cached| cached.discovery_path.clone())
+                .into_iter()
+                .collect();
+            for path in std::iter::once(DISCOVERY_PATH).chain(FALLBACK_DISCOVERY_PATHS) {
+                if !paths.iter().any(|tried| tried == path) {
+                    paths.push(path.to_string());
+                }
+            }
+            paths
+        }
+    };
+
+    // Create HTTP client; redirects to another site are left unfollowed
+    let client = client_builder()?
+        .timeout(timeout)
+        .redirect(redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() > MAX_DISCOVERY_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if same_site(&attempt.previous()[0], attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        }))
+        .build()?;
+
+    for path in &paths {
+        // Only the path the cached copy came from can revalidate it
+        let cached = cached
+            .filter(|cached| cached.discovery_path.as_deref().unwrap_or(DISCOVERY_PATH) == path);
+        if let Some(fetched) = fetch_descriptor_at(&client, url, path, cached).await? {
+            return Ok(fetched);
+        }
+    }
+
+    Err(anyhow!(
+        "No Socket Agent API found at {}. Make sure it's a Socket Agent compliant API.",
+        base_url
+    ))
+}
+
+/// Fetch the descriptor at `path` under `url`; `None` when the server has
+/// nothing there
+async fn fetch_descriptor_at(
+    client: &reqwest::Client,
+    url: &str,
+    path: &str,
+    cached: Option<&CachedDescriptor>,
+) -> Result<Option<FetchedDescriptor>> {
     // Build discovery URL
-    let discovery_url = format!("{}/.well-known/socket-agent", url);
+    let discovery_url = format!("{}{}", url, path);
 
     println!("Discovering Socket Agent at: {}", discovery_url);
 
-    // Create HTTP client
-    let client = Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()?;
-
     // Make request
-    let response = client
+    let mut request = client
         .get(&discovery_url)
         .header("Accept", "application/json")
-        .header("User-Agent", "Socket-Browser/0.1.0")
+        .header("User-Agent", "Socket-Browser/0.1.0");
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request
         .send()
         .await
         .context("Failed to connect to server")?;
 
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+
+    if let (StatusCode::NOT_MODIFIED, Some(cached)) = (response.status(), cached) {
+        println!(
+            "Descriptor at {} not modified; using cached copy",
+            discovery_url
+        );
+        // A 304 may carry updated validators; otherwise keep the cached ones
+        return Ok(Some(FetchedDescriptor {
+            descriptor: cached.descriptor.clone(),
+            base_url: cached.base_url.clone(),
+            discovery_path: path.to_string(),
+            warning: None,
+            bytes: 0,
+            etag: etag.or_else(|| cached.etag.clone()),
+            last_modified: last_modified.or_else(|| cached.last_modified.clone()),
+            not_modified: true,
+        }));
+    }
+
+    // Only redirects to another site are left for us to see
+    if response.status().is_redirection() && response.status() != StatusCode::NOT_MODIFIED {
+        return Err(anyhow!(
+            "Discovery at {} redirected to another site ({}); not following it",
+            discovery_url,
+            header(LOCATION).unwrap_or_default()
+        ));
+    }
+
     // Check status
     if !response.status().is_success() {
         if response.status().as_u16() == 404 {
-            return Err(anyhow!(
-                "No Socket Agent API found at {}. Make sure it's a Socket Agent compliant API.",
-                base_url
-            ));
+            println!("No descriptor at {}", discovery_url);
+            return Ok(None);
         } else {
             return Err(anyhow!(
                 "HTTP {}: {}",
@@ -80,26 +569,265 @@ pub async fn discover_socket_agent(base_url: &str) -> Result<SocketAgentDescript
         }
     }
 
+    // Redirects (www vs apex, http -> https) move the API; use where we ended up
+    let final_url = response.url().clone();
+    let final_base = discovery_base(&final_url, path);
+    let warning = match Url::parse(&discovery_url) {
+        Ok(requested) if requested.host_str() != final_url.host_str() => {
+            let warning = format!(
+                "Discovery redirected from {} to {}; using {} as the base URL",
+                requested.host_str().unwrap_or(url),
+                final_url.host_str().unwrap_or_default(),
+                final_base
+            );
+            println!("{}", warning);
+            Some(warning)
+        }
+        _ => None,
+    };
+
     // Parse response
-    let mut descriptor: SocketAgentDescriptor = response
-        .json()
+    let body = read_body_limited(response, max_response_bytes()).await?;
+    let descriptor = parse_descriptor(&body)
+        .map_err(|e| anyhow!("Failed to parse Socket Agent descriptor: {}", e))?;
+
+    Ok(Some(FetchedDescriptor {
+        descriptor,
+        base_url: final_base,
+        discovery_path: path.to_string(),
+        warning,
+        bytes: body.len(),
+        etag,
+        last_modified,
+        not_modified: false,
+    }))
+}
+
+/// Clean up a URL the user typed: trims whitespace, adds `https://` when
+/// there's no scheme and drops trailing slashes and fragments. Rejects
+/// other schemes and hosts that can't be real, e.g. `example` or `exa mple.com`.
+pub fn normalize_url(input: &str) -> Result<String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(anyhow!("Enter a URL"));
+    }
+    if input.chars().any(char::is_whitespace) {
+        return Err(anyhow!("URL can't contain spaces"));
+    }
+
+    let with_scheme = if has_scheme(input) {
+        input.to_string()
+    } else {
+        format!("https://{}", input)
+    };
+    let mut url = Url::parse(&with_scheme).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow!("Only http and https URLs are supported"));
+    }
+
+    if url.host_str().is_none() {
+        return Err(anyhow!("URL has no host"));
+    }
+    // IP addresses aren't domains and are taken as they are
+    if let Some(domain) = url.domain().filter(|domain| !is_valid_domain(domain)) {
+        return Err(anyhow!("Invalid host: {}", domain));
+    }
+
+    url.set_fragment(None);
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+/// Whether `input` starts with a scheme, as opposed to having `://`
+/// somewhere later on, e.g. in a query parameter
+fn has_scheme(input: &str) -> bool {
+    input.split_once("://").is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Whether `domain` could resolve: `localhost`, or dot-separated labels of
+/// letters, digits and inner hyphens ending in a non-numeric top-level label
+fn is_valid_domain(domain: &str) -> bool {
+    if domain == "localhost" {
+        return true;
+    }
+    let labels: Vec<&str> = domain.trim_end_matches('.').split('.').collect();
+    let valid_labels = labels.iter().all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    let top_level = labels.last().copied().unwrap_or_default();
+    valid_labels && labels.len() > 1 && !top_level.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Quick check for whether `base_url` serves a descriptor, looking only at the
+/// status and content type of `/.well-known/socket-agent` and then the other
+/// places `fetch_descriptor` looks. A 404 or 410 everywhere is `false`
+/// rather than an error.
+pub async fn probe_socket_agent(base_url: &str) -> Result<bool> {
+    let url = base_url.trim_end_matches('/');
+    let client = client_builder()?.timeout(PROBE_TIMEOUT).build()?;
+
+    for path in std::iter::once(DISCOVERY_PATH).chain(FALLBACK_DISCOVERY_PATHS) {
+        let discovery_url = format!("{}{}", url, path);
+        if probe_socket_agent_at(&client, &discovery_url).await? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Whether `discovery_url` answers with JSON. Sends a HEAD, falling back to a
+/// GET whose body is never read for servers that refuse HEAD.
+async fn probe_socket_agent_at(client: &reqwest::Client, discovery_url: &str) -> Result<bool> {
+    let send = |method| {
+        client
+            .request(method, discovery_url)
+            .header("Accept", "application/json")
+            .header("User-Agent", "Socket-Browser/0.1.0")
+            .send()
+    };
+
+    let mut response = send(reqwest::Method::HEAD)
         .await
-        .context("Failed to parse Socket Agent descriptor")?;
+        .context("Failed to connect to server")?;
+    if matches!(
+        response.status(),
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+        response = send(reqwest::Method::GET)
+            .await
+            .context("Failed to connect to server")?;
+    }
 
-    // Validate descriptor
-    if descriptor.name.is_empty() || descriptor.endpoints.is_empty() {
+    let status = response.status();
+    if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+        return Ok(false);
+    }
+    if !status.is_success() {
         return Err(anyhow!(
-            "Invalid Socket Agent descriptor: missing required fields"
+            "HTTP {}: {}",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or("Unknown")
         ));
     }
 
-    // Ensure baseUrl is set
+    // HEAD responses often leave the content type out, so only a non-JSON
+    // one counts against the site
+    let is_json = match response.headers().get(CONTENT_TYPE) {
+        Some(value) => value
+            .to_str()
+            .map(|content_type| {
+                let mime = content_type.split(';').next().unwrap_or("").trim();
+                mime.eq_ignore_ascii_case("application/json") || mime.ends_with("+json")
+            })
+            .unwrap_or(false),
+        None => true,
+    };
+    Ok(is_json)
+}
+
+/// Parse a descriptor. Errors name the offending field (`endpoints[2]:
+/// missing field `path``) and quote the JSON around where parsing stopped.
+fn parse_descriptor(body: &[u8]) -> Result<SocketAgentDescriptor> {
+    let describe = |path: Option<String>, e: serde_json::Error| {
+        let mut message = match path {
+            Some(path) => format!("{}: {}", path, e),
+            None => e.to_string(),
+        };
+        if let Some(snippet) = error_snippet(body, e.line(), e.column()) {
+            message.push_str(&format!(", near `{}`", snippet));
+        }
+        anyhow!(message)
+    };
+
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    let descriptor = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        // The root path prints as "."; only name real fields
+        let path = Some(e.path().to_string()).filter(|path| path != ".");
+        describe(path, e.into_inner())
+    })?;
+    deserializer.end().map_err(|e| describe(None, e))?;
+
+    Ok(descriptor)
+}
+
+/// Text of line `line` around `column` (both 1-based, as serde reports them),
+/// cut down to `PARSE_SNIPPET_CHARS`
+fn error_snippet(body: &[u8], line: usize, column: usize) -> Option<String> {
+    let text = String::from_utf8_lossy(body);
+    let chars: Vec<char> = text.lines().nth(line.checked_sub(1)?)?.chars().collect();
+
+    let start = column
+        .saturating_sub(PARSE_SNIPPET_CHARS / 2)
+        .min(chars.len());
+    let end = (start + PARSE_SNIPPET_CHARS).min(chars.len());
+    let snippet: String = chars[start..end].iter().collect();
+    let snippet = snippet.trim();
+    if snippet.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{}{}{}",
+        if start > 0 { "..." } else { "" },
+        snippet,
+        if end < chars.len() { "..." } else { "" }
+    ))
+}
+
+/// Whether a redirect from `from` to `to` stays on the same site: the same
+/// host, or that host with or without `www.`. Scheme and port may change,
+/// e.g. http -> https.
+fn same_site(from: &Url, to: &Url) -> bool {
+    let host = |url: &Url| {
+        url.host_str()
+            .map(|host| host.trim_start_matches("www.").to_ascii_lowercase())
+    };
+    host(from).is_some() && host(from) == host(to)
+}
+
+/// Base URL implied by the (possibly redirected) discovery URL
+fn discovery_base(final_url: &Url, path: &str) -> String {
+    let final_str = final_url.as_str();
+    match final_str.strip_suffix(path) {
+        Some(base) => base.to_string(),
+        None => final_url.origin().ascii_serialization(),
+    }
+}
+
+/// Load a Socket Agent descriptor from a local JSON file.
+/// `base_url` is used when the file doesn't declare its own `baseUrl`.
+pub fn load_descriptor_from_file(
+    path: &Path,
+    base_url: Option<&str>,
+) -> Result<SocketAgentDescriptor> {
+    if !path.exists() {
+        return Err(anyhow!("Descriptor file not found: {}", path.display()));
+    }
+
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read descriptor file {}", path.display()))?;
+
+    let mut descriptor = parse_descriptor(contents.as_bytes())
+        .map_err(|e| anyhow!("Malformed descriptor JSON in {}: {}", path.display(), e))?;
+
+    validate_descriptor(&mut descriptor, base_url)?;
+
     if descriptor.base_url.is_none() {
-        descriptor.base_url = Some(url.to_string());
+        return Err(anyhow!(
+            "Descriptor file has no baseUrl. Provide a base URL to call its endpoints."
+        ));
     }
 
     println!(
-        "Discovered API: {} with {} endpoints",
+        "Loaded API from file: {} with {} endpoints",
         descriptor.name,
         descriptor.endpoints.len()
     );
@@ -107,6 +835,64 @@ pub async fn discover_socket_agent(base_url: &str) -> Result<SocketAgentDescript
     Ok(descriptor)
 }
 
+/// Check required fields and fill in `baseUrl` when the descriptor omits it
+pub(crate) fn validate_descriptor(descriptor: &mut SocketAgentDescriptor, base_url: Option<&str>) -> Result<()> {
+    if descriptor.name.is_empty() || descriptor.endpoints.is_empty() {
+        return Err(anyhow!(
+            "Invalid Socket Agent descriptor: missing required fields"
+        ));
+    }
+
+    if descriptor.base_url.is_none() {
+        descriptor.base_url = base_url.map(|url| url.trim_end_matches('/').to_string());
+    }
+
+    Ok(())
+}
+
+/// Warning for a descriptor whose version is newer than
+/// `SUPPORTED_DESCRIPTOR_VERSIONS` or can't be read. Unversioned
+/// descriptors predate the field and are treated as supported.
+pub fn version_warning(descriptor: &SocketAgentDescriptor) -> Option<String> {
+    let declared = descriptor.version.as_ref()?;
+    let shown = declared
+        .as_str()
+        .map(String::from)
+        .unwrap_or_else(|| declared.to_string());
+    let warning = match parse_version(declared) {
+        Some(version) if version > *SUPPORTED_DESCRIPTOR_VERSIONS.end() => {
+            let (major, minor) = SUPPORTED_DESCRIPTOR_VERSIONS.end();
+            format!(
+                "{} uses descriptor version {}, newer than the {}.{} this browser supports; some features may be missing",
+                descriptor.name, shown, major, minor
+            )
+        }
+        Some(_) => return None,
+        None => format!(
+            "{} declares an unrecognized descriptor version {}",
+            descriptor.name, shown
+        ),
+    };
+    println!("{}", warning);
+    Some(warning)
+}
+
+/// `"1.2"`, `"1.2.3"`, `"1"` or `1.2` as (major, minor)
+fn parse_version(version: &Value) -> Option<(u64, u64)> {
+    let text = match version {
+        Value::String(text) => text.trim().trim_start_matches('v').to_string(),
+        Value::Number(number) => number.to_string(),
+        _ => return None,
+    };
+    let mut parts = text.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = match parts.next() {
+        Some(minor) => minor.parse().ok()?,
+        None => 0,
+    };
+    Some((major, minor))
+}
+
 /// Get endpoint details by operation ID or path
 pub fn get_endpoint(descriptor: &SocketAgentDescriptor, endpoint_id: &str) -> Option<Endpoint> {
     descriptor.endpoints.iter().find(|ep| {
@@ -124,6 +910,746 @@ pub fn get_endpoint(descriptor: &SocketAgentDescriptor, endpoint_id: &str) -> Op
                 return true;
             }
         }
-        false
+        synthetic_id(ep) == endpoint_id
     }).cloned()
 }
+
+/// `METHOD:path` id for an endpoint, with the method uppercased and
+/// defaulting to GET. Accepted by `get_endpoint`.
+pub fn synthetic_id(endpoint: &Endpoint) -> String {
+    format!(
+        "{}:{}",
+        endpoint.method.as_deref().unwrap_or("GET").to_uppercase(),
+        endpoint.path
+    )
+}
+
+/// Identity of an endpoint across descriptor versions: its operationId,
+/// or its synthetic id when it has none
+pub fn endpoint_key(endpoint: &Endpoint) -> String {
+    endpoint
+        .operation_id
+        .clone()
+        .unwrap_or_else(|| synthetic_id(endpoint))
+}
+
+/// Normalized view of the descriptor's endpoints for building navigation:
+/// one entry per endpoint id, sorted by path then method
+pub fn list_endpoints(descriptor: &SocketAgentDescriptor) -> Vec<EndpointSummary> {
+    let requires_auth = get_auth_scheme(descriptor).is_some();
+
+    let mut endpoints: Vec<EndpointSummary> = descriptor
+        .endpoints
+        .iter()
+        .map(|ep| summarize_endpoint(ep, requires_auth))
+        .collect();
+
+    endpoints.sort_by(|a, b| (&a.path, &a.method, &a.id).cmp(&(&b.path, &b.method, &b.id)));
+    let mut seen = HashSet::new();
+    endpoints.retain(|ep| seen.insert(ep.id.clone()));
+
+    endpoints
+}
+
+/// The descriptor's endpoints by tag for a sectioned menu. An endpoint with
+/// several tags appears in each of their groups and one without any goes in
+/// `UNTAGGED_GROUP`. Endpoints keep their descriptor order within a group.
+pub fn group_endpoints_by_tag(
+    descriptor: &SocketAgentDescriptor,
+) -> BTreeMap<String, Vec<EndpointSummary>> {
+    let requires_auth = get_auth_scheme(descriptor).is_some();
+
+    let mut groups: BTreeMap<String, Vec<EndpointSummary>> = BTreeMap::new();
+    let mut seen = HashSet::new();
+    for ep in &descriptor.endpoints {
+        if !seen.insert(endpoint_key(ep)) {
+            continue;
+        }
+
+        let mut tags: Vec<&str> = ep
+            .tags
+            .iter()
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        if tags.is_empty() {
+            tags.push(UNTAGGED_GROUP);
+        }
+
+        let mut added = HashSet::new();
+        for tag in tags {
+            if added.insert(tag) {
+                groups
+                    .entry(tag.to_string())
+                    .or_default()
+                    .push(summarize_endpoint(ep, requires_auth));
+            }
+        }
+    }
+
+    groups
+}
+
+fn summarize_endpoint(ep: &Endpoint, requires_auth: bool) -> EndpointSummary {
+    EndpointSummary {
+        id: endpoint_key(ep),
+        operation_id: ep.operation_id.clone(),
+        method: ep.method.as_deref().unwrap_or("GET").to_uppercase(),
+        path: ep.path.clone(),
+        summary: ep.summary.clone().or_else(|| ep.description.clone()),
+        requires_auth,
+        params: path_params(&ep.path),
+        tags: ep.tags.clone(),
+    }
+}
+
+/// Names of the `{placeholder}` segments in an endpoint path
+pub(crate) fn path_params(path: &str) -> Vec<String> {
+    path.split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}'))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Read pagination settings from the descriptor context.
+///
+/// Recognized keys under `context.pagination`: `style` ("cursor" | "offset" |
+/// "page"), `itemsField`, `nextField`, `cursorParam`, `offsetParam`,
+/// `pageParam`, `limitParam` and `pageSize`. When `style` is absent it is
+/// inferred from `offsetParam`, `pageParam` or `cursorParam`, in that order;
+/// `limitParam` only sets the page size param.
+pub fn get_pagination(descriptor: &SocketAgentDescriptor) -> PaginationConfig {
+    let mut config = PaginationConfig::default();
+
+    let hints = match descriptor
+        .context
+        .as_ref()
+        .and_then(|ctx| ctx.get("pagination"))
+        .and_then(|p| p.as_object())
+    {
+        Some(hints) => hints,
+        None => return config,
+    };
+
+    let field = |key: &str| hints.get(key).and_then(|v| v.as_str()).map(String::from);
+
+    if let Some(items_field) = field("itemsField") {
+        config.items_field = items_field;
+    }
+    if let Some(next_field) = field("nextField") {
+        config.next_field = next_field;
+    }
+    if let Some(cursor_param) = field("cursorParam") {
+        config.cursor_param = cursor_param;
+    }
+    if let Some(offset_param) = field("offsetParam") {
+        config.offset_param = offset_param;
+    }
+    if let Some(page_param) = field("pageParam") {
+        config.page_param = page_param;
+    }
+    if let Some(limit_param) = field("limitParam") {
+        config.limit_param = limit_param;
+    }
+    if let Some(page_size) = hints.get("pageSize").and_then(|v| v.as_u64()) {
+        config.page_size = page_size.max(1);
+    }
+
+    config.style = match field("style").as_deref() {
+        Some("offset") => PaginationStyle::Offset,
+        Some("page") => PaginationStyle::Page,
+        Some(_) => PaginationStyle::Cursor,
+        None if hints.contains_key("offsetParam") => PaginationStyle::Offset,
+        None if hints.contains_key("pageParam") => PaginationStyle::Page,
+        None => PaginationStyle::Cursor,
+    };
+
+    config
+}
+
+/// Required params `provided` doesn't fill, in declaration order, so the UI
+/// can hold back sending until the form is complete. Covers declared
+/// params, path placeholders and required request body fields; null and
+/// empty-string values count as missing. Endpoints that declare nothing
+/// have nothing missing.
+pub fn missing_required_params(
+    endpoint: &Endpoint,
+    provided: &HashMap<String, Value>,
+) -> Vec<String> {
+    let body_required = endpoint
+        .request_schema
+        .as_ref()
+        .and_then(|schema| schema.get("required")?.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str().map(String::from));
+    let required = endpoint_params(endpoint)
+        .into_iter()
+        .filter(|param| param.required)
+        .map(|param| param.name)
+        .chain(body_required);
+
+    let mut missing: Vec<String> = Vec::new();
+    for name in required {
+        let filled = match provided.get(&name) {
+            None | Some(Value::Null) => false,
+            Some(Value::String(value)) => !value.trim().is_empty(),
+            Some(_) => true,
+        };
+        if !filled && !missing.contains(&name) {
+            missing.push(name);
+        }
+    }
+    missing
+}
+
+/// Declared parameters of an endpoint, for prefilling and constraining inputs.
+/// Path params that aren't declared are listed as required strings.
+pub fn endpoint_params(endpoint: &Endpoint) -> Vec<ParamInfo> {
+    let mut params: Vec<ParamInfo> = endpoint
+        .parameters
+        .iter()
+        .map(|param| ParamInfo {
+            name: param.name.clone(),
+            location: param.location.clone(),
+            required: param.required,
+            description: param.description.clone(),
+            param_type: param.type_name(),
+            enum_values: param.allowed_values(),
+            default: param.default_value(),
+        })
+        .collect();
+
+    for name in path_params(&endpoint.path) {
+        if !params.iter().any(|p| p.name == name) {
+            params.push(ParamInfo {
+                name,
+                location: Some("path".to_string()),
+                required: true,
+                description: None,
+                param_type: Some("string".to_string()),
+                enum_values: None,
+                default: None,
+            });
+        }
+    }
+
+    params
+}
+
+/// Read default request params from the descriptor context.
+///
+/// Recognized keys: `context.defaults` (an object of param name to value),
+/// plus the shorthands `defaultCurrency` and `defaultLanguage`, which fill the
+/// `currency` and `language` params. Explicit `defaults` entries win.
+pub fn get_default_params(descriptor: &SocketAgentDescriptor) -> HashMap<String, Value> {
+    let mut defaults = HashMap::new();
+
+    let context = match &descriptor.context {
+        Some(context) => context,
+        None => return defaults,
+    };
+
+    for (hint, param) in [("defaultCurrency", "currency"), ("defaultLanguage", "language")] {
+        if let Some(value) = context.get(hint).filter(|v| !v.is_null()) {
+            defaults.insert(param.to_string(), value.clone());
+        }
+    }
+
+    if let Some(map) = context.get("defaults").and_then(|d| d.as_object()) {
+        for (key, value) in map {
+            defaults.insert(key.clone(), value.clone());
+        }
+    }
+
+    defaults
+}
+
+/// Calls allowed per time window, advertised in `context.rateLimits`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub window_secs: u64,
+}
+
+impl RateLimit {
+    pub fn per_second(&self) -> f64 {
+        f64::from(self.requests) / self.window_secs as f64
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let requests = value
+            .get("requests")
+            .or_else(|| value.get("limit"))
+            .and_then(|v| v.as_u64())
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v > 0)?;
+        let unit = value.get("per").and_then(|v| v.as_str()).unwrap_or("second");
+        let window_secs = match value.get("window").and_then(|v| v.as_u64()) {
+            Some(secs) => secs,
+            None => match unit {
+                "second" => 1,
+                "minute" => 60,
+                "hour" => 3600,
+                "day" => 86400,
+                _ => return None,
+            },
+        };
+
+        (window_secs > 0).then_some(Self {
+            requests,
+            window_secs,
+        })
+    }
+}
+
+/// Rate limit advertised for `endpoint` in `context.rateLimits`.
+///
+/// Recognized shape: a map from endpoint id (operationId, `METHOD:path` or
+/// path) or `"*"` for every other endpoint, to `{"requests": n, "window": secs}`
+/// or `{"requests": n, "per": "second" | "minute" | "hour" | "day"}`.
+pub fn get_rate_limit(
+    descriptor: &SocketAgentDescriptor,
+    endpoint: &Endpoint,
+) -> Option<RateLimit> {
+    let limits = descriptor
+        .context
+        .as_ref()?
+        .get("rateLimits")?
+        .as_object()?;
+
+    let candidates = [
+        endpoint.operation_id.clone(),
+        Some(synthetic_id(endpoint)),
+        Some(endpoint.path.clone()),
+        Some("*".to_string()),
+    ];
+    candidates
+        .iter()
+        .flatten()
+        .find_map(|key| limits.get(key.as_str()))
+        .and_then(RateLimit::from_value)
+}
+
+/// Read the auth scheme from `context.auth` in the descriptor.
+///
+/// Recognized shape: `{"type": "basic" | "bearer" | "apiKey", "in": "header" | "query",
+/// "name": "<key name>"}`. `in` and `name` only apply to `apiKey`, which defaults
+/// to the `X-API-Key` header.
+pub fn get_auth_scheme(descriptor: &SocketAgentDescriptor) -> Option<AuthScheme> {
+    let auth = descriptor.context.as_ref()?.get("auth")?.as_object()?;
+    let field = |key: &str| auth.get(key).and_then(|v| v.as_str());
+
+    match field("type")? {
+        "basic" => Some(AuthScheme::Basic),
+        "bearer" => Some(AuthScheme::Bearer),
+        "apiKey" => {
+            let location = field("in").unwrap_or("header");
+            match (location, field("name")) {
+                ("query", Some(name)) => Some(AuthScheme::ApiKeyQuery(name.to_string())),
+                ("query", None) => AuthScheme::from_name("apiKeyQuery"),
+                (_, Some(name)) => Some(AuthScheme::ApiKeyHeader(name.to_string())),
+                (_, None) => AuthScheme::from_name("apiKey"),
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `routes` (path, status, body) until the test ends; other paths 404
+    async fn serve(routes: Vec<(&'static str, u16, &'static str)>) -> String {
+        serve_recording(routes).await.0
+    }
+
+    /// Like `serve`, also returning the path of every request received
+    async fn serve_recording(
+        routes: Vec<(&'static str, u16, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 4096];
+                let read = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let mut words = request.split_whitespace();
+                let method = words.next().unwrap_or_default();
+                let path = words.next().unwrap_or_default();
+                received.lock().unwrap().push(path.to_string());
+                let (status, body) = routes
+                    .iter()
+                    .find(|(route, _, _)| *route == path)
+                    .map(|(_, status, body)| (*status, *body))
+                    .unwrap_or((404, ""));
+                let head = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                if method != "HEAD" {
+                    socket.write_all(body.as_bytes()).await.unwrap();
+                }
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    /// Answer every request with a 301 to `location`
+    async fn serve_redirect(location: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                let head = format!(
+                    "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    location
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn descriptor_with_pagination(pagination: Value) -> SocketAgentDescriptor {
+        serde_json::from_value(json!({
+            "name": "Test API",
+            "endpoints": [{ "path": "/items", "method": "GET" }],
+            "context": { "pagination": pagination },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn pagination_style_comes_from_the_position_param() {
+        let cases = [
+            (json!({ "limitParam": "per_page" }), PaginationStyle::Cursor),
+            (json!({ "offsetParam": "skip" }), PaginationStyle::Offset),
+            (
+                json!({ "pageParam": "p", "limitParam": "per_page" }),
+                PaginationStyle::Page,
+            ),
+            (json!({ "cursorParam": "after" }), PaginationStyle::Cursor),
+            (
+                json!({ "style": "page", "cursorParam": "after" }),
+                PaginationStyle::Page,
+            ),
+        ];
+
+        for (hints, style) in cases {
+            let config = get_pagination(&descriptor_with_pagination(hints.clone()));
+            assert_eq!(config.style, style, "{}", hints);
+        }
+        let config = get_pagination(&descriptor_with_pagination(
+            json!({ "pageParam": "p", "limitParam": "per_page" }),
+        ));
+        assert_eq!(config.page_param, "p");
+        assert_eq!(config.limit_param, "per_page");
+    }
+
+    #[test]
+    fn normalize_url_adds_https_when_there_is_no_scheme() {
+        let cases = [
+            ("example.com", "https://example.com"),
+            ("  example.com/  ", "https://example.com"),
+            ("http://example.com/api/#top", "http://example.com/api"),
+            ("localhost:3000", "https://localhost:3000"),
+            ("127.0.0.1:8080/v1", "https://127.0.0.1:8080/v1"),
+            (
+                "example.com/go?to=https://other.org",
+                "https://example.com/go?to=https://other.org",
+            ),
+        ];
+
+        for (input, normalized) in cases {
+            assert_eq!(normalize_url(input).unwrap(), normalized, "{}", input);
+        }
+    }
+
+    #[test]
+    fn normalize_url_rejects_what_cant_be_a_web_address() {
+        for input in [
+            "",
+            "exa mple.com",
+            "example",
+            "ftp://example.com",
+            "https://-bad-.com",
+            "https://example.123",
+        ] {
+            assert!(normalize_url(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn default_params_come_from_the_context() {
+        let descriptor: SocketAgentDescriptor = serde_json::from_value(json!({
+            "name": "Test API",
+            "endpoints": [],
+            "context": {
+                "defaultCurrency": "USD",
+                "defaultLanguage": null,
+                "defaults": { "currency": "EUR", "region": "eu" },
+            },
+        }))
+        .unwrap();
+
+        let defaults = get_default_params(&descriptor);
+
+        assert_eq!(
+            defaults,
+            HashMap::from([
+                ("currency".to_string(), json!("EUR")),
+                ("region".to_string(), json!("eu")),
+            ])
+        );
+        assert!(get_default_params(&descriptor_with_pagination(json!({}))).is_empty());
+    }
+
+    #[tokio::test]
+    async fn probe_finds_descriptors_at_fallback_paths() {
+        let base_url = serve(vec![("/socket-agent.json", 200, "{}")]).await;
+
+        assert!(probe_socket_agent(&base_url).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn probe_is_false_when_no_path_has_a_descriptor() {
+        let base_url = serve(vec![]).await;
+
+        assert!(!probe_socket_agent(&base_url).await.unwrap());
+    }
+
+    const REDIRECT_DESCRIPTOR: &str =
+        r#"{"name":"Moved","endpoints":[{"path":"/items","method":"GET"}]}"#;
+
+    #[tokio::test]
+    async fn discovery_follows_redirects_on_the_same_site() {
+        let target = serve(vec![(DISCOVERY_PATH, 200, REDIRECT_DESCRIPTOR)]).await;
+        let origin = serve_redirect(format!("{}{}", target, DISCOVERY_PATH)).await;
+
+        let discovery = discover_socket_agent(&origin, None, Duration::from_secs(5), None)
+            .await
+            .unwrap();
+
+        assert_eq!(discovery.descriptor.name, "Moved");
+        assert_eq!(
+            discovery.descriptor.base_url.as_deref(),
+            Some(target.as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn discovery_refuses_redirects_to_another_site() {
+        let target = serve(vec![(DISCOVERY_PATH, 200, REDIRECT_DESCRIPTOR)]).await;
+        let elsewhere = target.replace("127.0.0.1", "localhost");
+        let origin = serve_redirect(format!("{}{}", elsewhere, DISCOVERY_PATH)).await;
+
+        let error = discover_socket_agent(&origin, None, Duration::from_secs(5), None)
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("redirected to another site"), "{}", error);
+        assert!(error.contains(&elsewhere), "{}", error);
+    }
+
+    #[test]
+    fn www_and_scheme_changes_stay_on_the_same_site() {
+        let url = |url: &str| Url::parse(url).unwrap();
+
+        assert!(same_site(
+            &url("http://example.com/a"),
+            &url("https://www.example.com/b")
+        ));
+        assert!(same_site(
+            &url("https://www.example.com"),
+            &url("https://example.com:8443")
+        ));
+        assert!(!same_site(
+            &url("https://example.com"),
+            &url("https://example.com.evil.test")
+        ));
+        assert!(!same_site(
+            &url("https://example.com"),
+            &url("https://api.example.com")
+        ));
+    }
+
+    #[tokio::test]
+    async fn newer_descriptor_versions_parse_with_a_warning() {
+        let base_url = serve(vec![(
+            DISCOVERY_PATH,
+            200,
+            r#"{"name":"Future","version":"2.3","endpoints":[{"path":"/items","method":"GET","summary":"List"}],"webhooks":[{"event":"item.created"}]}"#,
+        )])
+        .await;
+
+        let discovery = discover_socket_agent(&base_url, None, Duration::from_secs(5), None)
+            .await
+            .unwrap();
+
+        let warning = discovery.warning.unwrap();
+        assert!(warning.contains("descriptor version 2.3"), "{}", warning);
+        assert!(warning.contains("newer than the 1.0"), "{}", warning);
+        assert_eq!(discovery.descriptor.endpoints.len(), 1);
+        let endpoint = &discovery.descriptor.endpoints[0];
+        assert_eq!(endpoint.summary.as_deref(), Some("List"));
+    }
+
+    #[test]
+    fn supported_and_unversioned_descriptors_have_no_version_warning() {
+        let warning = |version: Value| {
+            let descriptor = json!({ "name": "Test API", "version": version, "endpoints": [] });
+            version_warning(&serde_json::from_value(descriptor).unwrap())
+        };
+
+        assert!(warning(json!("1.0")).is_none());
+        assert!(warning(json!("v1")).is_none());
+        assert!(warning(json!(1)).is_none());
+        assert!(version_warning(&descriptor_with_pagination(json!({}))).is_none());
+        assert!(warning(json!(1.1)).unwrap().contains("newer"));
+        assert!(warning(json!("beta")).unwrap().contains("unrecognized"));
+    }
+
+    #[tokio::test]
+    async fn parse_errors_name_the_missing_field() {
+        let base_url = serve(vec![(
+            DISCOVERY_PATH,
+            200,
+            r#"{"name":"Broken","endpoints":[{"path":"/a","method":"GET"},{"method":"POST"}]}"#,
+        )])
+        .await;
+
+        let error = discover_socket_agent(&base_url, None, Duration::from_secs(5), None)
+            .await
+            .unwrap_err()
+            .to_string();
+
+        let expected =
+            "Failed to parse Socket Agent descriptor: endpoints[1]: missing field `path`";
+        assert!(error.starts_with(expected), "{}", error);
+        assert!(error.contains("near `"), "{}", error);
+    }
+
+    #[test]
+    fn syntax_errors_quote_the_json_where_parsing_stopped() {
+        let body = "{\n  \"name\": \"Test API\",\n  \"endpoints\": [}\n}";
+
+        let error = parse_descriptor(body.as_bytes()).unwrap_err().to_string();
+
+        assert!(error.contains("line 3"), "{}", error);
+        assert!(error.ends_with(", near `\"endpoints\": [}`"), "{}", error);
+    }
+
+    #[test]
+    fn snippets_are_cut_down_around_the_error() {
+        let line = format!("{}X{}", "a".repeat(200), "b".repeat(200));
+
+        let snippet = error_snippet(line.as_bytes(), 1, 201).unwrap();
+
+        assert!(snippet.starts_with("...a"), "{}", snippet);
+        assert!(snippet.ends_with("b..."), "{}", snippet);
+        assert!(snippet.contains('X'));
+        assert_eq!(snippet.chars().count(), PARSE_SNIPPET_CHARS + 6);
+        assert_eq!(error_snippet(line.as_bytes(), 2, 1), None);
+    }
+
+    #[test]
+    fn endpoints_are_grouped_by_tag_in_descriptor_order() {
+        let descriptor: SocketAgentDescriptor = serde_json::from_value(json!({
+            "name": "Test API",
+            "endpoints": [
+                { "path": "/users", "method": "GET", "tags": ["users"] },
+                { "path": "/health", "method": "GET" },
+                { "path": "/orders", "method": "GET", "tags": ["orders", " users ", "orders"] },
+                { "path": "/users", "method": "POST", "tags": ["users"] },
+                { "path": "/version", "method": "GET", "tags": [""] },
+            ],
+        }))
+        .unwrap();
+
+        let groups = group_endpoints_by_tag(&descriptor);
+
+        let ids = |tag: &str| -> Vec<String> { groups[tag].iter().map(|e| e.id.clone()).collect() };
+        assert_eq!(
+            groups.keys().collect::<Vec<_>>(),
+            ["orders", "untagged", "users"]
+        );
+        assert_eq!(ids("users"), ["GET:/users", "GET:/orders", "POST:/users"]);
+        assert_eq!(ids("orders"), ["GET:/orders"]);
+        assert_eq!(ids(UNTAGGED_GROUP), ["GET:/health", "GET:/version"]);
+    }
+
+    #[tokio::test]
+    async fn fallback_paths_are_tried_after_the_standard_one() {
+        let [first, second, _] = FALLBACK_DISCOVERY_PATHS;
+        let (base_url, requests) = serve_recording(vec![(second, 200, REDIRECT_DESCRIPTOR)]).await;
+
+        let discovery = discover_socket_agent(&base_url, None, Duration::from_secs(5), None)
+            .await
+            .unwrap();
+
+        assert_eq!(discovery.discovery_path, second);
+        assert_eq!(*requests.lock().unwrap(), [DISCOVERY_PATH, first, second]);
+    }
+
+    #[tokio::test]
+    async fn an_explicit_path_is_the_only_one_tried() {
+        let (base_url, requests) =
+            serve_recording(vec![("/custom/agent.json", 200, REDIRECT_DESCRIPTOR)]).await;
+        let timeout = Duration::from_secs(5);
+
+        let discovery = discover_socket_agent(&base_url, Some("custom/agent.json"), timeout, None)
+            .await
+            .unwrap();
+        assert_eq!(discovery.discovery_path, "/custom/agent.json");
+
+        let error = discover_socket_agent(&base_url, Some("/missing"), timeout, None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with("No Socket Agent API found"));
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["/custom/agent.json", "/missing"]
+        );
+    }
+
+    #[test]
+    fn required_params_not_yet_provided_are_listed() {
+        let endpoint: Endpoint = serde_json::from_value(json!({
+            "path": "/users/{id}",
+            "method": "PUT",
+            "parameters": [
+                { "name": "email", "required": true },
+                { "name": "name", "required": true },
+                { "name": "nickname" },
+            ],
+        }))
+        .unwrap();
+        let provided = HashMap::from([
+            ("id".to_string(), json!(7)),
+            ("name".to_string(), Value::Null),
+        ]);
+
+        let missing = missing_required_params(&endpoint, &provided);
+        assert_eq!(missing, ["email", "name"]);
+
+        let undeclared: Endpoint = serde_json::from_value(json!({ "path": "/items" })).unwrap();
+        assert!(missing_required_params(&undeclared, &HashMap::new()).is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+// API error types
+// Structured errors for Socket Agent API calls
+
+use serde::Serialize;
+use std::path::Path;
+use thiserror::Error;
+
+/// Error from a Socket Agent API call, serialized with a `kind` discriminant
+/// so the frontend can decide whether to retry
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ApiError {
+    #[error("Network error: {message}")]
+    Network { message: String },
+    #[error("Request timed out: {message}")]
+    Timeout { message: String },
+    #[error("Client error ({status}): {message}")]
+    ClientError { status: u16, message: String },
+    #[error("Server error ({status}): {message}")]
+    ServerError { status: u16, message: String },
+    #[error("Failed to parse API response: {message}")]
+    Parse { message: String },
+    #[error("Invalid descriptor: {message}")]
+    InvalidDescriptor { message: String },
+    #[error("Invalid value for {name}: {message}")]
+    InvalidParameter { name: String, message: String },
+    #[error("Missing required parameters: {}", .names.join(", "))]
+    MissingParameters { names: Vec<String> },
+    #[error("Response is larger than the {limit} byte limit")]
+    TooLarge { limit: usize },
+    #[error("Rate limit advertised by the API reached; retry in {retry_after}s")]
+    RateLimited { retry_after: u64 },
+    #[error("API is failing repeatedly; retrying in {retry_after}s")]
+    CircuitOpen { retry_after: u64 },
+    #[error("Failed to write {path}: {message}")]
+    Write { path: String, message: String },
+}
+
+impl ApiError {
+    /// Whether the same request might succeed if sent again
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self,
+            ApiError::Network { .. } | ApiError::Timeout { .. } | ApiError::ServerError { .. }
+        )
+    }
+
+    /// Build an error from a failed write to `path`
+    pub fn from_io(path: &Path, e: std::io::Error) -> Self {
+        let message = match e.kind() {
+            std::io::ErrorKind::StorageFull => "disk is full".to_string(),
+            std::io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+            _ => e.to_string(),
+        };
+        ApiError::Write {
+            path: path.display().to_string(),
+            message,
+        }
+    }
+
+    /// Build an error from a non-success response
+    pub fn from_status(status: u16, message: String) -> Self {
+        if status >= 500 {
+            ApiError::ServerError { status, message }
+        } else {
+            ApiError::ClientError { status, message }
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        let message = e.to_string();
+        if e.is_timeout() {
+            ApiError::Timeout { message }
+        } else if e.is_decode() {
+            ApiError::Parse { message }
+        } else {
+            ApiError::Network { message }
+        }
+    }
+}
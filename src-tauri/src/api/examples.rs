@@ -0,0 +1,169 @@
+// Endpoint examples
+// Sample parameter sets a descriptor declares, checked against the
+// endpoint's parameters so "try it" forms don't prefill a broken call
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use super::client::param_to_string;
+use super::discovery::{path_params, Endpoint};
+use super::schema::validate_against_schema;
+
+/// An endpoint example with what's wrong with it, if anything
+#[derive(Debug, Serialize)]
+pub struct ExampleInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub params: Map<String, Value>,
+    /// Missing required params, wrong types, values outside an enum and
+    /// params the endpoint doesn't take
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// The endpoint's examples, each checked against its declared parameters
+/// and request body schema
+pub fn endpoint_examples(endpoint: &Endpoint) -> Vec<ExampleInfo> {
+    let schema = params_schema(endpoint);
+    endpoint
+        .examples
+        .iter()
+        .map(|example| ExampleInfo {
+            name: example.name.clone(),
+            summary: example.summary.clone(),
+            params: example.params.clone(),
+            warnings: example_warnings(endpoint, &schema, &example.params),
+        })
+        .collect()
+}
+
+/// One object schema covering the params: declared parameters, path
+/// placeholders and the request body's top-level properties
+fn params_schema(endpoint: &Endpoint) -> Value {
+    let body = endpoint.request_schema.as_ref();
+    let mut properties = body
+        .and_then(|schema| schema.get("properties")?.as_object().cloned())
+        .unwrap_or_default();
+    let mut required: Vec<Value> = body
+        .and_then(|schema| schema.get("required")?.as_array().cloned())
+        .unwrap_or_default();
+
+    for param in &endpoint.parameters {
+        let schema = match (&param.schema, param.type_name()) {
+            (Some(schema), _) => schema.clone(),
+            (None, Some(type_name)) => json!({ "type": type_name }),
+            (None, None) => json!({}),
+        };
+        properties.insert(param.name.clone(), schema);
+        if param.required {
+            required.push(Value::String(param.name.clone()));
+        }
+    }
+    for name in path_params(&endpoint.path) {
+        if !properties.contains_key(&name) {
+            properties.insert(name.clone(), json!({}));
+        }
+        required.push(Value::String(name));
+    }
+
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+fn example_warnings(
+    endpoint: &Endpoint,
+    schema: &Value,
+    params: &Map<String, Value>,
+) -> Vec<String> {
+    let mut warnings = validate_against_schema(schema, &Value::Object(params.clone()));
+
+    for param in &endpoint.parameters {
+        let (Some(value), Some(allowed)) = (params.get(&param.name), param.allowed_values()) else {
+            continue;
+        };
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let text = param_to_string(value);
+            if !allowed
+                .iter()
+                .any(|option| option == value || param_to_string(option) == text)
+            {
+                warnings.push(format!(
+                    "/{}: '{}' is not an allowed value",
+                    param.name, text
+                ));
+            }
+        }
+    }
+
+    // Only flagged when the endpoint says what it takes
+    let properties = schema.get("properties").and_then(|p| p.as_object());
+    if let Some(properties) = properties.filter(|properties| !properties.is_empty()) {
+        for name in params.keys().filter(|name| !properties.contains_key(*name)) {
+            warnings.push(format!("/{}: not a parameter of this endpoint", name));
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint() -> Endpoint {
+        serde_json::from_value(json!({
+            "path": "/users/{id}/posts",
+            "method": "GET",
+            "parameters": [
+                { "name": "limit", "in": "query", "type": "integer" },
+                { "name": "sort", "in": "query", "enum": ["new", "top"] },
+            ],
+            "examples": [
+                { "name": "Newest", "params": { "id": 7, "limit": 10, "sort": "new" } },
+                { "summary": "Broken", "parameters": { "limit": "ten", "sort": "old", "page": 2 } },
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn declared_examples_are_returned_in_order() {
+        let examples = endpoint_examples(&endpoint());
+
+        assert_eq!(examples.len(), 2);
+        assert_eq!(examples[0].name.as_deref(), Some("Newest"));
+        assert_eq!(
+            Value::Object(examples[0].params.clone()),
+            json!({ "id": 7, "limit": 10, "sort": "new" })
+        );
+        assert!(examples[0].warnings.is_empty());
+        assert_eq!(examples[1].summary.as_deref(), Some("Broken"));
+    }
+
+    #[test]
+    fn examples_that_dont_fit_the_parameters_are_flagged() {
+        let examples = endpoint_examples(&endpoint());
+
+        assert_eq!(
+            examples[1].warnings,
+            [
+                "/: missing required field `id`",
+                "/limit: expected integer, got string",
+                "/sort: 'old' is not an allowed value",
+                "/page: not a parameter of this endpoint",
+            ]
+        );
+    }
+
+    #[test]
+    fn endpoints_without_examples_have_none() {
+        let endpoint: Endpoint = serde_json::from_value(json!({ "path": "/health" })).unwrap();
+
+        assert!(endpoint_examples(&endpoint).is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+// Descriptor merging
+// Combines several APIs into one descriptor for dashboards that aggregate
+// them, remembering which API each endpoint belongs to
+
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+
+use super::discovery::{endpoint_key, get_endpoint, synthetic_id, SocketAgentDescriptor};
+
+/// Prefix of a merged descriptor's `baseUrl`, which is only a cache key
+pub const MERGED_BASE_URL_PREFIX: &str = "merged:";
+
+/// One descriptor with every source's endpoints. Each operationId is
+/// prefixed with its source's namespace (`weather.getForecast`), so ids that
+/// collide across sources stay distinct, and each endpoint keeps its
+/// source's `baseUrl` so calls can be routed back with `route_endpoint`.
+pub fn merge_descriptors(sources: Vec<SocketAgentDescriptor>) -> Result<SocketAgentDescriptor> {
+    if sources.len() < 2 {
+        return Err(anyhow!("Merging needs at least two descriptors"));
+    }
+
+    let mut namespaces = HashSet::new();
+    let mut names = Vec::new();
+    let mut base_urls = Vec::new();
+    let mut endpoints = Vec::new();
+    for source in sources {
+        let base_url = source
+            .base_url
+            .as_deref()
+            .map(|url| url.trim_end_matches('/').to_string())
+            .ok_or_else(|| anyhow!("{} has no baseUrl to route its calls to", source.name))?;
+
+        let namespace = unique_namespace(&source.name, &mut namespaces);
+        for mut endpoint in source.endpoints {
+            endpoint.operation_id = Some(format!("{}.{}", namespace, endpoint_key(&endpoint)));
+            endpoint.base_url = Some(base_url.clone());
+            endpoints.push(endpoint);
+        }
+        names.push(source.name);
+        base_urls.push(base_url);
+    }
+
+    Ok(SocketAgentDescriptor {
+        version: None,
+        name: names.join(" + "),
+        description: None,
+        base_url: Some(format!("{}{}", MERGED_BASE_URL_PREFIX, base_urls.join("+"))),
+        endpoints,
+        context: None,
+    })
+}
+
+/// The API an endpoint of a merged descriptor came from and its id there,
+/// or `None` when the endpoint isn't from a merge
+pub fn route_endpoint(
+    descriptor: &SocketAgentDescriptor,
+    endpoint_id: &str,
+) -> Option<(String, String)> {
+    let endpoint = get_endpoint(descriptor, endpoint_id)?;
+    let base_url = endpoint.base_url.clone()?;
+    Some((base_url, synthetic_id(&endpoint)))
+}
+
+/// `name` as an identifier, suffixed with a number if already taken
+fn unique_namespace(name: &str, taken: &mut HashSet<String>) -> String {
+    let slug: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let slug = match slug.trim_matches('_') {
+        "" => "api".to_string(),
+        slug => slug.to_string(),
+    };
+
+    let mut namespace = slug.clone();
+    let mut n = 2;
+    while !taken.insert(namespace.clone()) {
+        namespace = format!("{}_{}", slug, n);
+        n += 1;
+    }
+    namespace
+}
@@ -1,6 +1,46 @@
 // API module
+pub mod breaker;
+pub mod cache;
 pub mod client;
+pub mod cookies;
+pub mod credentials;
+pub mod diff;
 pub mod discovery;
+pub mod error;
+pub mod examples;
+pub mod merge;
+pub mod openapi;
+pub mod ratelimit;
+pub mod reachability;
+pub mod schema;
+pub mod search;
+pub mod sse;
 
-pub use client::{call_api, ApiCallResponse};
-pub use discovery::{discover_socket_agent, SocketAgentDescriptor, DiscoveryResponse};
+pub use breaker::{CircuitBreaker, CircuitState};
+pub use cache::{load_cached_descriptor, save_cached_descriptor, CachedDescriptor};
+pub use client::{
+    call_api, call_api_batch, call_api_binary, call_api_download, call_api_paginated,
+    download_path, raw_request, response_warnings, ApiCallResponse, ApiClients, BatchCall, CallContext, RawHttpResult,
+    DEFAULT_MAX_PAGES,
+};
+pub use cookies::{reseal_cookies, CookieJars, CookiePolicy};
+pub use credentials::{
+    credential_host, load_credentials, reseal_credentials, save_credentials, ApiCredential,
+};
+pub use diff::{diff_descriptors, load_last_seen, save_last_seen};
+pub use discovery::{
+    discover_socket_agent, endpoint_params, fetch_descriptor, get_endpoint, group_endpoints_by_tag,
+    list_endpoints, load_descriptor_from_file, missing_required_params, normalize_url,
+    probe_socket_agent, BodyEncoding, Discovery, DiscoveryResponse, EndpointSummary, ParamInfo,
+    SocketAgentDescriptor,
+};
+pub use error::ApiError;
+pub use examples::{endpoint_examples, ExampleInfo};
+pub use merge::{merge_descriptors, route_endpoint};
+pub use openapi::{export_openapi, import_openapi, import_openapi_from_url};
+pub use ratelimit::{HostThrottle, RateLimitState, RateLimiter};
+pub use reachability::{
+    check_cors, reachability_warnings, scheme_warnings, ReachabilityIssue, ReachabilityWarning,
+};
+pub use search::{search_endpoints, EndpointMatch};
+pub use sse::{stream_sse, SseEvent};
@@ -0,0 +1,588 @@
+// OpenAPI import and export module
+// Converts OpenAPI 3.0 / Swagger 2.0 specs into Socket Agent descriptors,
+// and descriptors back into OpenAPI 3.0 for other tooling
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+
+use crate::config::{client_builder, max_response_bytes};
+
+use super::client::read_body_limited;
+use super::discovery::{
+    get_auth_scheme, path_params, validate_descriptor, AuthScheme, Endpoint, EndpointParam,
+    SocketAgentDescriptor, FORM_CONTENT_TYPE,
+};
+use super::merge::MERGED_BASE_URL_PREFIX;
+
+const HTTP_METHODS: [&str; 8] = ["get", "put", "post", "delete", "patch", "head", "options", "trace"];
+
+/// Convert an OpenAPI 3.0 or Swagger 2.0 spec into a Socket Agent descriptor
+pub fn import_openapi(spec: Value) -> Result<SocketAgentDescriptor> {
+    let is_openapi = spec.get("openapi").and_then(|v| v.as_str()).is_some();
+    let is_swagger = spec.get("swagger").and_then(|v| v.as_str()).is_some();
+
+    if !is_openapi && !is_swagger {
+        return Err(anyhow!("Not an OpenAPI document: missing `openapi` or `swagger` version"));
+    }
+
+    let info = spec.get("info");
+    let name = info
+        .and_then(|i| i.get("title"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("Imported API")
+        .to_string();
+    let description = info
+        .and_then(|i| i.get("description"))
+        .and_then(|d| d.as_str())
+        .map(String::from);
+
+    let base_url = if is_openapi {
+        spec.get("servers")
+            .and_then(|s| s.as_array())
+            .and_then(|servers| servers.first())
+            .and_then(|server| server.get("url"))
+            .and_then(|u| u.as_str())
+            .map(|u| u.trim_end_matches('/').to_string())
+    } else {
+        swagger_base_url(&spec)
+    };
+
+    let paths = spec
+        .get("paths")
+        .and_then(|p| p.as_object())
+        .ok_or_else(|| anyhow!("OpenAPI document has no paths"))?;
+
+    let mut endpoints = Vec::new();
+    for (path, item) in paths {
+        for method in HTTP_METHODS {
+            let operation = match item.get(method) {
+                Some(operation) => operation,
+                None => continue,
+            };
+            let text = |key: &str| operation.get(key).and_then(|v| v.as_str()).map(String::from);
+
+            endpoints.push(Endpoint {
+                path: path.clone(),
+                method: Some(method.to_uppercase()),
+                operation_id: text("operationId"),
+                summary: text("summary"),
+                description: text("description"),
+                parameters: openapi_params(item, operation),
+                tags: openapi_tags(operation),
+                base_url: None,
+                request_schema: openapi_request_schema(item, operation),
+                request_content_type: openapi_request_content_type(&spec, operation),
+                response_schema: openapi_response_schema(operation),
+                examples: Vec::new(),
+            });
+        }
+    }
+
+    let mut descriptor = SocketAgentDescriptor {
+        version: None,
+        name,
+        description,
+        base_url,
+        endpoints,
+        context: None,
+    };
+
+    validate_descriptor(&mut descriptor, None)?;
+
+    println!(
+        "Imported OpenAPI spec: {} with {} endpoints",
+        descriptor.name,
+        descriptor.endpoints.len()
+    );
+
+    Ok(descriptor)
+}
+
+/// The operation's `tags`, which name its section in the spec's docs
+fn openapi_tags(operation: &Value) -> Vec<String> {
+    operation
+        .get("tags")
+        .and_then(|t| t.as_array())
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|tag| tag.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parameters declared on the path item and the operation; the operation's
+/// win when both declare the same name. `$ref` parameters are skipped, and so
+/// is Swagger 2's `in: body` one, whose schema becomes the request schema.
+fn openapi_params(item: &Value, operation: &Value) -> Vec<EndpointParam> {
+    let declared = |node: &Value| -> Vec<EndpointParam> {
+        node.get("parameters")
+            .and_then(|p| p.as_array())
+            .map(|params| {
+                params
+                    .iter()
+                    .filter(|p| p.get("in").and_then(|v| v.as_str()) != Some("body"))
+                    .filter_map(|p| serde_json::from_value(p.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut params = declared(operation);
+    for param in declared(item) {
+        if !params.iter().any(|p| p.name == param.name) {
+            params.push(param);
+        }
+    }
+    params
+}
+
+/// JSON schema of the request body: under `requestBody.content.application/json.schema`
+/// (or the form content's) in OpenAPI 3, on the `in: body` parameter of the
+/// operation or path item in Swagger 2
+fn openapi_request_schema(item: &Value, operation: &Value) -> Option<Value> {
+    let content = operation.pointer("/requestBody/content");
+    if let Some(schema) = content.and_then(|content| {
+        content
+            .pointer("/application~1json/schema")
+            .or_else(|| content.get(FORM_CONTENT_TYPE)?.get("schema"))
+    }) {
+        return Some(schema.clone());
+    }
+
+    let body_schema = |node: &Value| {
+        node.get("parameters")?
+            .as_array()?
+            .iter()
+            .find(|p| p.get("in").and_then(|v| v.as_str()) == Some("body"))
+            .and_then(|p| p.get("schema"))
+            .cloned()
+    };
+    body_schema(operation).or_else(|| body_schema(item))
+}
+
+/// `FORM_CONTENT_TYPE` when the operation takes a form body and not JSON:
+/// from `requestBody.content` in OpenAPI 3, `consumes` in Swagger 2
+fn openapi_request_content_type(spec: &Value, operation: &Value) -> Option<String> {
+    let media_types: Vec<&str> = match operation.pointer("/requestBody/content") {
+        Some(content) => content.as_object()?.keys().map(String::as_str).collect(),
+        None => operation
+            .get("consumes")
+            .or_else(|| spec.get("consumes"))?
+            .as_array()?
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect(),
+    };
+
+    let accepts = |wanted: &str| {
+        media_types
+            .iter()
+            .any(|media_type| media_type.to_ascii_lowercase().starts_with(wanted))
+    };
+    (!accepts("application/json") && accepts(FORM_CONTENT_TYPE))
+        .then(|| FORM_CONTENT_TYPE.to_string())
+}
+
+/// JSON schema of the first 2xx (or default) response: under
+/// `content.application/json.schema` in OpenAPI 3, `schema` in Swagger 2
+fn openapi_response_schema(operation: &Value) -> Option<Value> {
+    let responses = operation.get("responses")?.as_object()?;
+    let response = ["200", "201", "202", "2XX", "default"]
+        .iter()
+        .find_map(|status| responses.get(*status))?;
+
+    response
+        .pointer("/content/application~1json/schema")
+        .or_else(|| response.get("schema"))
+        .cloned()
+}
+
+/// Fetch an OpenAPI spec and convert it, resolving relative server URLs
+/// against the spec's own location
+pub async fn import_openapi_from_url(url: &str, timeout: Duration) -> Result<SocketAgentDescriptor> {
+    let spec_url = Url::parse(url).context("Invalid OpenAPI spec URL")?;
+
+    println!("Fetching OpenAPI spec from: {}", spec_url);
+
+    let client = client_builder()?.timeout(timeout).build()?;
+
+    let response = client
+        .get(spec_url.clone())
+        .header("Accept", "application/json")
+        .header("User-Agent", "Socket-Browser/0.1.0")
+        .send()
+        .await
+        .context("Failed to connect to server")?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "HTTP {}: {}",
+            response.status().as_u16(),
+            response.status().canonical_reason().unwrap_or("Unknown")
+        ));
+    }
+
+    let body = read_body_limited(response, max_response_bytes()).await?;
+    let spec: Value = serde_json::from_slice(&body)
+        .context("Failed to parse OpenAPI spec (only JSON specs are supported)")?;
+
+    let mut descriptor = import_openapi(spec)?;
+
+    let resolved = match &descriptor.base_url {
+        Some(base) if Url::parse(base).is_ok() => base.clone(),
+        Some(base) => spec_url.join(base)?.as_str().trim_end_matches('/').to_string(),
+        None => spec_url.origin().ascii_serialization(),
+    };
+    descriptor.base_url = Some(resolved);
+
+    Ok(descriptor)
+}
+
+/// Convert a descriptor into an OpenAPI 3.0 document. Params go where the
+/// browser sends them: path placeholders in the path, undeclared ones in the
+/// query for GET and DELETE and in the request body otherwise.
+pub fn export_openapi(descriptor: &SocketAgentDescriptor) -> Result<Value> {
+    let mut info = json!({
+        "title": descriptor.name,
+        "version": match &descriptor.version {
+            Some(Value::String(version)) => version.clone(),
+            Some(version) => version.to_string(),
+            None => "1.0.0".to_string(),
+        },
+    });
+    if let Some(description) = &descriptor.description {
+        info["description"] = json!(description);
+    }
+
+    let mut spec = json!({ "openapi": "3.0.3", "info": info });
+    // A merged descriptor's baseUrl is only a cache key; its endpoints carry
+    // their own servers
+    if let Some(base_url) = descriptor
+        .base_url
+        .as_deref()
+        .filter(|url| !url.starts_with(MERGED_BASE_URL_PREFIX))
+    {
+        spec["servers"] = json!([{ "url": base_url }]);
+    }
+
+    let mut paths = Map::new();
+    for endpoint in &descriptor.endpoints {
+        let method = endpoint.method.as_deref().unwrap_or("GET").to_lowercase();
+        if !HTTP_METHODS.contains(&method.as_str()) {
+            return Err(anyhow!(
+                "{} {} has a method OpenAPI can't express",
+                method.to_uppercase(),
+                endpoint.path
+            ));
+        }
+
+        let item = paths
+            .entry(endpoint.path.clone())
+            .or_insert_with(|| json!({}));
+        if item.get(&method).is_some() {
+            return Err(anyhow!(
+                "{} {} is declared more than once",
+                method.to_uppercase(),
+                endpoint.path
+            ));
+        }
+        item[&method] = export_operation(endpoint, &method);
+    }
+    spec["paths"] = Value::Object(paths);
+
+    if let Some(scheme) = get_auth_scheme(descriptor) {
+        let scheme = match scheme {
+            AuthScheme::Basic => json!({ "type": "http", "scheme": "basic" }),
+            AuthScheme::Bearer => json!({ "type": "http", "scheme": "bearer" }),
+            AuthScheme::ApiKeyHeader(name) => {
+                json!({ "type": "apiKey", "in": "header", "name": name })
+            }
+            AuthScheme::ApiKeyQuery(name) => {
+                json!({ "type": "apiKey", "in": "query", "name": name })
+            }
+        };
+        spec["components"] = json!({ "securitySchemes": { "auth": scheme } });
+        spec["security"] = json!([{ "auth": [] }]);
+    }
+
+    Ok(spec)
+}
+
+fn export_operation(endpoint: &Endpoint, method: &str) -> Value {
+    let mut operation = Map::new();
+    let mut text = |key: &str, value: &Option<String>| {
+        if let Some(value) = value {
+            operation.insert(key.to_string(), json!(value));
+        }
+    };
+    text("operationId", &endpoint.operation_id);
+    text("summary", &endpoint.summary);
+    text("description", &endpoint.description);
+    if !endpoint.tags.is_empty() {
+        operation.insert("tags".to_string(), json!(endpoint.tags));
+    }
+    if let Some(base_url) = &endpoint.base_url {
+        operation.insert("servers".to_string(), json!([{ "url": base_url }]));
+    }
+
+    let placeholders = path_params(&endpoint.path);
+    let sends_query = matches!(method, "get" | "delete");
+    let mut parameters = Vec::new();
+    let mut body_properties = Map::new();
+    let mut body_required = Vec::new();
+    for param in &endpoint.parameters {
+        let location = match param.location.as_deref() {
+            _ if placeholders.contains(&param.name) => "path",
+            Some(location @ ("query" | "header" | "cookie")) => location,
+            Some("body" | "formData") => "body",
+            _ if sends_query => "query",
+            _ => "body",
+        };
+        if location == "body" {
+            body_properties.insert(param.name.clone(), param_schema(param));
+            if param.required {
+                body_required.push(param.name.clone());
+            }
+            continue;
+        }
+
+        let mut exported = json!({
+            "name": param.name,
+            "in": location,
+            "required": param.required || location == "path",
+            "schema": param_schema(param),
+        });
+        if let Some(description) = &param.description {
+            exported["description"] = json!(description);
+        }
+        if location == "query" {
+            // `comma` and `brackets` are the browser's own styles
+            match (param.style.as_deref(), param.explode) {
+                (Some("comma"), _) => {
+                    exported["style"] = json!("form");
+                    exported["explode"] = json!(false);
+                }
+                (Some("brackets" | "repeat"), _) | (None, None) => {}
+                (style, explode) => {
+                    if let Some(style) = style {
+                        exported["style"] = json!(style);
+                    }
+                    if let Some(explode) = explode {
+                        exported["explode"] = json!(explode);
+                    }
+                }
+            }
+        }
+        parameters.push(exported);
+    }
+    for name in placeholders {
+        if !endpoint.parameters.iter().any(|param| param.name == name) {
+            parameters.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            }));
+        }
+    }
+    if !parameters.is_empty() {
+        operation.insert("parameters".to_string(), json!(parameters));
+    }
+
+    let body_schema = endpoint.request_schema.clone().or_else(|| {
+        (!body_properties.is_empty()).then(|| {
+            let mut schema = json!({ "type": "object", "properties": body_properties });
+            if !body_required.is_empty() {
+                schema["required"] = json!(body_required);
+            }
+            schema
+        })
+    });
+    if let Some(schema) = body_schema {
+        let content_type = endpoint
+            .request_content_type
+            .as_deref()
+            .unwrap_or("application/json");
+        operation.insert(
+            "requestBody".to_string(),
+            json!({ "content": { content_type: { "schema": schema } } }),
+        );
+    }
+
+    let mut response = json!({ "description": "Successful response" });
+    if let Some(schema) = &endpoint.response_schema {
+        response["content"] = json!({ "application/json": { "schema": schema } });
+    }
+    operation.insert("responses".to_string(), json!({ "200": response }));
+
+    Value::Object(operation)
+}
+
+/// The param's `schema`, or one built from its `type`, `enum` and `default`
+fn param_schema(param: &EndpointParam) -> Value {
+    if let Some(schema) = &param.schema {
+        return schema.clone();
+    }
+    let mut schema = Map::new();
+    if let Some(type_name) = &param.param_type {
+        schema.insert("type".to_string(), json!(type_name));
+    }
+    if let Some(values) = &param.enum_values {
+        schema.insert("enum".to_string(), json!(values));
+    }
+    if let Some(default) = &param.default {
+        schema.insert("default".to_string(), default.clone());
+    }
+    Value::Object(schema)
+}
+
+/// Swagger 2.0 splits the server URL into `schemes`, `host` and `basePath`
+fn swagger_base_url(spec: &Value) -> Option<String> {
+    let host = spec.get("host").and_then(|h| h.as_str())?;
+    let scheme = spec
+        .get("schemes")
+        .and_then(|s| s.as_array())
+        .and_then(|schemes| {
+            schemes
+                .iter()
+                .filter_map(|s| s.as_str())
+                .find(|s| *s == "https")
+                .or_else(|| schemes.first().and_then(|s| s.as_str()))
+        })
+        .unwrap_or("https");
+    let base_path = spec.get("basePath").and_then(|b| b.as_str()).unwrap_or("");
+
+    Some(format!("{}://{}{}", scheme, host, base_path.trim_end_matches('/')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn swagger_body_params_become_the_request_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+        });
+        let spec = json!({
+            "swagger": "2.0",
+            "info": { "title": "Pets" },
+            "host": "pets.example.com",
+            "paths": {
+                "/pets": {
+                    "post": {
+                        "operationId": "addPet",
+                        "parameters": [
+                            { "name": "pet", "in": "body", "required": true, "schema": schema },
+                            { "name": "dryRun", "in": "query", "type": "boolean" },
+                        ],
+                    },
+                },
+            },
+        });
+
+        let descriptor = import_openapi(spec).unwrap();
+
+        let endpoint = &descriptor.endpoints[0];
+        assert_eq!(endpoint.request_schema, Some(schema));
+        let names: Vec<&str> = endpoint
+            .parameters
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, ["dryRun"]);
+    }
+
+    #[test]
+    fn exported_descriptors_import_back_unchanged() {
+        let descriptor: SocketAgentDescriptor = serde_json::from_value(json!({
+            "name": "Pets",
+            "description": "Pet store",
+            "baseUrl": "https://pets.example.com/v1",
+            "endpoints": [
+                {
+                    "path": "/pets/{id}",
+                    "method": "GET",
+                    "operationId": "getPet",
+                    "summary": "Get a pet",
+                    "tags": ["pets"],
+                    "parameters": [
+                        { "name": "id", "in": "path", "required": true, "schema": { "type": "integer" } },
+                        { "name": "fields", "in": "query", "schema": { "type": "string" } },
+                    ],
+                    "responseSchema": { "type": "object" },
+                },
+                {
+                    "path": "/pets",
+                    "method": "POST",
+                    "operationId": "addPet",
+                    "requestSchema": {
+                        "type": "object",
+                        "properties": { "name": { "type": "string" } },
+                        "required": ["name"],
+                    },
+                    "requestContentType": FORM_CONTENT_TYPE,
+                },
+            ],
+        }))
+        .unwrap();
+
+        let imported = import_openapi(export_openapi(&descriptor).unwrap()).unwrap();
+
+        assert_eq!(imported.name, descriptor.name);
+        assert_eq!(imported.description, descriptor.description);
+        assert_eq!(imported.base_url, descriptor.base_url);
+        assert_eq!(imported.endpoints.len(), descriptor.endpoints.len());
+        for original in &descriptor.endpoints {
+            let endpoint = imported
+                .endpoints
+                .iter()
+                .find(|endpoint| endpoint.operation_id == original.operation_id)
+                .unwrap();
+            assert_eq!(endpoint.path, original.path);
+            assert_eq!(endpoint.method, original.method);
+            assert_eq!(endpoint.summary, original.summary);
+            assert_eq!(endpoint.tags, original.tags);
+            assert_eq!(endpoint.request_schema, original.request_schema);
+            assert_eq!(endpoint.request_content_type, original.request_content_type);
+            assert_eq!(endpoint.response_schema, original.response_schema);
+            let params = |endpoint: &Endpoint| -> Vec<Value> {
+                endpoint
+                    .parameters
+                    .iter()
+                    .map(|p| json!([p.name, p.location, p.required, p.schema]))
+                    .collect()
+            };
+            assert_eq!(params(endpoint), params(original));
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_specs_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                max_response_bytes() + 1
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+        });
+
+        let url = format!("http://{}/openapi.json", addr);
+        let error = import_openapi_from_url(&url, Duration::from_secs(5))
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("larger than"), "{}", error);
+    }
+}
@@ -0,0 +1,254 @@
+// Client-side rate limiting
+// Token buckets that keep calls within the limits a descriptor advertises,
+// plus a per-host minimum interval so bursts don't trip server limits
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::discovery::RateLimit;
+use super::error::ApiError;
+
+/// Longest a call waits for a token before it is rejected instead
+pub const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(10);
+
+struct Bucket {
+    limit: RateLimit,
+    // Negative while calls are waiting on tokens that haven't refilled yet
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.requests),
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second()).min(self.capacity());
+        self.updated = now;
+    }
+
+    fn capacity(&self) -> f64 {
+        f64::from(self.limit.requests)
+    }
+}
+
+/// Snapshot of one bucket, for debugging
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitState {
+    pub key: String,
+    pub requests: u32,
+    pub window_secs: u64,
+    /// Calls that can be made right now without waiting
+    pub available: f64,
+}
+
+/// Token buckets keyed by base URL and endpoint
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a token for `key`, waiting for one to refill if needed. Fails
+    /// without waiting when the wait would exceed `MAX_RATE_LIMIT_WAIT`.
+    pub async fn acquire(&self, key: &str, limit: RateLimit) -> Result<(), ApiError> {
+        let wait = {
+            let mut buckets = self.buckets.lock().unwrap();
+            let now = Instant::now();
+            let bucket = buckets
+                .entry(key.to_string())
+                .or_insert_with(|| Bucket::new(limit));
+            // The descriptor may have changed since the bucket was created
+            if bucket.limit != limit {
+                *bucket = Bucket::new(limit);
+            }
+            bucket.refill(now);
+
+            let wait = if bucket.tokens >= 1.0 {
+                Duration::ZERO
+            } else {
+                Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_second())
+            };
+            if wait > MAX_RATE_LIMIT_WAIT {
+                return Err(ApiError::RateLimited {
+                    retry_after: wait.as_secs() + u64::from(wait.subsec_nanos() > 0),
+                });
+            }
+
+            bucket.tokens -= 1.0;
+            wait
+        };
+
+        if !wait.is_zero() {
+            println!(
+                "Rate limit for {} reached, waiting {}ms",
+                key,
+                wait.as_millis()
+            );
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+
+    /// Forget every bucket
+    pub fn clear(&self) {
+        self.buckets.lock().unwrap().clear();
+    }
+
+    /// Current state of every bucket, sorted by key
+    pub fn snapshot(&self) -> Vec<RateLimitState> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        let mut states: Vec<RateLimitState> = buckets
+            .iter_mut()
+            .map(|(key, bucket)| {
+                bucket.refill(now);
+                RateLimitState {
+                    key: key.clone(),
+                    requests: bucket.limit.requests,
+                    window_secs: bucket.limit.window_secs,
+                    available: bucket.tokens.max(0.0),
+                }
+            })
+            .collect();
+        states.sort_by(|a, b| a.key.cmp(&b.key));
+        states
+    }
+}
+
+/// Spaces out requests to the same host, whatever the descriptor says.
+/// Each call reserves the next free slot, so a burst is queued rather than
+/// sent at once.
+#[derive(Default)]
+pub struct HostThrottle {
+    // Earliest time the next request to each host may start
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait until a request to `host` may be sent, at least `interval` after
+    /// the previous one
+    pub async fn wait(&self, host: &str, interval: Duration) {
+        if interval.is_zero() {
+            return;
+        }
+
+        let wait = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_slot
+                .get(host)
+                .copied()
+                .filter(|slot| *slot > now)
+                .unwrap_or(now);
+            next_slot.insert(host.to_string(), slot + interval);
+            slot - now
+        };
+
+        if !wait.is_zero() {
+            println!("Throttling request to {} for {}ms", host, wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Forget every host
+    pub fn clear(&self) {
+        self.next_slot.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "https://api.example.com GET /items";
+
+    #[tokio::test]
+    async fn calls_past_the_limit_are_rejected_when_the_wait_is_too_long() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit {
+            requests: 2,
+            window_secs: 60,
+        };
+
+        limiter.acquire(KEY, limit).await.unwrap();
+        limiter.acquire(KEY, limit).await.unwrap();
+        let result = limiter.acquire(KEY, limit).await;
+
+        assert!(matches!(
+            result,
+            Err(ApiError::RateLimited { retry_after: 30 })
+        ));
+        // A rejected call doesn't use up a token
+        assert!(limiter.snapshot()[0].available < 1.0);
+        assert!(limiter.acquire("another key", limit).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn calls_past_the_limit_wait_for_a_token() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit {
+            requests: 20,
+            window_secs: 1,
+        };
+        for _ in 0..20 {
+            limiter.acquire(KEY, limit).await.unwrap();
+        }
+
+        let started = Instant::now();
+        limiter.acquire(KEY, limit).await.unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[tokio::test]
+    async fn a_changed_limit_starts_a_new_bucket() {
+        let limiter = RateLimiter::new();
+        let old = RateLimit {
+            requests: 1,
+            window_secs: 3600,
+        };
+        limiter.acquire(KEY, old).await.unwrap();
+        assert!(limiter.acquire(KEY, old).await.is_err());
+
+        let new = RateLimit {
+            requests: 5,
+            window_secs: 3600,
+        };
+        limiter.acquire(KEY, new).await.unwrap();
+
+        let state = &limiter.snapshot()[0];
+        assert_eq!(state.requests, 5);
+        assert!((state.available - 4.0).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn requests_to_a_host_are_spaced_out() {
+        let throttle = HostThrottle::new();
+        let interval = Duration::from_millis(50);
+
+        let started = Instant::now();
+        throttle.wait("api.example.com", interval).await;
+        throttle.wait("other.example.com", interval).await;
+        assert!(started.elapsed() < interval);
+
+        throttle.wait("api.example.com", interval).await;
+        assert!(started.elapsed() >= interval);
+    }
+}
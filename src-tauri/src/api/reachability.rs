@@ -0,0 +1,244 @@
+// Reachability checks
+// Why a generated page can't reach its API. Pages run in the app's webview,
+// a secure origin, so plain-http APIs are blocked as mixed content and
+// cross-origin calls fail unless the API allows the app's origin (CORS).
+// The webview reports neither to the page, so calls just fail silently.
+
+use anyhow::{Context, Result};
+use reqwest::header::{
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD,
+    ORIGIN,
+};
+use reqwest::Url;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use super::client::ApiClients;
+use super::discovery::{SocketAgentDescriptor, DISCOVERY_PATH};
+use super::merge::MERGED_BASE_URL_PREFIX;
+
+/// Origin generated pages are served from
+pub const APP_ORIGIN: &str = if cfg!(windows) {
+    "https://tauri.localhost"
+} else {
+    "tauri://localhost"
+};
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReachabilityIssue {
+    /// Plain http, blocked from the app's secure origin
+    MixedContent,
+    /// Not the scheme the descriptor was discovered over, or not http(s)
+    SchemeMismatch,
+    /// The API doesn't allow the app's origin
+    Cors,
+}
+
+/// Why calls from a generated page to `base_url` would fail
+#[derive(Debug, Clone, Serialize)]
+pub struct ReachabilityWarning {
+    pub issue: ReachabilityIssue,
+    pub base_url: String,
+    pub message: String,
+}
+
+/// Scheme problems with the URLs the descriptor's calls go to: its
+/// `baseUrl` and, in merged descriptors, each endpoint's. `discovered_base`
+/// is where the descriptor was fetched from, when it was.
+pub fn reachability_warnings(
+    descriptor: &SocketAgentDescriptor,
+    discovered_base: Option<&str>,
+) -> Vec<ReachabilityWarning> {
+    let mut base_urls: Vec<&str> = Vec::new();
+    let targets = descriptor
+        .base_url
+        .as_deref()
+        .filter(|url| !url.starts_with(MERGED_BASE_URL_PREFIX))
+        .into_iter()
+        .chain(
+            descriptor
+                .endpoints
+                .iter()
+                .filter_map(|ep| ep.base_url.as_deref()),
+        );
+    for base_url in targets {
+        if !base_urls.contains(&base_url) {
+            base_urls.push(base_url);
+        }
+    }
+
+    let discovered_scheme = discovered_base
+        .and_then(|url| Url::parse(url).ok())
+        .map(|url| url.scheme().to_string());
+    base_urls
+        .into_iter()
+        .flat_map(|base_url| scheme_warnings(base_url, discovered_scheme.as_deref()))
+        .collect()
+}
+
+/// Scheme problems with calls to `base_url`, including a scheme other than
+/// the `discovered_scheme` the descriptor was fetched over
+pub fn scheme_warnings(
+    base_url: &str,
+    discovered_scheme: Option<&str>,
+) -> Vec<ReachabilityWarning> {
+    let Ok(url) = Url::parse(base_url) else {
+        return Vec::new();
+    };
+    let warning = |issue, message| ReachabilityWarning {
+        issue,
+        base_url: base_url.to_string(),
+        message,
+    };
+
+    let mut warnings = Vec::new();
+    match url.scheme() {
+        "https" => {}
+        // Browsers treat loopback as secure, so local development works
+        "http" if is_loopback(&url) => {}
+        "http" => warnings.push(warning(
+            ReachabilityIssue::MixedContent,
+            format!(
+                "{} uses plain http; generated pages can only call https APIs",
+                base_url
+            ),
+        )),
+        scheme => warnings.push(warning(
+            ReachabilityIssue::SchemeMismatch,
+            format!(
+                "{} uses {}://, which generated pages can't call",
+                base_url, scheme
+            ),
+        )),
+    }
+    if let Some(discovered) = discovered_scheme.filter(|scheme| *scheme != url.scheme()) {
+        warnings.push(warning(
+            ReachabilityIssue::SchemeMismatch,
+            format!(
+                "The descriptor was served over {} but its API is at {}",
+                discovered, base_url
+            ),
+        ));
+    }
+    warnings
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_matches(['[', ']'])
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback()),
+        None => false,
+    }
+}
+
+/// Send the CORS preflight a generated page's JSON call would, and warn
+/// unless the API allows the app's origin
+pub async fn check_cors(
+    base_url: &str,
+    clients: &ApiClients,
+) -> Result<Option<ReachabilityWarning>> {
+    let url = format!("{}{}", base_url.trim_end_matches('/'), DISCOVERY_PATH);
+    let client = clients.client(base_url, None)?;
+    let response = client
+        .request(reqwest::Method::OPTIONS, &url)
+        .timeout(PREFLIGHT_TIMEOUT)
+        .header(ORIGIN, APP_ORIGIN)
+        .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .header(
+            ACCESS_CONTROL_REQUEST_HEADERS,
+            "authorization, content-type",
+        )
+        .header("User-Agent", "Socket-Browser/0.1.0")
+        .send()
+        .await
+        .context("Failed to connect to server")?;
+
+    let allowed = response
+        .headers()
+        .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim);
+    let message = match allowed {
+        Some("*") => return Ok(None),
+        Some(origin) if origin.eq_ignore_ascii_case(APP_ORIGIN) => return Ok(None),
+        Some(origin) => format!(
+            "{} only allows calls from {}, not from generated pages ({})",
+            base_url, origin, APP_ORIGIN
+        ),
+        None => format!(
+            "{} doesn't allow cross-origin calls (no Access-Control-Allow-Origin), so generated pages can't reach it",
+            base_url
+        ),
+    };
+    println!("{}", message);
+
+    Ok(Some(ReachabilityWarning {
+        issue: ReachabilityIssue::Cors,
+        base_url: base_url.to_string(),
+        message,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn issues(warnings: &[ReachabilityWarning]) -> Vec<ReachabilityIssue> {
+        warnings.iter().map(|warning| warning.issue).collect()
+    }
+
+    #[test]
+    fn plain_http_is_mixed_content_unless_it_is_loopback() {
+        assert!(scheme_warnings("https://api.example.com", None).is_empty());
+        assert!(scheme_warnings("http://localhost:8080", None).is_empty());
+        assert!(scheme_warnings("http://127.0.0.1:8080", None).is_empty());
+        assert!(scheme_warnings("http://[::1]:8080", None).is_empty());
+
+        let warnings = scheme_warnings("http://api.example.com", None);
+        assert_eq!(issues(&warnings), vec![ReachabilityIssue::MixedContent]);
+        assert_eq!(warnings[0].base_url, "http://api.example.com");
+    }
+
+    #[test]
+    fn other_schemes_and_a_changed_scheme_are_mismatches() {
+        assert_eq!(
+            issues(&scheme_warnings("ws://api.example.com", None)),
+            vec![ReachabilityIssue::SchemeMismatch]
+        );
+        assert_eq!(
+            issues(&scheme_warnings("http://api.example.com", Some("https"))),
+            vec![
+                ReachabilityIssue::MixedContent,
+                ReachabilityIssue::SchemeMismatch
+            ]
+        );
+        assert!(scheme_warnings("https://api.example.com", Some("https")).is_empty());
+        assert!(scheme_warnings("not a url", Some("https")).is_empty());
+    }
+
+    #[test]
+    fn each_base_url_is_checked_once() {
+        let descriptor: SocketAgentDescriptor = serde_json::from_value(json!({
+            "name": "Merged",
+            "baseUrl": format!("{}test", MERGED_BASE_URL_PREFIX),
+            "endpoints": [
+                { "path": "/a", "method": "GET", "baseUrl": "http://a.example.com" },
+                { "path": "/b", "method": "GET", "baseUrl": "http://a.example.com" },
+                { "path": "/c", "method": "GET", "baseUrl": "https://c.example.com" },
+            ],
+        }))
+        .unwrap();
+
+        let warnings = reachability_warnings(&descriptor, None);
+
+        assert_eq!(issues(&warnings), vec![ReachabilityIssue::MixedContent]);
+        assert_eq!(warnings[0].base_url, "http://a.example.com");
+    }
+}
@@ -0,0 +1,180 @@
+// Response schema validation
+// Checks API responses against the schema an endpoint advertises, so an API
+// drifting from its contract shows up as warnings
+
+use serde_json::Value;
+
+/// Warnings reported per response; the rest are dropped
+const MAX_SCHEMA_WARNINGS: usize = 50;
+
+/// Mismatches between `data` and a JSON schema: wrong types and missing
+/// required fields, each prefixed with the JSON pointer where it was found.
+/// Only `type`, `nullable`, `required`, `properties` and `items` are checked,
+/// and `$ref` schemas are skipped.
+pub fn validate_against_schema(schema: &Value, data: &Value) -> Vec<String> {
+    let mut warnings = Vec::new();
+    check(schema, data, "", &mut warnings);
+    warnings.truncate(MAX_SCHEMA_WARNINGS);
+    warnings
+}
+
+fn check(schema: &Value, data: &Value, pointer: &str, warnings: &mut Vec<String>) {
+    if warnings.len() >= MAX_SCHEMA_WARNINGS || schema.get("$ref").is_some() {
+        return;
+    }
+    let location = if pointer.is_empty() { "/" } else { pointer };
+
+    if data.is_null() && schema.get("nullable").and_then(|n| n.as_bool()) == Some(true) {
+        return;
+    }
+    if let Some(expected) = declared_types(schema) {
+        if !expected.iter().any(|t| type_matches(t, data)) {
+            warnings.push(format!(
+                "{}: expected {}, got {}",
+                location,
+                expected.join(" or "),
+                type_name(data)
+            ));
+            return;
+        }
+    }
+
+    match data {
+        Value::Object(object) => {
+            let required = schema.get("required").and_then(|r| r.as_array());
+            for field in required.into_iter().flatten().filter_map(|f| f.as_str()) {
+                if !object.contains_key(field) {
+                    warnings.push(format!("{}: missing required field `{}`", location, field));
+                }
+            }
+
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+            for (name, property) in properties.into_iter().flatten() {
+                if let Some(value) = object.get(name) {
+                    let pointer = format!("{}/{}", pointer, escape_pointer(name));
+                    check(property, value, &pointer, warnings);
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    let pointer = format!("{}/{}", pointer, index);
+                    check(item_schema, item, &pointer, warnings);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `type` as a list, whether the schema gives one type or several
+fn declared_types(schema: &Value) -> Option<Vec<&str>> {
+    match schema.get("type")? {
+        Value::String(name) => Some(vec![name.as_str()]),
+        Value::Array(names) => Some(names.iter().filter_map(|n| n.as_str()).collect()),
+        _ => None,
+    }
+}
+
+fn type_matches(expected: &str, data: &Value) -> bool {
+    match expected {
+        "object" => data.is_object(),
+        "array" => data.is_array(),
+        "string" => data.is_string(),
+        "number" => data.is_number(),
+        "integer" => {
+            data.is_i64() || data.is_u64() || data.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "boolean" => data.is_boolean(),
+        "null" => data.is_null(),
+        // Unknown types aren't held against the response
+        _ => true,
+    }
+}
+
+fn type_name(data: &Value) -> &'static str {
+    match data {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn escape_pointer(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn user_schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["id", "email"],
+            "properties": {
+                "id": { "type": "integer" },
+                "email": { "type": "string" },
+                "nickname": { "type": "string", "nullable": true },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            }
+        })
+    }
+
+    #[test]
+    fn missing_required_fields_are_reported() {
+        let warnings = validate_against_schema(&user_schema(), &json!({ "id": 7 }));
+
+        assert_eq!(warnings, ["/: missing required field `email`"]);
+    }
+
+    #[test]
+    fn wrong_types_are_reported_where_they_were_found() {
+        let data = json!({ "id": "7", "email": "a@b.c", "tags": ["admin", 3] });
+
+        let warnings = validate_against_schema(&user_schema(), &data);
+
+        assert_eq!(
+            warnings,
+            [
+                "/id: expected integer, got string",
+                "/tags/1: expected string, got number"
+            ]
+        );
+    }
+
+    #[test]
+    fn matching_responses_have_no_warnings() {
+        let data = json!({ "id": 7.0, "email": "a@b.c", "nickname": null, "extra": true });
+
+        assert!(validate_against_schema(&user_schema(), &data).is_empty());
+        assert!(
+            validate_against_schema(&json!({ "type": ["string", "null"] }), &json!(null))
+                .is_empty()
+        );
+        assert!(validate_against_schema(&json!({ "$ref": "#/User" }), &json!(1)).is_empty());
+    }
+
+    #[test]
+    fn pointers_escape_field_names() {
+        let schema = json!({ "properties": { "a/b~c": { "type": "string" } } });
+
+        let warnings = validate_against_schema(&schema, &json!({ "a/b~c": 1 }));
+
+        assert_eq!(warnings, ["/a~1b~0c: expected string, got number"]);
+    }
+
+    #[test]
+    fn warnings_are_capped() {
+        let schema = json!({ "type": "array", "items": { "type": "string" } });
+
+        let warnings = validate_against_schema(&schema, &json!(vec![0; 80]));
+
+        assert_eq!(warnings.len(), MAX_SCHEMA_WARNINGS);
+    }
+}
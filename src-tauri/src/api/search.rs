@@ -0,0 +1,156 @@
+// Endpoint search
+// Ranks a descriptor's endpoints against a keyword query for the search box
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::discovery::{
+    endpoint_key, list_endpoints, Endpoint, EndpointSummary, SocketAgentDescriptor,
+};
+
+// How much a hit in each field counts; identifiers outrank prose
+const OPERATION_ID_WEIGHT: u32 = 4;
+const PATH_WEIGHT: u32 = 3;
+const SUMMARY_WEIGHT: u32 = 2;
+const DESCRIPTION_WEIGHT: u32 = 1;
+
+// How closely a term matched a field
+const EXACT_MATCH: u32 = 100;
+const PREFIX_MATCH: u32 = 60;
+const SUBSTRING_MATCH: u32 = 40;
+const FUZZY_MATCH: u32 = 10;
+
+/// An endpoint matching a search, best matches scoring highest
+#[derive(Debug, Serialize)]
+pub struct EndpointMatch {
+    #[serde(flatten)]
+    pub endpoint: EndpointSummary,
+    pub score: u32,
+}
+
+/// Endpoints matching every whitespace-separated term of `query`, ranked by
+/// score. Terms match case-insensitively against the operationId, path,
+/// summary and description; operationIds and paths also match fuzzily, so
+/// `gtusr` finds `getUser`.
+pub fn search_endpoints(descriptor: &SocketAgentDescriptor, query: &str) -> Vec<EndpointMatch> {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+
+    let mut scores: HashMap<String, u32> = HashMap::new();
+    for endpoint in &descriptor.endpoints {
+        if let Some(score) = score_endpoint(endpoint, &terms) {
+            let best = scores.entry(endpoint_key(endpoint)).or_default();
+            *best = (*best).max(score);
+        }
+    }
+
+    let mut matches: Vec<EndpointMatch> = list_endpoints(descriptor)
+        .into_iter()
+        .filter_map(|endpoint| {
+            let score = *scores.get(&endpoint.id)?;
+            Some(EndpointMatch { endpoint, score })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.endpoint.path.cmp(&b.endpoint.path))
+    });
+    matches
+}
+
+/// Sum of each term's best field score, or `None` when a term matches nothing
+fn score_endpoint(endpoint: &Endpoint, terms: &[String]) -> Option<u32> {
+    let fields = [
+        (endpoint.operation_id.as_deref(), OPERATION_ID_WEIGHT, true),
+        (Some(endpoint.path.as_str()), PATH_WEIGHT, true),
+        (endpoint.summary.as_deref(), SUMMARY_WEIGHT, false),
+        (endpoint.description.as_deref(), DESCRIPTION_WEIGHT, false),
+    ];
+    let fields: Vec<(String, u32, bool)> = fields
+        .into_iter()
+        .filter_map(|(text, weight, fuzzy)| Some((text?.to_lowercase(), weight, fuzzy)))
+        .collect();
+
+    terms.iter().try_fold(0, |total, term| {
+        let best = fields
+            .iter()
+            .filter_map(|(text, weight, fuzzy)| Some(match_quality(text, term, *fuzzy)? * weight))
+            .max()?;
+        Some(total + best)
+    })
+}
+
+fn match_quality(text: &str, term: &str, fuzzy: bool) -> Option<u32> {
+    if text == term {
+        Some(EXACT_MATCH)
+    } else if text.starts_with(term) {
+        Some(PREFIX_MATCH)
+    } else if text.contains(term) {
+        Some(SUBSTRING_MATCH)
+    } else if fuzzy && is_subsequence(term, text) {
+        Some(FUZZY_MATCH)
+    } else {
+        None
+    }
+}
+
+// Every character of `term` appears in `text`, in order
+fn is_subsequence(term: &str, text: &str) -> bool {
+    let mut chars = text.chars();
+    term.chars().all(|c| chars.any(|t| t == c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn descriptor(endpoints: serde_json::Value) -> SocketAgentDescriptor {
+        serde_json::from_value(json!({ "name": "Test API", "endpoints": endpoints })).unwrap()
+    }
+
+    #[test]
+    fn exact_operation_ids_outrank_description_matches() {
+        let descriptor = descriptor(json!([
+            { "path": "/accounts", "method": "GET", "description": "Lists accounts and their users" },
+            { "path": "/people", "method": "GET", "operationId": "users" },
+            { "path": "/orders", "method": "GET", "summary": "Orders" },
+        ]));
+
+        let matches = search_endpoints(&descriptor, "USERS");
+
+        let paths: Vec<&str> = matches.iter().map(|m| m.endpoint.path.as_str()).collect();
+        assert_eq!(paths, ["/people", "/accounts"]);
+        assert!(matches[0].score > matches[1].score);
+    }
+
+    #[test]
+    fn partial_terms_match_identifiers_fuzzily() {
+        let descriptor = descriptor(json!([
+            { "path": "/users/{id}", "method": "GET", "operationId": "getUser" },
+            { "path": "/orders", "method": "GET", "description": "gets user orders" },
+        ]));
+
+        let matches = search_endpoints(&descriptor, "gtusr");
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].endpoint.path, "/users/{id}");
+    }
+
+    #[test]
+    fn every_term_has_to_match() {
+        let descriptor = descriptor(json!([
+            { "path": "/users", "method": "GET", "summary": "List users" },
+            { "path": "/users", "method": "POST", "summary": "Create a user" },
+        ]));
+
+        let matches = search_endpoints(&descriptor, "create users");
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].endpoint.method, "POST");
+        assert!(search_endpoints(&descriptor, "   ").is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+// Server-sent event streams
+// Endpoints that push live updates (prices, notifications) as
+// `text/event-stream`; dropped connections resume from the last event id
+
+use reqwest::header::{HeaderValue, ACCEPT, CACHE_CONTROL, CONTENT_TYPE};
+use reqwest::{Response, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::max_response_bytes;
+
+use super::client::{build_api_request, read_body_limited, record_outcome, CallContext};
+use super::error::ApiError;
+
+/// Wait before reconnecting when the server hasn't sent a `retry` field
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+/// Servers send comments as keep-alives; a stream silent for this long is
+/// treated as dropped and reconnected
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+/// Connections in a row that deliver no events before giving up
+const MAX_RECONNECTS: u32 = 5;
+
+/// One event from an SSE stream
+#[derive(Debug, Clone, Serialize)]
+pub struct SseEvent {
+    /// `message` unless the server named the event
+    pub event: String,
+    pub data: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+/// Incremental parser for the `text/event-stream` format
+#[derive(Default)]
+struct SseParser {
+    line: Vec<u8>,
+    event: Option<String>,
+    data: String,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl SseParser {
+    /// Events completed by `chunk`; a partial event waits for the next chunk
+    fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        for &byte in chunk {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let mut line = std::mem::take(&mut self.line);
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            if let Some(event) = self.process_line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        // Comment, usually a keep-alive
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(ms) = value.parse() {
+                    self.retry = Some(Duration::from_millis(ms));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        Some(SseEvent {
+            event: event.unwrap_or_else(|| "message".to_string()),
+            data,
+            id: self.last_event_id.clone().filter(|id| !id.is_empty()),
+        })
+    }
+
+    /// Bytes of the event being assembled
+    fn pending_bytes(&self) -> usize {
+        self.line.len() + self.data.len()
+    }
+
+    /// Drop a half-received event before reconnecting; the last event id
+    /// and retry interval carry over
+    fn discard_pending(&mut self) {
+        self.line.clear();
+        self.event = None;
+        self.data.clear();
+    }
+}
+
+/// Open an endpoint's event stream and pass each event to `on_event` until
+/// the server ends it with a 204 or it fails for good. Dropped connections
+/// are reopened with `Last-Event-ID` after the server's `retry` interval.
+/// The request is resolved and authenticated like any other call.
+pub async fn stream_sse(
+    base_url: &str,
+    endpoint_id: &str,
+    params: HashMap<String, Value>,
+    context: &CallContext,
+    mut on_event: impl FnMut(SseEvent),
+) -> Result<(), ApiError> {
+    let mut parser = SseParser::default();
+    let mut failures = 0;
+
+    loop {
+        let (events, result) = read_stream(
+            base_url,
+            endpoint_id,
+            params.clone(),
+            context,
+            &mut parser,
+            &mut on_event,
+        )
+        .await;
+        if events > 0 {
+            failures = 0;
+        } else {
+            failures += 1;
+        }
+
+        let error = match result {
+            Ok(false) => return Ok(()),
+            Ok(true) => ApiError::Network {
+                message: "Event stream closed by the server".to_string(),
+            },
+            Err(e) if !e.is_retriable() => return Err(e),
+            Err(e) => e,
+        };
+        if failures > MAX_RECONNECTS {
+            return Err(error);
+        }
+
+        let retry = parser.retry.unwrap_or(DEFAULT_RETRY);
+        println!(
+            "Event stream {} dropped ({}); reconnecting in {:?}",
+            endpoint_id, error, retry
+        );
+        parser.discard_pending();
+        tokio::time::sleep(retry).await;
+    }
+}
+
+/// Read one connection's events. Returns how many were delivered, and
+/// whether to reconnect: `Ok(false)` when the server said to stop.
+async fn read_stream(
+    base_url: &str,
+    endpoint_id: &str,
+    params: HashMap<String, Value>,
+    context: &CallContext,
+    parser: &mut SseParser,
+    on_event: &mut impl FnMut(SseEvent),
+) -> (usize, Result<bool, ApiError>) {
+    let mut events = 0;
+    let result = async {
+        let last_event_id = parser.last_event_id.clone();
+        let mut response = connect(
+            base_url,
+            endpoint_id,
+            params,
+            context,
+            last_event_id.as_deref(),
+        )
+        .await?;
+        if response.status() == StatusCode::NO_CONTENT {
+            return Ok(false);
+        }
+
+        loop {
+            let chunk = tokio::time::timeout(IDLE_TIMEOUT, response.chunk())
+                .await
+                .map_err(|_| ApiError::Timeout {
+                    message: "Event stream went silent".to_string(),
+                })??;
+            let Some(chunk) = chunk else {
+                return Ok(true);
+            };
+
+            for event in parser.feed(&chunk) {
+                events += 1;
+                on_event(event);
+            }
+            let limit = max_response_bytes();
+            if parser.pending_bytes() > limit {
+                return Err(ApiError::TooLarge { limit });
+            }
+        }
+    }
+    .await;
+
+    (events, result)
+}
+
+async fn connect(
+    base_url: &str,
+    endpoint_id: &str,
+    params: HashMap<String, Value>,
+    context: &CallContext,
+    last_event_id: Option<&str>,
+) -> Result<Response, ApiError> {
+    let (client, request) = build_api_request(base_url, endpoint_id, params, None, context)
+        .await?
+        .build_split();
+    let mut request = request?;
+
+    let headers = request.headers_mut();
+    headers.insert(ACCEPT, HeaderValue::from_static("text/event-stream"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if let Some(id) = last_event_id.filter(|id| !id.is_empty()) {
+        if let Ok(value) = HeaderValue::from_str(id) {
+            headers.insert("Last-Event-ID", value);
+        }
+    }
+    // The stream stays open indefinitely, so only waiting for the response
+    // headers is bounded by the call timeout
+    *request.timeout_mut() = None;
+
+    let result: Result<Response, ApiError> = async {
+        let response = tokio::time::timeout(context.timeout, client.execute(request))
+            .await
+            .map_err(|_| ApiError::Timeout {
+                message: "No response from the event stream".to_string(),
+            })??;
+
+        let status = response.status();
+        println!("SSE response: {}", status);
+
+        if !status.is_success() {
+            let body = read_body_limited(response, max_response_bytes()).await?;
+            let error_text = String::from_utf8_lossy(&body).into_owned();
+            return Err(ApiError::from_status(status.as_u16(), error_text));
+        }
+
+        let is_event_stream = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.to_ascii_lowercase().starts_with("text/event-stream"));
+        if status != StatusCode::NO_CONTENT && !is_event_stream {
+            return Err(ApiError::Parse {
+                message: "Endpoint did not return an event stream".to_string(),
+            });
+        }
+        Ok(response)
+    }
+    .await;
+
+    record_outcome(base_url, endpoint_id, context, &result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_split_on_blank_lines() {
+        let mut parser = SseParser::default();
+
+        let events =
+            parser.feed(b": keep-alive\nevent: price\ndata: {\"a\":1}\nid: 7\n\ndata: hi\n\n");
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "price");
+        assert_eq!(events[0].data, "{\"a\":1}");
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[1].event, "message");
+        assert_eq!(events[1].data, "hi");
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+    }
+
+    #[test]
+    fn events_can_span_chunks_and_lines() {
+        let mut parser = SseParser::default();
+
+        assert!(parser.feed(b"data: first\r\nda").is_empty());
+        assert!(parser.feed(b"ta:second\r\n").is_empty());
+        let events = parser.feed(b"\r\n");
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "first\nsecond");
+        assert_eq!(events[0].id, None);
+    }
+
+    #[test]
+    fn retry_and_event_id_are_remembered() {
+        let mut parser = SseParser::default();
+
+        parser.feed(b"retry: 1500\nid: 42\n\nretry: soon\nevent: ping\n\n");
+
+        assert_eq!(parser.retry, Some(Duration::from_millis(1500)));
+        assert_eq!(parser.last_event_id.as_deref(), Some("42"));
+        // An event with no data isn't dispatched and its name doesn't leak
+        // into the next one
+        let events = parser.feed(b"data: x\n\n");
+        assert_eq!(events[0].event, "message");
+    }
+
+    #[test]
+    fn a_pending_event_is_dropped_on_reconnect() {
+        let mut parser = SseParser::default();
+        parser.feed(b"id: 3\n\nevent: update\ndata: half");
+        assert!(parser.pending_bytes() > 0);
+
+        parser.discard_pending();
+        let events = parser.feed(b"data: whole\n\n");
+
+        assert_eq!(parser.pending_bytes(), 0);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "message");
+        assert_eq!(events[0].data, "whole");
+        assert_eq!(events[0].id.as_deref(), Some("3"));
+    }
+}
@@ -2,11 +2,62 @@
 // Handles user registration, login, token management
 
 use anyhow::{anyhow, Context, Result};
-use reqwest::Client;
+use reqwest::header::DATE;
+use reqwest::{Client, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{auth_retries, client_builder};
+
+mod sessions;
+
+pub use sessions::{
+    forget_session, list_sessions, prune_expired_sessions, record_session, refresh_session,
+    reseal_sessions, take_all_sessions, take_session, SessionInfo,
+};
+
+// Delay before the first retry; doubles for each one after
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+// Clocks further apart than this make a rejected token look like a clock problem
+const CLOCK_SKEW_TOLERANCE_SECS: i64 = 60;
+
+// Auth service clock minus ours, in seconds, from its `Date` headers
+static CLOCK_SKEW_SECS: AtomicI64 = AtomicI64::new(0);
+
+/// The auth service rejected a token while this device's clock is well off
+/// the server's, so expiry times worked out here can't be trusted
+#[derive(Debug)]
+pub struct ClockSkewDetected {
+    /// Server time minus local time
+    pub skew_secs: i64,
+}
+
+impl ClockSkewDetected {
+    /// Error `code` reported to the frontend
+    pub const CODE: &'static str = "clock_skew";
+}
+
+impl fmt::Display for ClockSkewDetected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.skew_secs > 0 {
+            "behind"
+        } else {
+            "ahead of"
+        };
+        write!(
+            f,
+            "Clock skew detected: this device's clock is {}s {} the server's. \
+             Correct the system time and sign in again.",
+            self.skew_secs.unsigned_abs(),
+            direction
+        )
+    }
+}
 
-const ID_SERVICE_URL: &str = "https://socketagent.io";
+impl std::error::Error for ClockSkewDetected {}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegisterRequest {
@@ -57,48 +108,75 @@ pub struct UserInfo {
     pub created_at: String,
 }
 
-#[derive(Serialize)]
-pub struct AuthResponse {
-    pub success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub user_id: Option<u64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub access_token: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub refresh_token: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub expires_in: Option<u64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub user: Option<UserInfo>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
-}
-
 pub struct AuthClient {
-    client: Client,
+    client: RwLock<Client>,
     base_url: String,
 }
 
 impl AuthClient {
-    pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to create HTTP client");
-
-        Self {
-            client,
-            base_url: ID_SERVICE_URL.to_string(),
-        }
+    pub fn with_url(base_url: String) -> Result<Self> {
+        let client = client_builder()?.timeout(Duration::from_secs(10)).build()?;
+
+        Ok(Self {
+            client: RwLock::new(client),
+            base_url,
+        })
     }
 
-    pub fn with_url(base_url: String) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to create HTTP client");
+    /// Replace the HTTP client, e.g. after the timeout setting changes
+    pub fn rebuild_client(&self, timeout: Duration) -> Result<()> {
+        let client = client_builder()?.timeout(timeout).build()?;
+        *self.client.write().unwrap() = client;
+        Ok(())
+    }
+
+    fn http(&self) -> Client {
+        self.client.read().unwrap().clone()
+    }
 
-        Self { client, base_url }
+    /// Send the request built by `build`, retrying with backoff on network
+    /// errors and 5xx responses. Other statuses (401, 409, ...) are returned
+    /// as-is for the caller to interpret.
+    async fn send_with_retry<F>(&self, operation: &str, build: F) -> Result<Response>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let retries = auth_retries();
+        let mut attempt = 0;
+
+        loop {
+            let result = build(&self.http()).send().await;
+            let failure = match &result {
+                Ok(response) if response.status().is_server_error() => {
+                    Some(response.status().to_string())
+                }
+                Ok(_) => None,
+                Err(e) if e.is_builder() => None,
+                Err(e) => Some(e.to_string()),
+            };
+
+            match failure {
+                Some(reason) if attempt < retries => {
+                    attempt += 1;
+                    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+                    println!(
+                        "{} failed ({}), retrying in {}ms ({}/{})",
+                        operation,
+                        reason,
+                        delay.as_millis(),
+                        attempt,
+                        retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                _ => {
+                    if let Ok(response) = &result {
+                        record_clock_skew(response);
+                    }
+                    return result.context("Failed to connect to authentication service");
+                }
+            }
+        }
     }
 
     /// Register a new user
@@ -114,13 +192,12 @@ impl AuthClient {
         println!("Registering user at: {}", url);
 
         let response = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to connect to authentication service")?;
+            .send_with_retry("Registration", |http| {
+                http.post(&url)
+                    .header("Content-Type", "application/json")
+                    .json(&request)
+            })
+            .await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -152,13 +229,12 @@ impl AuthClient {
         println!("Logging in at: {}", url);
 
         let response = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to connect to authentication service")?;
+            .send_with_retry("Login", |http| {
+                http.post(&url)
+                    .header("Content-Type", "application/json")
+                    .json(&request)
+            })
+            .await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -188,18 +264,18 @@ impl AuthClient {
         println!("Getting user info from: {}", url);
 
         let response = self
-            .client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", access_token))
-            .send()
-            .await
-            .context("Failed to connect to authentication service")?;
+            .send_with_retry("User info", |http| {
+                http.get(&url)
+                    .header("Authorization", format!("Bearer {}", access_token))
+            })
+            .await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
 
             if status.as_u16() == 401 {
+                check_clock_skew()?;
                 return Err(anyhow!("Invalid or expired access token"));
             }
 
@@ -225,19 +301,19 @@ impl AuthClient {
         println!("Refreshing access token at: {}", url);
 
         let response = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to connect to authentication service")?;
+            .send_with_retry("Token refresh", |http| {
+                http.post(&url)
+                    .header("Content-Type", "application/json")
+                    .json(&request)
+            })
+            .await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
 
             if status.as_u16() == 401 {
+                check_clock_skew()?;
                 return Err(anyhow!("Invalid or expired refresh token"));
             }
 
@@ -263,7 +339,7 @@ impl AuthClient {
         println!("Logging out at: {}", url);
 
         let response = self
-            .client
+            .http()
             .post(&url)
             .header("Content-Type", "application/json")
             .json(&request)
@@ -286,4 +362,255 @@ impl AuthClient {
 
         Ok(())
     }
+
+    /// Revoke every refresh token of the signed-in user. Returns `false`
+    /// when the service doesn't support it, so sessions must be signed out
+    /// one at a time.
+    pub async fn logout_all(&self, access_token: &str) -> Result<bool> {
+        let url = format!("{}/v1/auth/logout-all", self.base_url);
+
+        println!("Logging out all sessions at: {}", url);
+
+        let response = self
+            .http()
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .context("Failed to connect to authentication service")?;
+
+        let status = response.status();
+        if matches!(status.as_u16(), 404 | 405 | 501) {
+            println!("Auth service doesn't support logging out all sessions");
+            return Ok(false);
+        }
+
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+
+            if status.as_u16() == 401 {
+                check_clock_skew()?;
+                return Err(anyhow!("Invalid or expired access token"));
+            }
+
+            return Err(anyhow!("Logout failed ({}): {}", status.as_u16(), error_text));
+        }
+
+        let result: LogoutResponse = response
+            .json()
+            .await
+            .context("Failed to parse logout response")?;
+
+        println!("Logged out all sessions: {}", result.status);
+
+        Ok(true)
+    }
+
+    /// Health check for authentication service
+    pub async fn health_check(&self) -> Result<bool> {
+        let url = format!("{}/health", self.base_url);
+
+        let response = self
+            .http()
+            .get(&url)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .context("Failed to connect to authentication service")?;
+
+        Ok(response.status().is_success())
+    }
+}
+
+/// Seconds the auth service's clock is ahead of ours (negative when behind),
+/// as of its last response
+pub fn clock_skew_secs() -> i64 {
+    CLOCK_SKEW_SECS.load(Ordering::Relaxed)
+}
+
+/// Current Unix time by the auth service's clock, for expiry times that
+/// have to hold up when the local clock is wrong
+pub fn server_now() -> u64 {
+    (local_now() + clock_skew_secs()).max(0) as u64
+}
+
+fn local_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Note how far the server's clock is from ours, from the `Date` header
+fn record_clock_skew(response: &Response) {
+    let Some(server) = response
+        .headers()
+        .get(DATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+    else {
+        return;
+    };
+
+    let skew = server.as_secs() as i64 - local_now();
+    let previous = CLOCK_SKEW_SECS.swap(skew, Ordering::Relaxed);
+    // Only log when it first goes off, not on every response
+    let changed = (skew - previous).abs() > CLOCK_SKEW_TOLERANCE_SECS;
+    if changed && skew.abs() > CLOCK_SKEW_TOLERANCE_SECS {
+        println!("Local clock is {}s off the auth service's", skew);
+    }
+}
+
+/// Called when a token is rejected; blames the clock if it's far enough off
+fn check_clock_skew() -> Result<()> {
+    let skew_secs = clock_skew_secs();
+    if skew_secs.abs() > CLOCK_SKEW_TOLERANCE_SECS {
+        return Err(ClockSkewDetected { skew_secs }.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const LOGIN_BODY: &str = r#"{"access_token":"access","refresh_token":"refresh","expires_in":3600,"token_type":"Bearer"}"#;
+
+    /// Serve `responses` to one connection each, in order, returning the URL
+    /// and the request line of every request received
+    async fn serve_each(responses: Vec<(u16, &'static str)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let responses = responses
+            .into_iter()
+            .map(|(status, body)| (status, String::new(), body))
+            .collect();
+        serve_each_with_headers(responses).await
+    }
+
+    /// Like `serve_each`, with extra header lines per response
+    async fn serve_each_with_headers(
+        responses: Vec<(u16, String, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for (status, headers, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut socket).await;
+                let line = request.lines().next().unwrap_or_default().to_string();
+                received.lock().unwrap().push(line);
+                let head = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+                    status,
+                    body.len(),
+                    headers
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(body.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    /// Read a request's headers and the body they announce
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                    .and_then(|(_, value)| value.trim().parse().ok())
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    return text;
+                }
+            }
+            match socket.read(&mut buf).await {
+                Ok(read) if read > 0 => request.extend_from_slice(&buf[..read]),
+                _ => return text,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_are_retried_after_a_503() {
+        let (base_url, requests) = serve_each(vec![(503, "busy"), (200, LOGIN_BODY)]).await;
+        let client = AuthClient::with_url(base_url).unwrap();
+
+        let login = client
+            .login("alice".to_string(), "pw".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(login.access_token, "access");
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["POST /v1/auth/login HTTP/1.1"; 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_tokens_are_blamed_on_a_skewed_clock() {
+        let server_time = SystemTime::now() + Duration::from_secs(3600);
+        let date = format!("Date: {}\r\n", httpdate::fmt_http_date(server_time));
+        let (base_url, _) =
+            serve_each_with_headers(vec![(401, date, r#"{"detail":"expired"}"#)]).await;
+        let client = AuthClient::with_url(base_url).unwrap();
+
+        let error = client.get_user("access").await.unwrap_err();
+
+        let skew = error.downcast_ref::<ClockSkewDetected>().unwrap().skew_secs;
+        assert!((3595..=3605).contains(&skew), "skew was {}", skew);
+        assert!(error.to_string().contains("behind the server's"));
+        // Expiry times are worked out by the server's clock from now on
+        let ahead = server_now() as i64 - local_now();
+        assert!((3595..=3605).contains(&ahead), "{}", ahead);
+
+        CLOCK_SKEW_SECS.store(0, Ordering::Relaxed);
+    }
+
+    #[tokio::test]
+    async fn logging_out_everywhere_clears_every_stored_session() {
+        let (base_url, requests) = serve_each(vec![(200, r#"{"status":"ok"}"#)]).await;
+        let client = AuthClient::with_url(base_url).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let storage = crate::storage::Storage::open(dir.path().join("storage.json")).unwrap();
+        for refresh_token in ["refresh-laptop", "refresh-phone"] {
+            let login = LoginResponse {
+                refresh_token: refresh_token.to_string(),
+                ..serde_json::from_str(LOGIN_BODY).unwrap()
+            };
+            record_session(&storage, "alice", &login).unwrap();
+        }
+
+        // As the logout-all command does once the service has revoked them
+        assert!(client.logout_all("access").await.unwrap());
+        let mut taken = take_all_sessions(&storage).unwrap();
+
+        taken.sort();
+        assert_eq!(taken, ["refresh-laptop", "refresh-phone"]);
+        assert!(list_sessions(&storage).is_empty());
+        assert!(take_all_sessions(&storage).unwrap().is_empty());
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["POST /v1/auth/logout-all HTTP/1.1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn logging_out_everywhere_reports_when_it_is_unsupported() {
+        let (base_url, _) = serve_each(vec![(404, r#"{"detail":"Not Found"}"#)]).await;
+        let client = AuthClient::with_url(base_url).unwrap();
+
+        assert!(!client.logout_all("access").await.unwrap());
+    }
 }
@@ -0,0 +1,297 @@
+// Stored sessions
+// Records each sign-in so users can see and revoke them; refresh tokens
+// stay in the backend, encrypted at rest, and only metadata is handed to
+// the frontend
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{server_now, LoginResponse};
+use crate::storage::{KeyRotation, Storage};
+
+const SESSIONS_STORAGE_KEY: &str = "auth_sessions";
+const SESSIONS_KEY_STORAGE_KEY: &str = "auth_sessions_key";
+
+/// A recorded sign-in. The refresh token is encrypted with the sessions key
+/// when saved.
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredSession {
+    id: String,
+    user: String,
+    issued_at: u64,
+    expires_at: u64,
+    refresh_token: String,
+}
+
+/// What the frontend sees of a stored session. Times are Unix seconds by the
+/// auth service's clock, so a wrong local clock doesn't throw them off;
+/// `expires_at` moves forward each time the session is refreshed.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub user: String,
+    pub issued_at: u64,
+    pub expires_at: u64,
+}
+
+/// Record a new session after `user` signs in
+pub fn record_session(storage: &Storage, user: &str, login: &LoginResponse) -> Result<()> {
+    let issued_at = now();
+    let mut sessions = load_sessions(storage);
+    sessions.push(StoredSession {
+        id: hex::encode(rand::random::<[u8; 16]>()),
+        user: user.to_string(),
+        issued_at,
+        expires_at: issued_at + login.expires_in,
+        refresh_token: login.refresh_token.clone(),
+    });
+    save_sessions(storage, &sessions)
+}
+
+/// Swap in the tokens from a refresh. Sessions signed in before they were
+/// being recorded aren't found and are left alone.
+pub fn refresh_session(
+    storage: &Storage,
+    old_refresh_token: &str,
+    login: &LoginResponse,
+) -> Result<()> {
+    let mut sessions = load_sessions(storage);
+    let Some(session) = sessions
+        .iter_mut()
+        .find(|session| session.refresh_token == old_refresh_token)
+    else {
+        return Ok(());
+    };
+    session.expires_at = now() + login.expires_in;
+    session.refresh_token = login.refresh_token.clone();
+    save_sessions(storage, &sessions)
+}
+
+/// Stored sessions, oldest first
+pub fn list_sessions(storage: &Storage) -> Vec<SessionInfo> {
+    load_sessions(storage)
+        .into_iter()
+        .map(|session| SessionInfo {
+            id: session.id,
+            user: session.user,
+            issued_at: session.issued_at,
+            expires_at: session.expires_at,
+        })
+        .collect()
+}
+
+/// Remove the session `id`, returning its refresh token so it can be revoked
+pub fn take_session(storage: &Storage, id: &str) -> Result<Option<String>> {
+    let mut sessions = load_sessions(storage);
+    let Some(index) = sessions.iter().position(|session| session.id == id) else {
+        return Ok(None);
+    };
+    let session = sessions.remove(index);
+    save_sessions(storage, &sessions)?;
+    Ok(Some(session.refresh_token))
+}
+
+/// Remove every session, returning their refresh tokens
+pub fn take_all_sessions(storage: &Storage) -> Result<Vec<String>> {
+    let sessions = load_sessions(storage);
+    if sessions.is_empty() {
+        return Ok(Vec::new());
+    }
+    save_sessions(storage, &[])?;
+    Ok(sessions
+        .into_iter()
+        .map(|session| session.refresh_token)
+        .collect())
+}
+
+/// Remove the session holding `refresh_token`, e.g. after signing out
+pub fn forget_session(storage: &Storage, refresh_token: &str) -> Result<()> {
+    let mut sessions = load_sessions(storage);
+    let before = sessions.len();
+    sessions.retain(|session| session.refresh_token != refresh_token);
+    if sessions.len() == before {
+        return Ok(());
+    }
+    save_sessions(storage, &sessions)
+}
+
+/// Drop sessions past their expiry, returning how many were removed
+pub fn prune_expired_sessions(storage: &Storage) -> Result<usize> {
+    let now = now();
+    let mut sessions = load_sessions(storage);
+    let before = sessions.len();
+    sessions.retain(|session| session.expires_at > now);
+    let pruned = before - sessions.len();
+    if pruned > 0 {
+        save_sessions(storage, &sessions)?;
+    }
+    Ok(pruned)
+}
+
+/// Stored sessions with their refresh tokens decrypted. Tokens saved before
+/// they were encrypted are read as they are and encrypted on the next save.
+fn load_sessions(storage: &Storage) -> Vec<StoredSession> {
+    let sessions: Vec<StoredSession> = storage
+        .get(SESSIONS_STORAGE_KEY)
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default();
+
+    sessions
+        .into_iter()
+        .map(|mut session| {
+            if let Some(token) = storage
+                .unseal(SESSIONS_KEY_STORAGE_KEY, &session.refresh_token)
+                .ok()
+                .and_then(|token| String::from_utf8(token).ok())
+            {
+                session.refresh_token = token;
+            }
+            session
+        })
+        .collect()
+}
+
+fn save_sessions(storage: &Storage, sessions: &[StoredSession]) -> Result<()> {
+    let sealed = sessions
+        .iter()
+        .map(|session| {
+            let token = storage.seal(SESSIONS_KEY_STORAGE_KEY, session.refresh_token.as_bytes())?;
+            Ok(StoredSession {
+                refresh_token: token,
+                ..session.clone()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    storage.set(
+        SESSIONS_STORAGE_KEY.to_string(),
+        serde_json::to_value(sealed)?,
+    )
+}
+
+/// Entries that move the stored refresh tokens to a fresh local key, the
+/// key included, for saving with the other sealed stores. Tokens saved
+/// before they were encrypted are encrypted now.
+pub fn reseal_sessions(storage: &Storage) -> Result<Vec<(String, Value)>> {
+    let rotation = KeyRotation::new(storage, SESSIONS_KEY_STORAGE_KEY)?;
+    let mut entries = vec![rotation.key_entry()];
+    let Some(stored) = storage.get(SESSIONS_STORAGE_KEY)? else {
+        return Ok(entries);
+    };
+
+    let sessions: Vec<StoredSession> = serde_json::from_value(stored).unwrap_or_default();
+    let resealed = sessions
+        .into_iter()
+        .map(|session| {
+            let token = rotation
+                .unseal(&session.refresh_token)
+                .unwrap_or_else(|_| session.refresh_token.clone().into_bytes());
+            Ok(StoredSession {
+                refresh_token: rotation.seal(&token)?,
+                ..session
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    entries.push((
+        SESSIONS_STORAGE_KEY.to_string(),
+        serde_json::to_value(resealed)?,
+    ));
+    Ok(entries)
+}
+
+fn now() -> u64 {
+    server_now()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login(refresh_token: &str) -> LoginResponse {
+        LoginResponse {
+            access_token: "access".to_string(),
+            refresh_token: refresh_token.to_string(),
+            expires_in: 3600,
+            token_type: "Bearer".to_string(),
+        }
+    }
+
+    fn stored_json(storage: &Storage) -> String {
+        storage
+            .get(SESSIONS_STORAGE_KEY)
+            .unwrap()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn refresh_tokens_are_encrypted_at_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+
+        record_session(&storage, "alice", &login("refresh-one")).unwrap();
+        refresh_session(&storage, "refresh-one", &login("refresh-two")).unwrap();
+
+        assert!(!stored_json(&storage).contains("refresh-"));
+        let file = std::fs::read_to_string(dir.path().join("storage.json")).unwrap();
+        assert!(!file.contains("refresh-"));
+        assert_eq!(load_sessions(&storage)[0].refresh_token, "refresh-two");
+    }
+
+    #[test]
+    fn revoking_removes_the_stored_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        record_session(&storage, "alice", &login("refresh-one")).unwrap();
+        record_session(&storage, "alice", &login("refresh-two")).unwrap();
+        let id = list_sessions(&storage)[0].id.clone();
+
+        let token = take_session(&storage, &id).unwrap();
+
+        assert_eq!(token.as_deref(), Some("refresh-one"));
+        let remaining = list_sessions(&storage);
+        assert_eq!(remaining.len(), 1);
+        assert_ne!(remaining[0].id, id);
+        assert!(!stored_json(&storage).contains(&id));
+        assert_eq!(take_session(&storage, &id).unwrap(), None);
+    }
+
+    #[test]
+    fn reads_tokens_saved_before_encryption() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let legacy = serde_json::json!([{
+            "id": "legacy",
+            "user": "alice",
+            "issued_at": 1,
+            "expires_at": u64::MAX,
+            "refresh_token": "plain-token",
+        }]);
+        storage
+            .set(SESSIONS_STORAGE_KEY.to_string(), legacy)
+            .unwrap();
+
+        forget_session(&storage, "plain-token").unwrap();
+
+        assert!(list_sessions(&storage).is_empty());
+    }
+
+    #[test]
+    fn refresh_tokens_survive_a_key_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        record_session(&storage, "alice", &login("refresh-one")).unwrap();
+        let old_key = storage.get(SESSIONS_KEY_STORAGE_KEY).unwrap();
+        let old_json = stored_json(&storage);
+
+        let entries = reseal_sessions(&storage).unwrap();
+        storage.set_many(entries).unwrap();
+
+        assert_ne!(storage.get(SESSIONS_KEY_STORAGE_KEY).unwrap(), old_key);
+        assert_ne!(stored_json(&storage), old_json);
+        assert!(!stored_json(&storage).contains("refresh-"));
+        assert_eq!(load_sessions(&storage)[0].refresh_token, "refresh-one");
+    }
+}
@@ -0,0 +1,347 @@
+// config.json in the app data dir
+// Service URLs, timeouts, network limits, proxy, auto-lock and logging read
+// once at startup; missing fields fall back to their defaults, so a partial
+// file is fine
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{
+    take_stored, LogLevel, ProxyConfig, Timeouts, AUTH_RETRIES_STORAGE_KEY, DEFAULT_AUTH_RETRIES,
+    DEFAULT_HOST_INTERVAL_MS, DEFAULT_MAX_RESPONSE_BYTES, HOST_INTERVAL_STORAGE_KEY,
+    MAX_RESPONSE_STORAGE_KEY, PROXY_KEY_STORAGE_KEY,
+};
+use crate::storage::{KeyRotation, Storage};
+
+pub const CONFIG_FILE_NAME: &str = "config.json";
+pub const DEFAULT_AUTH_URL: &str = "https://socketagent.io";
+pub const DEFAULT_RENDER_URL: &str = "http://localhost:8000";
+pub const DEFAULT_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+// An invalid config.json is copied here before settings overwrite it
+const BACKUP_EXTENSION: &str = "json.bak";
+const MAX_AUTO_LOCK_MINS: u64 = 24 * 60;
+
+/// App settings kept in `config.json`. The service URLs are read at startup
+/// and the environment variables still override them; the rest apply as
+/// soon as they're saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub auth_url: String,
+    pub render_url: String,
+    pub rpc_url: String,
+    pub timeouts: Timeouts,
+    pub log_level: LogLevel,
+    /// Turns on `raw_http_call`. Off unless set in the file itself, since
+    /// anything running in the webview could otherwise reach any URL.
+    pub allow_raw_http: bool,
+    /// Minutes a saved wallet stays unlocked before it locks itself; zero
+    /// leaves it unlocked until the user locks it
+    pub auto_lock_mins: u64,
+    /// Largest Socket Agent API response body that gets read
+    pub max_response_bytes: usize,
+    /// Retries after a transient failure talking to socketagent.id
+    pub auth_retries: u32,
+    /// Minimum gap between requests to the same API host; zero turns it off
+    pub host_interval_ms: u64,
+    /// Outbound proxy. Its password is encrypted with a key kept in app
+    /// storage, so it's never written to this file in the clear.
+    pub proxy: Option<ProxyConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            auth_url: DEFAULT_AUTH_URL.to_string(),
+            render_url: DEFAULT_RENDER_URL.to_string(),
+            rpc_url: DEFAULT_RPC_URL.to_string(),
+            timeouts: Timeouts::default(),
+            log_level: LogLevel::default(),
+            allow_raw_http: false,
+            auto_lock_mins: 0,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            auth_retries: DEFAULT_AUTH_RETRIES,
+            host_interval_ms: DEFAULT_HOST_INTERVAL_MS,
+            proxy: None,
+        }
+    }
+}
+
+impl Config {
+    /// Where the config lives inside `app_dir`
+    pub fn path(app_dir: &Path) -> PathBuf {
+        app_dir.join(CONFIG_FILE_NAME)
+    }
+
+    /// Where an invalid config is backed up before it's overwritten
+    pub fn backup_path(path: &Path) -> PathBuf {
+        path.with_extension(BACKUP_EXTENSION)
+    }
+
+    /// Read the config at `path`, writing the defaults there if it's missing.
+    /// A file that doesn't parse or validate is used as defaults, and left
+    /// alone until settings are next saved, which backs it up first.
+    pub fn load(path: &Path, storage: &Storage) -> Self {
+        if !path.exists() {
+            let config = Self::default();
+            if let Err(e) = config.save(path, storage) {
+                println!("Failed to write default config: {}", e);
+            }
+            return config;
+        }
+
+        let mut config = match Self::read(path) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Ignoring {}: {:#}", path.display(), e);
+                return Self::default();
+            }
+        };
+
+        let Some(sealed) = config
+            .proxy
+            .as_mut()
+            .and_then(|proxy| proxy.password.as_mut())
+        else {
+            return config;
+        };
+        match ProxyConfig::unseal_password(sealed, storage) {
+            Some(password) => *sealed = password,
+            // Typed into the file by hand, so encrypt it now
+            None => {
+                if let Err(e) = config.save(path, storage) {
+                    println!("Failed to encrypt the proxy password: {}", e);
+                }
+            }
+        }
+        config
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).context("Failed to read config")?;
+        let config: Self = serde_json::from_str(&contents).context("Invalid config")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Write the config with the proxy password encrypted. A file there that
+    /// doesn't parse or validate is copied to `backup_path` first, so edits
+    /// the user hasn't fixed yet aren't lost.
+    pub fn save(&self, path: &Path, storage: &Storage) -> Result<()> {
+        self.write(path, |password| {
+            storage.seal(PROXY_KEY_STORAGE_KEY, password)
+        })
+    }
+
+    /// `save` with the proxy password encrypted under a fresh key. Returns
+    /// the rotation whose `key_entry` must be saved to storage before the
+    /// password can be read back.
+    pub fn save_rotated(&self, path: &Path, storage: &Storage) -> Result<KeyRotation> {
+        let rotation = KeyRotation::new(storage, PROXY_KEY_STORAGE_KEY)?;
+        self.write(path, |password| rotation.seal(password))?;
+        Ok(rotation)
+    }
+
+    fn write(&self, path: &Path, seal: impl Fn(&[u8]) -> Result<String>) -> Result<()> {
+        let mut stored = self.clone();
+        if let Some(proxy) = stored.proxy.as_mut() {
+            proxy.seal_password(seal)?;
+        }
+        let contents = serde_json::to_string_pretty(&stored)?;
+
+        if path.exists() && Self::read(path).is_err() {
+            let backup = Self::backup_path(path);
+            fs::copy(path, &backup).context("Failed to back up the invalid config")?;
+            println!(
+                "Backed up invalid {} to {}",
+                path.display(),
+                backup.display()
+            );
+        }
+        fs::write(path, contents).context("Failed to write config")
+    }
+
+    /// Carry over settings saved to storage before they moved to config.json,
+    /// skipping any that don't validate. Returns whether any were carried over.
+    pub fn take_stored(&mut self, storage: &Storage) -> bool {
+        let mut found = false;
+        if let Some(timeouts) = Timeouts::take_stored(storage).filter(|t| t.validate().is_ok()) {
+            self.timeouts = timeouts;
+            found = true;
+        }
+        if let Some(proxy) = ProxyConfig::take_stored(storage).filter(|p| p.validate().is_ok()) {
+            self.proxy = Some(proxy);
+            found = true;
+        }
+        if let Some(bytes) = take_stored(storage, MAX_RESPONSE_STORAGE_KEY)
+            .filter(|bytes| super::validate_max_response_bytes(*bytes).is_ok())
+        {
+            self.max_response_bytes = bytes;
+            found = true;
+        }
+        if let Some(retries) = take_stored(storage, AUTH_RETRIES_STORAGE_KEY)
+            .filter(|retries| super::validate_auth_retries(*retries).is_ok())
+        {
+            self.auth_retries = retries;
+            found = true;
+        }
+        if let Some(ms) = take_stored(storage, HOST_INTERVAL_STORAGE_KEY)
+            .filter(|ms| super::validate_host_interval_ms(*ms).is_ok())
+        {
+            self.host_interval_ms = ms;
+            found = true;
+        }
+        found
+    }
+
+    /// Put the log level, proxy and network limits into effect for clients
+    /// built and requests sent from now on. Without a proxy here,
+    /// `SOCKETBROWSER_PROXY` is used.
+    pub fn apply(&self) {
+        super::set_log_level(self.log_level);
+        super::set_active_proxy(self.proxy.clone().or_else(ProxyConfig::from_env));
+        super::set_max_response_bytes(self.max_response_bytes);
+        super::set_auth_retries(self.auth_retries);
+        super::set_host_interval_ms(self.host_interval_ms);
+    }
+
+    /// Reject URLs that aren't http(s) and settings outside the sane range
+    pub fn validate(&self) -> Result<()> {
+        for (name, url) in [
+            ("auth", &self.auth_url),
+            ("render", &self.render_url),
+            ("RPC", &self.rpc_url),
+        ] {
+            let parsed = Url::parse(url).with_context(|| format!("Invalid {} URL", name))?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err(anyhow!("The {} URL must be http or https", name));
+            }
+        }
+        self.timeouts.validate()?;
+        super::validate_max_response_bytes(self.max_response_bytes)?;
+        super::validate_auth_retries(self.auth_retries)?;
+        super::validate_host_interval_ms(self.host_interval_ms)?;
+        if self.auto_lock_mins > MAX_AUTO_LOCK_MINS {
+            return Err(anyhow!(
+                "Auto-lock must be at most {} minutes",
+                MAX_AUTO_LOCK_MINS
+            ));
+        }
+        match &self.proxy {
+            Some(proxy) => proxy.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_and_storage() -> (PathBuf, Storage, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        (Config::path(dir.path()), storage, dir)
+    }
+
+    #[test]
+    fn partial_config_is_filled_in_with_defaults() {
+        let (path, storage, _dir) = config_and_storage();
+        let partial = r#"{"rpc_url": "https://rpc.example.com", "timeouts": {"api_secs": 30}}"#;
+        fs::write(&path, partial).unwrap();
+
+        let config = Config::load(&path, &storage);
+
+        assert_eq!(config.rpc_url, "https://rpc.example.com");
+        assert_eq!(config.auth_url, DEFAULT_AUTH_URL);
+        assert_eq!(config.timeouts.api_secs, 30);
+        assert_eq!(config.timeouts.auth_secs, Timeouts::default().auth_secs);
+        assert_eq!(config.max_response_bytes, DEFAULT_MAX_RESPONSE_BYTES);
+        assert_eq!(config.auto_lock_mins, 0);
+        assert_eq!(config.proxy, None);
+    }
+
+    #[test]
+    fn invalid_config_is_backed_up_before_it_is_overwritten() {
+        let (path, storage, _dir) = config_and_storage();
+        let invalid = r#"{"rpc_url": "ftp://rpc.example.com"}"#;
+        fs::write(&path, invalid).unwrap();
+
+        let config = Config::load(&path, &storage);
+        assert_eq!(config.rpc_url, DEFAULT_RPC_URL);
+        assert_eq!(fs::read_to_string(&path).unwrap(), invalid);
+
+        config.save(&path, &storage).unwrap();
+
+        let backup = fs::read_to_string(Config::backup_path(&path)).unwrap();
+        assert_eq!(backup, invalid);
+        assert!(Config::read(&path).is_ok());
+    }
+
+    #[test]
+    fn proxy_password_is_encrypted_in_the_file() {
+        let (path, storage, _dir) = config_and_storage();
+        let config = Config {
+            proxy: Some(ProxyConfig {
+                url: "http://proxy.example.com:8080".to_string(),
+                username: Some("alice".to_string()),
+                password: Some("proxy-secret".to_string()),
+            }),
+            ..Config::default()
+        };
+
+        config.save(&path, &storage).unwrap();
+
+        assert!(!fs::read_to_string(&path).unwrap().contains("proxy-secret"));
+        let loaded = Config::load(&path, &storage);
+        assert_eq!(loaded.proxy, config.proxy);
+    }
+
+    #[test]
+    fn proxy_password_survives_a_key_rotation() {
+        let (path, storage, _dir) = config_and_storage();
+        let config = Config {
+            proxy: Some(ProxyConfig {
+                url: "http://proxy.example.com:8080".to_string(),
+                username: Some("alice".to_string()),
+                password: Some("proxy-secret".to_string()),
+            }),
+            ..Config::default()
+        };
+        config.save(&path, &storage).unwrap();
+        let old_key = storage.get(PROXY_KEY_STORAGE_KEY).unwrap();
+
+        let rotation = config.save_rotated(&path, &storage).unwrap();
+        storage.set_many(vec![rotation.key_entry()]).unwrap();
+
+        assert_ne!(storage.get(PROXY_KEY_STORAGE_KEY).unwrap(), old_key);
+        assert!(!fs::read_to_string(&path).unwrap().contains("proxy-secret"));
+        let loaded = Config::load(&path, &storage);
+        assert_eq!(loaded.proxy, config.proxy);
+    }
+
+    #[test]
+    fn stored_settings_move_into_the_config() {
+        let (_path, storage, _dir) = config_and_storage();
+        for (key, value) in [
+            (MAX_RESPONSE_STORAGE_KEY, serde_json::json!(1024 * 1024)),
+            (AUTH_RETRIES_STORAGE_KEY, serde_json::json!(4)),
+            // Out of range, so the default is kept
+            (HOST_INTERVAL_STORAGE_KEY, serde_json::json!(60_000)),
+        ] {
+            storage.set(key.to_string(), value).unwrap();
+        }
+
+        let mut config = Config::default();
+        assert!(config.take_stored(&storage));
+
+        assert_eq!(config.max_response_bytes, 1024 * 1024);
+        assert_eq!(config.auth_retries, 4);
+        assert_eq!(config.host_interval_ms, DEFAULT_HOST_INTERVAL_MS);
+        assert!(storage.get(AUTH_RETRIES_STORAGE_KEY).unwrap().is_none());
+        assert!(!config.take_stored(&storage));
+    }
+}
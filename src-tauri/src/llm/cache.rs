@@ -0,0 +1,175 @@
+// Render cache
+// Generated HTML keyed by a hash of the descriptor and prompt, so revisiting
+// a site doesn't spend another render credit. Kept in its own file so the
+// cache churning never rewrites the wallet's storage file.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::api::discovery::SocketAgentDescriptor;
+use crate::storage::Storage;
+
+const RENDER_CACHE_FILE_NAME: &str = "render-cache.json";
+// Key of the cached pages, both in the cache file and in app storage,
+// where they were kept before
+const RENDER_CACHE_STORAGE_KEY: &str = "render_cache";
+
+/// How long a generated UI is reused before rendering again
+const RENDER_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Cached pages kept; the oldest are evicted first
+const MAX_RENDER_CACHE_ENTRIES: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedRender {
+    html: String,
+    cached_at: u64,
+}
+
+/// Cache key for a descriptor, prompt and language
+pub fn render_cache_key(
+    descriptor: &SocketAgentDescriptor,
+    prompt: Option<&str>,
+    language: Option<&str>,
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(descriptor)?);
+    for part in [prompt, language] {
+        hasher.update([0u8]);
+        if let Some(part) = part {
+            hasher.update(part.as_bytes());
+        }
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Rendered pages, in `render-cache.json` in the app data dir
+pub struct RenderCache {
+    storage: Storage,
+}
+
+impl RenderCache {
+    pub fn open(app_dir: &Path) -> Result<Self> {
+        Ok(Self {
+            storage: Storage::open(app_dir.join(RENDER_CACHE_FILE_NAME))?,
+        })
+    }
+
+    /// Drop the pages cached in app storage before the cache had its own file
+    pub fn remove_stored(storage: &Storage) {
+        if storage
+            .get(RENDER_CACHE_STORAGE_KEY)
+            .ok()
+            .flatten()
+            .is_none()
+        {
+            return;
+        }
+        if let Err(e) = storage.remove(RENDER_CACHE_STORAGE_KEY) {
+            println!("Failed to remove the old render cache: {}", e);
+        }
+    }
+
+    /// HTML rendered for `key` within the TTL, if any
+    pub fn get(&self, key: &str) -> Option<String> {
+        let entry = self.load().remove(key)?;
+        (unix_now().saturating_sub(entry.cached_at) < RENDER_CACHE_TTL_SECS).then_some(entry.html)
+    }
+
+    /// Store rendered HTML under `key`, dropping expired and excess entries
+    pub fn insert(&self, key: String, html: String) -> Result<()> {
+        let now = unix_now();
+        let mut cache = self.load();
+        cache.retain(|_, entry| now.saturating_sub(entry.cached_at) < RENDER_CACHE_TTL_SECS);
+        cache.insert(
+            key,
+            CachedRender {
+                html,
+                cached_at: now,
+            },
+        );
+
+        while cache.len() > MAX_RENDER_CACHE_ENTRIES {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, entry)| entry.cached_at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => cache.remove(&key),
+                None => break,
+            };
+        }
+
+        self.storage.set(
+            RENDER_CACHE_STORAGE_KEY.to_string(),
+            serde_json::to_value(cache)?,
+        )
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.storage.clear()
+    }
+
+    fn load(&self) -> HashMap<String, CachedRender> {
+        self.storage
+            .get(RENDER_CACHE_STORAGE_KEY)
+            .ok()
+            .flatten()
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_are_kept_in_their_own_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        storage
+            .set(RENDER_CACHE_STORAGE_KEY.to_string(), serde_json::json!({}))
+            .unwrap();
+        RenderCache::remove_stored(&storage);
+        let cache = RenderCache::open(dir.path()).unwrap();
+
+        cache
+            .insert("key".to_string(), "<p>hi</p>".to_string())
+            .unwrap();
+
+        assert_eq!(cache.get("key").as_deref(), Some("<p>hi</p>"));
+        assert!(dir.path().join(RENDER_CACHE_FILE_NAME).exists());
+        assert!(storage.get(RENDER_CACHE_STORAGE_KEY).unwrap().is_none());
+        let reopened = RenderCache::open(dir.path()).unwrap();
+        assert_eq!(reopened.get("key").as_deref(), Some("<p>hi</p>"));
+    }
+
+    #[test]
+    fn expired_renders_are_not_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = RenderCache::open(dir.path()).unwrap();
+        let expired = CachedRender {
+            html: "<p>old</p>".to_string(),
+            cached_at: unix_now() - RENDER_CACHE_TTL_SECS,
+        };
+        let stored = serde_json::json!({ "key": expired });
+        cache
+            .storage
+            .set(RENDER_CACHE_STORAGE_KEY.to_string(), stored)
+            .unwrap();
+
+        assert_eq!(cache.get("key"), None);
+    }
+}
@@ -4,17 +4,40 @@
 use anyhow::{anyhow, Context, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
 
+use crate::api::breaker::{CircuitBreaker, CircuitState};
 use crate::api::discovery::SocketAgentDescriptor;
+use crate::config::client_builder;
 
-const RENDER_API_URL: &str = "http://localhost:8000";
+mod cache;
+
+pub use cache::{render_cache_key, RenderCache};
+
+// Identifies a generation so the service can cancel it
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+// Lets the service recognise a retried generation and not charge for it twice
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+// How long a generation that may have gone through keeps its key for a retry
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(15 * 60);
+// Descriptor JSON per credit when the service can't estimate the cost itself
+const HEURISTIC_BYTES_PER_CREDIT: usize = 16 * 1024;
 
 #[derive(Debug, Serialize)]
 pub struct GenerateRequest {
     pub descriptor: SocketAgentDescriptor,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prompt: Option<String>,
+    /// BCP-47 tag for the language of the generated UI, e.g. `pt-BR`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Free, watermarked or shortened preview instead of a full generation
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub sandbox: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,87 +46,420 @@ pub struct GenerateResponse {
     pub credits_remaining: u64,
 }
 
+/// Sandbox preview; costs no credits, so none are reported
+#[derive(Debug, Deserialize)]
+pub struct PreviewResponse {
+    pub html: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EstimateResponse {
+    #[serde(alias = "estimated_credits", alias = "credits_required")]
+    credits: u64,
+}
+
+/// Credits a generation would cost, returned in an `ApiResult`
+#[derive(Serialize)]
+pub struct CostEstimateResponse {
+    pub credits: u64,
+    /// The service couldn't estimate, so `credits` is a local guess
+    pub heuristic: bool,
+}
+
+/// A generated UI, returned in an `ApiResult`
 #[derive(Serialize)]
 pub struct RenderResponse {
-    pub success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub html: Option<String>,
+    pub html: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub credits_remaining: Option<u64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
+    pub descriptor_name: String,
+    /// The HTML came from the render cache and cost no credits
+    pub from_cache: bool,
+}
+
+/// Errors from the render service, structured so the UI can act on them
+#[derive(Debug, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RenderError {
+    #[error("Authentication failed. Please login again.")]
+    Unauthorized,
+    #[error("{}", insufficient_credits_message(.credits_remaining, .credits_required))]
+    InsufficientCredits {
+        credits_remaining: Option<u64>,
+        credits_required: Option<u64>,
+    },
+    #[error("{}", rate_limited_message(.retry_after))]
+    RateLimited { retry_after: Option<u64> },
+    #[error("Render service error: {message}")]
+    Service { message: String },
+    #[error("GPU server error. Please try again later.")]
+    Gateway,
+    #[error("Render failed ({status}): {message}")]
+    Failed { status: u16, message: String },
+    #[error("Failed to connect to render service: {message}")]
+    Network { message: String },
+    #[error("Failed to parse render response: {message}")]
+    InvalidResponse { message: String },
+    #[error("Render service is failing repeatedly; retrying in {retry_after}s")]
+    CircuitOpen { retry_after: u64 },
+    #[error("This render service doesn't offer sandbox previews")]
+    SandboxUnsupported,
+}
+
+impl RenderError {
+    /// The service itself is down or broken, as opposed to rejecting the request
+    pub fn is_service_failure(&self) -> bool {
+        match self {
+            RenderError::Service { .. } | RenderError::Gateway | RenderError::Network { .. } => {
+                true
+            }
+            RenderError::Failed { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// The service doesn't offer the requested endpoint
+    pub fn is_unsupported(&self) -> bool {
+        matches!(
+            self,
+            RenderError::Failed {
+                status: 404 | 405 | 501,
+                ..
+            }
+        )
+    }
+
+    /// Build an error from a failed response's status and body.
+    /// `retry_after` is the parsed `Retry-After` header, preferred over the body.
+    fn from_response(status: u16, body: &str, retry_after: Option<u64>) -> Self {
+        // Structured fields may sit at the top level or under FastAPI's `detail`
+        let json: Option<Value> = serde_json::from_str(body).ok();
+        let field = |name: &str| {
+            json.as_ref().and_then(|j| {
+                j.get(name)
+                    .or_else(|| j.get("detail").and_then(|d| d.get(name)))
+                    .and_then(|v| v.as_u64())
+            })
+        };
+
+        match status {
+            401 => RenderError::Unauthorized,
+            402 => RenderError::InsufficientCredits {
+                credits_remaining: field("credits_remaining"),
+                credits_required: field("credits_required"),
+            },
+            429 => RenderError::RateLimited {
+                retry_after: retry_after.or_else(|| field("retry_after")),
+            },
+            500 => RenderError::Service {
+                message: body.to_string(),
+            },
+            502 => RenderError::Gateway,
+            _ => RenderError::Failed {
+                status,
+                message: body.to_string(),
+            },
+        }
+    }
+}
+
+fn insufficient_credits_message(remaining: &Option<u64>, required: &Option<u64>) -> String {
+    match (remaining, required) {
+        (Some(remaining), Some(required)) => format!(
+            "Insufficient credits: {} available, {} required. Please buy more credits from your account.",
+            remaining, required
+        ),
+        _ => "Insufficient credits. Please buy more credits from your account.".to_string(),
+    }
+}
+
+/// Parse a `Retry-After` header value, given either as delay seconds or an HTTP date
+pub fn parse_retry_after(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds);
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    let delay = date
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO);
+    Some(delay.as_secs() + u64::from(delay.subsec_nanos() > 0))
+}
+
+/// Rough credit cost from the descriptor's size, for services without `/estimate`
+pub fn heuristic_generation_cost(descriptor: &SocketAgentDescriptor) -> u64 {
+    let size = serde_json::to_vec(descriptor).map(|v| v.len()).unwrap_or(0);
+    1 + (size / HEURISTIC_BYTES_PER_CREDIT) as u64
+}
+
+/// Random (version 4) UUID
+fn new_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Merge client-side UI preferences (theme, branding, layout) over the
+/// descriptor's `context`, which goes to the render service with the rest of
+/// the descriptor. Follows JSON merge patch: nested objects merge, a `null`
+/// hint removes the site's value.
+pub fn apply_render_hints(descriptor: &mut SocketAgentDescriptor, hints: Value) -> Result<()> {
+    if !hints.is_object() {
+        return Err(anyhow!("Render hints must be a JSON object"));
+    }
+    let context = descriptor.context.get_or_insert(Value::Null);
+    merge_patch(context, hints);
+    Ok(())
+}
+
+fn merge_patch(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(&key);
+            } else {
+                merge_patch(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Check that `tag` looks like a BCP-47 language tag (`en`, `pt-BR`,
+/// `zh-Hant-TW`); underscores are accepted and turned into hyphens
+pub fn validate_language_tag(tag: &str) -> Result<String> {
+    let tag = tag.trim().replace('_', "-");
+    let valid = tag.split('-').enumerate().all(|(i, subtag)| match i {
+        0 => (2..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphabetic()),
+        _ => (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()),
+    });
+
+    if !valid {
+        return Err(anyhow!("Invalid language tag: {}", tag));
+    }
+    Ok(tag)
+}
+
+/// Language of the OS locale from `LC_ALL`, `LC_MESSAGES` or `LANG`,
+/// e.g. `en_US.UTF-8` becomes `en-US`
+pub fn detect_os_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| value.split(['.', '@']).next().map(String::from))
+        .filter(|locale| locale != "C" && locale != "POSIX")
+        .and_then(|locale| validate_language_tag(&locale).ok())
+}
+
+fn rate_limited_message(retry_after: &Option<u64>) -> String {
+    match retry_after {
+        Some(seconds) => format!("Rate limit exceeded. Please try again in {} seconds.", seconds),
+        None => "Rate limit exceeded. Please try again later.".to_string(),
+    }
 }
 
 pub struct RenderClient {
-    client: Client,
+    client: RwLock<Client>,
     base_url: String,
+    // OS language detected at startup, used when a generation doesn't name one
+    default_language: Option<String>,
+    // Earliest time the service said we may generate again after a 429
+    cooldown_until: Mutex<Option<Instant>>,
+    // Fails generations fast while the service keeps failing
+    breaker: CircuitBreaker,
+    // Idempotency keys of generations in flight or that failed in a way the
+    // service may still have completed, by render cache key
+    idempotency_keys: Mutex<HashMap<String, (String, Instant)>>,
 }
 
 impl RenderClient {
-    pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(120)) // Long timeout for LLM generation
-            .build()
-            .expect("Failed to create HTTP client");
+    pub fn with_url(base_url: String) -> Result<Self> {
+        let client = client_builder()?
+            .timeout(Duration::from_secs(120))
+            .build()?;
+
+        Ok(Self {
+            client: RwLock::new(client),
+            base_url,
+            default_language: detect_os_language(),
+            cooldown_until: Mutex::new(None),
+            breaker: CircuitBreaker::new(),
+            idempotency_keys: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Replace the HTTP client, e.g. after the timeout setting changes
+    pub fn rebuild_client(&self, timeout: Duration) -> Result<()> {
+        let client = client_builder()?.timeout(timeout).build()?;
+        *self.client.write().unwrap() = client;
+        Ok(())
+    }
 
-        Self {
-            client,
-            base_url: RENDER_API_URL.to_string(),
+    /// Forget any rate-limit cooldown and circuit breaker failures
+    pub fn reset(&self) {
+        *self.cooldown_until.lock().unwrap() = None;
+        self.breaker.clear();
+        self.idempotency_keys.lock().unwrap().clear();
+    }
+
+    /// Circuit breaker state for the render service
+    pub fn circuit_state(&self) -> Vec<CircuitState> {
+        self.breaker.snapshot()
+    }
+
+    /// Seconds left before the service accepts generations again, if rate limited
+    pub fn cooldown_remaining(&self) -> Option<u64> {
+        let mut cooldown = self.cooldown_until.lock().unwrap();
+        match *cooldown {
+            Some(until) if until > Instant::now() => {
+                let remaining = until - Instant::now();
+                // Round up so the UI never shows "0 seconds" while still blocked
+                Some(remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0))
+            }
+            Some(_) => {
+                *cooldown = None;
+                None
+            }
+            None => None,
         }
     }
 
-    pub fn with_url(base_url: String) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .expect("Failed to create HTTP client");
+    fn http(&self) -> Client {
+        self.client.read().unwrap().clone()
+    }
 
-        Self { client, base_url }
+    /// Language used for generations that don't specify one
+    pub fn default_language(&self) -> Option<String> {
+        self.default_language.clone()
     }
 
     /// Generate UI from Socket Agent descriptor
-    /// Requires valid access token from socketagent.id
+    /// Requires valid access token from socketagent.id. `request_id` is sent
+    /// along so the generation can later be passed to `cancel_generation`.
+    /// Retrying the same generation after a timeout or server error reuses
+    /// its idempotency key, so the service doesn't charge for it again.
     pub async fn generate(
         &self,
         access_token: &str,
         descriptor: SocketAgentDescriptor,
         prompt: Option<String>,
-    ) -> Result<GenerateResponse> {
+        language: Option<String>,
+        request_id: Option<&str>,
+    ) -> Result<GenerateResponse, RenderError> {
+        // Don't hit the service again until its rate-limit cooldown has passed
+        if let Some(remaining) = self.cooldown_remaining() {
+            return Err(RenderError::RateLimited {
+                retry_after: Some(remaining),
+            });
+        }
+
+        // Fail fast while the service keeps failing
+        self.breaker
+            .check(&self.base_url)
+            .map_err(|retry_after| RenderError::CircuitOpen { retry_after })?;
+
+        let fingerprint = render_cache_key(&descriptor, prompt.as_deref(), language.as_deref())
+            .map_err(|e| RenderError::InvalidResponse {
+                message: e.to_string(),
+            })?;
+        let idempotency_key = self.idempotency_key(&fingerprint);
+
+        let result = self
+            .send_generate(
+                access_token,
+                descriptor,
+                prompt,
+                language,
+                request_id,
+                &idempotency_key,
+            )
+            .await;
+        match &result {
+            Err(e) if e.is_service_failure() => self.breaker.record_failure(&self.base_url),
+            _ => self.breaker.record_success(&self.base_url),
+        }
+        // Only a generation the service may have finished keeps its key
+        if !matches!(&result, Err(e) if e.is_service_failure()) {
+            self.idempotency_keys.lock().unwrap().remove(&fingerprint);
+        }
+        result
+    }
+
+    /// Key for the generation identified by `fingerprint`: the one it was
+    /// last sent with if that's recent, otherwise a new one
+    fn idempotency_key(&self, fingerprint: &str) -> String {
+        let mut keys = self.idempotency_keys.lock().unwrap();
+        keys.retain(|_, (_, created)| created.elapsed() < IDEMPOTENCY_KEY_TTL);
+        keys.entry(fingerprint.to_string())
+            .or_insert_with(|| (new_uuid(), Instant::now()))
+            .0
+            .clone()
+    }
+
+    async fn send_generate(
+        &self,
+        access_token: &str,
+        descriptor: SocketAgentDescriptor,
+        prompt: Option<String>,
+        language: Option<String>,
+        request_id: Option<&str>,
+        idempotency_key: &str,
+    ) -> Result<GenerateResponse, RenderError> {
         let url = format!("{}/generate", self.base_url);
 
-        let request = GenerateRequest { descriptor, prompt };
+        let request = GenerateRequest {
+            descriptor,
+            prompt,
+            language,
+            sandbox: false,
+        };
 
         println!("Generating UI at: {}", url);
 
-        let response = self
-            .client
+        let mut builder = self
+            .http()
             .post(&url)
             .header("Authorization", format!("Bearer {}", access_token))
             .header("Content-Type", "application/json")
-            .json(&request)
+            .header(IDEMPOTENCY_KEY_HEADER, idempotency_key)
+            .json(&request);
+        if let Some(request_id) = request_id {
+            builder = builder.header(REQUEST_ID_HEADER, request_id);
+        }
+
+        let response = builder
             .send()
             .await
-            .context("Failed to connect to render service")?;
-
-        let status = response.status();
+            .map_err(|e| RenderError::Network {
+                message: e.to_string(),
+            })?;
 
-        if !status.is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-
-            match status.as_u16() {
-                401 => return Err(anyhow!("Authentication failed. Please login again.")),
-                402 => return Err(anyhow!("Insufficient credits. Please buy more credits from your account.")),
-                429 => return Err(anyhow!("Rate limit exceeded. Please try again later.")),
-                500 => return Err(anyhow!("Render service error: {}", error_text)),
-                502 => return Err(anyhow!("GPU server error. Please try again later.")),
-                _ => return Err(anyhow!("Render failed ({}): {}", status.as_u16(), error_text)),
-            }
+        if !response.status().is_success() {
+            return Err(self.response_error(response).await);
         }
 
-        let result: GenerateResponse = response
-            .json()
-            .await
-            .context("Failed to parse render response")?;
+        let result: GenerateResponse = response.json().await.map_err(|e| {
+            RenderError::InvalidResponse {
+                message: e.to_string(),
+            }
+        })?;
 
         println!(
             "UI generated successfully. {} credits remaining",
@@ -113,12 +469,156 @@ impl RenderClient {
         Ok(result)
     }
 
+    /// Generate a sandbox preview from `/generate/preview`, which costs no
+    /// credits but may be watermarked or cut short. Fails with
+    /// `SandboxUnsupported` when the service has no preview endpoint.
+    pub async fn preview(
+        &self,
+        access_token: &str,
+        descriptor: SocketAgentDescriptor,
+        prompt: Option<String>,
+        language: Option<String>,
+    ) -> Result<PreviewResponse, RenderError> {
+        if let Some(remaining) = self.cooldown_remaining() {
+            return Err(RenderError::RateLimited {
+                retry_after: Some(remaining),
+            });
+        }
+
+        let url = format!("{}/generate/preview", self.base_url);
+
+        let request = GenerateRequest {
+            descriptor,
+            prompt,
+            language,
+            sandbox: true,
+        };
+
+        println!("Generating UI preview at: {}", url);
+
+        let response = self
+            .http()
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| RenderError::Network {
+                message: e.to_string(),
+            })?;
+
+        if !response.status().is_success() {
+            return Err(match self.response_error(response).await {
+                error if error.is_unsupported() => RenderError::SandboxUnsupported,
+                error => error,
+            });
+        }
+
+        response.json().await.map_err(|e| RenderError::InvalidResponse {
+            message: e.to_string(),
+        })
+    }
+
+    /// Ask the service to stop generation `request_id` so it isn't charged.
+    /// Services without `/cancel` only see the dropped connection, which
+    /// isn't an error
+    pub async fn cancel_generation(
+        &self,
+        access_token: &str,
+        request_id: &str,
+    ) -> Result<(), RenderError> {
+        let url = format!("{}/cancel", self.base_url);
+
+        let response = self
+            .http()
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .json(&serde_json::json!({ "request_id": request_id }))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| RenderError::Network {
+                message: e.to_string(),
+            })?;
+
+        if response.status().is_success() {
+            return Ok(());
+        }
+        match self.response_error(response).await {
+            error if error.is_unsupported() => Ok(()),
+            error => Err(error),
+        }
+    }
+
+    /// Credits a generation of `descriptor` would cost, as estimated by the service
+    pub async fn estimate_generation_cost(
+        &self,
+        access_token: &str,
+        descriptor: &SocketAgentDescriptor,
+    ) -> Result<u64, RenderError> {
+        let url = format!("{}/estimate", self.base_url);
+
+        let request = GenerateRequest {
+            descriptor: descriptor.clone(),
+            prompt: None,
+            language: None,
+            sandbox: false,
+        };
+
+        let response = self
+            .http()
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .json(&request)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| RenderError::Network {
+                message: e.to_string(),
+            })?;
+
+        if !response.status().is_success() {
+            return Err(self.response_error(response).await);
+        }
+
+        let estimate: EstimateResponse = response.json().await.map_err(|e| {
+            RenderError::InvalidResponse {
+                message: e.to_string(),
+            }
+        })?;
+
+        Ok(estimate.credits)
+    }
+
+    /// Turn a failed response into a `RenderError`, starting the rate-limit
+    /// cooldown when the service asks us to back off
+    async fn response_error(&self, response: reqwest::Response) -> RenderError {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        let error = RenderError::from_response(status.as_u16(), &error_text, retry_after);
+
+        if let RenderError::RateLimited {
+            retry_after: Some(seconds),
+        } = &error
+        {
+            *self.cooldown_until.lock().unwrap() =
+                Some(Instant::now() + Duration::from_secs(*seconds));
+        }
+
+        error
+    }
+
     /// Health check for render service
     pub async fn health_check(&self) -> Result<bool> {
         let url = format!("{}/health", self.base_url);
 
         let response = self
-            .client
+            .http()
             .get(&url)
             .timeout(Duration::from_secs(5))
             .send()
@@ -128,3 +628,202 @@ impl RenderClient {
         Ok(response.status().is_success())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `responses` to one connection each, in order, returning the URL
+    /// and every request received in full
+    async fn serve_each(responses: Vec<(u16, &'static str)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut socket).await;
+                received.lock().unwrap().push(request);
+                let head = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(body.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    /// Read a request's headers and the body they announce
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                    .and_then(|(_, value)| value.trim().parse().ok())
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    return text;
+                }
+            }
+            match socket.read(&mut buf).await {
+                Ok(read) if read > 0 => request.extend_from_slice(&buf[..read]),
+                _ => return text,
+            }
+        }
+    }
+
+    fn descriptor() -> SocketAgentDescriptor {
+        serde_json::from_value(json!({ "name": "Test API", "endpoints": [] })).unwrap()
+    }
+
+    /// JSON body of a recorded request
+    fn request_body(request: &str) -> Value {
+        serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap()
+    }
+
+    /// Value of header `name` in a recorded request
+    fn request_header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+        request
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    }
+
+    fn rate_limit(body: &str, header: &str) -> Option<u64> {
+        match RenderError::from_response(429, body, parse_retry_after(header)) {
+            RenderError::RateLimited { retry_after } => retry_after,
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn retry_after_seconds_are_read_as_they_are() {
+        assert_eq!(parse_retry_after("120"), Some(120));
+        assert_eq!(parse_retry_after(" 0 "), Some(0));
+    }
+
+    #[test]
+    fn retry_after_dates_give_the_delay_until_then() {
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(90));
+        let delay = parse_retry_after(&later).unwrap();
+        assert!((89..=91).contains(&delay), "delay was {}", delay);
+
+        let earlier = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(90));
+        assert_eq!(parse_retry_after(&earlier), Some(0));
+    }
+
+    #[test]
+    fn invalid_retry_after_falls_back_to_the_body() {
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(
+            rate_limit(r#"{"detail":{"retry_after":30}}"#, "soon"),
+            Some(30)
+        );
+        assert_eq!(rate_limit(r#"{"retry_after":30}"#, "5"), Some(5));
+        assert_eq!(rate_limit("Too many requests", "soon"), None);
+    }
+
+    #[tokio::test]
+    async fn previews_go_to_the_sandbox_endpoint() {
+        let (base_url, requests) = serve_each(vec![(200, r#"{"html":"<p>preview</p>"}"#)]).await;
+        let client = RenderClient::with_url(base_url).unwrap();
+
+        let preview = client
+            .preview("token", descriptor(), None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(preview.html, "<p>preview</p>");
+        let request = &requests.lock().unwrap()[0];
+        let line = request.lines().next().unwrap();
+        assert_eq!(line, "POST /generate/preview HTTP/1.1");
+        assert!(request.contains(r#""sandbox":true"#), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn previews_fail_clearly_without_sandbox_support() {
+        let (base_url, _) = serve_each(vec![(404, r#"{"detail":"Not Found"}"#)]).await;
+        let client = RenderClient::with_url(base_url).unwrap();
+
+        let error = client
+            .preview("token", descriptor(), None, None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, RenderError::SandboxUnsupported));
+    }
+
+    #[tokio::test]
+    async fn context_and_render_hints_reach_the_render_service() {
+        let (base_url, requests) =
+            serve_each(vec![(200, r#"{"html":"<p>ui</p>","credits_remaining":4}"#)]).await;
+        let client = RenderClient::with_url(base_url).unwrap();
+        let mut descriptor: SocketAgentDescriptor = serde_json::from_value(json!({
+            "name": "Test API",
+            "endpoints": [],
+            "context": {
+                "theme": { "primary": "blue", "mode": "light" },
+                "branding": { "logo": "https://example.com/logo.png" },
+            },
+        }))
+        .unwrap();
+        let hints = json!({ "theme": { "mode": "dark" }, "branding": null, "layout": "grid" });
+
+        apply_render_hints(&mut descriptor, hints).unwrap();
+        client
+            .generate("token", descriptor, None, None, None)
+            .await
+            .unwrap();
+
+        let body = request_body(&requests.lock().unwrap()[0]);
+        assert_eq!(
+            body["descriptor"]["context"],
+            json!({ "theme": { "primary": "blue", "mode": "dark" }, "layout": "grid" })
+        );
+    }
+
+    #[test]
+    fn render_hints_have_to_be_an_object() {
+        let mut descriptor = descriptor();
+
+        assert!(apply_render_hints(&mut descriptor, json!("dark")).is_err());
+        apply_render_hints(&mut descriptor, json!({ "layout": "grid" })).unwrap();
+        assert_eq!(descriptor.context, Some(json!({ "layout": "grid" })));
+    }
+
+    #[tokio::test]
+    async fn retried_generations_reuse_their_idempotency_key() {
+        let generated = r#"{"html":"<p>ui</p>","credits_remaining":4}"#;
+        let (base_url, requests) =
+            serve_each(vec![(503, "busy"), (200, generated), (200, generated)]).await;
+        let client = RenderClient::with_url(base_url).unwrap();
+        let generate = || client.generate("token", descriptor(), None, None, None);
+
+        assert!(generate().await.unwrap_err().is_service_failure());
+        generate().await.unwrap();
+        generate().await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let keys: Vec<&str> = requests
+            .iter()
+            .map(|request| request_header(request, IDEMPOTENCY_KEY_HEADER).unwrap())
+            .collect();
+        assert_eq!(keys[0], keys[1]);
+        // Once a generation succeeds, the next one is a new request
+        assert_ne!(keys[1], keys[2]);
+    }
+}
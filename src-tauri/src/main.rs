@@ -3,21 +3,59 @@
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::collections::HashMap;
-use std::sync::Mutex;
-use tauri::State;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::webview::PageLoadEvent;
+use tauri::{Emitter, Manager, State};
+use tokio::sync::oneshot;
 
 mod api;
 mod auth;
+mod config;
 mod llm;
+mod response;
+mod sites;
+mod status;
 mod storage;
 mod wallet;
 
-use api::{call_api, discover_socket_agent, ApiCallResponse, DiscoveryResponse};
-use auth::{AuthClient, AuthResponse};
-use llm::{RenderClient, RenderResponse};
-use storage::Storage;
-use wallet::{SolanaWallet, WalletResponse};
+use api::{
+    call_api, call_api_batch, call_api_binary, call_api_download, call_api_paginated, check_cors,
+    credential_host, diff_descriptors, discover_socket_agent, download_path, endpoint_examples,
+    endpoint_params, fetch_descriptor, get_endpoint, group_endpoints_by_tag, import_openapi,
+    import_openapi_from_url, list_endpoints, load_cached_descriptor, load_credentials,
+    load_descriptor_from_file, load_last_seen, merge_descriptors, missing_required_params,
+    normalize_url, probe_socket_agent, raw_request, reachability_warnings, reseal_cookies,
+    reseal_credentials, route_endpoint, save_cached_descriptor, save_credentials, save_last_seen,
+    scheme_warnings, search_endpoints, stream_sse, ApiCallResponse, ApiClients, ApiCredential,
+    BatchCall, BodyEncoding, CachedDescriptor, CallContext, CircuitBreaker, CircuitState,
+    CookieJars, CookiePolicy, Discovery, DiscoveryResponse, EndpointMatch, EndpointSummary,
+    ExampleInfo, HostThrottle, ParamInfo, RateLimitState, RateLimiter, RawHttpResult,
+    ReachabilityWarning, SocketAgentDescriptor, SseEvent, DEFAULT_MAX_PAGES,
+};
+use auth::{
+    reseal_sessions, AuthClient, ClockSkewDetected, LoginResponse, RegisterResponse, SessionInfo,
+    UserInfo,
+};
+use config::{Config, ProxyConfig, Timeouts};
+use llm::{CostEstimateResponse, RenderCache, RenderClient, RenderResponse};
+use response::ApiResult;
+use sites::{Bookmark, HistoryEntry, SiteIcon};
+use status::{
+    check_service, measure_latency, ping_socket_agent, Connectivity, DeferredOperation,
+    LatencyStats, SystemStatusResponse, CONNECTIVITY_CHECK_INTERVAL, DEFAULT_LATENCY_SAMPLES,
+    OFFLINE_MESSAGE, STATUS_BUDGET,
+};
+use storage::{BatchWrite, Storage};
+use wallet::{
+    AccountBalance, AddressValidation, AddressVerification, AuditEntry, AuditLog,
+    ConfirmationState, KdfParams, MnemonicImport, NftInfo, RpcEndpointsInfo, RpcHealth,
+    SolanaPayRequest, SolanaWallet, TokenAccount, UnsignedTransfer, WalletAccount,
+    WalletAlreadyExists, WalletBalance, WalletDebugInfo,
+};
 
 // Application state
 struct AppState {
@@ -25,420 +63,2597 @@ struct AppState {
     wallet: SolanaWallet,
     auth_client: AuthClient,
     render_client: RenderClient,
+    render_cache: RenderCache,
+    // Wallet operations, in their own append-only file
+    audit_log: AuditLog,
+    // Discovered descriptors keyed by normalized base URL
+    descriptors: Mutex<HashMap<String, SocketAgentDescriptor>>,
+    // On in debug builds; set SOCKETBROWSER_WALLET_DEBUG to turn on
+    // wallet_debug_info in release builds
+    wallet_debug_enabled: bool,
+    // Contents of config.json and where it's saved
+    config: Mutex<Config>,
+    config_path: PathBuf,
+    cookies: CookieJars,
+    rate_limiter: Arc<RateLimiter>,
+    host_throttle: Arc<HostThrottle>,
+    breaker: Arc<CircuitBreaker>,
+    // Pooled HTTP clients shared by every API call
+    api_clients: Arc<ApiClients>,
+    // Cancel senders for generations started with start_generation
+    generations: Mutex<HashMap<u64, oneshot::Sender<()>>>,
+    next_generation_id: AtomicU64,
+    // Cancel senders for calls started with call_api_start
+    api_calls: Mutex<HashMap<u64, oneshot::Sender<()>>>,
+    next_api_call_id: AtomicU64,
+    // Close senders for streams opened with subscribe_sse
+    sse_streams: Mutex<HashMap<u64, oneshot::Sender<()>>>,
+    next_sse_id: AtomicU64,
+    // Online state and refreshes waiting for the connection to return
+    connectivity: Connectivity,
+}
+
+fn current_timeouts(state: &AppState) -> Timeouts {
+    state.config.lock().unwrap().timeouts
+}
+
+fn cache_descriptor(state: &AppState, url: &str, descriptor: &SocketAgentDescriptor) {
+    let mut descriptors = state.descriptors.lock().unwrap();
+    descriptors.insert(url.trim_end_matches('/').to_string(), descriptor.clone());
+    if let Some(base_url) = &descriptor.base_url {
+        descriptors.insert(base_url.trim_end_matches('/').to_string(), descriptor.clone());
+    }
+}
+
+fn cached_descriptor(state: &AppState, base_url: &str) -> Option<SocketAgentDescriptor> {
+    let descriptors = state.descriptors.lock().unwrap();
+    descriptors.get(base_url.trim_end_matches('/')).cloned()
+}
+
+/// Descriptor cached on disk for `url`, to revalidate instead of downloading again
+fn disk_cached_descriptor(state: &AppState, url: &str) -> Option<CachedDescriptor> {
+    let storage_guard = state.storage.lock().unwrap();
+    load_cached_descriptor(storage_guard.as_ref()?, url)
+}
+
+/// Save a freshly fetched descriptor to the disk cache
+fn save_disk_cache(state: &AppState, url: &str, discovery: &Discovery) {
+    let Some(entry) = discovery.cache_entry.clone() else {
+        return;
+    };
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        if let Err(e) = save_cached_descriptor(storage, url, entry) {
+            println!("Failed to cache descriptor: {}", e);
+        }
+    }
+}
+
+/// Remember a successfully discovered site in history and as the one to
+/// restore on next launch
+fn record_visit(state: &AppState, url: &str, descriptor: &SocketAgentDescriptor) {
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        if let Err(e) = sites::save_last_site(storage, Some(url)) {
+            println!("Failed to save last site: {}", e);
+        }
+        if let Err(e) = sites::record_history(storage, url, Some(&descriptor.name)) {
+            println!("Failed to record history: {}", e);
+        }
+    }
+}
+
+/// Emit a progress event for the UI's progress bar; failures are only logged
+fn emit_progress(app_handle: &tauri::AppHandle, event: &str, payload: serde_json::Value) {
+    if let Err(e) = app_handle.emit(event, payload) {
+        println!("Failed to emit {}: {}", event, e);
+    }
+}
+
+/// Emit `generation-done`, however the generation ended
+fn emit_generation_done(
+    app_handle: &tauri::AppHandle,
+    id: Option<u64>,
+    result: Result<&ApiResult<RenderResponse>, &str>,
+) {
+    let payload = match result {
+        Ok(response) => serde_json::json!({
+            "id": id,
+            "success": response.success,
+            "from_cache": response.data.as_ref().is_some_and(|render| render.from_cache),
+            "error": response.error_message(),
+        }),
+        Err(error) => serde_json::json!({
+            "id": id,
+            "success": false,
+            "from_cache": false,
+            "error": error,
+        }),
+    };
+    emit_progress(app_handle, "generation-done", payload);
+}
+
+/// Credential the user stored for the host of `base_url`, if any
+fn api_credential(state: &AppState, base_url: &str) -> Option<ApiCredential> {
+    let host = credential_host(base_url)?;
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref()?;
+
+    match load_credentials(storage, &host) {
+        Ok(credential) => credential,
+        Err(e) => {
+            println!("Failed to load credentials for {}: {}", host, e);
+            None
+        }
+    }
+}
+
+/// API and endpoint id a call should go to. Endpoints of a merged
+/// descriptor are routed to the API they came from, so its descriptor,
+/// credential and cookies apply.
+fn call_target(state: &AppState, base_url: &str, endpoint_id: &str) -> (String, String) {
+    cached_descriptor(state, base_url)
+        .and_then(|descriptor| route_endpoint(&descriptor, endpoint_id))
+        .unwrap_or_else(|| (base_url.to_string(), endpoint_id.to_string()))
+}
+
+/// Descriptor, credential, cookies and timeout for calls to the API at `base_url`
+fn call_context(state: &AppState, base_url: &str) -> CallContext {
+    CallContext {
+        descriptor: cached_descriptor(state, base_url),
+        credential: api_credential(state, base_url),
+        cookie_jar: state.cookies.jar(base_url),
+        timeout: current_timeouts(state).api(),
+        rate_limiter: state.rate_limiter.clone(),
+        throttle: state.host_throttle.clone(),
+        breaker: state.breaker.clone(),
+        clients: state.api_clients.clone(),
+    }
+}
+
+/// Save the API's session cookies if the user opted into keeping them
+fn persist_cookies(state: &AppState, base_url: &str) {
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        if let Err(e) = state.cookies.persist(base_url, storage) {
+            println!("Failed to save cookies for {}: {}", base_url, e);
+        }
+    }
+}
+
+// ============================================================================
+// AUTHENTICATION COMMANDS
+// ============================================================================
+
+#[tauri::command]
+async fn auth_register(
+    username: String,
+    email: Option<String>,
+    password: String,
+    state: State<'_, AppState>,
+) -> ApiResult<RegisterResponse> {
+    state
+        .auth_client
+        .register(username, email, password)
+        .await
+        .map(|user_id| RegisterResponse { user_id })
+        .into()
+}
+
+#[tauri::command]
+async fn auth_login(
+    username: String,
+    password: String,
+    state: State<'_, AppState>,
+) -> ApiResult<LoginResponse> {
+    let login_response = match state.auth_client.login(username.clone(), password).await {
+        Ok(response) => response,
+        Err(e) => return ApiResult::err(e.to_string()),
+    };
+
+    // Session bookkeeping never fails the sign-in itself
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        if let Err(e) = auth::record_session(storage, &username, &login_response) {
+            println!("Failed to record session: {}", e);
+        }
+    }
+
+    ApiResult::ok(login_response)
+}
+
+/// Failed result for an auth error, with the `clock_skew` code when the
+/// token was rejected because of a wrong local clock
+fn clock_skew_result<T>(error: anyhow::Error) -> ApiResult<T> {
+    match error.downcast_ref::<ClockSkewDetected>() {
+        Some(_) => ApiResult::err_with_code(ClockSkewDetected::CODE, error.to_string()),
+        None => ApiResult::err(error.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn auth_get_user(access_token: String, state: State<'_, AppState>) -> ApiResult<UserInfo> {
+    match state.auth_client.get_user(&access_token).await {
+        Ok(user_info) => ApiResult::ok(user_info),
+        Err(e) => clock_skew_result(e),
+    }
+}
+
+#[tauri::command]
+async fn auth_refresh(
+    refresh_token: String,
+    state: State<'_, AppState>,
+) -> ApiResult<LoginResponse> {
+    let refresh_response = match state.auth_client.refresh(refresh_token.clone()).await {
+        Ok(response) => response,
+        Err(e) => return clock_skew_result(e),
+    };
+
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        if let Err(e) = auth::refresh_session(storage, &refresh_token, &refresh_response) {
+            println!("Failed to update session: {}", e);
+        }
+    }
+
+    ApiResult::ok(refresh_response)
+}
+
+#[tauri::command]
+async fn auth_logout(refresh_token: String, state: State<'_, AppState>) -> ApiResult<()> {
+    if let Err(e) = state.auth_client.logout(refresh_token.clone()).await {
+        return ApiResult::err(e.to_string());
+    }
+
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        if let Err(e) = auth::forget_session(storage, &refresh_token) {
+            println!("Failed to remove session: {}", e);
+        }
+    }
+
+    ApiResult::ok(())
+}
+
+/// Sign the user out on every device and forget all stored sessions. When
+/// the auth service can't revoke everything at once, each stored session
+/// and `refresh_token` (the current one) is signed out on its own instead.
+#[tauri::command]
+async fn auth_logout_all(
+    access_token: String,
+    refresh_token: Option<String>,
+    state: State<'_, AppState>,
+) -> ApiResult<()> {
+    let revoked_all = match state.auth_client.logout_all(&access_token).await {
+        Ok(revoked_all) => revoked_all,
+        Err(e) => return ApiResult::err(e.to_string()),
+    };
+
+    let taken = {
+        let storage_guard = state.storage.lock().unwrap();
+        match storage_guard.as_ref() {
+            Some(storage) => auth::take_all_sessions(storage).map_err(|e| e.to_string()),
+            None => Err("Storage not initialized".to_string()),
+        }
+    };
+    let mut refresh_tokens = match taken {
+        Ok(refresh_tokens) => refresh_tokens,
+        Err(e) => return ApiResult::err(e),
+    };
+    if revoked_all {
+        return ApiResult::ok(());
+    }
+
+    if let Some(token) = refresh_token {
+        if !refresh_tokens.contains(&token) {
+            refresh_tokens.push(token);
+        }
+    }
+    let mut failed = 0;
+    for token in refresh_tokens {
+        if let Err(e) = state.auth_client.logout(token).await {
+            println!("Failed to revoke session: {}", e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return ApiResult::err(format!(
+            "Sessions removed, but the server didn't revoke {} of them",
+            failed
+        ));
+    }
+    ApiResult::ok(())
+}
+
+/// Sessions signed in from this browser, without their tokens
+#[tauri::command]
+fn list_sessions(state: State<'_, AppState>) -> Result<Vec<SessionInfo>, String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    Ok(auth::list_sessions(storage))
+}
+
+/// Sign a stored session out on the server and forget its tokens. The
+/// session is removed locally even when the server can't be reached.
+#[tauri::command]
+async fn revoke_session(id: String, state: State<'_, AppState>) -> Result<(), String> {
+    let refresh_token = {
+        let storage_guard = state.storage.lock().unwrap();
+        let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+        auth::take_session(storage, &id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Session {} not found", id))?
+    };
+
+    state
+        .auth_client
+        .logout(refresh_token)
+        .await
+        .map_err(|e| format!("Session removed, but the server didn't revoke it: {}", e))
+}
+
+// ============================================================================
+// API DISCOVERY AND CLIENT COMMANDS
+// ============================================================================
+
+/// The URL the user typed, cleaned up for discovery and display
+#[tauri::command]
+fn normalize_url_cmd(input: String) -> Result<String, String> {
+    normalize_url(&input).map_err(|e| e.to_string())
+}
+
+/// Whether `url` looks like a Socket Agent site, without fetching the descriptor
+#[tauri::command]
+async fn probe_site(url: String) -> Result<bool, String> {
+    probe_socket_agent(&url).await.map_err(|e| e.to_string())
+}
+
+/// Emits `discovery-started`, `descriptor-fetched` and `validation-done`
+/// as discovery progresses
+#[tauri::command]
+async fn discover_socket_agent_cmd(
+    url: String,
+    discovery_path: Option<String>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> ApiResult<DiscoveryResponse> {
+    let timeouts = current_timeouts(&state);
+
+    emit_progress(
+        &app_handle,
+        "discovery-started",
+        serde_json::json!({ "url": url }),
+    );
+    let cached = disk_cached_descriptor(&state, &url);
+    let fetched = fetch_descriptor(
+        &url,
+        discovery_path.as_deref(),
+        timeouts.discovery(),
+        cached.as_ref(),
+    )
+    .await;
+    let discovery = match fetched {
+        Ok(fetched) => {
+            emit_progress(
+                &app_handle,
+                "descriptor-fetched",
+                serde_json::json!({ "url": url, "bytes": fetched.bytes }),
+            );
+            fetched.validate()
+        }
+        Err(e) => Err(e),
+    };
+    // Fires on failure too, so the UI can clear its progress bar
+    emit_progress(
+        &app_handle,
+        "validation-done",
+        serde_json::json!({
+            "url": url,
+            "success": discovery.is_ok(),
+            "error": discovery.as_ref().err().map(|e| e.to_string()),
+        }),
+    );
+
+    if let Ok(discovery) = &discovery {
+        save_disk_cache(&state, &url, discovery);
+    }
+
+    match discovery {
+        Ok(Discovery {
+            descriptor,
+            discovery_path,
+            warning,
+            reachability,
+            ..
+        }) => {
+            cache_descriptor(&state, &url, &descriptor);
+            record_visit(&state, &url, &descriptor);
+
+            ApiResult::ok(DiscoveryResponse {
+                descriptor,
+                diff: None,
+                discovery_path: Some(discovery_path),
+                warning,
+                reachability,
+            })
+        }
+        Err(e) => ApiResult::err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn discover_with_diff(
+    url: String,
+    discovery_path: Option<String>,
+    state: State<'_, AppState>,
+) -> ApiResult<DiscoveryResponse> {
+    let timeouts = current_timeouts(&state);
+    let cached = disk_cached_descriptor(&state, &url);
+
+    let discovery = discover_socket_agent(
+        &url,
+        discovery_path.as_deref(),
+        timeouts.discovery(),
+        cached.as_ref(),
+    )
+    .await;
+    let discovery = match discovery {
+        Ok(discovery) => discovery,
+        Err(e) => return ApiResult::err(e.to_string()),
+    };
+    save_disk_cache(&state, &url, &discovery);
+    let Discovery {
+        descriptor,
+        discovery_path,
+        warning,
+        reachability,
+        ..
+    } = discovery;
+
+    cache_descriptor(&state, &url, &descriptor);
+    record_visit(&state, &url, &descriptor);
+
+    let base_url = descriptor.base_url.clone().unwrap_or_else(|| url.clone());
+    let diff = {
+        let storage_guard = state.storage.lock().unwrap();
+        let Some(storage) = storage_guard.as_ref() else {
+            return ApiResult::err("Storage not initialized");
+        };
+
+        let previous = load_last_seen(storage, &base_url);
+        if let Err(e) = save_last_seen(storage, &base_url, &descriptor) {
+            return ApiResult::err(e.to_string());
+        }
+        previous.map(|old| diff_descriptors(&old, &descriptor))
+    };
+
+    ApiResult::ok(DiscoveryResponse {
+        descriptor,
+        diff,
+        discovery_path: Some(discovery_path),
+        warning,
+        reachability,
+    })
+}
+
+/// Discover several APIs and merge them into one descriptor, with each
+/// endpoint's operationId prefixed by its API's name. Calls to the merged
+/// descriptor's `baseUrl` are routed to the API each endpoint came from.
+#[tauri::command]
+async fn discover_multiple(
+    urls: Vec<String>,
+    state: State<'_, AppState>,
+) -> ApiResult<DiscoveryResponse> {
+    let timeouts = current_timeouts(&state);
+
+    let mut descriptors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut reachability = Vec::new();
+    for url in &urls {
+        let cached = disk_cached_descriptor(&state, url);
+        let discovery =
+            discover_socket_agent(url, None, timeouts.discovery(), cached.as_ref()).await;
+        let discovery = match discovery {
+            Ok(discovery) => discovery,
+            Err(e) => return ApiResult::err(format!("{}: {}", url, e)),
+        };
+        save_disk_cache(&state, url, &discovery);
+        let Discovery {
+            mut descriptor,
+            warning,
+            reachability: issues,
+            ..
+        } = discovery;
+
+        if descriptor.base_url.is_none() {
+            descriptor.base_url = Some(url.clone());
+        }
+        cache_descriptor(&state, url, &descriptor);
+        warnings.extend(warning.map(|warning| format!("{}: {}", url, warning)));
+        reachability.extend(issues);
+        descriptors.push(descriptor);
+    }
+
+    match merge_descriptors(descriptors) {
+        Ok(merged) => {
+            if let Some(base_url) = &merged.base_url {
+                cache_descriptor(&state, base_url, &merged);
+            }
+            ApiResult::ok(DiscoveryResponse {
+                descriptor: merged,
+                diff: None,
+                discovery_path: None,
+                warning: (!warnings.is_empty()).then(|| warnings.join("; ")),
+                reachability,
+            })
+        }
+        Err(e) => ApiResult::err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+fn discover_from_file(
+    path: String,
+    base_url: Option<String>,
+    state: State<'_, AppState>,
+) -> ApiResult<DiscoveryResponse> {
+    match load_descriptor_from_file(Path::new(&path), base_url.as_deref()) {
+        Ok(descriptor) => {
+            let url = descriptor.base_url.clone().unwrap_or_default();
+            cache_descriptor(&state, &url, &descriptor);
+
+            let reachability = reachability_warnings(&descriptor, None);
+
+            ApiResult::ok(DiscoveryResponse {
+                descriptor,
+                diff: None,
+                discovery_path: None,
+                warning: None,
+                reachability,
+            })
+        }
+        Err(e) => ApiResult::err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn import_openapi_descriptor(
+    source: String,
+    state: State<'_, AppState>,
+) -> ApiResult<DiscoveryResponse> {
+    // Accept either a URL to fetch or the raw JSON spec itself
+    let result = if source.trim_start().starts_with('{') {
+        serde_json::from_str(&source)
+            .map_err(|e| anyhow::anyhow!("Malformed OpenAPI JSON: {}", e))
+            .and_then(import_openapi)
+    } else {
+        import_openapi_from_url(source.trim(), current_timeouts(&state).discovery()).await
+    };
+
+    match result {
+        Ok(descriptor) => {
+            if let Some(base_url) = descriptor.base_url.clone() {
+                cache_descriptor(&state, &base_url, &descriptor);
+            }
+
+            let reachability = reachability_warnings(&descriptor, None);
+
+            ApiResult::ok(DiscoveryResponse {
+                descriptor,
+                diff: None,
+                discovery_path: None,
+                warning: None,
+                reachability,
+            })
+        }
+        Err(e) => ApiResult::err(e.to_string()),
+    }
+}
+
+/// The cached descriptor for `base_url` as an OpenAPI 3.0 document
+#[tauri::command]
+fn export_openapi(
+    base_url: String,
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let descriptor = cached_descriptor(&state, &base_url)
+        .ok_or_else(|| format!("No descriptor cached for {}; discover it first", base_url))?;
+    api::export_openapi(&descriptor).map_err(|e| e.to_string())
+}
+
+/// Endpoints of a previously discovered API, normalized for navigation
+#[tauri::command]
+fn list_endpoints_cmd(
+    base_url: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<EndpointSummary>, String> {
+    let descriptor = cached_descriptor(&state, &base_url)
+        .ok_or_else(|| format!("No descriptor cached for {}; discover it first", base_url))?;
+    Ok(list_endpoints(&descriptor))
+}
+
+/// Endpoints of a previously discovered API grouped by tag for a sectioned
+/// menu; untagged endpoints are under `untagged`
+#[tauri::command]
+fn group_endpoints_by_tag_cmd(
+    base_url: String,
+    state: State<'_, AppState>,
+) -> Result<BTreeMap<String, Vec<EndpointSummary>>, String> {
+    let descriptor = cached_descriptor(&state, &base_url)
+        .ok_or_else(|| format!("No descriptor cached for {}; discover it first", base_url))?;
+    Ok(group_endpoints_by_tag(&descriptor))
+}
+
+/// Endpoints of a previously discovered API matching a keyword query, best first
+#[tauri::command]
+fn search_endpoints_cmd(
+    base_url: String,
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<EndpointMatch>, String> {
+    let descriptor = cached_descriptor(&state, &base_url)
+        .ok_or_else(|| format!("No descriptor cached for {}; discover it first", base_url))?;
+    Ok(search_endpoints(&descriptor, &query))
+}
+
+/// Declared parameters of an endpoint with their enums and defaults, so the
+/// UI can prefill and constrain inputs
+#[tauri::command]
+fn get_endpoint_params(
+    base_url: String,
+    endpoint_id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<ParamInfo>, String> {
+    let descriptor = cached_descriptor(&state, &base_url)
+        .ok_or_else(|| format!("No descriptor cached for {}; discover it first", base_url))?;
+    let endpoint = get_endpoint(&descriptor, &endpoint_id)
+        .ok_or_else(|| format!("Unknown endpoint: {}", endpoint_id))?;
+    Ok(endpoint_params(&endpoint))
+}
+
+/// Required params of an endpoint that `provided` doesn't fill yet, for
+/// inline form validation; empty when the endpoint declares none
+#[tauri::command]
+fn missing_required_params_cmd(
+    base_url: String,
+    endpoint_id: String,
+    provided: HashMap<String, serde_json::Value>,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let descriptor = cached_descriptor(&state, &base_url)
+        .ok_or_else(|| format!("No descriptor cached for {}; discover it first", base_url))?;
+    let endpoint = get_endpoint(&descriptor, &endpoint_id)
+        .ok_or_else(|| format!("Unknown endpoint: {}", endpoint_id))?;
+    Ok(missing_required_params(&endpoint, &provided))
+}
+
+/// Example parameter sets the descriptor declares for an endpoint, each
+/// with warnings where it doesn't match the endpoint's parameters, for
+/// prefilled "try it" forms
+#[tauri::command]
+fn get_endpoint_examples(
+    base_url: String,
+    endpoint_id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<ExampleInfo>, String> {
+    let descriptor = cached_descriptor(&state, &base_url)
+        .ok_or_else(|| format!("No descriptor cached for {}; discover it first", base_url))?;
+    let endpoint = get_endpoint(&descriptor, &endpoint_id)
+        .ok_or_else(|| format!("Unknown endpoint: {}", endpoint_id))?;
+    Ok(endpoint_examples(&endpoint))
+}
+
+/// Client-side rate limit buckets for APIs that advertise limits, for debugging
+#[tauri::command]
+fn get_rate_limits(state: State<'_, AppState>) -> Vec<RateLimitState> {
+    state.rate_limiter.snapshot()
+}
+
+/// Circuit breaker state for Socket Agent APIs and the render service
+#[tauri::command]
+fn get_circuit_breakers(state: State<'_, AppState>) -> Vec<CircuitState> {
+    let mut circuits = state.breaker.snapshot();
+    circuits.extend(state.render_client.circuit_state());
+    circuits
+}
+
+/// Call an endpoint and return its body as base64 whatever the content type,
+/// for downloads and images
+#[tauri::command]
+async fn call_api_binary_cmd(
+    base_url: String,
+    endpoint_id: String,
+    params: HashMap<String, serde_json::Value>,
+    state: State<'_, AppState>,
+) -> ApiResult<ApiCallResponse> {
+    let (base_url, endpoint_id) = call_target(&state, &base_url, &endpoint_id);
+    let context = call_context(&state, &base_url);
+    let result = call_api_binary(&base_url, &endpoint_id, params, &context).await;
+    persist_cookies(&state, &base_url);
+
+    ApiCallResponse::from_result(result)
+}
+
+/// Call an endpoint and stream its body into a new file named `file_name`
+/// in the user's downloads folder, emitting `download-progress` as it's
+/// written
+#[tauri::command]
+async fn call_api_download_cmd(
+    base_url: String,
+    endpoint_id: String,
+    params: HashMap<String, serde_json::Value>,
+    file_name: String,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> ApiResult<ApiCallResponse> {
+    let download_dir = match app_handle.path().download_dir() {
+        Ok(download_dir) => download_dir,
+        Err(e) => return ApiResult::err(format!("No downloads folder: {}", e)),
+    };
+    let file_path = match download_path(&download_dir, &file_name) {
+        Ok(file_path) => file_path,
+        Err(e) => return ApiCallResponse::from_result(Err(e)),
+    };
+    let path = file_path.display().to_string();
+
+    let (base_url, endpoint_id) = call_target(&state, &base_url, &endpoint_id);
+    let context = call_context(&state, &base_url);
+    let on_progress = |bytes: u64, total: Option<u64>| {
+        emit_progress(
+            &app_handle,
+            "download-progress",
+            serde_json::json!({ "path": path, "bytes": bytes, "total": total }),
+        );
+    };
+    let result = call_api_download(
+        &base_url,
+        &endpoint_id,
+        params,
+        &context,
+        &file_path,
+        on_progress,
+    )
+    .await;
+    persist_cookies(&state, &base_url);
+
+    ApiCallResponse::from_result(result.map(|file| {
+        let status = file.status;
+        ApiCallResponse::new(serde_json::to_value(file).unwrap_or_default(), status)
+    }))
+}
+
+/// Call an endpoint. With `validate_response`, JSON responses are checked
+/// against the endpoint's declared response schema and mismatches come back
+/// as `warnings` alongside the data. `body_encoding` (`json` or `form`)
+/// overrides how the endpoint says its body is sent.
+#[tauri::command]
+async fn call_api_cmd(
+    base_url: String,
+    endpoint_id: String,
+    params: HashMap<String, serde_json::Value>,
+    validate_response: Option<bool>,
+    body_encoding: Option<BodyEncoding>,
+    state: State<'_, AppState>,
+) -> ApiResult<ApiCallResponse> {
+    let options = CallOptions {
+        validate_response: validate_response.unwrap_or(false),
+        body_encoding,
+    };
+    run_api_call(&state, &base_url, &endpoint_id, params, options).await
+}
+
+/// Start a call in the background and return its id, for slow endpoints the
+/// user may give up on. The result arrives as a `call-complete` event, or
+/// `call-cancelled` if `call_api_cancel` is called first.
+#[tauri::command]
+fn call_api_start(
+    base_url: String,
+    endpoint_id: String,
+    params: HashMap<String, serde_json::Value>,
+    validate_response: Option<bool>,
+    body_encoding: Option<BodyEncoding>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> u64 {
+    let options = CallOptions {
+        validate_response: validate_response.unwrap_or(false),
+        body_encoding,
+    };
+    let id = state.next_api_call_id.fetch_add(1, Ordering::Relaxed);
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    state.api_calls.lock().unwrap().insert(id, cancel_tx);
+
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+
+        // Dropping the call future on cancel drops its HTTP request, which
+        // closes the connection rather than waiting for the response
+        let call = run_api_call(&state, &base_url, &endpoint_id, params, options);
+        let emitted = tokio::select! {
+            response = call => {
+                state.api_calls.lock().unwrap().remove(&id);
+                app_handle.emit(
+                    "call-complete",
+                    serde_json::json!({ "id": id, "response": response }),
+                )
+            }
+            _ = cancel_rx => {
+                app_handle.emit("call-cancelled", serde_json::json!({ "id": id }))
+            }
+        };
+
+        if let Err(e) = emitted {
+            println!("Failed to emit API call {} result: {}", id, e);
+        }
+    });
+
+    id
+}
+
+/// Abort a call started with `call_api_start`; returns whether it was still
+/// running
+#[tauri::command]
+fn call_api_cancel(id: u64, state: State<'_, AppState>) -> bool {
+    match state.api_calls.lock().unwrap().remove(&id) {
+        Some(cancel_tx) => cancel_tx.send(()).is_ok(),
+        None => false,
+    }
+}
+
+/// Open an endpoint's server-sent event stream and return its id. Each
+/// event arrives as an `sse-message` event, and `sse-closed` follows once
+/// the stream ends for good; dropped connections resume on their own.
+#[tauri::command]
+fn subscribe_sse(
+    base_url: String,
+    endpoint_id: String,
+    params: HashMap<String, serde_json::Value>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> u64 {
+    let id = state.next_sse_id.fetch_add(1, Ordering::Relaxed);
+    let (close_tx, close_rx) = oneshot::channel();
+    state.sse_streams.lock().unwrap().insert(id, close_tx);
+
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        let (base_url, endpoint_id) = call_target(&state, &base_url, &endpoint_id);
+        let context = call_context(&state, &base_url);
+
+        let on_event = |event: SseEvent| {
+            let payload = serde_json::json!({ "id": id, "event": event });
+            if let Err(e) = app_handle.emit("sse-message", payload) {
+                println!("Failed to emit event from stream {}: {}", id, e);
+            }
+        };
+        let stream = stream_sse(&base_url, &endpoint_id, params, &context, on_event);
+        let error = tokio::select! {
+            result = stream => {
+                state.sse_streams.lock().unwrap().remove(&id);
+                result.err()
+            }
+            _ = close_rx => None,
+        };
+        persist_cookies(&state, &base_url);
+
+        if let Err(e) = app_handle.emit(
+            "sse-closed",
+            serde_json::json!({ "id": id, "error": error }),
+        ) {
+            println!("Failed to emit close of stream {}: {}", id, e);
+        }
+    });
+
+    id
+}
+
+/// Close a stream opened with `subscribe_sse`; returns whether it was open
+#[tauri::command]
+fn unsubscribe_sse(id: u64, state: State<'_, AppState>) -> bool {
+    match state.sse_streams.lock().unwrap().remove(&id) {
+        Some(close_tx) => close_tx.send(()).is_ok(),
+        None => false,
+    }
+}
+
+/// Per-call choices of `call_api_cmd` and `call_api_start`
+struct CallOptions {
+    validate_response: bool,
+    body_encoding: Option<BodyEncoding>,
+}
+
+async fn run_api_call(
+    state: &AppState,
+    base_url: &str,
+    endpoint_id: &str,
+    params: HashMap<String, serde_json::Value>,
+    options: CallOptions,
+) -> ApiResult<ApiCallResponse> {
+    let (base_url, endpoint_id) = call_target(state, base_url, endpoint_id);
+    let context = call_context(state, &base_url);
+    let result = call_api(
+        &base_url,
+        &endpoint_id,
+        params,
+        options.body_encoding,
+        &context,
+    )
+    .await;
+    persist_cookies(state, &base_url);
+
+    let mut response = ApiCallResponse::from_result(result);
+    if let Some(call) = response.data.as_mut().filter(|_| options.validate_response) {
+        call.warnings = response_warnings(&context, &endpoint_id, &call.body);
+    }
+    response
+}
+
+/// Run several calls concurrently; results come back in input order
+#[tauri::command]
+async fn call_api_batch_cmd(
+    base_url: String,
+    calls: Vec<BatchCall>,
+    state: State<'_, AppState>,
+) -> Vec<ApiResult<ApiCallResponse>> {
+    // Calls to a merged descriptor go to the API each endpoint came from
+    let mut contexts: HashMap<String, Arc<CallContext>> = HashMap::new();
+    let calls = calls
+        .into_iter()
+        .map(|mut call| {
+            let (target, endpoint_id) = call_target(&state, &base_url, &call.endpoint_id);
+            call.endpoint_id = endpoint_id;
+            let context = contexts
+                .entry(target.clone())
+                .or_insert_with(|| Arc::new(call_context(&state, &target)))
+                .clone();
+            (target, call, context)
+        })
+        .collect();
+    let results = call_api_batch(calls).await;
+    for target in contexts.keys() {
+        persist_cookies(&state, target);
+    }
+
+    results
+}
+
+#[tauri::command]
+async fn call_api_all(
+    base_url: String,
+    endpoint_id: String,
+    params: HashMap<String, serde_json::Value>,
+    max_pages: Option<u32>,
+    state: State<'_, AppState>,
+) -> ApiResult<ApiCallResponse> {
+    let max_pages = max_pages.unwrap_or(DEFAULT_MAX_PAGES);
+    let (base_url, endpoint_id) = call_target(&state, &base_url, &endpoint_id);
+    let context = call_context(&state, &base_url);
+    let result = call_api_paginated(&base_url, &endpoint_id, params, max_pages, &context).await;
+    persist_cookies(&state, &base_url);
+
+    ApiCallResponse::from_result(result)
+}
+
+#[tauri::command]
+async fn raw_http_call(
+    method: String,
+    url: String,
+    headers: Option<HashMap<String, String>>,
+    body: Option<serde_json::Value>,
+    state: State<'_, AppState>,
+) -> ApiResult<RawHttpResult> {
+    if !state.config.lock().unwrap().allow_raw_http {
+        return ApiResult::err("Raw HTTP calls are off; set allow_raw_http in config.json");
+    }
+
+    // No descriptor, credential or cookies: nothing is sent that the caller
+    // didn't put in the request
+    let context = CallContext {
+        descriptor: None,
+        credential: None,
+        cookie_jar: None,
+        timeout: current_timeouts(&state).api(),
+        rate_limiter: state.rate_limiter.clone(),
+        throttle: state.host_throttle.clone(),
+        breaker: state.breaker.clone(),
+        clients: state.api_clients.clone(),
+    };
+
+    raw_request(&method, &url, headers.unwrap_or_default(), body, &context)
+        .await
+        .into()
+}
+
+// ============================================================================
+// RENDER/UI GENERATION COMMANDS
+// ============================================================================
+
+#[tauri::command]
+async fn generate_website(
+    access_token: String,
+    descriptor: serde_json::Value,
+    force: Option<bool>,
+    language: Option<String>,
+    render_hints: Option<serde_json::Value>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> ApiResult<RenderResponse> {
+    let descriptor = match render_descriptor(descriptor, render_hints) {
+        Ok(descriptor) => descriptor,
+        Err(e) => return ApiResult::err(e),
+    };
+    let language = match generation_language(&state, language) {
+        Ok(language) => language,
+        Err(e) => return ApiResult::err(e),
+    };
+
+    emit_progress(
+        &app_handle,
+        "generation-started",
+        serde_json::json!({ "id": null, "name": descriptor.name }),
+    );
+    let result = render_website(
+        &state,
+        &access_token,
+        descriptor,
+        force.unwrap_or(false),
+        language,
+        None,
+    )
+    .await;
+    emit_generation_done(&app_handle, None, Ok(&result));
+
+    result
+}
+
+/// Generate a free sandbox preview of the UI for `descriptor`, so descriptor
+/// authors can iterate without spending credits. Previews aren't cached.
+#[tauri::command]
+async fn preview_website(
+    access_token: String,
+    descriptor: serde_json::Value,
+    language: Option<String>,
+    render_hints: Option<serde_json::Value>,
+    state: State<'_, AppState>,
+) -> ApiResult<RenderResponse> {
+    let descriptor = match render_descriptor(descriptor, render_hints) {
+        Ok(descriptor) => descriptor,
+        Err(e) => return ApiResult::err(e),
+    };
+    let language = match generation_language(&state, language) {
+        Ok(language) => language,
+        Err(e) => return ApiResult::err(e),
+    };
+    let descriptor_name = descriptor.name.clone();
+
+    let result = state
+        .render_client
+        .preview(&access_token, descriptor, None, language)
+        .await;
+    match result {
+        Ok(preview) => ApiResult::ok(RenderResponse {
+            html: preview.html,
+            credits_remaining: None,
+            descriptor_name,
+            from_cache: false,
+        }),
+        Err(e) => ApiResult::err_from(&e),
+    }
+}
+
+/// Parse the descriptor to render, with the user's `render_hints` merged
+/// over its context. Hints are part of the descriptor from here on, so
+/// renders with different hints are cached separately.
+fn render_descriptor(
+    descriptor: serde_json::Value,
+    render_hints: Option<serde_json::Value>,
+) -> Result<SocketAgentDescriptor, String> {
+    let mut descriptor: SocketAgentDescriptor =
+        serde_json::from_value(descriptor).map_err(|e| e.to_string())?;
+    if let Some(hints) = render_hints {
+        llm::apply_render_hints(&mut descriptor, hints).map_err(|e| e.to_string())?;
+    }
+    Ok(descriptor)
+}
+
+/// Validate a requested UI language, defaulting to the OS locale
+fn generation_language(
+    state: &AppState,
+    language: Option<String>,
+) -> Result<Option<String>, String> {
+    match language.filter(|tag| !tag.trim().is_empty()) {
+        Some(tag) => llm::validate_language_tag(&tag)
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        None => Ok(state.render_client.default_language()),
+    }
+}
+
+/// Start a generation in the background and return its id. The result
+/// arrives as a `render-complete` event, or `render-cancelled` if
+/// `cancel_generation` is called first
+#[tauri::command]
+fn start_generation(
+    access_token: String,
+    descriptor: serde_json::Value,
+    force: Option<bool>,
+    language: Option<String>,
+    render_hints: Option<serde_json::Value>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<u64, String> {
+    let descriptor = render_descriptor(descriptor, render_hints)?;
+    let language = generation_language(&state, language)?;
+
+    let id = state.next_generation_id.fetch_add(1, Ordering::Relaxed);
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    state.generations.lock().unwrap().insert(id, cancel_tx);
+
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        let request_id = hex::encode(rand::random::<[u8; 16]>());
+
+        emit_progress(
+            &app_handle,
+            "generation-started",
+            serde_json::json!({ "id": id, "name": descriptor.name }),
+        );
+
+        // Dropping the render future on cancel drops its HTTP request too
+        let render = render_website(
+            &state,
+            &access_token,
+            descriptor,
+            force.unwrap_or(false),
+            language,
+            Some(&request_id),
+        );
+        let emitted = tokio::select! {
+            result = render => {
+                state.generations.lock().unwrap().remove(&id);
+                emit_generation_done(&app_handle, Some(id), Ok(&result));
+                app_handle.emit(
+                    "render-complete",
+                    serde_json::json!({ "id": id, "response": result }),
+                )
+            }
+            _ = cancel_rx => {
+                if let Err(e) = state
+                    .render_client
+                    .cancel_generation(&access_token, &request_id)
+                    .await
+                {
+                    println!("Failed to cancel generation {}: {}", id, e);
+                }
+                emit_generation_done(&app_handle, Some(id), Err("Generation cancelled"));
+                app_handle.emit("render-cancelled", serde_json::json!({ "id": id }))
+            }
+        };
+
+        if let Err(e) = emitted {
+            println!("Failed to emit generation {} result: {}", id, e);
+        }
+    });
+
+    Ok(id)
+}
+
+/// Abort a generation started with `start_generation`; returns whether it
+/// was still running
+#[tauri::command]
+fn cancel_generation(id: u64, state: State<'_, AppState>) -> bool {
+    match state.generations.lock().unwrap().remove(&id) {
+        Some(cancel_tx) => cancel_tx.send(()).is_ok(),
+        None => false,
+    }
+}
+
+/// Render `descriptor`, reusing a recent render of it unless `force` is set
+async fn render_website(
+    state: &AppState,
+    access_token: &str,
+    descriptor: SocketAgentDescriptor,
+    force: bool,
+    language: Option<String>,
+    request_id: Option<&str>,
+) -> ApiResult<RenderResponse> {
+    let descriptor_name = descriptor.name.clone();
+    let cache_key = match llm::render_cache_key(&descriptor, None, language.as_deref()) {
+        Ok(cache_key) => cache_key,
+        Err(e) => return ApiResult::err(e.to_string()),
+    };
+
+    if !force {
+        if let Some(html) = state.render_cache.get(&cache_key) {
+            return ApiResult::ok(RenderResponse {
+                html,
+                credits_remaining: None,
+                descriptor_name,
+                from_cache: true,
+            });
+        }
+    }
+
+    match state
+        .render_client
+        .generate(access_token, descriptor, None, language, request_id)
+        .await
+    {
+        Ok(response) => {
+            if let Err(e) = state.render_cache.insert(cache_key, response.html.clone()) {
+                println!("Failed to cache render: {}", e);
+            }
+
+            ApiResult::ok(RenderResponse {
+                html: response.html,
+                credits_remaining: Some(response.credits_remaining),
+                descriptor_name,
+                from_cache: false,
+            })
+        }
+        Err(e) => ApiResult::err_from(&e),
+    }
+}
+
+/// Credits generating a UI for `descriptor` would cost. Falls back to a
+/// size-based guess, flagged `heuristic`, when the service can't estimate
+#[tauri::command]
+async fn estimate_website_cost(
+    access_token: String,
+    descriptor: serde_json::Value,
+    state: State<'_, AppState>,
+) -> ApiResult<CostEstimateResponse> {
+    let descriptor: SocketAgentDescriptor = match serde_json::from_value(descriptor) {
+        Ok(descriptor) => descriptor,
+        Err(e) => return ApiResult::err(e.to_string()),
+    };
+
+    match state
+        .render_client
+        .estimate_generation_cost(&access_token, &descriptor)
+        .await
+    {
+        Ok(credits) => ApiResult::ok(CostEstimateResponse {
+            credits,
+            heuristic: false,
+        }),
+        Err(e) if e.is_unsupported() => ApiResult::ok(CostEstimateResponse {
+            credits: llm::heuristic_generation_cost(&descriptor),
+            heuristic: true,
+        }),
+        Err(e) => ApiResult::err_from(&e),
+    }
+}
+
+#[tauri::command]
+fn clear_render_cache(state: State<'_, AppState>) -> Result<(), String> {
+    state.render_cache.clear().map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// WALLET COMMANDS
+// ============================================================================
+
+/// Result of a wallet save, giving a refused overwrite the
+/// `wallet_already_exists` code so the UI can ask for confirmation
+fn wallet_save_result(result: anyhow::Result<WalletAccount>) -> ApiResult<WalletAccount> {
+    match result {
+        Ok(account) => ApiResult::ok(account),
+        Err(e) if e.is::<WalletAlreadyExists>() => {
+            ApiResult::err_with_code(WalletAlreadyExists::CODE, e.to_string())
+        }
+        Err(e) => ApiResult::err(e.to_string()),
+    }
+}
+
+/// Append a wallet operation and its outcome to the audit log. A failed
+/// write is only logged so it never blocks the operation itself
+fn audit_wallet<T>(state: &AppState, storage: &Storage, operation: &str, result: &ApiResult<T>) {
+    let error = result.error_message().map(String::from);
+    record_audit(state, storage, operation, error);
+}
+
+/// Audit an operation whose only failure detail is `error`
+fn record_audit(state: &AppState, storage: &Storage, operation: &str, error: Option<String>) {
+    state
+        .audit_log
+        .record(&state.wallet, storage, operation, error);
+}
+
+/// `audit_wallet` for commands that don't already hold the storage lock
+fn audit_wallet_op<T>(state: &AppState, operation: &str, result: &ApiResult<T>) {
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        audit_wallet(state, storage, operation, result);
+    }
+}
+
+#[tauri::command]
+async fn wallet_generate_new(
+    password: String,
+    passphrase: Option<String>,
+    store_mnemonic: Option<bool>,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> ApiResult<WalletAccount> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+
+    let result = wallet_save_result(state.wallet.generate_new(
+        &password,
+        passphrase.as_deref(),
+        store_mnemonic.unwrap_or(false),
+        overwrite.unwrap_or(false),
+        storage,
+    ));
+    audit_wallet(&state, storage, "generate", &result);
+    result
+}
+
+#[tauri::command]
+async fn wallet_import_mnemonic(
+    mnemonic: String,
+    language: Option<String>,
+    password: String,
+    passphrase: Option<String>,
+    store_mnemonic: Option<bool>,
+    overwrite: Option<bool>,
+    derivation: Option<String>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> ApiResult<WalletAccount> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+
+    let options = MnemonicImport {
+        language: language.as_deref(),
+        passphrase: passphrase.as_deref(),
+        store_mnemonic: store_mnemonic.unwrap_or(false),
+        overwrite: overwrite.unwrap_or(false),
+        derivation: derivation.as_deref(),
+    };
+    let result = wallet_save_result(
+        state
+            .wallet
+            .import_from_mnemonic(&mnemonic, &password, options, storage),
+    );
+    audit_wallet(&state, storage, "import_mnemonic", &result);
+    result
+}
+
+#[tauri::command]
+async fn wallet_import_private_key(
+    private_key: String,
+    password: String,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> ApiResult<WalletAccount> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+
+    let result = wallet_save_result(state.wallet.import_from_private_key(
+        &private_key,
+        &password,
+        overwrite.unwrap_or(false),
+        storage,
+    ));
+    audit_wallet(&state, storage, "import_private_key", &result);
+    result
+}
+
+/// Generate a wallet kept only in memory; it's gone once locked, so the
+/// returned recovery phrase is the only way back to it
+#[tauri::command]
+fn wallet_generate_ephemeral(
+    passphrase: Option<String>,
+    state: State<'_, AppState>,
+) -> ApiResult<WalletAccount> {
+    state
+        .wallet
+        .generate_ephemeral(passphrase.as_deref())
+        .into()
+}
+
+/// Load a recovery phrase or base58 private key into memory without saving it
+#[tauri::command]
+fn wallet_import_ephemeral(
+    secret: String,
+    passphrase: Option<String>,
+    derivation: Option<String>,
+    state: State<'_, AppState>,
+) -> ApiResult<WalletAccount> {
+    state
+        .wallet
+        .import_ephemeral(&secret, passphrase.as_deref(), derivation.as_deref())
+        .into()
+}
+
+/// Import the Solana CLI keypair file at `path`, saving it under `password`
+#[tauri::command]
+fn wallet_import_keypair_file(
+    path: String,
+    password: String,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> ApiResult<WalletAccount> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+
+    let result = wallet_save_result(state.wallet.import_from_keypair_file(
+        Path::new(&path),
+        &password,
+        overwrite.unwrap_or(false),
+        storage,
+    ));
+    audit_wallet(&state, storage, "import_keypair_file", &result);
+    result
+}
+
+#[tauri::command]
+async fn wallet_unlock(
+    password: String,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> ApiResult<WalletAccount> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+
+    let result = state.wallet.unlock(&password, storage).into();
+    audit_wallet(&state, storage, "unlock", &result);
+    result
+}
+
+/// Opt in to unlocking at startup by saving keys derived from `password` to
+/// the OS keyring. The password itself is never stored.
+#[tauri::command]
+fn remember_device(password: String, state: State<'_, AppState>) -> Result<(), String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+
+    let result = state
+        .wallet
+        .remember_device(&password, storage)
+        .map_err(|e| e.to_string());
+    record_audit(
+        &state,
+        storage,
+        "remember_device",
+        result.as_ref().err().cloned(),
+    );
+    result
+}
+
+/// Remove the keyring entry so the next start asks for the password again
+#[tauri::command]
+fn forget_device(state: State<'_, AppState>) -> Result<(), String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+
+    let result = state
+        .wallet
+        .forget_device(storage)
+        .map_err(|e| e.to_string());
+    record_audit(
+        &state,
+        storage,
+        "forget_device",
+        result.as_ref().err().cloned(),
+    );
+    result
+}
+
+#[tauri::command]
+fn is_device_remembered(state: State<'_, AppState>) -> Result<bool, String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    Ok(state.wallet.is_device_remembered(storage))
+}
+
+#[tauri::command]
+fn wallet_lock(state: State<'_, AppState>) -> ApiResult<()> {
+    state.wallet.lock();
+    let result = ApiResult::ok(());
+    audit_wallet_op(&state, "lock", &result);
+    result
+}
+
+#[tauri::command]
+fn wallet_get_address(state: State<'_, AppState>) -> ApiResult<String> {
+    state.wallet.get_address().into()
+}
+
+#[tauri::command]
+async fn wallet_get_balance(state: State<'_, AppState>) -> ApiResult<WalletBalance> {
+    if !state.connectivity.is_online() {
+        state.connectivity.defer(DeferredOperation::BalanceRefresh);
+        return ApiResult::err(
+            "You're offline; the balance will refresh when the connection returns",
+        );
+    }
+
+    state
+        .wallet
+        .get_balance()
+        .await
+        .map(|balance| WalletBalance {
+            balance,
+            balance_usd: None,
+        })
+        .into()
+}
+
+/// SOL balance with its USD value; the USD part is omitted if the price is unavailable
+#[tauri::command]
+async fn wallet_get_balance_usd(state: State<'_, AppState>) -> ApiResult<WalletBalance> {
+    state
+        .wallet
+        .get_balance_usd()
+        .await
+        .map(|(balance, balance_usd)| WalletBalance {
+            balance,
+            balance_usd,
+        })
+        .into()
+}
+
+/// Balances of every wallet account; failed lookups carry an error instead
+#[tauri::command]
+async fn wallet_get_all_balances(
+    state: State<'_, AppState>,
+) -> Result<Vec<AccountBalance>, String> {
+    state
+        .wallet
+        .get_all_balances()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn wallet_confirm_signature(
+    signature: String,
+    timeout_secs: Option<u64>,
+    state: State<'_, AppState>,
+) -> ApiResult<ConfirmationState> {
+    // Without a timeout, report the current state once instead of polling
+    let result = match timeout_secs {
+        Some(secs) if secs > 0 => {
+            state
+                .wallet
+                .wait_for_signature(&signature, Duration::from_secs(secs))
+                .await
+        }
+        _ => state.wallet.get_signature_status(&signature).await,
+    };
+    result.into()
+}
+
+#[tauri::command]
+async fn wallet_get_nfts(
+    refresh: Option<bool>,
+    state: State<'_, AppState>,
+) -> ApiResult<Vec<NftInfo>> {
+    // Offline, fall back to the cache and refresh once the connection returns
+    let online = state.connectivity.is_online();
+    if !online {
+        state.connectivity.defer(DeferredOperation::NftRefresh);
+    }
+
+    if !online || !refresh.unwrap_or(false) {
+        let cached = {
+            let storage_guard = state.storage.lock().unwrap();
+            let Some(storage) = storage_guard.as_ref() else {
+                return ApiResult::err("Storage not initialized");
+            };
+            state.wallet.cached_nfts(storage)
+        };
+
+        if let Some(nfts) = cached {
+            return ApiResult::ok(nfts);
+        }
+    }
+
+    if !online {
+        return ApiResult::err("You're offline; NFTs will refresh when the connection returns");
+    }
+
+    match state.wallet.get_nfts().await {
+        Ok(nfts) => {
+            {
+                let storage_guard = state.storage.lock().unwrap();
+                if let Some(storage) = storage_guard.as_ref() {
+                    if let Err(e) = state.wallet.cache_nfts(&nfts, storage) {
+                        println!("Failed to cache NFTs: {}", e);
+                    }
+                }
+            }
+
+            ApiResult::ok(nfts)
+        }
+        Err(e) => ApiResult::err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+fn wallet_export_private_key(state: State<'_, AppState>) -> ApiResult<String> {
+    let result = state.wallet.export_private_key().into();
+    audit_wallet_op(&state, "export_private_key", &result);
+    result
+}
+
+#[tauri::command]
+fn wallet_export_mnemonic(state: State<'_, AppState>) -> ApiResult<String> {
+    let result = state.wallet.export_mnemonic().into();
+    audit_wallet_op(&state, "export_mnemonic", &result);
+    result
+}
+
+/// Write an encrypted backup of the unlocked wallet to `path`
+#[tauri::command]
+fn wallet_export_backup(
+    path: String,
+    backup_password: String,
+    state: State<'_, AppState>,
+) -> ApiResult<()> {
+    let result = state
+        .wallet
+        .export_encrypted_backup(&backup_password)
+        .and_then(|backup| std::fs::write(&path, backup).map_err(Into::into))
+        .into();
+    audit_wallet_op(&state, "export_backup", &result);
+    result
+}
+
+/// Restore a wallet from the backup file at `path`, saving it under `password`
+#[tauri::command]
+fn wallet_import_backup(
+    path: String,
+    backup_password: String,
+    password: String,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> ApiResult<WalletAccount> {
+    let backup = std::fs::read_to_string(&path).map_err(Into::into);
+    import_backup(&state, backup, &backup_password, &password, overwrite)
+}
+
+/// Restore a wallet from backup text pasted from another install, saving it
+/// under `password`
+#[tauri::command]
+fn wallet_import_backup_text(
+    backup: String,
+    backup_password: String,
+    password: String,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> ApiResult<WalletAccount> {
+    import_backup(&state, Ok(backup), &backup_password, &password, overwrite)
+}
+
+fn import_backup(
+    state: &AppState,
+    backup: anyhow::Result<String>,
+    backup_password: &str,
+    password: &str,
+    overwrite: Option<bool>,
+) -> ApiResult<WalletAccount> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+
+    let result = backup.and_then(|backup| {
+        state.wallet.import_encrypted_backup(
+            &backup,
+            backup_password,
+            password,
+            overwrite.unwrap_or(false),
+            storage,
+        )
+    });
+
+    let result = wallet_save_result(result);
+    audit_wallet(state, storage, "import_backup", &result);
+    result
+}
+
+#[tauri::command]
+fn wallet_has_wallet(state: State<'_, AppState>, app_handle: tauri::AppHandle) -> ApiResult<bool> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+
+    ApiResult::ok(state.wallet.has_wallet(storage))
+}
+
+/// Full details of a transaction by signature; works without an unlocked wallet
+#[tauri::command]
+async fn wallet_get_transaction(
+    signature: String,
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    state
+        .wallet
+        .get_transaction_details(signature.trim())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Build a SOL transfer signed by the wallet for external co-signing;
+/// `fee_payer` lets another account pay the fees and `priority_fee`
+/// overrides the saved compute unit price. `versioned` builds a v0
+/// transaction, which can use the address `lookup_tables`. A `memo` is
+/// attached for recipients that need one, e.g. exchange deposits.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn wallet_build_unsigned_transfer(
+    to: String,
+    amount_sol: f64,
+    fee_payer: Option<String>,
+    priority_fee: Option<u64>,
+    versioned: Option<bool>,
+    lookup_tables: Option<Vec<String>>,
+    memo: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<UnsignedTransfer, String> {
+    let result = state
+        .wallet
+        .build_unsigned_transfer(
+            &to,
+            amount_sol,
+            fee_payer.as_deref(),
+            priority_fee,
+            versioned.unwrap_or(false),
+            &lookup_tables.unwrap_or_default(),
+            memo.as_deref(),
+        )
+        .await
+        .map_err(|e| e.to_string());
+
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        record_audit(
+            &state,
+            storage,
+            "build_transfer",
+            result.as_ref().err().cloned(),
+        );
+    }
+    result
+}
+
+/// Broadcast a fully signed, base64-encoded transaction and return its signature
+#[tauri::command]
+async fn wallet_submit_signed(
+    transaction: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    // Sends are never queued; the user should know right away
+    let result = if state.connectivity.is_online() {
+        state
+            .wallet
+            .submit_signed_transaction(&transaction)
+            .await
+            .map_err(|e| e.to_string())
+    } else {
+        Err(OFFLINE_MESSAGE.to_string())
+    };
+
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        record_audit(
+            &state,
+            storage,
+            "submit_transaction",
+            result.as_ref().err().cloned(),
+        );
+    }
+    result
+}
+
+/// Fetch and check the transaction behind a Solana Pay transaction-request
+/// URL, for the user to approve. Nothing is signed yet.
+#[tauri::command]
+async fn wallet_handle_solana_pay(
+    url: String,
+    state: State<'_, AppState>,
+) -> Result<SolanaPayRequest, String> {
+    if !state.connectivity.is_online() {
+        return Err(OFFLINE_MESSAGE.to_string());
+    }
+    state
+        .wallet
+        .solana_pay_request(&url)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Sign and submit an approved Solana Pay transaction, returning its signature
+#[tauri::command]
+async fn wallet_approve_solana_pay(
+    transaction: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let result = if state.connectivity.is_online() {
+        state
+            .wallet
+            .approve_solana_pay(&transaction)
+            .await
+            .map_err(|e| e.to_string())
+    } else {
+        Err(OFFLINE_MESSAGE.to_string())
+    };
+
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        record_audit(
+            &state,
+            storage,
+            "solana_pay",
+            result.as_ref().err().cloned(),
+        );
+    }
+    result
+}
+
+/// Associated token account for receiving `mint`, created if it doesn't exist
+#[tauri::command]
+async fn wallet_create_token_account(
+    mint: String,
+    state: State<'_, AppState>,
+) -> Result<TokenAccount, String> {
+    if !state.connectivity.is_online() {
+        return Err(OFFLINE_MESSAGE.to_string());
+    }
+    let result = state
+        .wallet
+        .create_token_account(&mint)
+        .await
+        .map_err(|e| e.to_string());
+
+    // Only an actual creation spends SOL, so only that is audited
+    if result.as_ref().map_or(true, |account| account.created) {
+        let storage_guard = state.storage.lock().unwrap();
+        if let Some(storage) = storage_guard.as_ref() {
+            record_audit(
+                &state,
+                storage,
+                "create_token_account",
+                result.as_ref().err().cloned(),
+            );
+        }
+    }
+    result
+}
+
+#[tauri::command]
+fn wallet_get_rpc_endpoints(state: State<'_, AppState>) -> RpcEndpointsInfo {
+    state.wallet.rpc_endpoints()
+}
+
+/// Set the ordered Solana RPC endpoints; later ones are tried when earlier ones fail
+#[tauri::command]
+fn wallet_set_rpc_endpoints(
+    endpoints: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<RpcEndpointsInfo, String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    state
+        .wallet
+        .set_rpc_endpoints(endpoints, storage)
+        .map_err(|e| e.to_string())
+}
+
+/// Check a Solana RPC endpoint answers, and report its version and health
+#[tauri::command]
+async fn wallet_check_rpc(url: String, state: State<'_, AppState>) -> Result<RpcHealth, String> {
+    state
+        .wallet
+        .check_rpc(&url)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Compute unit price added to transfers, in micro-lamports
+#[tauri::command]
+fn wallet_get_priority_fee(state: State<'_, AppState>) -> Option<u64> {
+    state.wallet.priority_fee()
+}
+
+/// Set the compute unit price added to transfers; `None` or zero turns it off
+#[tauri::command]
+fn wallet_set_priority_fee(
+    microlamports: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    state
+        .wallet
+        .set_priority_fee(microlamports, storage)
+        .map_err(|e| e.to_string())
+}
+
+/// Key derivation work factor used the next time the wallet is encrypted
+#[tauri::command]
+fn wallet_get_kdf_params(state: State<'_, AppState>) -> KdfParams {
+    state.wallet.kdf_params()
+}
+
+/// Set the PBKDF2 iterations for future encryptions; existing wallet data
+/// keeps decrypting with the factor it was written with
+#[tauri::command]
+fn wallet_set_kdf_params(params: KdfParams, state: State<'_, AppState>) -> Result<(), String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    state
+        .wallet
+        .set_kdf_params(params, storage)
+        .map_err(|e| e.to_string())
+}
+
+/// Suggested compute unit price from the cluster's recent prioritization fees
+#[tauri::command]
+async fn wallet_estimate_priority_fee(state: State<'_, AppState>) -> Result<u64, String> {
+    state
+        .wallet
+        .estimate_priority_fee()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Wallet audit log, oldest first; `redact` shortens addresses
+#[tauri::command]
+fn wallet_get_audit_log(
+    redact: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<AuditEntry>, String> {
+    Ok(state.audit_log.load(redact.unwrap_or(false)))
+}
+
+/// What the frontend must pass to `wallet_clear_audit_log`, so the record
+/// of wallet operations can't be wiped by accident
+const CLEAR_AUDIT_LOG_CONFIRMATION: &str = "CLEAR";
+
+#[tauri::command]
+fn wallet_clear_audit_log(confirmation: String, state: State<'_, AppState>) -> Result<(), String> {
+    if confirmation != CLEAR_AUDIT_LOG_CONFIRMATION {
+        return Err(format!(
+            "Clearing the audit log not confirmed; pass \"{}\" to clear it",
+            CLEAR_AUDIT_LOG_CONFIRMATION
+        ));
+    }
+
+    state.audit_log.clear().map_err(|e| e.to_string())
+}
+
+/// Codes of the recovery phrase wordlists `wallet_import_mnemonic` accepts
+#[tauri::command]
+fn list_mnemonic_languages() -> Vec<String> {
+    wallet::mnemonic_languages()
+}
+
+/// Check whether `address` is a valid Solana address and whether it's a
+/// regular account or a program-derived one; works while locked
+#[tauri::command]
+fn validate_address(address: String) -> AddressValidation {
+    wallet::validate_address(&address)
+}
+
+/// Short fingerprint of `address` for confirming it by eye before sending
+#[tauri::command]
+fn address_fingerprint(address: String) -> Result<String, String> {
+    wallet::address_fingerprint(&address).map_err(|e| e.to_string())
+}
+
+/// Address a private key corresponds to, so it can be checked before
+/// importing; leaves storage and the unlocked wallet alone
+#[tauri::command]
+fn wallet_preview_address(private_key: String) -> Result<String, String> {
+    wallet::pubkey_from_private_key(&private_key).map_err(|e| e.to_string())
+}
+
+/// Check a signed message against the signer's address; doesn't require unlocking
+#[tauri::command]
+fn wallet_verify_message(
+    pubkey: String,
+    message: String,
+    signature: String,
+) -> Result<bool, String> {
+    wallet::verify_message(&pubkey, &message, &signature).map_err(|e| e.to_string())
+}
+
+/// Saved wallet address for the lock screen, `null` without a wallet;
+/// doesn't require unlocking
+#[tauri::command]
+fn wallet_get_stored_address(state: State<'_, AppState>) -> ApiResult<Option<String>> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+
+    ApiResult::ok(state.wallet.stored_address(storage))
+}
+
+/// Derivation path, KDF parameters and public key prefix of the saved
+/// wallet, for diagnosing imports that give a different address than another
+/// wallet. Never includes key or phrase material.
+#[tauri::command]
+fn wallet_debug_info(state: State<'_, AppState>) -> Result<WalletDebugInfo, String> {
+    if !state.wallet_debug_enabled {
+        return Err("Wallet debug info is disabled in this build".to_string());
+    }
+
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    state.wallet.debug_info(storage).map_err(|e| e.to_string())
+}
+
+/// Re-derive the saved wallet's address and check it still matches the
+/// stored one. Without `password` the unlocked wallet is checked.
+#[tauri::command]
+fn wallet_verify_address(
+    password: Option<String>,
+    state: State<'_, AppState>,
+) -> ApiResult<AddressVerification> {
+    let storage_guard = state.storage.lock().unwrap();
+    let Some(storage) = storage_guard.as_ref() else {
+        return ApiResult::err("Storage not initialized");
+    };
+    state
+        .wallet
+        .verify_address(password.as_deref(), storage)
+        .into()
+}
+
+#[tauri::command]
+fn wallet_is_unlocked(state: State<'_, AppState>) -> ApiResult<bool> {
+    ApiResult::ok(state.wallet.is_unlocked())
+}
+
+/// Whether the unlocked wallet is an in-memory one that isn't saved
+#[tauri::command]
+fn wallet_is_ephemeral(state: State<'_, AppState>) -> ApiResult<bool> {
+    ApiResult::ok(state.wallet.is_ephemeral())
 }
 
 // ============================================================================
-// AUTHENTICATION COMMANDS
+// STORAGE COMMANDS
 // ============================================================================
 
 #[tauri::command]
-async fn auth_register(
-    username: String,
-    email: Option<String>,
-    password: String,
+fn get_storage(key: String, state: State<'_, AppState>) -> Result<Option<serde_json::Value>, String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+
+    storage.get(&key).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_storage(
+    key: String,
+    value: serde_json::Value,
     state: State<'_, AppState>,
-) -> Result<AuthResponse, String> {
-    let user_id = state
-        .auth_client
-        .register(username, email, password)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<(), String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
 
-    Ok(AuthResponse {
-        success: true,
-        user_id: Some(user_id),
-        access_token: None,
-        refresh_token: None,
-        expires_in: None,
-        user: None,
-        error: None,
-    })
+    storage.set(key, value).map_err(|e| e.to_string())
 }
 
+/// Write several keys in one file write, reporting any that couldn't be stored
 #[tauri::command]
-async fn auth_login(
-    username: String,
-    password: String,
+fn set_storage_many(
+    entries: HashMap<String, serde_json::Value>,
     state: State<'_, AppState>,
-) -> Result<AuthResponse, String> {
-    let login_response = state
-        .auth_client
-        .login(username, password)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<BatchWrite, String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
 
-    Ok(AuthResponse {
-        success: true,
-        user_id: None,
-        access_token: Some(login_response.access_token),
-        refresh_token: Some(login_response.refresh_token),
-        expires_in: Some(login_response.expires_in),
-        user: None,
-        error: None,
-    })
+    storage.set_each(entries).map_err(|e| e.to_string())
 }
 
+// ============================================================================
+// STATUS COMMANDS
+// ============================================================================
+
 #[tauri::command]
-async fn auth_get_user(
-    access_token: String,
+async fn system_status(
+    base_url: Option<String>,
     state: State<'_, AppState>,
-) -> Result<AuthResponse, String> {
-    let user_info = state
-        .auth_client
-        .get_user(&access_token)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<SystemStatusResponse, String> {
+    let api_check = async {
+        match &base_url {
+            Some(url) => Some(check_service("api", STATUS_BUDGET, ping_socket_agent(url)).await),
+            None => None,
+        }
+    };
 
-    Ok(AuthResponse {
-        success: true,
-        user_id: None,
-        access_token: None,
-        refresh_token: None,
-        expires_in: None,
-        user: Some(user_info),
-        error: None,
-    })
+    let (auth, render, api) = tokio::join!(
+        check_service("auth", STATUS_BUDGET, state.auth_client.health_check()),
+        check_service("render", STATUS_BUDGET, state.render_client.health_check()),
+        api_check,
+    );
+
+    Ok(SystemStatusResponse { auth, render, api })
 }
 
+/// Round-trip latency to the API at `base_url` over `samples` requests
+/// to its discovery endpoint
 #[tauri::command]
-async fn auth_refresh(
-    refresh_token: String,
+async fn measure_api_latency(
+    base_url: String,
+    samples: Option<u32>,
     state: State<'_, AppState>,
-) -> Result<AuthResponse, String> {
-    let refresh_response = state
-        .auth_client
-        .refresh(refresh_token)
+) -> Result<LatencyStats, String> {
+    let samples = samples.unwrap_or(DEFAULT_LATENCY_SAMPLES);
+    measure_latency(&base_url, samples, &state.api_clients)
         .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(AuthResponse {
-        success: true,
-        user_id: None,
-        access_token: Some(refresh_response.access_token),
-        refresh_token: Some(refresh_response.refresh_token),
-        expires_in: Some(refresh_response.expires_in),
-        user: None,
-        error: None,
-    })
+        .map_err(|e| e.to_string())
 }
 
+/// Why generated pages may not be able to call the API at `base_url`:
+/// scheme problems with it (and its cached descriptor's endpoints), and
+/// whether a CORS preflight from the app's origin is allowed
 #[tauri::command]
-async fn auth_logout(
-    refresh_token: String,
+async fn check_api_reachability(
+    base_url: String,
     state: State<'_, AppState>,
-) -> Result<AuthResponse, String> {
-    state
-        .auth_client
-        .logout(refresh_token)
+) -> Result<Vec<ReachabilityWarning>, String> {
+    let mut warnings = match cached_descriptor(&state, &base_url) {
+        Some(descriptor) => reachability_warnings(&descriptor, None),
+        None => scheme_warnings(&base_url, None),
+    };
+
+    if let Some(warning) = check_cors(&base_url, &state.api_clients)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())?
+    {
+        warnings.push(warning);
+    }
+    Ok(warnings)
+}
 
-    Ok(AuthResponse {
-        success: true,
-        user_id: None,
-        access_token: None,
-        refresh_token: None,
-        expires_in: None,
-        user: None,
-        error: None,
-    })
+/// Whether the machine can reach the internet, checked now
+#[tauri::command]
+async fn is_online(app_handle: tauri::AppHandle) -> bool {
+    check_connectivity(&app_handle).await
 }
 
-// ============================================================================
-// API DISCOVERY AND CLIENT COMMANDS
-// ============================================================================
+/// Probe connectivity, announcing changes with `online` and `offline` events
+/// and running work deferred while offline once the connection returns
+async fn check_connectivity(app_handle: &tauri::AppHandle) -> bool {
+    let state = app_handle.state::<AppState>();
+    let Some(online) = state.connectivity.check().await else {
+        return state.connectivity.is_online();
+    };
 
-#[tauri::command]
-async fn discover_socket_agent_cmd(url: String) -> Result<DiscoveryResponse, String> {
-    match discover_socket_agent(&url).await {
-        Ok(descriptor) => Ok(DiscoveryResponse {
-            success: true,
-            descriptor: Some(descriptor),
-            error: None,
-        }),
-        Err(e) => Ok(DiscoveryResponse {
-            success: false,
-            descriptor: None,
-            error: Some(e.to_string()),
-        }),
+    let event = if online { "online" } else { "offline" };
+    println!("Connectivity changed: {}", event);
+    if let Err(e) = app_handle.emit(event, ()) {
+        println!("Failed to emit {}: {}", event, e);
     }
+    if online {
+        run_deferred(app_handle, &state).await;
+    }
+    online
 }
 
-#[tauri::command]
-async fn call_api_cmd(
-    base_url: String,
-    endpoint_id: String,
-    params: HashMap<String, serde_json::Value>,
-) -> Result<ApiCallResponse, String> {
-    match call_api(&base_url, &endpoint_id, params, None).await {
-        Ok(data) => Ok(ApiCallResponse {
-            success: true,
-            data: Some(data),
-            status_code: Some(200),
-            error: None,
-        }),
-        Err(e) => Ok(ApiCallResponse {
-            success: false,
-            data: None,
-            status_code: None,
-            error: Some(e.to_string()),
-        }),
+/// How often the wallet is checked for auto-lock
+const AUTO_LOCK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Lock the wallet if auto-lock is on and it has been unlocked long
+/// enough, emitting `wallet-locked`
+fn check_auto_lock(app_handle: &tauri::AppHandle) {
+    let state = app_handle.state::<AppState>();
+    let mins = state.config.lock().unwrap().auto_lock_mins;
+    let after = Duration::from_secs(mins * 60);
+    if mins == 0 || !state.wallet.lock_if_unlocked_for(after) {
+        return;
+    }
+
+    println!("Wallet auto-locked after {} minute(s)", mins);
+    audit_wallet_op(&state, "auto_lock", &ApiResult::ok(()));
+    if let Err(e) = app_handle.emit("wallet-locked", ()) {
+        println!("Failed to emit wallet-locked: {}", e);
+    }
+}
+
+/// Run operations queued while offline, emitting their results
+async fn run_deferred(app_handle: &tauri::AppHandle, state: &AppState) {
+    for operation in state.connectivity.take_deferred() {
+        let emitted = match operation {
+            DeferredOperation::BalanceRefresh => match state.wallet.get_balance().await {
+                Ok(balance) => {
+                    app_handle.emit("balance-updated", serde_json::json!({ "balance": balance }))
+                }
+                Err(e) => {
+                    println!("Deferred balance refresh failed: {}", e);
+                    continue;
+                }
+            },
+            DeferredOperation::NftRefresh => match state.wallet.get_nfts().await {
+                Ok(nfts) => {
+                    {
+                        let storage_guard = state.storage.lock().unwrap();
+                        if let Some(storage) = storage_guard.as_ref() {
+                            if let Err(e) = state.wallet.cache_nfts(&nfts, storage) {
+                                println!("Failed to cache NFTs: {}", e);
+                            }
+                        }
+                    }
+                    app_handle.emit("nfts-updated", serde_json::json!({ "nfts": nfts }))
+                }
+                Err(e) => {
+                    println!("Deferred NFT refresh failed: {}", e);
+                    continue;
+                }
+            },
+        };
+
+        if let Err(e) = emitted {
+            println!("Failed to emit {:?} result: {}", operation, e);
+        }
     }
 }
 
 // ============================================================================
-// RENDER/UI GENERATION COMMANDS
+// SETTINGS COMMANDS
 // ============================================================================
 
 #[tauri::command]
-async fn generate_website(
-    access_token: String,
-    descriptor: serde_json::Value,
-    state: State<'_, AppState>,
-) -> Result<RenderResponse, String> {
-    // Parse descriptor
-    let descriptor: api::discovery::SocketAgentDescriptor =
-        serde_json::from_value(descriptor).map_err(|e| e.to_string())?;
+fn get_timeouts(state: State<'_, AppState>) -> Result<Timeouts, String> {
+    Ok(current_timeouts(&state))
+}
 
-    match state
+/// Save `config` to config.json and put it into effect, rebuilding the
+/// long-lived clients for the new timeouts and proxy. The service URLs are
+/// picked up on the next start.
+fn save_config(state: &AppState, config: Config) -> Result<(), String> {
+    config.validate().map_err(|e| e.to_string())?;
+    {
+        let storage_guard = state.storage.lock().unwrap();
+        let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+        config
+            .save(&state.config_path, storage)
+            .map_err(|e| e.to_string())?;
+    }
+
+    let proxy_changed = state.config.lock().unwrap().proxy != config.proxy;
+    config.apply();
+    state
+        .auth_client
+        .rebuild_client(config.timeouts.auth())
+        .map_err(|e| e.to_string())?;
+    state
         .render_client
-        .generate(&access_token, descriptor, None)
-        .await
+        .rebuild_client(config.timeouts.render())
+        .map_err(|e| e.to_string())?;
+    if proxy_changed {
+        state.api_clients.reset().map_err(|e| e.to_string())?;
+    }
+
+    *state.config.lock().unwrap() = config;
+    Ok(())
+}
+
+/// The config as the frontend sees it, which never includes the proxy password
+fn frontend_config(mut config: Config) -> Config {
+    if let Some(proxy) = config.proxy.as_mut() {
+        proxy.password = None;
+    }
+    config
+}
+
+#[tauri::command]
+fn set_timeouts(timeouts: Timeouts, state: State<'_, AppState>) -> Result<Timeouts, String> {
+    let mut config = state.config.lock().unwrap().clone();
+    config.timeouts = timeouts;
+    save_config(&state, config)?;
+    Ok(timeouts)
+}
+
+#[tauri::command]
+fn get_config(state: State<'_, AppState>) -> Result<Config, String> {
+    Ok(frontend_config(state.config.lock().unwrap().clone()))
+}
+
+/// Save `config` to config.json and apply it; the service URLs are picked
+/// up on the next start. `allow_raw_http` can only be changed by editing the
+/// file, and the proxy only with `set_proxy`.
+#[tauri::command]
+fn update_config(mut config: Config, state: State<'_, AppState>) -> Result<Config, String> {
     {
-        Ok(response) => Ok(RenderResponse {
-            success: true,
-            html: Some(response.html),
-            credits_remaining: Some(response.credits_remaining),
-            error: None,
-        }),
-        Err(e) => Ok(RenderResponse {
-            success: false,
-            html: None,
-            credits_remaining: None,
-            error: Some(e.to_string()),
-        }),
+        let current = state.config.lock().unwrap();
+        config.allow_raw_http = current.allow_raw_http;
+        config.proxy = current.proxy.clone();
     }
+    save_config(&state, config.clone())?;
+    Ok(frontend_config(config))
 }
 
-// ============================================================================
-// WALLET COMMANDS
-// ============================================================================
+#[tauri::command]
+fn get_proxy() -> Result<Option<ProxyConfig>, String> {
+    // Never hand the stored password back to the frontend
+    Ok(config::active_proxy().map(|proxy| ProxyConfig {
+        password: None,
+        ..proxy
+    }))
+}
 
 #[tauri::command]
-async fn wallet_generate_new(
-    password: String,
+fn set_proxy(proxy: Option<ProxyConfig>, state: State<'_, AppState>) -> Result<(), String> {
+    let mut config = state.config.lock().unwrap().clone();
+    config.proxy = proxy;
+    save_config(&state, config)
+}
+
+/// Store the credential used for API calls to `host`; an empty value removes it
+#[tauri::command]
+fn set_api_credentials(
+    host: String,
+    scheme: String,
+    value: String,
     state: State<'_, AppState>,
-    app_handle: tauri::AppHandle,
-) -> Result<WalletResponse, String> {
+) -> Result<(), String> {
+    // Accept a full URL as well as a bare host
+    let host = credential_host(&host).unwrap_or(host);
+
     let storage_guard = state.storage.lock().unwrap();
     let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    save_credentials(storage, &host, &scheme, &value).map_err(|e| e.to_string())
+}
 
-    state
-        .wallet
-        .generate_new(&password, storage)
-        .map_err(|e| e.to_string())
+#[tauri::command]
+fn get_max_response_size() -> usize {
+    config::max_response_bytes()
 }
 
+/// Set the largest API response body, in bytes, that will be read
 #[tauri::command]
-async fn wallet_import_mnemonic(
-    mnemonic: String,
-    password: String,
-    state: State<'_, AppState>,
-    app_handle: tauri::AppHandle,
-) -> Result<WalletResponse, String> {
-    let storage_guard = state.storage.lock().unwrap();
-    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+fn set_max_response_size(bytes: usize, state: State<'_, AppState>) -> Result<(), String> {
+    let mut config = state.config.lock().unwrap().clone();
+    config.max_response_bytes = bytes;
+    save_config(&state, config)
+}
 
-    state
-        .wallet
-        .import_from_mnemonic(&mnemonic, &password, storage)
-        .map_err(|e| e.to_string())
+#[tauri::command]
+fn get_auth_retries() -> u32 {
+    config::auth_retries()
 }
 
+/// Set how many times auth requests are retried after a network error or 5xx
 #[tauri::command]
-async fn wallet_import_private_key(
-    private_key: String,
-    password: String,
-    state: State<'_, AppState>,
-    app_handle: tauri::AppHandle,
-) -> Result<WalletResponse, String> {
+fn set_auth_retries(retries: u32, state: State<'_, AppState>) -> Result<(), String> {
+    let mut config = state.config.lock().unwrap().clone();
+    config.auth_retries = retries;
+    save_config(&state, config)
+}
+
+#[tauri::command]
+fn get_host_request_interval() -> u64 {
+    config::host_interval_ms()
+}
+
+/// Set the minimum gap, in milliseconds, between requests to the same API
+/// host; zero turns throttling off
+#[tauri::command]
+fn set_host_request_interval(ms: u64, state: State<'_, AppState>) -> Result<(), String> {
+    let mut config = state.config.lock().unwrap().clone();
+    config.host_interval_ms = ms;
+    save_config(&state, config)
+}
+
+#[tauri::command]
+fn get_cookie_policy(state: State<'_, AppState>) -> CookiePolicy {
+    state.cookies.policy()
+}
+
+#[tauri::command]
+fn set_cookie_policy(policy: CookiePolicy, state: State<'_, AppState>) -> Result<(), String> {
     let storage_guard = state.storage.lock().unwrap();
     let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
-
     state
-        .wallet
-        .import_from_private_key(&private_key, &password, storage)
+        .cookies
+        .set_policy(policy, storage)
         .map_err(|e| e.to_string())
 }
 
+/// Drop the session cookies held for one API
 #[tauri::command]
-async fn wallet_unlock(
-    password: String,
-    state: State<'_, AppState>,
-    app_handle: tauri::AppHandle,
-) -> Result<WalletResponse, String> {
+fn clear_cookies(base_url: String, state: State<'_, AppState>) -> Result<(), String> {
     let storage_guard = state.storage.lock().unwrap();
     let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
-
     state
-        .wallet
-        .unlock(&password, storage)
+        .cookies
+        .clear(&base_url, storage)
         .map_err(|e| e.to_string())
 }
 
+/// Move every secret encrypted with a local key to a fresh key: API
+/// credentials, saved cookies, refresh tokens and the proxy password
 #[tauri::command]
-fn wallet_lock(state: State<'_, AppState>) -> Result<WalletResponse, String> {
-    state.wallet.lock();
-    Ok(WalletResponse {
-        success: true,
-        address: None,
-        mnemonic: None,
-        balance: None,
-        private_key: None,
-        has_wallet: None,
-        is_unlocked: Some(false),
-        error: None,
-    })
+fn rotate_storage_key(state: State<'_, AppState>) -> Result<(), String> {
+    let config = state.config.lock().unwrap().clone();
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    rotate_local_keys(storage, &config, &state.config_path).map_err(|e| e.to_string())
 }
 
-#[tauri::command]
-fn wallet_get_address(state: State<'_, AppState>) -> Result<WalletResponse, String> {
-    match state.wallet.get_address() {
-        Ok(address) => Ok(WalletResponse {
-            success: true,
-            address: Some(address),
-            mnemonic: None,
-            balance: None,
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: None,
-        }),
-        Err(e) => Ok(WalletResponse {
-            success: false,
-            address: None,
-            mnemonic: None,
-            balance: None,
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: Some(e.to_string()),
-        }),
+/// Storage is written in one `set_many`, so a failure leaves the old keys
+/// and data in place. config.json, which holds the proxy password, lives
+/// outside it, so it's written first and put back if that write fails.
+fn rotate_local_keys(storage: &Storage, config: &Config, config_path: &Path) -> anyhow::Result<()> {
+    let mut entries = reseal_credentials(storage)?;
+    entries.extend(reseal_cookies(storage)?);
+    entries.extend(reseal_sessions(storage)?);
+
+    let previous_config = std::fs::read(config_path).ok();
+    let proxy_key = config.save_rotated(config_path, storage)?;
+    entries.push(proxy_key.key_entry());
+
+    if let Err(e) = storage.set_many(entries) {
+        if let Some(previous) = previous_config {
+            if let Err(restore) = std::fs::write(config_path, previous) {
+                println!("Failed to restore {}: {}", config_path.display(), restore);
+            }
+        }
+        return Err(e);
     }
+    Ok(())
 }
 
+/// What the frontend must pass to `factory_reset`, so it can't run by accident
+const FACTORY_RESET_CONFIRMATION: &str = "RESET";
+
+/// Erase all app data: wallet, credentials, settings, history and caches.
+/// Emits `app-reset` once done
 #[tauri::command]
-async fn wallet_get_balance(state: State<'_, AppState>) -> Result<WalletResponse, String> {
-    match state.wallet.get_balance() {
-        Ok(balance) => Ok(WalletResponse {
-            success: true,
-            address: None,
-            mnemonic: None,
-            balance: Some(balance),
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: None,
-        }),
-        Err(e) => Ok(WalletResponse {
-            success: false,
-            address: None,
-            mnemonic: None,
-            balance: None,
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: Some(e.to_string()),
-        }),
+fn factory_reset(
+    confirmation: String,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    if confirmation != FACTORY_RESET_CONFIRMATION {
+        return Err(format!(
+            "Factory reset not confirmed; pass \"{}\" to erase all data",
+            FACTORY_RESET_CONFIRMATION
+        ));
     }
-}
 
-#[tauri::command]
-fn wallet_export_private_key(state: State<'_, AppState>) -> Result<WalletResponse, String> {
-    match state.wallet.export_private_key() {
-        Ok(private_key) => Ok(WalletResponse {
-            success: true,
-            address: None,
-            mnemonic: None,
-            balance: None,
-            private_key: Some(private_key),
-            has_wallet: None,
-            is_unlocked: None,
-            error: None,
-        }),
-        Err(e) => Ok(WalletResponse {
-            success: false,
-            address: None,
-            mnemonic: None,
-            balance: None,
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: Some(e.to_string()),
-        }),
+    // Lock first so no key material outlives the stored wallet
+    state.wallet.reset();
+
+    {
+        let storage_guard = state.storage.lock().unwrap();
+        let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+        // The keyring lives outside app storage, so clearing won't reach it
+        if let Err(e) = state.wallet.forget_device(storage) {
+            println!("Failed to forget remembered device: {}", e);
+        }
+        storage.clear().map_err(|e| e.to_string())?;
+    }
+    state.render_cache.clear().map_err(|e| e.to_string())?;
+    state.audit_log.clear().map_err(|e| e.to_string())?;
+
+    for (_, cancel_tx) in state.generations.lock().unwrap().drain() {
+        let _ = cancel_tx.send(());
+    }
+    for (_, cancel_tx) in state.api_calls.lock().unwrap().drain() {
+        let _ = cancel_tx.send(());
+    }
+    for (_, close_tx) in state.sse_streams.lock().unwrap().drain() {
+        let _ = close_tx.send(());
+    }
+    state.descriptors.lock().unwrap().clear();
+    state.cookies.reset();
+    state.rate_limiter.clear();
+    state.host_throttle.clear();
+    state.breaker.clear();
+    state.render_client.reset();
+    state.connectivity.clear();
+
+    // Back to default settings, rebuilding the clients that depend on them
+    save_config(&state, Config::default())?;
+    state.api_clients.reset().map_err(|e| e.to_string())?;
+
+    println!("Factory reset complete");
+    if let Err(e) = app_handle.emit("app-reset", ()) {
+        println!("Failed to emit app-reset: {}", e);
     }
+
+    Ok(())
 }
 
+// ============================================================================
+// SITE COMMANDS
+// ============================================================================
+
 #[tauri::command]
-fn wallet_has_wallet(
-    state: State<'_, AppState>,
-    app_handle: tauri::AppHandle,
-) -> Result<WalletResponse, String> {
+fn get_last_site(state: State<'_, AppState>) -> Result<Option<String>, String> {
     let storage_guard = state.storage.lock().unwrap();
     let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
-
-    let has_wallet = state.wallet.has_wallet(storage);
-
-    Ok(WalletResponse {
-        success: true,
-        address: None,
-        mnemonic: None,
-        balance: None,
-        private_key: None,
-        has_wallet: Some(has_wallet),
-        is_unlocked: None,
-        error: None,
-    })
+    Ok(sites::load_last_site(storage))
 }
 
 #[tauri::command]
-fn wallet_is_unlocked(state: State<'_, AppState>) -> Result<WalletResponse, String> {
-    let is_unlocked = state.wallet.is_unlocked();
+fn set_last_site(url: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    sites::save_last_site(storage, url.as_deref()).map_err(|e| e.to_string())
+}
 
-    Ok(WalletResponse {
-        success: true,
-        address: None,
-        mnemonic: None,
-        balance: None,
-        private_key: None,
-        has_wallet: None,
-        is_unlocked: Some(is_unlocked),
-        error: None,
-    })
+#[tauri::command]
+fn add_bookmark(url: String, name: String, state: State<'_, AppState>) -> Result<Bookmark, String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    sites::add_bookmark(storage, &url, &name).map_err(|e| e.to_string())
 }
 
-// ============================================================================
-// STORAGE COMMANDS
-// ============================================================================
+#[tauri::command]
+fn list_bookmarks(state: State<'_, AppState>) -> Result<Vec<Bookmark>, String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    Ok(sites::list_bookmarks(storage))
+}
 
 #[tauri::command]
-fn get_storage(key: String, state: State<'_, AppState>) -> Result<Option<serde_json::Value>, String> {
+fn remove_bookmark(url: String, state: State<'_, AppState>) -> Result<bool, String> {
     let storage_guard = state.storage.lock().unwrap();
     let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    sites::remove_bookmark(storage, &url).map_err(|e| e.to_string())
+}
 
-    storage.get(&key).map_err(|e| e.to_string())
+#[tauri::command]
+fn list_history(state: State<'_, AppState>) -> Result<Vec<HistoryEntry>, String> {
+    let storage_guard = state.storage.lock().unwrap();
+    let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    Ok(sites::list_history(storage))
 }
 
 #[tauri::command]
-fn set_storage(
-    key: String,
-    value: serde_json::Value,
-    state: State<'_, AppState>,
-) -> Result<(), String> {
+fn clear_history(state: State<'_, AppState>) -> Result<(), String> {
     let storage_guard = state.storage.lock().unwrap();
     let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+    sites::clear_history(storage).map_err(|e| e.to_string())
+}
 
-    storage.set(key, value).map_err(|e| e.to_string())
+/// Icon for the site at `base_url`: the logo its descriptor names or its
+/// favicon, cached in storage. `placeholder` is set when it has neither.
+#[tauri::command]
+async fn get_site_icon(base_url: String, state: State<'_, AppState>) -> Result<SiteIcon, String> {
+    {
+        let storage_guard = state.storage.lock().unwrap();
+        let storage = storage_guard.as_ref().ok_or("Storage not initialized")?;
+        if let Some(icon) = sites::cached_site_icon(storage, &base_url) {
+            return Ok(icon);
+        }
+    }
+
+    let descriptor = cached_descriptor(&state, &base_url);
+    let icon = sites::fetch_site_icon(&base_url, descriptor.as_ref()).await;
+
+    let storage_guard = state.storage.lock().unwrap();
+    if let Some(storage) = storage_guard.as_ref() {
+        if let Err(e) = sites::save_site_icon(storage, &base_url, &icon) {
+            println!("Failed to cache icon for {}: {}", base_url, e);
+        }
+    }
+    Ok(icon)
 }
 
 // ============================================================================
@@ -452,18 +2667,137 @@ fn main() {
             // Initialize storage
             let storage = Storage::new(app.handle())?;
 
+            // config.json, the render cache and the audit log sit next to
+            // storage, which has created the directory
+            let app_dir = app.path().app_data_dir()?;
+            let config_path = Config::path(&app_dir);
+            let render_cache = RenderCache::open(&app_dir)?;
+            RenderCache::remove_stored(&storage);
+            let audit_log = AuditLog::open(&app_dir);
+            audit_log.take_stored(&storage);
+            let mut app_config = Config::load(&config_path, &storage);
+            // Timeouts, the proxy and network limits used to be kept in storage
+            if app_config.take_stored(&storage) {
+                if let Err(e) = app_config.save(&config_path, &storage) {
+                    println!("Failed to save migrated settings: {}", e);
+                }
+            }
+
+            // Apply the proxy, limits and timeouts to the long-lived clients
+            app_config.apply();
+            let timeouts = app_config.timeouts;
+            match auth::prune_expired_sessions(&storage) {
+                Ok(0) => {}
+                Ok(pruned) => println!("Pruned {} expired session(s)", pruned),
+                Err(e) => println!("Failed to prune expired sessions: {}", e),
+            }
+
+            // Service URLs come from config.json, and the environment overrides them
+            let auth_client = AuthClient::with_url(config::service_url(
+                "Auth service",
+                config::AUTH_URL_ENV_VAR,
+                &app_config.auth_url,
+            ))?;
+            auth_client.rebuild_client(timeouts.auth())?;
+            let render_client = RenderClient::with_url(config::service_url(
+                "Render service",
+                config::RENDER_URL_ENV_VAR,
+                &app_config.render_url,
+            ))?;
+            render_client.rebuild_client(timeouts.render())?;
+            let cookies = CookieJars::load(&storage);
+            let wallet = SolanaWallet::with_rpc_url(config::service_url(
+                "Solana RPC",
+                config::RPC_URL_ENV_VAR,
+                &app_config.rpc_url,
+            ));
+            wallet.load_rpc_endpoints(&storage);
+            wallet.load_priority_fee(&storage);
+            wallet.load_kdf_params(&storage);
+
+            // Remembered devices unlock without the password
+            let keyring_unlock = wallet.unlock_from_keyring(&storage);
+            if !matches!(keyring_unlock, Ok(None)) {
+                let error = keyring_unlock.err().map(|e| e.to_string());
+                match &error {
+                    Some(e) => println!("Failed to unlock wallet from the OS keyring: {}", e),
+                    None => println!("Wallet unlocked from the OS keyring"),
+                }
+                let entry = AuditEntry::new(
+                    "unlock_from_keyring",
+                    wallet.stored_address(&storage),
+                    error,
+                );
+                if let Err(e) = audit_log.append(entry) {
+                    println!("Failed to write wallet audit log: {}", e);
+                }
+            }
+
             // Initialize application state
             let app_state = AppState {
                 storage: Mutex::new(Some(storage)),
-                wallet: SolanaWallet::new(),
-                auth_client: AuthClient::new(),
-                render_client: RenderClient::new(),
+                wallet,
+                auth_client,
+                render_client,
+                render_cache,
+                audit_log,
+                descriptors: Mutex::new(HashMap::new()),
+                wallet_debug_enabled: cfg!(debug_assertions)
+                    || std::env::var_os("SOCKETBROWSER_WALLET_DEBUG").is_some(),
+                config: Mutex::new(app_config),
+                config_path,
+                cookies,
+                rate_limiter: Arc::new(RateLimiter::new()),
+                host_throttle: Arc::new(HostThrottle::new()),
+                breaker: Arc::new(CircuitBreaker::new()),
+                api_clients: Arc::new(ApiClients::new()?),
+                generations: Mutex::new(HashMap::new()),
+                next_generation_id: AtomicU64::new(1),
+                api_calls: Mutex::new(HashMap::new()),
+                next_api_call_id: AtomicU64::new(1),
+                sse_streams: Mutex::new(HashMap::new()),
+                next_sse_id: AtomicU64::new(1),
+                connectivity: Connectivity::new(),
             };
 
             app.manage(app_state);
 
+            // Watch for the connection dropping and coming back
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    check_connectivity(&app_handle).await;
+                    tokio::time::sleep(CONNECTIVITY_CHECK_INTERVAL).await;
+                }
+            });
+
+            // Lock the wallet once it's been unlocked for `auto_lock_mins`
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(AUTO_LOCK_CHECK_INTERVAL).await;
+                    check_auto_lock(&app_handle);
+                }
+            });
+
             Ok(())
         })
+        .on_page_load(|webview, payload| {
+            // Offer the last visited site once the frontend has loaded
+            if payload.event() != PageLoadEvent::Finished {
+                return;
+            }
+            let state = webview.state::<AppState>();
+            let last_site = {
+                let storage_guard = state.storage.lock().unwrap();
+                storage_guard.as_ref().and_then(sites::load_last_site)
+            };
+            if let Some(url) = last_site {
+                if let Err(e) = webview.emit("restore-site", url) {
+                    println!("Failed to emit restore-site: {}", e);
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             // Auth commands
             auth_register,
@@ -471,24 +2805,129 @@ fn main() {
             auth_get_user,
             auth_refresh,
             auth_logout,
+            auth_logout_all,
+            list_sessions,
+            revoke_session,
             // API commands
+            normalize_url_cmd,
+            probe_site,
             discover_socket_agent_cmd,
+            discover_with_diff,
+            discover_multiple,
+            discover_from_file,
+            import_openapi_descriptor,
+            export_openapi,
+            list_endpoints_cmd,
+            group_endpoints_by_tag_cmd,
+            search_endpoints_cmd,
+            get_endpoint_params,
+            get_endpoint_examples,
+            missing_required_params_cmd,
+            get_rate_limits,
+            get_circuit_breakers,
             call_api_cmd,
+            call_api_start,
+            call_api_cancel,
+            subscribe_sse,
+            unsubscribe_sse,
+            call_api_binary_cmd,
+            call_api_download_cmd,
+            call_api_all,
+            call_api_batch_cmd,
+            raw_http_call,
             generate_website,
+            preview_website,
+            start_generation,
+            cancel_generation,
+            estimate_website_cost,
+            clear_render_cache,
             // Wallet commands
             wallet_generate_new,
             wallet_import_mnemonic,
             wallet_import_private_key,
+            wallet_import_keypair_file,
+            wallet_generate_ephemeral,
+            wallet_import_ephemeral,
             wallet_unlock,
             wallet_lock,
+            remember_device,
+            forget_device,
+            is_device_remembered,
             wallet_get_address,
             wallet_get_balance,
+            wallet_get_balance_usd,
+            wallet_get_all_balances,
+            wallet_confirm_signature,
+            wallet_get_transaction,
+            wallet_build_unsigned_transfer,
+            wallet_submit_signed,
+            wallet_handle_solana_pay,
+            wallet_approve_solana_pay,
+            wallet_create_token_account,
+            wallet_get_nfts,
             wallet_export_private_key,
+            wallet_export_mnemonic,
+            wallet_export_backup,
+            wallet_import_backup,
+            wallet_import_backup_text,
             wallet_has_wallet,
+            wallet_get_stored_address,
+            wallet_debug_info,
+            wallet_verify_address,
+            validate_address,
+            address_fingerprint,
+            list_mnemonic_languages,
+            wallet_verify_message,
+            wallet_preview_address,
+            wallet_get_rpc_endpoints,
+            wallet_set_rpc_endpoints,
+            wallet_check_rpc,
+            wallet_get_priority_fee,
+            wallet_set_priority_fee,
+            wallet_estimate_priority_fee,
+            wallet_get_kdf_params,
+            wallet_set_kdf_params,
+            wallet_get_audit_log,
+            wallet_clear_audit_log,
             wallet_is_unlocked,
+            wallet_is_ephemeral,
             // Storage commands
             get_storage,
             set_storage,
+            set_storage_many,
+            // Status commands
+            system_status,
+            measure_api_latency,
+            check_api_reachability,
+            is_online,
+            // Settings commands
+            get_timeouts,
+            set_timeouts,
+            get_config,
+            update_config,
+            get_proxy,
+            set_proxy,
+            get_max_response_size,
+            set_max_response_size,
+            get_auth_retries,
+            set_auth_retries,
+            get_host_request_interval,
+            set_host_request_interval,
+            set_api_credentials,
+            get_cookie_policy,
+            set_cookie_policy,
+            clear_cookies,
+            rotate_storage_key,
+            factory_reset,
+            // Site commands
+            get_last_site,
+            set_last_site,
+            add_bookmark,
+            list_bookmarks,
+            remove_bookmark,
+            list_history,
+            clear_history,
+            get_site_icon,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,178 @@
+// Command response envelope
+// Typed payload plus a structured error, so commands don't carry a field
+// for every possible result
+
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Display;
+
+/// Result of a command that reports failures in-band. Serializes as
+/// `{ "success": true, "data": ... }` or
+/// `{ "success": false, "error": { "code": ..., "message": ... } }`.
+#[derive(Debug, Serialize)]
+pub struct ApiResult<T> {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<StructuredError>,
+}
+
+/// Why a command failed
+#[derive(Debug, Clone, Serialize)]
+pub struct StructuredError {
+    /// Stable identifier the frontend can branch on, e.g. `wallet_already_exists`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub message: String,
+    /// Fields of the underlying error the frontend can act on, such as an
+    /// HTTP `status` or `retry_after`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl<T> ApiResult<T> {
+    pub fn ok(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    pub fn err(message: impl Into<String>) -> Self {
+        Self::failure(None, message.into())
+    }
+
+    pub fn err_with_code(code: &str, message: impl Into<String>) -> Self {
+        Self::failure(Some(code.to_string()), message.into())
+    }
+
+    /// Failure from an error enum serialized with a `kind` tag, such as
+    /// `ApiError`: the tag becomes the code and the error's fields the details
+    pub fn err_from<E: Display + Serialize>(error: &E) -> Self {
+        let details = serde_json::to_value(error).ok();
+        let code = details
+            .as_ref()
+            .and_then(|details| details.get("kind"))
+            .and_then(Value::as_str)
+            .map(String::from);
+        Self {
+            success: false,
+            data: None,
+            error: Some(StructuredError {
+                code,
+                message: error.to_string(),
+                details,
+            }),
+        }
+    }
+
+    fn failure(code: Option<String>, message: String) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(StructuredError {
+                code,
+                message,
+                details: None,
+            }),
+        }
+    }
+
+    /// Message of the error, when the command failed
+    pub fn error_message(&self) -> Option<&str> {
+        self.error.as_ref().map(|error| error.message.as_str())
+    }
+}
+
+impl<T, E: Display> From<Result<T, E>> for ApiResult<T> {
+    fn from(result: Result<T, E>) -> Self {
+        match result {
+            Ok(data) => Self::ok(data),
+            Err(e) => Self::err(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Serialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    enum TestError {
+        RateLimited { retry_after: u64 },
+    }
+
+    impl Display for TestError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Slow down")
+        }
+    }
+
+    fn shape<T: Serialize>(result: &ApiResult<T>) -> Value {
+        serde_json::to_value(result).unwrap()
+    }
+
+    #[test]
+    fn successes_carry_only_their_data() {
+        assert_eq!(
+            shape(&ApiResult::ok(json!({ "balance": 1.5 }))),
+            json!({ "success": true, "data": { "balance": 1.5 } })
+        );
+        assert_eq!(
+            shape(&ApiResult::ok(())),
+            json!({ "success": true, "data": null })
+        );
+    }
+
+    #[test]
+    fn failures_carry_only_their_error() {
+        assert_eq!(
+            shape(&ApiResult::<u64>::err("Wallet is locked")),
+            json!({ "success": false, "error": { "message": "Wallet is locked" } })
+        );
+        assert_eq!(
+            shape(&ApiResult::<u64>::err_with_code(
+                "wallet_already_exists",
+                "A wallet already exists"
+            )),
+            json!({
+                "success": false,
+                "error": {
+                    "code": "wallet_already_exists",
+                    "message": "A wallet already exists",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn tagged_errors_give_their_kind_as_the_code() {
+        let result = ApiResult::<u64>::err_from(&TestError::RateLimited { retry_after: 30 });
+
+        assert_eq!(
+            shape(&result),
+            json!({
+                "success": false,
+                "error": {
+                    "code": "rate_limited",
+                    "message": "Slow down",
+                    "details": { "kind": "rate_limited", "retry_after": 30 },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn results_convert_with_the_error_message() {
+        let ok: ApiResult<u64> = Ok::<_, String>(7).into();
+        let failed: ApiResult<u64> = Err::<u64, _>("no route to host").into();
+
+        assert_eq!(shape(&ok), json!({ "success": true, "data": 7 }));
+        assert_eq!(failed.error_message(), Some("no route to host"));
+        assert!(failed.data.is_none());
+    }
+}
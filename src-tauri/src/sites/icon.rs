@@ -0,0 +1,325 @@
+// Site icons
+// The logo a descriptor advertises, or the site's /favicon.ico, cached in
+// storage so the tab strip doesn't refetch it on every visit
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::api::client::read_body_limited;
+use crate::api::SocketAgentDescriptor;
+use crate::config::client_builder;
+use crate::storage::Storage;
+
+use super::{normalize_url, unix_now};
+
+const ICON_CACHE_STORAGE_KEY: &str = "site_icons";
+/// Descriptor context keys that may hold a logo URL, in order of preference
+const LOGO_CONTEXT_KEYS: [&str; 5] = ["logo", "logoUrl", "icon", "iconUrl", "favicon"];
+const FAVICON_PATH: &str = "/favicon.ico";
+/// Icons larger than this are treated as missing
+const MAX_ICON_BYTES: usize = 256 * 1024;
+const ICON_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a fetched icon is reused
+const ICON_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+/// Sites without an icon are retried sooner, in case they add one
+const MISSING_ICON_TTL_SECS: u64 = 24 * 60 * 60;
+/// Cached icons kept; the least recently fetched are evicted first
+const MAX_ICON_CACHE_ENTRIES: usize = 100;
+
+/// A site's icon, base64 encoded for an `<img>` data URL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteIcon {
+    /// URL the icon was fetched from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base64: Option<String>,
+    /// No icon was found; the UI draws its own
+    pub placeholder: bool,
+    pub fetched_at: u64,
+}
+
+impl SiteIcon {
+    fn placeholder() -> Self {
+        Self {
+            source: None,
+            content_type: None,
+            base64: None,
+            placeholder: true,
+            fetched_at: unix_now(),
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        let ttl = if self.placeholder {
+            MISSING_ICON_TTL_SECS
+        } else {
+            ICON_TTL_SECS
+        };
+        unix_now().saturating_sub(self.fetched_at) < ttl
+    }
+}
+
+/// Cached icon for the site at `base_url`, unless it has expired
+pub fn cached_site_icon(storage: &Storage, base_url: &str) -> Option<SiteIcon> {
+    load_cache(storage)
+        .remove(&normalize_url(base_url))
+        .filter(SiteIcon::is_fresh)
+}
+
+/// Cache `icon` for the site at `base_url`, evicting the oldest past the cap
+pub fn save_site_icon(storage: &Storage, base_url: &str, icon: &SiteIcon) -> Result<()> {
+    let mut cache = load_cache(storage);
+    cache.insert(normalize_url(base_url), icon.clone());
+
+    while cache.len() > MAX_ICON_CACHE_ENTRIES {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, icon)| icon.fetched_at)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => cache.remove(&key),
+            None => break,
+        };
+    }
+
+    storage.set(
+        ICON_CACHE_STORAGE_KEY.to_string(),
+        serde_json::to_value(cache)?,
+    )
+}
+
+/// Fetch the logo the descriptor's context names, falling back to
+/// `/favicon.ico`. Failures are logged and give a placeholder.
+pub async fn fetch_site_icon(
+    base_url: &str,
+    descriptor: Option<&SocketAgentDescriptor>,
+) -> SiteIcon {
+    let base = match Url::parse(base_url) {
+        Ok(base) => base,
+        Err(e) => {
+            println!("Not fetching icon for {}: {}", base_url, e);
+            return SiteIcon::placeholder();
+        }
+    };
+
+    let mut candidates: Vec<Url> = descriptor
+        .and_then(|descriptor| descriptor.context.as_ref())
+        .into_iter()
+        .flat_map(|context| {
+            LOGO_CONTEXT_KEYS
+                .iter()
+                .filter_map(|key| context.get(*key)?.as_str())
+        })
+        .filter_map(|logo| base.join(logo).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .collect();
+    if let Ok(favicon) = base.join(FAVICON_PATH) {
+        candidates.push(favicon);
+    }
+
+    for url in candidates {
+        match fetch_icon(&url).await {
+            Ok(icon) => return icon,
+            Err(e) => println!("No icon at {}: {:#}", url, e),
+        }
+    }
+    SiteIcon::placeholder()
+}
+
+async fn fetch_icon(url: &Url) -> Result<SiteIcon> {
+    let client = client_builder()?.timeout(ICON_TIMEOUT).build()?;
+    let response = client
+        .get(url.clone())
+        .header("Accept", "image/*")
+        .send()
+        .await
+        .context("Failed to connect to server")?;
+    if !response.status().is_success() {
+        return Err(anyhow!("HTTP {}", response.status().as_u16()));
+    }
+
+    let declared = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        });
+    // Servers often send favicon.ico without an image type
+    let content_type = match declared {
+        Some(content_type) if content_type.starts_with("image/") => content_type,
+        _ if url.path().ends_with(".ico") => "image/x-icon".to_string(),
+        other => {
+            return Err(anyhow!(
+                "Not an image ({})",
+                other.as_deref().unwrap_or("no content type")
+            ))
+        }
+    };
+
+    let bytes = read_body_limited(response, MAX_ICON_BYTES).await?;
+    if bytes.is_empty() {
+        return Err(anyhow!("Empty response"));
+    }
+
+    Ok(SiteIcon {
+        source: Some(url.to_string()),
+        content_type: Some(content_type),
+        base64: Some(base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            &bytes,
+        )),
+        placeholder: false,
+        fetched_at: unix_now(),
+    })
+}
+
+fn load_cache(storage: &Storage) -> HashMap<String, SiteIcon> {
+    storage
+        .get(ICON_CACHE_STORAGE_KEY)
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `routes` (path, status, content type, body) until the test
+    /// ends; other paths 404
+    async fn serve(routes: Vec<(&'static str, u16, &'static str, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 4096];
+                let read = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, content_type, body) = routes
+                    .iter()
+                    .find(|(route, ..)| *route == path)
+                    .map(|(_, status, content_type, body)| (*status, *content_type, body.clone()))
+                    .unwrap_or((404, "text/plain", Vec::new()));
+                let head = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    content_type,
+                    body.len()
+                );
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(&body).await;
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn descriptor_with_logo(logo: &str) -> SocketAgentDescriptor {
+        serde_json::from_value(json!({
+            "name": "Test API",
+            "endpoints": [],
+            "context": { "logo": logo },
+        }))
+        .unwrap()
+    }
+
+    fn decoded(icon: &SiteIcon) -> Vec<u8> {
+        let base64 = icon.base64.as_deref().unwrap();
+        base64::engine::general_purpose::STANDARD
+            .decode(base64)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_descriptor_logo_is_preferred() {
+        let base_url = serve(vec![
+            ("/logo.png", 200, "image/png", b"png".to_vec()),
+            (FAVICON_PATH, 200, "image/x-icon", b"ico".to_vec()),
+        ])
+        .await;
+
+        let icon = fetch_site_icon(&base_url, Some(&descriptor_with_logo("/logo.png"))).await;
+
+        assert!(!icon.placeholder);
+        assert_eq!(icon.source, Some(format!("{}/logo.png", base_url)));
+        assert_eq!(icon.content_type.as_deref(), Some("image/png"));
+        assert_eq!(decoded(&icon), b"png");
+    }
+
+    #[tokio::test]
+    async fn the_favicon_is_used_when_the_logo_is_missing_or_too_large() {
+        let too_large = vec![0; MAX_ICON_BYTES + 1];
+        let base_url = serve(vec![
+            ("/big.png", 200, "image/png", too_large),
+            // Servers often send favicon.ico without an image type
+            (
+                FAVICON_PATH,
+                200,
+                "application/octet-stream",
+                b"ico".to_vec(),
+            ),
+        ])
+        .await;
+
+        for logo in ["/missing.png", "/big.png"] {
+            let icon = fetch_site_icon(&base_url, Some(&descriptor_with_logo(logo))).await;
+
+            assert_eq!(icon.source, Some(format!("{}{}", base_url, FAVICON_PATH)));
+            assert_eq!(icon.content_type.as_deref(), Some("image/x-icon"));
+            assert_eq!(decoded(&icon), b"ico");
+        }
+    }
+
+    #[tokio::test]
+    async fn sites_without_an_icon_get_a_placeholder() {
+        let base_url = serve(vec![("/logo", 200, "text/html", b"<html>".to_vec())]).await;
+
+        let icon = fetch_site_icon(&base_url, Some(&descriptor_with_logo("/logo"))).await;
+
+        assert!(icon.placeholder);
+        assert_eq!(icon.base64, None);
+    }
+
+    #[test]
+    fn cached_icons_are_reused_until_they_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let icon = SiteIcon {
+            source: Some("https://example.com/favicon.ico".to_string()),
+            content_type: Some("image/x-icon".to_string()),
+            base64: Some("aWNv".to_string()),
+            placeholder: false,
+            fetched_at: unix_now(),
+        };
+
+        save_site_icon(&storage, "https://example.com/", &icon).unwrap();
+        let cached = cached_site_icon(&storage, "https://example.com").unwrap();
+        assert_eq!(cached.base64.as_deref(), Some("aWNv"));
+
+        let placeholder = SiteIcon {
+            fetched_at: unix_now() - MISSING_ICON_TTL_SECS,
+            ..SiteIcon::placeholder()
+        };
+        save_site_icon(&storage, "https://example.com", &placeholder).unwrap();
+        assert!(cached_site_icon(&storage, "https://example.com").is_none());
+    }
+}
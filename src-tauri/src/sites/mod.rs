@@ -0,0 +1,161 @@
+// Sites module for Socket Browser
+// Remembers the Socket Agent sites the user visits
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::storage::Storage;
+
+mod icon;
+
+pub use icon::{cached_site_icon, fetch_site_icon, save_site_icon, SiteIcon};
+
+const LAST_SITE_STORAGE_KEY: &str = "last_site";
+const BOOKMARKS_STORAGE_KEY: &str = "bookmarks";
+const HISTORY_STORAGE_KEY: &str = "visit_history";
+
+/// Visits kept in history; older ones are dropped
+pub const MAX_HISTORY_ENTRIES: usize = 100;
+
+/// Canonical form of a site URL used as its storage key
+pub fn normalize_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+/// Base URL of the last site successfully discovered, if any
+pub fn load_last_site(storage: &Storage) -> Option<String> {
+    match storage.get(LAST_SITE_STORAGE_KEY).ok()? {
+        Some(Value::String(url)) if !url.is_empty() => Some(url),
+        _ => None,
+    }
+}
+
+/// Remember the last site; `None` forgets it
+pub fn save_last_site(storage: &Storage, url: Option<&str>) -> Result<()> {
+    match url.map(normalize_url).filter(|url| !url.is_empty()) {
+        Some(url) => storage.set(LAST_SITE_STORAGE_KEY.to_string(), Value::String(url)),
+        None => storage.remove(LAST_SITE_STORAGE_KEY),
+    }
+}
+
+/// A site the user saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub url: String,
+    pub name: String,
+    pub created_at: u64,
+}
+
+/// A discovered site and when it was last visited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub visited_at: u64,
+}
+
+/// Bookmarks sorted by name
+pub fn list_bookmarks(storage: &Storage) -> Vec<Bookmark> {
+    let mut bookmarks: Vec<Bookmark> = load_list(storage, BOOKMARKS_STORAGE_KEY);
+    bookmarks.sort_by_key(|b| b.name.to_lowercase());
+    bookmarks
+}
+
+/// Save a bookmark, renaming it if the URL is already bookmarked
+pub fn add_bookmark(storage: &Storage, url: &str, name: &str) -> Result<Bookmark> {
+    let url = normalize_url(url);
+    if url.is_empty() {
+        return Err(anyhow!("Bookmark URL cannot be empty"));
+    }
+    let name = match name.trim() {
+        "" => url.clone(),
+        name => name.to_string(),
+    };
+
+    let mut bookmarks: Vec<Bookmark> = load_list(storage, BOOKMARKS_STORAGE_KEY);
+    let bookmark = match bookmarks.iter_mut().find(|b| b.url == url) {
+        Some(existing) => {
+            existing.name = name;
+            existing.clone()
+        }
+        None => {
+            let bookmark = Bookmark {
+                url,
+                name,
+                created_at: unix_now(),
+            };
+            bookmarks.push(bookmark.clone());
+            bookmark
+        }
+    };
+
+    save_list(storage, BOOKMARKS_STORAGE_KEY, &bookmarks)?;
+    Ok(bookmark)
+}
+
+/// Remove a bookmark; returns whether one was removed
+pub fn remove_bookmark(storage: &Storage, url: &str) -> Result<bool> {
+    let url = normalize_url(url);
+    let mut bookmarks: Vec<Bookmark> = load_list(storage, BOOKMARKS_STORAGE_KEY);
+    let before = bookmarks.len();
+    bookmarks.retain(|b| b.url != url);
+
+    if bookmarks.len() == before {
+        return Ok(false);
+    }
+    save_list(storage, BOOKMARKS_STORAGE_KEY, &bookmarks)?;
+    Ok(true)
+}
+
+/// Visit history, most recent first
+pub fn list_history(storage: &Storage) -> Vec<HistoryEntry> {
+    let mut history: Vec<HistoryEntry> = load_list(storage, HISTORY_STORAGE_KEY);
+    history.sort_by_key(|entry| std::cmp::Reverse(entry.visited_at));
+    history
+}
+
+/// Record a visit, keeping one entry per URL and at most `MAX_HISTORY_ENTRIES`
+pub fn record_history(storage: &Storage, url: &str, name: Option<&str>) -> Result<()> {
+    let url = normalize_url(url);
+    let mut history = list_history(storage);
+    history.retain(|entry| entry.url != url);
+    history.insert(
+        0,
+        HistoryEntry {
+            url,
+            name: name.map(String::from),
+            visited_at: unix_now(),
+        },
+    );
+    history.truncate(MAX_HISTORY_ENTRIES);
+
+    save_list(storage, HISTORY_STORAGE_KEY, &history)
+}
+
+pub fn clear_history(storage: &Storage) -> Result<()> {
+    storage.remove(HISTORY_STORAGE_KEY)
+}
+
+fn load_list<T: DeserializeOwned>(storage: &Storage, key: &str) -> Vec<T> {
+    storage
+        .get(key)
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn save_list<T: Serialize>(storage: &Storage, key: &str, items: &[T]) -> Result<()> {
+    storage.set(key.to_string(), serde_json::to_value(items)?)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
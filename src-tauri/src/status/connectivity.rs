@@ -0,0 +1,150 @@
+// Connectivity monitor
+// Tracks whether the machine is online and holds background refreshes
+// until the connection returns
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::client_builder;
+
+/// How often the background monitor probes for connectivity
+pub const CONNECTIVITY_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Any HTTP response from one of these means the network is up, whatever
+// the status of the services the browser talks to
+const PROBE_URLS: &[&str] = &[
+    "https://www.gstatic.com/generate_204",
+    "https://cloudflare.com/cdn-cgi/trace",
+];
+
+pub const OFFLINE_MESSAGE: &str = "You're offline. Check your connection and try again.";
+
+/// Background work that can wait for the connection to come back.
+/// User-initiated actions like sends are never deferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeferredOperation {
+    BalanceRefresh,
+    NftRefresh,
+}
+
+pub struct Connectivity {
+    online: AtomicBool,
+    deferred: Mutex<Vec<DeferredOperation>>,
+}
+
+impl Connectivity {
+    /// Starts out assuming the machine is online until a probe says otherwise
+    pub fn new() -> Self {
+        Self {
+            online: AtomicBool::new(true),
+            deferred: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::Relaxed)
+    }
+
+    /// Probe the network and record the result. Returns the new state when
+    /// it changed since the last check.
+    pub async fn check(&self) -> Option<bool> {
+        let online = probe().await;
+        self.set_online(online)
+    }
+
+    /// Record the connectivity state, returning it when it changed
+    pub fn set_online(&self, online: bool) -> Option<bool> {
+        let was_online = self.online.swap(online, Ordering::Relaxed);
+        (was_online != online).then_some(online)
+    }
+
+    /// Queue an operation for when the connection returns; queuing the same
+    /// operation twice runs it once
+    pub fn defer(&self, operation: DeferredOperation) {
+        let mut deferred = self.deferred.lock().unwrap();
+        if !deferred.contains(&operation) {
+            deferred.push(operation);
+        }
+    }
+
+    /// Take every queued operation, in the order they were queued
+    pub fn take_deferred(&self) -> Vec<DeferredOperation> {
+        std::mem::take(&mut *self.deferred.lock().unwrap())
+    }
+
+    pub fn clear(&self) {
+        self.deferred.lock().unwrap().clear();
+    }
+}
+
+impl Default for Connectivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn probe() -> bool {
+    let client = client_builder().and_then(|builder| Ok(builder.timeout(PROBE_TIMEOUT).build()?));
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => {
+            println!("Connectivity check failed to build a client: {}", e);
+            return false;
+        }
+    };
+
+    for url in PROBE_URLS {
+        if client.head(*url).send().await.is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changes_in_state_are_reported() {
+        let connectivity = Connectivity::new();
+        assert!(connectivity.is_online());
+
+        assert_eq!(connectivity.set_online(true), None);
+        assert_eq!(connectivity.set_online(false), Some(false));
+        assert!(!connectivity.is_online());
+        assert_eq!(connectivity.set_online(false), None);
+        assert_eq!(connectivity.set_online(true), Some(true));
+    }
+
+    #[test]
+    fn deferred_operations_run_once_in_order() {
+        let connectivity = Connectivity::new();
+        connectivity.defer(DeferredOperation::NftRefresh);
+        connectivity.defer(DeferredOperation::BalanceRefresh);
+        connectivity.defer(DeferredOperation::NftRefresh);
+
+        assert_eq!(
+            connectivity.take_deferred(),
+            vec![
+                DeferredOperation::NftRefresh,
+                DeferredOperation::BalanceRefresh
+            ]
+        );
+        assert!(connectivity.take_deferred().is_empty());
+    }
+
+    #[test]
+    fn clearing_drops_deferred_operations() {
+        let connectivity = Connectivity::new();
+        connectivity.defer(DeferredOperation::BalanceRefresh);
+
+        connectivity.clear();
+
+        assert!(connectivity.take_deferred().is_empty());
+    }
+}
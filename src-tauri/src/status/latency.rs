@@ -0,0 +1,161 @@
+// Latency measurement
+// Times round trips to a Socket Agent API's discovery endpoint for
+// developers tuning their API
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::api::discovery::DISCOVERY_PATH;
+use crate::api::ApiClients;
+
+pub const DEFAULT_LATENCY_SAMPLES: u32 = 10;
+pub const MAX_LATENCY_SAMPLES: u32 = 50;
+/// Pause between samples so they don't queue behind one another
+const SAMPLE_DELAY: Duration = Duration::from_millis(100);
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Round-trip times in milliseconds
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyStats {
+    pub samples: usize,
+    /// Requests that got no response; not counted in the statistics
+    pub failed: usize,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub mean_ms: f64,
+    pub p95_ms: f64,
+}
+
+/// Time `samples` HEAD requests to the discovery endpoint of `base_url`,
+/// one after another. An untimed request goes first so connection setup
+/// doesn't count against the first sample. Any HTTP response counts.
+pub async fn measure_latency(
+    base_url: &str,
+    samples: u32,
+    clients: &ApiClients,
+) -> Result<LatencyStats> {
+    let url = format!("{}{}", base_url.trim_end_matches('/'), DISCOVERY_PATH);
+    let client = clients.client(base_url, None)?;
+    let ping = || {
+        client
+            .head(&url)
+            .timeout(SAMPLE_TIMEOUT)
+            .header("User-Agent", "Socket-Browser/0.1.0")
+            .send()
+    };
+
+    ping().await.context("Failed to connect to server")?;
+
+    let mut durations = Vec::new();
+    let mut failed = 0;
+    for _ in 0..samples.clamp(1, MAX_LATENCY_SAMPLES) {
+        tokio::time::sleep(SAMPLE_DELAY).await;
+        let started = Instant::now();
+        match ping().await {
+            Ok(_) => durations.push(started.elapsed()),
+            Err(e) => {
+                println!("Latency sample to {} failed: {}", url, e);
+                failed += 1;
+            }
+        }
+    }
+
+    let mut stats = latency_stats(&durations).ok_or_else(|| anyhow!("No sample got a response"))?;
+    stats.failed = failed;
+    Ok(stats)
+}
+
+/// Statistics over `durations`, or `None` when there are none. The 95th
+/// percentile is by nearest rank.
+pub fn latency_stats(durations: &[Duration]) -> Option<LatencyStats> {
+    let mut millis: Vec<f64> = durations
+        .iter()
+        .map(|duration| duration.as_secs_f64() * 1000.0)
+        .collect();
+    millis.sort_by(f64::total_cmp);
+
+    let min_ms = *millis.first()?;
+    let max_ms = *millis.last()?;
+    let mean_ms = millis.iter().sum::<f64>() / millis.len() as f64;
+    let rank = (millis.len() as f64 * 0.95).ceil() as usize;
+    let p95_ms = millis[rank.saturating_sub(1)];
+
+    Some(LatencyStats {
+        samples: millis.len(),
+        failed: 0,
+        min_ms,
+        max_ms,
+        mean_ms,
+        p95_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve every request after `delay`, returning the URL and how many
+    /// requests came in
+    async fn serve_slowly(delay: Duration) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let received = received.clone();
+                tokio::spawn(async move {
+                    let mut request = [0u8; 4096];
+                    let _ = socket.read(&mut request).await;
+                    received.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(delay).await;
+                    let head = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                    let _ = socket.write_all(head.as_bytes()).await;
+                });
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    #[test]
+    fn stats_cover_min_max_mean_and_p95() {
+        let durations: Vec<Duration> = (1..=20).map(Duration::from_millis).collect();
+
+        let stats = latency_stats(&durations).unwrap();
+
+        assert_eq!(stats.samples, 20);
+        assert_eq!(stats.min_ms, 1.0);
+        assert_eq!(stats.max_ms, 20.0);
+        assert_eq!(stats.mean_ms, 10.5);
+        // Nearest rank: the 19th of 20
+        assert_eq!(stats.p95_ms, 19.0);
+    }
+
+    #[test]
+    fn stats_need_at_least_one_sample() {
+        assert!(latency_stats(&[]).is_none());
+
+        let stats = latency_stats(&[Duration::from_millis(7)]).unwrap();
+        assert_eq!((stats.min_ms, stats.max_ms, stats.p95_ms), (7.0, 7.0, 7.0));
+    }
+
+    #[tokio::test]
+    async fn samples_time_each_round_trip() {
+        let delay = Duration::from_millis(30);
+        let (base_url, requests) = serve_slowly(delay).await;
+        let clients = ApiClients::new().unwrap();
+
+        let stats = measure_latency(&base_url, 3, &clients).await.unwrap();
+
+        assert_eq!((stats.samples, stats.failed), (3, 0));
+        assert!(stats.min_ms >= 30.0, "{:?}", stats);
+        assert!(stats.min_ms <= stats.mean_ms && stats.mean_ms <= stats.max_ms);
+        // The untimed warm-up request goes first
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,82 @@
+// Service status checks for Socket Browser
+// Reports reachability and latency of the services the browser depends on
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::config::client_builder;
+
+mod connectivity;
+mod latency;
+
+pub use connectivity::{
+    Connectivity, DeferredOperation, CONNECTIVITY_CHECK_INTERVAL, OFFLINE_MESSAGE,
+};
+pub use latency::{measure_latency, LatencyStats, DEFAULT_LATENCY_SAMPLES};
+
+/// Overall time allowed for a status check
+pub const STATUS_BUDGET: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub up: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SystemStatusResponse {
+    pub auth: ServiceStatus,
+    pub render: ServiceStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api: Option<ServiceStatus>,
+}
+
+/// Run a health check within `budget`, timing it and turning failures and
+/// timeouts into a down status instead of an error
+pub async fn check_service<F>(name: &str, budget: Duration, check: F) -> ServiceStatus
+where
+    F: Future<Output = Result<bool>>,
+{
+    let started = Instant::now();
+    let result = tokio::time::timeout(budget, check).await;
+    let latency_ms = Some(started.elapsed().as_millis() as u64);
+
+    let (up, error) = match result {
+        Ok(Ok(true)) => (true, None),
+        Ok(Ok(false)) => (false, Some("Unhealthy response".to_string())),
+        Ok(Err(e)) => (false, Some(e.to_string())),
+        Err(_) => (false, Some(format!("No response within {}s", budget.as_secs()))),
+    };
+
+    ServiceStatus {
+        name: name.to_string(),
+        up,
+        latency_ms: if up { latency_ms } else { None },
+        error,
+    }
+}
+
+/// Check that a Socket Agent API still serves its discovery descriptor
+pub async fn ping_socket_agent(base_url: &str) -> Result<bool> {
+    let url = format!("{}/.well-known/socket-agent", base_url.trim_end_matches('/'));
+
+    let client = client_builder()?.timeout(STATUS_BUDGET).build()?;
+    let response = client
+        .get(&url)
+        .header("User-Agent", "Socket-Browser/0.1.0")
+        .send()
+        .await
+        .context("Failed to connect to server")?;
+
+    if response.status().is_server_error() {
+        return Err(anyhow!("HTTP {}", response.status().as_u16()));
+    }
+
+    Ok(response.status().is_success())
+}
@@ -1,7 +1,12 @@
 // Storage module for Socket Browser
 // Provides persistent JSON file storage
 
-use anyhow::{Context, Result};
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{anyhow, Context, Result};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -12,6 +17,18 @@ use std::sync::Mutex;
 pub struct Storage {
     file_path: PathBuf,
     data: Mutex<HashMap<String, Value>>,
+    /// File writes so far, so tests can tell how often the file is rewritten
+    #[cfg(test)]
+    writes: std::sync::atomic::AtomicUsize,
+}
+
+/// Outcome of `Storage::set_each`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BatchWrite {
+    /// Keys written, sorted
+    pub saved: Vec<String>,
+    /// Keys left alone, with why
+    pub failed: HashMap<String, String>,
 }
 
 impl Storage {
@@ -23,8 +40,11 @@ impl Storage {
 
         fs::create_dir_all(&app_dir).context("Failed to create app data directory")?;
 
-        let file_path = app_dir.join("wallet-storage.json");
+        Self::open(app_dir.join("wallet-storage.json"))
+    }
 
+    /// Storage backed by the file at `file_path`, created on first write
+    pub fn open(file_path: PathBuf) -> Result<Self> {
         // Load existing data or create new
         let data = if file_path.exists() {
             let contents = fs::read_to_string(&file_path)
@@ -38,6 +58,8 @@ impl Storage {
         Ok(Self {
             file_path,
             data: Mutex::new(data),
+            #[cfg(test)]
+            writes: Default::default(),
         })
     }
 
@@ -53,6 +75,38 @@ impl Storage {
         Ok(())
     }
 
+    /// Set several keys in one write: either all are saved or none are
+    pub fn set_many(&self, entries: Vec<(String, Value)>) -> Result<()> {
+        let mut data = self.data.lock().unwrap();
+        let mut updated = data.clone();
+        updated.extend(entries);
+        self.save(&updated)?;
+        *data = updated;
+        Ok(())
+    }
+
+    /// Set several unrelated keys in one write. An entry with a blank key is
+    /// reported in `failed` rather than failing the rest; only a failed file
+    /// write is an error, and then nothing is saved.
+    pub fn set_each(&self, entries: HashMap<String, Value>) -> Result<BatchWrite> {
+        let mut report = BatchWrite::default();
+        let mut valid = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            if key.trim().is_empty() {
+                report.failed.insert(key, "Key is empty".to_string());
+                continue;
+            }
+            report.saved.push(key.clone());
+            valid.push((key, value));
+        }
+
+        if !valid.is_empty() {
+            self.set_many(valid)?;
+        }
+        report.saved.sort();
+        Ok(report)
+    }
+
     pub fn remove(&self, key: &str) -> Result<()> {
         let mut data = self.data.lock().unwrap();
         data.remove(key);
@@ -60,11 +114,210 @@ impl Storage {
         Ok(())
     }
 
+    /// Remove every key and rewrite the file empty
+    pub fn clear(&self) -> Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.clear();
+        self.save(&data)?;
+        Ok(())
+    }
+
+    /// Encrypt `data` with the local key stored under `key_name`, generated
+    /// on first use. This keeps secrets out of plain sight in the storage
+    /// file; it is not a substitute for a password, since the key lives
+    /// alongside the data.
+    pub fn seal(&self, key_name: &str, data: &[u8]) -> Result<String> {
+        encrypt(data, &self.local_key(key_name)?)
+    }
+
+    /// Decrypt what `seal` encrypted with the key under `key_name`
+    pub fn unseal(&self, key_name: &str, sealed: &str) -> Result<Vec<u8>> {
+        decrypt(sealed, &self.local_key(key_name)?)
+    }
+
+    /// The local key stored under `key_name`, generated on first use
+    pub fn local_key(&self, key_name: &str) -> Result<[u8; 32]> {
+        if let Some(Value::String(encoded)) = self.get(key_name)? {
+            let bytes = hex::decode(encoded).context("Invalid local key")?;
+            return bytes
+                .try_into()
+                .map_err(|_| anyhow!("Invalid local key length"));
+        }
+
+        let key: [u8; 32] = rand::thread_rng().gen();
+        self.set(key_name.to_string(), Value::String(hex::encode(key)))?;
+        Ok(key)
+    }
+
     fn save(&self, data: &HashMap<String, Value>) -> Result<()> {
         let json = serde_json::to_string_pretty(data)
             .context("Failed to serialize storage")?;
-        fs::write(&self.file_path, json)
+
+        // Write a sibling file and rename it over the original, so a failed
+        // write never leaves a truncated storage file behind
+        let tmp_path = self.file_path.with_extension("json.tmp");
+        fs::write(&tmp_path, json)
             .context("Failed to write storage file")?;
+        fs::rename(&tmp_path, &self.file_path)
+            .context("Failed to replace storage file")?;
+        #[cfg(test)]
+        self.writes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         Ok(())
     }
 }
+
+/// A fresh local key to replace the one under `key_name`. Values sealed
+/// under the old key are moved over with `reseal`, but nothing is saved
+/// here: the caller writes `key_entry` in the same `set_many` as those
+/// values, so a failure part way leaves the old key and data in place.
+pub struct KeyRotation {
+    key_name: String,
+    old_key: [u8; 32],
+    new_key: [u8; 32],
+}
+
+impl KeyRotation {
+    pub fn new(storage: &Storage, key_name: &str) -> Result<Self> {
+        Ok(Self {
+            key_name: key_name.to_string(),
+            old_key: storage.local_key(key_name)?,
+            new_key: rand::thread_rng().gen(),
+        })
+    }
+
+    /// Decrypt a value sealed under the old key
+    pub fn unseal(&self, sealed: &str) -> Result<Vec<u8>> {
+        decrypt(sealed, &self.old_key)
+    }
+
+    /// Encrypt `data` under the new key
+    pub fn seal(&self, data: &[u8]) -> Result<String> {
+        encrypt(data, &self.new_key)
+    }
+
+    /// Re-encrypt a value sealed under the old key
+    pub fn reseal(&self, sealed: &str) -> Result<String> {
+        self.seal(&self.unseal(sealed)?)
+    }
+
+    /// Storage entry holding the new key
+    pub fn key_entry(&self) -> (String, Value) {
+        (
+            self.key_name.clone(),
+            Value::String(hex::encode(self.new_key)),
+        )
+    }
+}
+
+/// Encrypt with AES-256-GCM: nonce (12) + ciphertext, base64 encoded
+pub fn encrypt(data: &[u8], key: &[u8; 32]) -> Result<String> {
+    let nonce_bytes: [u8; 12] = rand::thread_rng().gen();
+    let cipher = Aes256Gcm::new(key.into());
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), data)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+
+    let mut combined = nonce_bytes.to_vec();
+    combined.extend_from_slice(&ciphertext);
+    Ok(base64::Engine::encode(
+        &base64::engine::general_purpose::STANDARD,
+        combined,
+    ))
+}
+
+pub fn decrypt(encrypted_data: &str, key: &[u8; 32]) -> Result<Vec<u8>> {
+    let combined =
+        base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encrypted_data)
+            .context("Invalid base64")?;
+    if combined.len() < 12 {
+        return Err(anyhow!("Invalid encrypted data"));
+    }
+
+    let (nonce_bytes, ciphertext) = combined.split_at(12);
+    let cipher = Aes256Gcm::new(key.into());
+    cipher
+        .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|_| anyhow!("Failed to decrypt stored data"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    fn temp_storage() -> (tempfile::TempDir, Storage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        (dir, storage)
+    }
+
+    #[test]
+    fn a_batch_is_written_once() {
+        let (dir, storage) = temp_storage();
+        let entries: HashMap<String, Value> = (0..10)
+            .map(|i| (format!("key{}", i), Value::from(i)))
+            .collect();
+
+        let report = storage.set_each(entries).unwrap();
+
+        assert_eq!(storage.writes.load(Ordering::SeqCst), 1);
+        assert_eq!(report.saved.len(), 10);
+        assert!(report.failed.is_empty());
+        let reopened = Storage::open(dir.path().join("storage.json")).unwrap();
+        assert_eq!(reopened.get("key7").unwrap(), Some(Value::from(7)));
+    }
+
+    #[test]
+    fn blank_keys_are_reported_and_the_rest_saved() {
+        let (_dir, storage) = temp_storage();
+        let entries = HashMap::from([
+            ("theme".to_string(), Value::from("dark")),
+            (" ".to_string(), Value::from(1)),
+        ]);
+
+        let report = storage.set_each(entries).unwrap();
+
+        assert_eq!(report.saved, vec!["theme".to_string()]);
+        assert_eq!(
+            report.failed.get(" ").map(String::as_str),
+            Some("Key is empty")
+        );
+        assert_eq!(storage.get("theme").unwrap(), Some(Value::from("dark")));
+        assert_eq!(storage.get(" ").unwrap(), None);
+    }
+
+    #[test]
+    fn a_batch_of_blank_keys_writes_nothing() {
+        let (dir, storage) = temp_storage();
+
+        let report = storage
+            .set_each(HashMap::from([(String::new(), Value::Null)]))
+            .unwrap();
+
+        assert!(report.saved.is_empty());
+        assert_eq!(storage.writes.load(Ordering::SeqCst), 0);
+        assert!(!dir.path().join("storage.json").exists());
+    }
+
+    #[test]
+    fn rotated_keys_open_what_was_resealed_and_nothing_else() {
+        let (_dir, storage) = temp_storage();
+        let sealed = storage.seal("test_key", b"secret").unwrap();
+        let old_key = storage.get("test_key").unwrap();
+
+        let rotation = KeyRotation::new(&storage, "test_key").unwrap();
+        let resealed = rotation.reseal(&sealed).unwrap();
+        // Nothing changes until the new key is saved
+        assert_eq!(storage.get("test_key").unwrap(), old_key);
+        storage
+            .set_many(vec![
+                rotation.key_entry(),
+                ("data".to_string(), Value::from(resealed.clone())),
+            ])
+            .unwrap();
+
+        assert_ne!(storage.get("test_key").unwrap(), old_key);
+        assert_eq!(storage.unseal("test_key", &resealed).unwrap(), b"secret");
+        assert!(storage.unseal("test_key", &sealed).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+// Wallet audit log
+// Append-only record of wallet operations; never stores keys, phrases or passwords.
+// Kept in its own file, one JSON entry per line, so the generic storage
+// commands can't rewrite it and an append never rewrites the storage file.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::SolanaWallet;
+use crate::storage::Storage;
+
+const AUDIT_LOG_FILE_NAME: &str = "wallet-audit.log";
+// Where the log was kept in app storage before it had its own file
+const AUDIT_LOG_STORAGE_KEY: &str = "wallet_audit_log";
+
+/// Entries kept in the log; the oldest are dropped first
+pub const MAX_AUDIT_ENTRIES: usize = 1000;
+
+/// One wallet operation and how it ended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub operation: String,
+    pub timestamp: u64,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(operation: &str, address: Option<String>, error: Option<String>) -> Self {
+        Self {
+            operation: operation.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            success: error.is_none(),
+            address,
+            error,
+        }
+    }
+}
+
+/// Audit log in `wallet-audit.log` in the app data dir
+pub struct AuditLog {
+    path: PathBuf,
+    // Lines in the file, once counted; also serializes writes
+    lines: Mutex<Option<usize>>,
+}
+
+impl AuditLog {
+    pub fn open(app_dir: &Path) -> Self {
+        Self {
+            path: app_dir.join(AUDIT_LOG_FILE_NAME),
+            lines: Mutex::new(None),
+        }
+    }
+
+    /// Move entries kept in app storage before the log had its own file
+    pub fn take_stored(&self, storage: &Storage) {
+        let Some(stored) = storage.get(AUDIT_LOG_STORAGE_KEY).ok().flatten() else {
+            return;
+        };
+        let entries: Vec<AuditEntry> = serde_json::from_value(stored).unwrap_or_default();
+        for entry in entries {
+            if let Err(e) = self.append(entry) {
+                println!("Failed to move the wallet audit log: {}", e);
+                return;
+            }
+        }
+        if let Err(e) = storage.remove(AUDIT_LOG_STORAGE_KEY) {
+            println!("Failed to remove the old wallet audit log: {}", e);
+        }
+    }
+
+    /// Record `operation` for `wallet`, which failed with `error` if given.
+    /// In-memory wallets leave no trace on disk. A failed write is only
+    /// logged, so it never blocks the operation itself.
+    pub fn record(
+        &self,
+        wallet: &SolanaWallet,
+        storage: &Storage,
+        operation: &str,
+        error: Option<String>,
+    ) {
+        if wallet.is_ephemeral() {
+            return;
+        }
+        let address = wallet
+            .get_address()
+            .ok()
+            .or_else(|| wallet.stored_address(storage));
+
+        if let Err(e) = self.append(AuditEntry::new(operation, address, error)) {
+            println!("Failed to write wallet audit log: {}", e);
+        }
+    }
+
+    /// Append an entry as one line. Once the file holds twice the entries
+    /// kept, it's rewritten with only the newest.
+    pub fn append(&self, entry: AuditEntry) -> Result<()> {
+        let mut lines = self.lines.lock().unwrap();
+        let count = match *lines {
+            Some(count) => count,
+            None => self.read_lines()?.len(),
+        };
+
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("Failed to open the wallet audit log")?;
+        file.write_all(line.as_bytes())
+            .context("Failed to write the wallet audit log")?;
+        *lines = Some(count + 1);
+
+        if count + 1 >= 2 * MAX_AUDIT_ENTRIES {
+            let kept = self.read_lines()?;
+            let kept = &kept[kept.len().saturating_sub(MAX_AUDIT_ENTRIES)..];
+            let tmp_path = self.path.with_extension("log.tmp");
+            fs::write(&tmp_path, kept.concat())
+                .and_then(|_| fs::rename(&tmp_path, &self.path))
+                .context("Failed to trim the wallet audit log")?;
+            *lines = Some(kept.len());
+        }
+        Ok(())
+    }
+
+    /// Newest entries, oldest first, optionally with addresses shortened.
+    /// Lines that don't parse are skipped.
+    pub fn load(&self, redact_addresses: bool) -> Vec<AuditEntry> {
+        let _lines = self.lines.lock().unwrap();
+        let mut entries: Vec<AuditEntry> = self
+            .read_lines()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        entries.drain(..entries.len().saturating_sub(MAX_AUDIT_ENTRIES));
+        if redact_addresses {
+            for entry in &mut entries {
+                entry.address = entry.address.as_deref().map(redact_address);
+            }
+        }
+        entries
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        let mut lines = self.lines.lock().unwrap();
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).context("Failed to clear the wallet audit log")
+            }
+            _ => {
+                *lines = Some(0);
+                Ok(())
+            }
+        }
+    }
+
+    /// Lines of the file, each with its newline
+    fn read_lines(&self) -> Result<Vec<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(contents.split_inclusive('\n').map(String::from).collect()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e).context("Failed to read the wallet audit log"),
+        }
+    }
+}
+
+/// Keep the first and last four characters, e.g. `7xKX…sAsU`
+pub fn redact_address(address: &str) -> String {
+    let chars: Vec<char> = address.chars().collect();
+    if chars.len() <= 8 {
+        return "…".to_string();
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU";
+
+    fn temp_log() -> (tempfile::TempDir, AuditLog) {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::open(dir.path());
+        (dir, log)
+    }
+
+    #[test]
+    fn addresses_are_shortened_to_their_ends() {
+        assert_eq!(redact_address(ADDRESS), "7xKX…gAsU");
+        assert_eq!(redact_address("123456789"), "1234…6789");
+        assert_eq!(redact_address("12345678"), "…");
+        assert_eq!(redact_address(""), "…");
+    }
+
+    #[test]
+    fn only_the_newest_entries_are_kept() {
+        let (dir, log) = temp_log();
+        let count = 2 * MAX_AUDIT_ENTRIES + 1;
+        for i in 0..count {
+            log.append(AuditEntry::new(&format!("op{}", i), None, None))
+                .unwrap();
+        }
+
+        let entries = log.load(false);
+
+        assert_eq!(entries.len(), MAX_AUDIT_ENTRIES);
+        assert_eq!(
+            entries[0].operation,
+            format!("op{}", count - MAX_AUDIT_ENTRIES)
+        );
+        assert_eq!(
+            entries.last().unwrap().operation,
+            format!("op{}", count - 1)
+        );
+        // The file was trimmed rather than left to grow
+        let file = fs::read_to_string(dir.path().join(AUDIT_LOG_FILE_NAME)).unwrap();
+        assert!(file.lines().count() < 2 * MAX_AUDIT_ENTRIES);
+    }
+
+    #[test]
+    fn addresses_are_redacted_on_request_and_the_log_can_be_cleared() {
+        let (_dir, log) = temp_log();
+        let failed = AuditEntry::new("unlock", None, Some("Wrong password".to_string()));
+        assert!(!failed.success);
+        log.append(failed).unwrap();
+        let unlocked = AuditEntry::new("unlock", Some(ADDRESS.to_string()), None);
+        log.append(unlocked).unwrap();
+
+        let full = log.load(false);
+        let redacted = log.load(true);
+
+        assert_eq!(full[1].address.as_deref(), Some(ADDRESS));
+        assert_eq!(redacted[1].address.as_deref(), Some("7xKX…gAsU"));
+        assert_eq!(redacted[0].address, None);
+        assert_eq!(redacted[0].error.as_deref(), Some("Wrong password"));
+        log.clear().unwrap();
+        assert!(log.load(false).is_empty());
+        log.append(AuditEntry::new("lock", None, None)).unwrap();
+        assert_eq!(log.load(false).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_send_appends_exactly_one_entry() {
+        let (dir, log) = temp_log();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let wallet = SolanaWallet::with_rpc_url("http://127.0.0.1:8899".to_string());
+        let key = bs58::encode(solana_sdk::signature::Keypair::new().to_bytes()).into_string();
+        let account = wallet
+            .import_from_private_key(&key, "password", false, &storage)
+            .unwrap();
+        let storage_file = dir.path().join("storage.json");
+        let stored = fs::read_to_string(&storage_file).unwrap();
+
+        // Fails before reaching the network, as `wallet_submit_signed` would report it
+        let result = wallet.submit_signed_transaction("not a transaction").await;
+        log.record(
+            &wallet,
+            &storage,
+            "submit_transaction",
+            result.err().map(|e| e.to_string()),
+        );
+
+        let entries = log.load(false);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].operation, "submit_transaction");
+        assert!(!entries[0].success);
+        assert_eq!(entries[0].address, Some(account.address));
+        // The wallet's storage file isn't touched by the log
+        assert_eq!(fs::read_to_string(&storage_file).unwrap(), stored);
+    }
+
+    #[test]
+    fn entries_kept_in_storage_move_to_the_file() {
+        let (dir, log) = temp_log();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let old = vec![AuditEntry::new("unlock", Some(ADDRESS.to_string()), None)];
+        storage
+            .set(
+                AUDIT_LOG_STORAGE_KEY.to_string(),
+                serde_json::to_value(old).unwrap(),
+            )
+            .unwrap();
+
+        log.take_stored(&storage);
+
+        assert_eq!(log.load(false)[0].address.as_deref(), Some(ADDRESS));
+        assert!(storage.get(AUDIT_LOG_STORAGE_KEY).unwrap().is_none());
+    }
+}
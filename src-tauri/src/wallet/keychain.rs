@@ -0,0 +1,69 @@
+// OS keyring entry for "remember this device"
+// Holds the keys derived from the wallet password, never the password or the
+// wallet key itself, so a remembered wallet can unlock after a restart
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+const KEYRING_SERVICE: &str = "socket-browser-wallet";
+
+/// AES keys for the wallet's encrypted blobs in storage
+pub struct DeviceKeys {
+    pub wallet_key: [u8; 32],
+    /// Only present when the recovery phrase is stored too
+    pub mnemonic_key: Option<[u8; 32]>,
+}
+
+// Keyring entries are strings, so the keys are hex encoded
+#[derive(Serialize, Deserialize)]
+struct StoredKeys {
+    wallet_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mnemonic_key: Option<String>,
+}
+
+/// Save the keys for the wallet at `address`, replacing any saved before
+pub fn save_device_keys(address: &str, keys: &DeviceKeys) -> Result<()> {
+    let stored = StoredKeys {
+        wallet_key: hex::encode(keys.wallet_key),
+        mnemonic_key: keys.mnemonic_key.map(hex::encode),
+    };
+    entry(address)?
+        .set_password(&serde_json::to_string(&stored)?)
+        .context("Failed to save to the OS keyring")
+}
+
+/// Keys saved for `address`, or `None` when there's no entry
+pub fn load_device_keys(address: &str) -> Result<Option<DeviceKeys>> {
+    let secret = match entry(address)?.get_password() {
+        Ok(secret) => secret,
+        Err(keyring::Error::NoEntry) => return Ok(None),
+        Err(e) => return Err(anyhow!("Failed to read the OS keyring: {}", e)),
+    };
+    let stored: StoredKeys =
+        serde_json::from_str(&secret).context("Invalid keyring entry")?;
+
+    Ok(Some(DeviceKeys {
+        wallet_key: decode_key(&stored.wallet_key)?,
+        mnemonic_key: stored.mnemonic_key.as_deref().map(decode_key).transpose()?,
+    }))
+}
+
+/// Remove the entry for `address`; a missing entry isn't an error
+pub fn delete_device_keys(address: &str) -> Result<()> {
+    match entry(address)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to remove the OS keyring entry: {}", e)),
+    }
+}
+
+fn entry(address: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, address).context("OS keyring unavailable")
+}
+
+fn decode_key(encoded: &str) -> Result<[u8; 32]> {
+    hex::decode(encoded)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("Invalid key in keyring entry"))
+}
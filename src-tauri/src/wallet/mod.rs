@@ -3,16 +3,34 @@
 
 use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
-use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::{RpcRequest, TokenAccountsFilter};
 use solana_sdk::{
     commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    derivation_path::DerivationPath,
+    instruction::{AccountMeta, Instruction},
+    message::{v0, AddressLookupTableAccount, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
     pubkey::Pubkey,
-    signature::{Keypair, Signer},
-    system_instruction,
-    transaction::Transaction,
+    signature::{keypair_from_seed, Keypair, Signature, Signer},
+    signer::keypair::keypair_from_seed_and_derivation_path,
+    system_instruction, system_program,
+    transaction::{Transaction, VersionedTransaction},
 };
 use std::str::FromStr;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod audit;
+mod keychain;
+mod rpc;
+mod solana_pay;
+
+pub use audit::{AuditEntry, AuditLog};
+pub use rpc::{RpcEndpointsInfo, RpcHealth};
+pub use solana_pay::{PayInstruction, SolanaPayRequest};
+use rpc::RpcPool;
 
 // Crypto imports
 use aes_gcm::{
@@ -21,48 +39,600 @@ use aes_gcm::{
 };
 use pbkdf2::pbkdf2_hmac;
 use rand::Rng;
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
 
 const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
-const RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+// Balance lookups in flight at once when checking every account
+const MAX_CONCURRENT_BALANCE_LOOKUPS: usize = 4;
+const SIGNATURE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const SPL_TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+const ASSOCIATED_TOKEN_PROGRAM_ID: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+// Associated token program instruction that succeeds if the account already exists
+const CREATE_IDEMPOTENT_INSTRUCTION: u8 = 1;
+const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+const ADDRESS_LOOKUP_TABLE_PROGRAM_ID: &str = "AddressLookupTab1e1111111111111111111111111";
+// Lookup table accounts start with this much metadata before the addresses
+const LOOKUP_TABLE_META_SIZE: usize = 56;
+const METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+const ADDRESS_STORAGE_KEY: &str = "solana_wallet_address";
+const DERIVATION_STORAGE_KEY: &str = "solana_wallet_derivation";
+const RPC_ENDPOINTS_STORAGE_KEY: &str = "solana_rpc_endpoints";
+const PRIORITY_FEE_STORAGE_KEY: &str = "solana_priority_fee";
+// Address of the wallet whose keys are in the OS keyring, set only on opt-in
+const REMEMBERED_DEVICE_STORAGE_KEY: &str = "solana_wallet_remembered";
+/// Highest compute unit price accepted, in micro-lamports, to catch typos
+/// that would burn a large fee
+pub const MAX_PRIORITY_FEE_MICROLAMPORTS: u64 = 10_000_000;
+const NFT_CACHE_KEY: &str = "solana_nft_cache";
+const NFT_CACHE_TTL_SECS: u64 = 300;
+const PRICE_API_URL: &str =
+    "https://api.coingecko.com/api/v3/simple/price?ids=solana&vs_currencies=usd";
+const PRICE_API_ENV_VAR: &str = "SOCKETBROWSER_PRICE_API";
+const PRICE_CACHE_TTL: Duration = Duration::from_secs(60);
+/// PBKDF2 iterations for new encryptions until the user picks a stronger
+/// work factor; also what blobs without a header were encrypted with
+pub const DEFAULT_KDF_ITERATIONS: u32 = 100_000;
+// Settable range: never weaker than the default, and bounded so a tampered
+// header can't stall decryption
+const MAX_KDF_ITERATIONS: u32 = 10_000_000;
+const KDF_STORAGE_KEY: &str = "wallet_kdf_params";
+// Header of encrypted blobs that record their work factor:
+// `pbkdf2-sha256$<iterations>$<base64>`
+const ENVELOPE_PREFIX: &str = "pbkdf2-sha256$";
+/// Phrase wallets derive their key at the standard Solana BIP-44 path, like
+/// other wallets do, so the same phrase shows the same address everywhere
+pub const MNEMONIC_DERIVATION: &str = "m/44'/501'/0'/0'";
+/// Earlier versions used the first 32 bytes of the BIP-39 seed directly, so
+/// their phrases must be imported at this path to get the same wallet back
+pub const LEGACY_MNEMONIC_DERIVATION: &str = "bip39-seed[0..32]";
+// Public key bytes shown by `debug_info`
+const DEBUG_PUBKEY_PREFIX_BYTES: usize = 4;
+// Backup file format; bump the version whenever the envelope or KDF changes
+const BACKUP_VERSION: u32 = 1;
+const BACKUP_KDF: &str = "pbkdf2-sha256";
+const BACKUP_CIPHER: &str = "aes-256-gcm";
+// Prefix hashed in front of the pubkey by `address_fingerprint`
+const ADDRESS_FINGERPRINT_DOMAIN: &[u8] = b"socket-browser/address-fingerprint/v1";
+/// BIP-39 wordlists a recovery phrase can be imported from, by the code the
+/// frontend passes. English comes first, so it wins when detecting.
+const MNEMONIC_LANGUAGES: &[(&str, bip39::Language)] = &[
+    ("english", bip39::Language::English),
+    ("chinese-simplified", bip39::Language::SimplifiedChinese),
+    ("chinese-traditional", bip39::Language::TraditionalChinese),
+    ("czech", bip39::Language::Czech),
+    ("french", bip39::Language::French),
+    ("italian", bip39::Language::Italian),
+    ("japanese", bip39::Language::Japanese),
+    ("korean", bip39::Language::Korean),
+    ("portuguese", bip39::Language::Portuguese),
+    ("spanish", bip39::Language::Spanish),
+];
 
-#[derive(Serialize, Deserialize)]
-pub struct WalletResponse {
-    pub success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub address: Option<String>,
+/// Generating or importing would replace the wallet already in storage
+#[derive(Debug, thiserror::Error)]
+#[error("A wallet already exists; confirm overwrite to replace it")]
+pub struct WalletAlreadyExists;
+
+impl WalletAlreadyExists {
+    /// Error `code` reported to the frontend
+    pub const CODE: &'static str = "wallet_already_exists";
+}
+
+/// How `import_from_mnemonic` reads a recovery phrase and saves the wallet
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MnemonicImport<'a> {
+    /// Code from `mnemonic_languages`; without one, each wordlist is tried in turn
+    pub language: Option<&'a str>,
+    /// BIP-39 passphrase the phrase was protected with, if any
+    pub passphrase: Option<&'a str>,
+    /// Also save the phrase encrypted for later backup
+    pub store_mnemonic: bool,
+    /// Replace a wallet already in storage instead of failing
+    pub overwrite: bool,
+    /// `MNEMONIC_DERIVATION` (the default) or `LEGACY_MNEMONIC_DERIVATION`
+    pub derivation: Option<&'a str>,
+}
+
+/// Wallet that was just created, imported or unlocked
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletAccount {
+    pub address: String,
+    /// Recovery phrase, only returned on generation so it can be written down
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mnemonic: Option<String>,
+    /// Whether a BIP-39 passphrase went into the key, for wallets derived from
+    /// a recovery phrase. The passphrase itself is never stored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passphrase_used: Option<bool>,
+    /// Address earlier versions derived from the same phrase, returned when
+    /// a phrase is imported at the BIP-44 path so the user can tell whether
+    /// they meant the wallet they had before
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub legacy_address: Option<String>,
+}
+
+/// SOL balance, with its USD value when the price is available
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletBalance {
+    pub balance: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance_usd: Option<UsdValue>,
+}
+
+/// How far a transaction has progressed on the cluster
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationStatus {
+    /// Not yet seen by the RPC node
+    Pending,
+    Processed,
+    Confirmed,
+    Finalized,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmationState {
+    pub status: ConfirmationStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ConfirmationState {
+    /// Whether the status can no longer change
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self.status,
+            ConfirmationStatus::Finalized | ConfirmationStatus::Failed
+        )
+    }
+}
+
+/// Balance of one wallet account; `balance` is `None` when the lookup
+/// failed, with the reason in `error`
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountBalance {
+    pub address: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub balance: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub private_key: Option<String>,
+    pub error: Option<String>,
+}
+
+/// An NFT held by the wallet. Metadata fields are `None` when they couldn't be
+/// fetched, with the reason in `error`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftInfo {
+    pub mint: String,
+    pub token_account: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub has_wallet: Option<bool>,
+    pub uri: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub is_unlocked: Option<bool>,
+    pub image: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+struct NftCache {
+    owner: String,
+    fetched_at: u64,
+    nfts: Vec<NftInfo>,
+}
+
+/// USD value of the SOL balance and the price it was computed with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsdValue {
+    pub usd: f64,
+    pub price_usd: f64,
+    /// Unix time the price was fetched
+    pub price_timestamp: u64,
+}
+
+/// Whether a string is a usable Solana address
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressValidation {
+    pub valid: bool,
+    /// On the ed25519 curve: a regular account rather than a program-derived address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_curve: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// SOL transfer from the wallet, signed by the wallet but possibly still
+/// waiting on other signers
+#[derive(Debug, Clone, Serialize)]
+pub struct UnsignedTransfer {
+    /// Bincode-serialized transaction, base64 encoded
+    pub transaction: String,
+    pub fee_payer: String,
+    pub recent_blockhash: String,
+    /// Accounts that still have to sign before the transaction can be submitted
+    pub missing_signers: Vec<String>,
+    /// Compute unit price included, in micro-lamports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority_fee: Option<u64>,
+    /// Whether this is a v0 transaction rather than a legacy one
+    pub versioned: bool,
+}
+
+/// The wallet's associated token account for a mint; `signature` is set
+/// when this call created it
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenAccount {
+    pub address: String,
+    pub mint: String,
+    pub created: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SolPrice {
+    usd: f64,
+    fetched_at: u64,
+}
+
+/// Portable encrypted wallet backup. The KDF parameters are recorded so
+/// later versions know how to decrypt older files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalletBackup {
+    pub version: u32,
+    pub kdf: String,
+    pub kdf_iterations: u32,
+    pub cipher: String,
+    pub address: String,
+    pub created_at: u64,
+    /// salt + nonce + ciphertext of a `BackupSecrets`, base64 encoded
+    pub payload: String,
+}
+
+/// What support needs to diagnose an import that produced an unexpected
+/// address. Only public key bytes and how keys were derived, never secrets.
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletDebugInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// First bytes of the public key, hex encoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pubkey_prefix: Option<String>,
+    /// How a recovery phrase becomes the wallet key
+    pub derivation_path: String,
+    /// Whether the recovery phrase is saved alongside the key
+    pub mnemonic_stored: bool,
+    pub kdf: String,
+    /// `versioned` when the stored wallet records its work factor, `legacy`
+    /// for blobs from before it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kdf_envelope: Option<String>,
+    /// Work factor the stored wallet was encrypted with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kdf_iterations: Option<u32>,
+    pub unlocked: bool,
+    /// The unlocked wallet lives only in memory
+    pub ephemeral: bool,
+    pub remembered_device: bool,
+}
+
+/// The saved wallet's address re-derived from its key, against the address
+/// stored alongside it that's shown while locked
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressVerification {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stored_address: Option<String>,
+    /// Address of the decrypted key
+    pub derived_address: String,
+    /// Address the stored recovery phrase derives without a passphrase
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phrase_address: Option<String>,
+    /// The stored address is the key's
+    pub matches: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// Key derivation work factor applied to the wallet's next encryption
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub iterations: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            iterations: DEFAULT_KDF_ITERATIONS,
+        }
+    }
+}
+
+impl KdfParams {
+    pub fn validate(&self) -> Result<()> {
+        if !(DEFAULT_KDF_ITERATIONS..=MAX_KDF_ITERATIONS).contains(&self.iterations) {
+            return Err(anyhow!(
+                "KDF iterations must be between {} and {}",
+                DEFAULT_KDF_ITERATIONS,
+                MAX_KDF_ITERATIONS
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Encrypted blob split into its parts
+struct Envelope {
+    iterations: u32,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupSecrets {
+    secret_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mnemonic: Option<String>,
+}
+
+/// How the saved wallet's key was derived from its recovery phrase. Only
+/// whether there was a BIP-39 passphrase is recorded, never the passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PhraseDerivation {
+    path: String,
+    passphrase: bool,
+}
+
+impl PhraseDerivation {
+    fn new(path: &str, passphrase: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            passphrase: !passphrase.is_empty(),
+        }
+    }
+
+    /// Assumed for wallets saved before the derivation was recorded
+    fn legacy() -> Self {
+        Self {
+            path: LEGACY_MNEMONIC_DERIVATION.to_string(),
+            passphrase: false,
+        }
+    }
+
+    /// Work out how `keypair` was derived from `phrase`, for wallets
+    /// restored without a record of it. A key neither path derives without
+    /// a passphrase is taken to have used one.
+    fn detect(phrase: &str, keypair: &Keypair) -> Result<Self> {
+        let mnemonic = parse_mnemonic(phrase, None).context("Invalid recovery phrase")?;
+        for path in [MNEMONIC_DERIVATION, LEGACY_MNEMONIC_DERIVATION] {
+            if keypair_from_mnemonic(&mnemonic, "", path)?.pubkey() == keypair.pubkey() {
+                return Ok(Self {
+                    path: path.to_string(),
+                    passphrase: false,
+                });
+            }
+        }
+        Ok(Self {
+            path: MNEMONIC_DERIVATION.to_string(),
+            passphrase: true,
+        })
+    }
+}
+
 pub struct SolanaWallet {
     keypair: Mutex<Option<Keypair>>,
-    rpc_client: RpcClient,
+    // Recovery phrase, only held while unlocked and only if the user opted to store it
+    mnemonic: Mutex<Option<String>>,
+    rpc: Arc<RpcPool>,
+    // Endpoint the wallet starts with and returns to on reset
+    default_rpc_url: String,
+    price_api_url: String,
+    price_cache: Mutex<Option<(SolPrice, Instant)>>,
+    // Compute unit price added to transfers, in micro-lamports
+    priority_fee: Mutex<Option<u64>>,
+    kdf_params: Mutex<KdfParams>,
+    // Set while an in-memory wallet is unlocked; nothing about it is saved
+    ephemeral: AtomicBool,
+    // When the saved wallet was unlocked, for auto-lock
+    unlocked_at: Mutex<Option<Instant>>,
     storage_key: String,
+    mnemonic_storage_key: String,
 }
 
 impl SolanaWallet {
-    pub fn new() -> Self {
-        let rpc_client = RpcClient::new_with_commitment(
-            RPC_URL.to_string(),
-            CommitmentConfig::confirmed(),
-        );
-
+    pub fn with_rpc_url(rpc_url: String) -> Self {
         Self {
             keypair: Mutex::new(None),
-            rpc_client,
+            mnemonic: Mutex::new(None),
+            rpc: Arc::new(RpcPool::new(&[rpc_url.as_str()])),
+            default_rpc_url: rpc_url,
+            price_api_url: std::env::var(PRICE_API_ENV_VAR)
+                .unwrap_or_else(|_| PRICE_API_URL.to_string()),
+            price_cache: Mutex::new(None),
+            priority_fee: Mutex::new(None),
+            kdf_params: Mutex::new(KdfParams::default()),
+            ephemeral: AtomicBool::new(false),
+            unlocked_at: Mutex::new(None),
             storage_key: "solana_wallet_encrypted".to_string(),
+            mnemonic_storage_key: "solana_wallet_mnemonic_encrypted".to_string(),
+        }
+    }
+
+    /// Apply the saved RPC endpoint list, if any
+    pub fn load_rpc_endpoints(&self, storage: &crate::storage::Storage) {
+        let saved: Option<Vec<String>> = storage
+            .get(RPC_ENDPOINTS_STORAGE_KEY)
+            .ok()
+            .flatten()
+            .and_then(|value| serde_json::from_value(value).ok());
+
+        if let Some(urls) = saved {
+            match self.rpc.set_endpoints(urls) {
+                Ok(()) => println!("Solana RPC: using saved endpoints"),
+                Err(e) => println!("Ignoring saved RPC endpoints: {}", e),
+            }
+        }
+    }
+
+    /// Apply the saved priority fee, if any
+    pub fn load_priority_fee(&self, storage: &crate::storage::Storage) {
+        let saved = storage
+            .get(PRIORITY_FEE_STORAGE_KEY)
+            .ok()
+            .flatten()
+            .and_then(|value| value.as_u64())
+            .filter(|fee| validate_priority_fee(*fee).is_ok());
+        *self.priority_fee.lock().unwrap() = saved;
+    }
+
+    /// Compute unit price added to transfers that don't set their own
+    pub fn priority_fee(&self) -> Option<u64> {
+        *self.priority_fee.lock().unwrap()
+    }
+
+    /// Set and save the default priority fee; `None` or zero turns it off
+    pub fn set_priority_fee(
+        &self,
+        microlamports: Option<u64>,
+        storage: &crate::storage::Storage,
+    ) -> Result<()> {
+        let fee = microlamports.filter(|fee| *fee > 0);
+        match fee {
+            Some(fee) => {
+                validate_priority_fee(fee)?;
+                storage.set(
+                    PRIORITY_FEE_STORAGE_KEY.to_string(),
+                    serde_json::to_value(fee)?,
+                )?;
+            }
+            None => storage.remove(PRIORITY_FEE_STORAGE_KEY)?,
+        }
+        *self.priority_fee.lock().unwrap() = fee;
+        Ok(())
+    }
+
+    /// Apply the saved KDF work factor, if any
+    pub fn load_kdf_params(&self, storage: &crate::storage::Storage) {
+        let saved = storage
+            .get(KDF_STORAGE_KEY)
+            .ok()
+            .flatten()
+            .and_then(|value| serde_json::from_value::<KdfParams>(value).ok())
+            .filter(|params| params.validate().is_ok());
+        *self.kdf_params.lock().unwrap() = saved.unwrap_or_default();
+    }
+
+    pub fn kdf_params(&self) -> KdfParams {
+        *self.kdf_params.lock().unwrap()
+    }
+
+    /// Set and save the KDF work factor. Existing blobs keep the factor they
+    /// were encrypted with; the new one applies from the next encryption.
+    pub fn set_kdf_params(
+        &self,
+        params: KdfParams,
+        storage: &crate::storage::Storage,
+    ) -> Result<()> {
+        params.validate()?;
+        storage.set(KDF_STORAGE_KEY.to_string(), serde_json::to_value(params)?)?;
+        *self.kdf_params.lock().unwrap() = params;
+        Ok(())
+    }
+
+    /// Suggested compute unit price from recently landed transactions: the
+    /// median of the cluster's recent prioritization fees, in micro-lamports
+    pub async fn estimate_priority_fee(&self) -> Result<u64> {
+        let accounts: Vec<Pubkey> = self.get_pubkey().into_iter().collect();
+        let mut fees: Vec<u64> = self
+            .rpc
+            .call_async(move |rpc| rpc.get_recent_prioritization_fees(&accounts))
+            .await?
+            .into_iter()
+            .map(|fee| fee.prioritization_fee)
+            .collect();
+        if fees.is_empty() {
+            return Ok(0);
         }
+        fees.sort_unstable();
+        Ok(fees[fees.len() / 2].min(MAX_PRIORITY_FEE_MICROLAMPORTS))
+    }
+
+    /// Replace the ordered RPC endpoint list used for failover and save it
+    pub fn set_rpc_endpoints(
+        &self,
+        urls: Vec<String>,
+        storage: &crate::storage::Storage,
+    ) -> Result<RpcEndpointsInfo> {
+        let urls = rpc::validate_endpoints(urls)?;
+        storage.set(
+            RPC_ENDPOINTS_STORAGE_KEY.to_string(),
+            serde_json::to_value(&urls)?,
+        )?;
+        self.rpc.set_endpoints(urls)?;
+        Ok(self.rpc.info())
+    }
+
+    pub fn rpc_endpoints(&self) -> RpcEndpointsInfo {
+        self.rpc.info()
+    }
+
+    /// Probe an RPC endpoint before switching to it; the active endpoints
+    /// are left as they are
+    pub async fn check_rpc(&self, url: &str) -> Result<RpcHealth> {
+        let url = url.to_string();
+        tokio::task::spawn_blocking(move || rpc::check_endpoint(&url))
+            .await
+            .map_err(|e| anyhow!("RPC task failed: {}", e))?
+    }
+
+    /// How the saved wallet's key was derived and encrypted, for interop
+    /// reports. Reads only public data, so it works while locked and has no
+    /// way to include key or phrase material.
+    pub fn debug_info(&self, storage: &crate::storage::Storage) -> Result<WalletDebugInfo> {
+        let address = self
+            .get_address()
+            .ok()
+            .or_else(|| self.stored_address(storage));
+        let pubkey_prefix = address
+            .as_deref()
+            .and_then(|address| Pubkey::from_str(address).ok())
+            .map(|pubkey| hex::encode(&pubkey.to_bytes()[..DEBUG_PUBKEY_PREFIX_BYTES]));
+
+        let envelope = self
+            .stored_blob(&self.storage_key, storage)?
+            .map(|blob| {
+                let versioned = blob.starts_with(ENVELOPE_PREFIX);
+                split_encrypted(&blob).map(|envelope| (versioned, envelope.iterations))
+            })
+            .transpose()?;
+
+        Ok(WalletDebugInfo {
+            address,
+            pubkey_prefix,
+            derivation_path: self
+                .phrase_derivation(storage)
+                .unwrap_or_else(PhraseDerivation::legacy)
+                .path,
+            mnemonic_stored: storage
+                .get(&self.mnemonic_storage_key)
+                .ok()
+                .flatten()
+                .is_some(),
+            kdf: BACKUP_KDF.to_string(),
+            kdf_envelope: envelope
+                .map(|(versioned, _)| if versioned { "versioned" } else { "legacy" }.to_string()),
+            kdf_iterations: envelope.map(|(_, iterations)| iterations),
+            unlocked: self.is_unlocked(),
+            ephemeral: self.is_ephemeral(),
+            remembered_device: self.is_device_remembered(storage),
+        })
     }
 
     /// Check if wallet exists in storage
@@ -70,100 +640,317 @@ impl SolanaWallet {
         storage.get(&self.storage_key).ok().flatten().is_some()
     }
 
-    /// Generate new wallet with BIP-39 mnemonic
+    /// Address of the saved wallet, readable without unlocking
+    pub fn stored_address(&self, storage: &crate::storage::Storage) -> Option<String> {
+        if !self.has_wallet(storage) {
+            return None;
+        }
+        match storage.get(ADDRESS_STORAGE_KEY).ok()? {
+            Some(serde_json::Value::String(address)) => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Re-derive the saved wallet's address and check it against the stored
+    /// one, to catch a key or derivation change the address didn't follow.
+    /// Decrypts with `password`, or uses the unlocked wallet without one.
+    pub fn verify_address(
+        &self,
+        password: Option<&str>,
+        storage: &crate::storage::Storage,
+    ) -> Result<AddressVerification> {
+        if self.is_ephemeral() {
+            return Err(anyhow!(
+                "An in-memory wallet has no stored address to verify"
+            ));
+        }
+
+        let (keypair, phrase) = match password {
+            Some(password) => {
+                let encrypted = self
+                    .stored_blob(&self.storage_key, storage)?
+                    .ok_or_else(|| anyhow!("No wallet found"))?;
+                let secret_key = self
+                    .decrypt(&encrypted, password)
+                    .context("Wrong password")?;
+                (
+                    Keypair::from_bytes(&secret_key)?,
+                    self.load_mnemonic(password, storage)?,
+                )
+            }
+            None => {
+                let keypair = self.keypair.lock().unwrap();
+                let keypair = keypair
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Wallet not unlocked"))?;
+                (
+                    Keypair::from_bytes(&keypair.to_bytes())?,
+                    self.mnemonic.lock().unwrap().clone(),
+                )
+            }
+        };
+
+        // A phrase used with a passphrase can't be re-derived without it
+        let derivation = self
+            .phrase_derivation(storage)
+            .unwrap_or_else(PhraseDerivation::legacy);
+        let derived_address = keypair.pubkey().to_string();
+        let phrase_address = phrase
+            .filter(|_| !derivation.passphrase)
+            .map(|phrase| {
+                parse_mnemonic(&phrase, None)
+                    .and_then(|mnemonic| keypair_from_mnemonic(&mnemonic, "", &derivation.path))
+                    .map(|keypair| keypair.pubkey().to_string())
+            })
+            .transpose()
+            .context("Stored recovery phrase is invalid")?;
+        let stored_address = self.stored_address(storage);
+
+        let mut warnings = Vec::new();
+        match &stored_address {
+            Some(stored) if *stored == derived_address => {}
+            Some(stored) => warnings.push(format!(
+                "The stored address {} isn't the wallet key's ({}); funds sent to it may not be in this wallet",
+                stored, derived_address
+            )),
+            None => warnings.push("No address is stored for this wallet".to_string()),
+        }
+        // Expected when the phrase was used with a BIP-39 passphrase, which isn't stored
+        if let Some(address) = phrase_address.as_ref().filter(|a| **a != derived_address) {
+            warnings.push(format!(
+                "The stored recovery phrase derives {} at {} without a passphrase, not the wallet key's address",
+                address, derivation.path
+            ));
+        }
+        for warning in &warnings {
+            println!("Wallet address check: {}", warning);
+        }
+
+        Ok(AddressVerification {
+            matches: stored_address.as_deref() == Some(derived_address.as_str()),
+            stored_address,
+            derived_address,
+            phrase_address,
+            warning: (!warnings.is_empty()).then(|| warnings.join("; ")),
+        })
+    }
+
+    /// Generate new wallet with BIP-39 mnemonic.
+    /// With `store_mnemonic` the phrase is also saved encrypted for later backup;
+    /// `passphrase` is the optional BIP-39 passphrase ("25th word").
     pub fn generate_new(
         &self,
         password: &str,
+        passphrase: Option<&str>,
+        store_mnemonic: bool,
+        overwrite: bool,
         storage: &crate::storage::Storage,
-    ) -> Result<WalletResponse> {
+    ) -> Result<WalletAccount> {
+        self.ensure_can_save(overwrite, storage)?;
+
         // Generate 12-word mnemonic (128 bits entropy)
         let mnemonic = bip39::Mnemonic::generate(12)?;
         let mnemonic_phrase = mnemonic.to_string();
 
-        // Derive seed from mnemonic
-        let seed = mnemonic.to_seed("");
-        let keypair = Keypair::from_bytes(&seed[..32])?;
+        // Derive keypair from mnemonic seed
+        let passphrase = passphrase.unwrap_or("");
+        let keypair = keypair_from_mnemonic(&mnemonic, passphrase, MNEMONIC_DERIVATION)?;
 
         // Encrypt and save
-        self.save_wallet(&keypair, password, storage)?;
+        let stored_phrase = store_mnemonic.then_some(mnemonic_phrase.as_str());
+        let derivation = PhraseDerivation::new(MNEMONIC_DERIVATION, passphrase);
+        self.save_wallet(
+            &keypair,
+            stored_phrase,
+            Some(&derivation),
+            password,
+            storage,
+        )?;
 
         // Unlock wallet
         let mut kp = self.keypair.lock().unwrap();
         *kp = Some(Keypair::from_bytes(&keypair.to_bytes())?);
+        *self.mnemonic.lock().unwrap() = stored_phrase.map(String::from);
+        self.mark_unlocked();
+
+        Ok(WalletAccount {
+            address: keypair.pubkey().to_string(),
+            mnemonic: Some(mnemonic_phrase),
+            passphrase_used: Some(!passphrase.is_empty()),
+            legacy_address: None,
+        })
+    }
+
+    /// Generate a wallet that lives only in memory, for kiosks and demos.
+    /// Nothing is written to storage and locking discards it for good, so
+    /// the returned phrase is the only way to recover the funds.
+    pub fn generate_ephemeral(&self, passphrase: Option<&str>) -> Result<WalletAccount> {
+        let mnemonic = bip39::Mnemonic::generate(12)?;
+        let passphrase = passphrase.unwrap_or("");
+        let keypair = keypair_from_mnemonic(&mnemonic, passphrase, MNEMONIC_DERIVATION)?;
+        let mnemonic_phrase = mnemonic.to_string();
 
-        Ok(WalletResponse {
-            success: true,
-            address: Some(keypair.pubkey().to_string()),
+        self.open_ephemeral(keypair, Some(mnemonic_phrase.clone()));
+        Ok(WalletAccount {
+            address: self.get_address()?,
             mnemonic: Some(mnemonic_phrase),
-            balance: None,
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: None,
+            passphrase_used: Some(!passphrase.is_empty()),
+            legacy_address: None,
+        })
+    }
+
+    /// Load an existing wallet into memory only, from a recovery phrase or
+    /// a base58 private key. Like `generate_ephemeral`, nothing is saved.
+    /// Phrases derive at `derivation` as in `import_from_mnemonic`.
+    pub fn import_ephemeral(
+        &self,
+        secret: &str,
+        passphrase: Option<&str>,
+        derivation: Option<&str>,
+    ) -> Result<WalletAccount> {
+        let secret = secret.trim();
+        // Recovery phrases have spaces between words; base58 keys never do
+        let (keypair, mnemonic_phrase, passphrase_used, legacy_address) =
+            if secret.contains(char::is_whitespace) {
+                let mnemonic = parse_mnemonic(secret, None)?;
+                let passphrase = passphrase.unwrap_or("");
+                let path = derivation.unwrap_or(MNEMONIC_DERIVATION);
+                let keypair = keypair_from_mnemonic(&mnemonic, passphrase, path)?;
+                let legacy_address = legacy_address(&mnemonic, passphrase, path)?;
+                let passphrase_used = Some(!passphrase.is_empty());
+                (
+                    keypair,
+                    Some(mnemonic.to_string()),
+                    passphrase_used,
+                    legacy_address,
+                )
+            } else {
+                (keypair_from_private_key(secret)?, None, None, None)
+            };
+
+        self.open_ephemeral(keypair, mnemonic_phrase);
+        Ok(WalletAccount {
+            address: self.get_address()?,
+            mnemonic: None,
+            passphrase_used,
+            legacy_address,
         })
     }
 
-    /// Import wallet from BIP-39 mnemonic
+    /// Unlock `keypair` without touching storage
+    fn open_ephemeral(&self, keypair: Keypair, mnemonic_phrase: Option<String>) {
+        self.lock();
+        *self.keypair.lock().unwrap() = Some(keypair);
+        *self.mnemonic.lock().unwrap() = mnemonic_phrase;
+        self.ephemeral.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the unlocked wallet lives only in memory
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral.load(Ordering::Relaxed)
+    }
+
+    /// Import wallet from BIP-39 mnemonic, saving it under `password`
     pub fn import_from_mnemonic(
         &self,
         mnemonic_phrase: &str,
         password: &str,
+        options: MnemonicImport<'_>,
         storage: &crate::storage::Storage,
-    ) -> Result<WalletResponse> {
+    ) -> Result<WalletAccount> {
+        let MnemonicImport {
+            language,
+            passphrase,
+            store_mnemonic,
+            overwrite,
+            derivation,
+        } = options;
+        self.ensure_can_save(overwrite, storage)?;
+
         // Parse and validate mnemonic
-        let mnemonic = bip39::Mnemonic::from_phrase(mnemonic_phrase, bip39::Language::English)
-            .context("Invalid recovery phrase")?;
+        let mnemonic = parse_mnemonic(mnemonic_phrase, language)?;
 
-        // Derive seed
-        let seed = mnemonic.to_seed("");
-        let keypair = Keypair::from_bytes(&seed[..32])?;
+        // Derive keypair from mnemonic seed
+        let passphrase = passphrase.unwrap_or("");
+        let path = derivation.unwrap_or(MNEMONIC_DERIVATION);
+        let keypair = keypair_from_mnemonic(&mnemonic, passphrase, path)?;
+        let legacy_address = legacy_address(&mnemonic, passphrase, path)?;
 
         // Save encrypted
-        self.save_wallet(&keypair, password, storage)?;
+        let mnemonic_phrase = mnemonic.to_string();
+        let stored_phrase = store_mnemonic.then_some(mnemonic_phrase.as_str());
+        let derivation = PhraseDerivation::new(path, passphrase);
+        self.save_wallet(
+            &keypair,
+            stored_phrase,
+            Some(&derivation),
+            password,
+            storage,
+        )?;
 
         // Unlock
         let mut kp = self.keypair.lock().unwrap();
         *kp = Some(Keypair::from_bytes(&keypair.to_bytes())?);
+        *self.mnemonic.lock().unwrap() = stored_phrase.map(String::from);
+        self.mark_unlocked();
 
-        Ok(WalletResponse {
-            success: true,
-            address: Some(keypair.pubkey().to_string()),
+        Ok(WalletAccount {
+            address: keypair.pubkey().to_string(),
             mnemonic: None,
-            balance: None,
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: None,
+            passphrase_used: Some(!passphrase.is_empty()),
+            legacy_address,
         })
     }
 
-    /// Import from private key (base58)
+    /// Import from a private key: base58, or a Solana CLI keypair's JSON array
     pub fn import_from_private_key(
         &self,
-        private_key_base58: &str,
+        private_key: &str,
         password: &str,
+        overwrite: bool,
         storage: &crate::storage::Storage,
-    ) -> Result<WalletResponse> {
-        let decoded = bs58::decode(private_key_base58)
-            .into_vec()
-            .context("Invalid private key")?;
+    ) -> Result<WalletAccount> {
+        self.ensure_can_save(overwrite, storage)?;
 
-        let keypair = Keypair::from_bytes(&decoded)?;
+        let keypair = keypair_from_private_key(private_key)?;
+        self.import_keypair(keypair, password, storage)
+    }
+
+    /// Import a Solana CLI keypair file (`id.json`, a JSON array of 64 bytes)
+    pub fn import_from_keypair_file(
+        &self,
+        path: &std::path::Path,
+        password: &str,
+        overwrite: bool,
+        storage: &crate::storage::Storage,
+    ) -> Result<WalletAccount> {
+        self.ensure_can_save(overwrite, storage)?;
 
-        self.save_wallet(&keypair, password, storage)?;
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read keypair file {}", path.display()))?;
+        let keypair = keypair_from_json(&contents)?;
+        self.import_keypair(keypair, password, storage)
+    }
+
+    /// Save an imported keypair (without a recovery phrase) and keep it unlocked
+    fn import_keypair(
+        &self,
+        keypair: Keypair,
+        password: &str,
+        storage: &crate::storage::Storage,
+    ) -> Result<WalletAccount> {
+        self.save_wallet(&keypair, None, None, password, storage)?;
 
         let mut kp = self.keypair.lock().unwrap();
         *kp = Some(Keypair::from_bytes(&keypair.to_bytes())?);
+        *self.mnemonic.lock().unwrap() = None;
+        self.mark_unlocked();
 
-        Ok(WalletResponse {
-            success: true,
-            address: Some(keypair.pubkey().to_string()),
+        Ok(WalletAccount {
+            address: keypair.pubkey().to_string(),
             mnemonic: None,
-            balance: None,
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: None,
+            passphrase_used: None,
+            legacy_address: None,
         })
     }
 
@@ -172,7 +959,7 @@ impl SolanaWallet {
         &self,
         password: &str,
         storage: &crate::storage::Storage,
-    ) -> Result<WalletResponse> {
+    ) -> Result<WalletAccount> {
         let encrypted = storage
             .get(&self.storage_key)?
             .ok_or_else(|| anyhow!("No wallet found"))?;
@@ -184,27 +971,210 @@ impl SolanaWallet {
         let secret_key = self.decrypt(encrypted_str, password)
             .context("Wrong password")?;
 
-        let keypair = Keypair::from_bytes(&secret_key)?;
+        let mut keypair = Keypair::from_bytes(&secret_key)?;
+
+        // Prefer deriving from the stored recovery phrase when there is one.
+        // The BIP-39 passphrase isn't stored, so a wallet that used one keeps its key.
+        let mnemonic_phrase = self.load_mnemonic(password, storage)?;
+        let recorded = self.phrase_derivation(storage);
+        let derivation = recorded.clone().unwrap_or_else(PhraseDerivation::legacy);
+        if let Some(phrase) = mnemonic_phrase.as_ref().filter(|_| !derivation.passphrase) {
+            let derived = parse_mnemonic(phrase, None)
+                .context("Stored recovery phrase is invalid")
+                .and_then(|mnemonic| keypair_from_mnemonic(&mnemonic, "", &derivation.path))?;
+
+            if derived.pubkey() == keypair.pubkey() {
+                keypair = derived;
+            } else if recorded.is_some() {
+                // Wallets saved before the derivation was recorded may have
+                // used a passphrase, so a mismatch is only reported for others
+                println!("Stored recovery phrase doesn't match the wallet key, using the key");
+            }
+        }
 
         let mut kp = self.keypair.lock().unwrap();
         *kp = Some(Keypair::from_bytes(&keypair.to_bytes())?);
+        *self.mnemonic.lock().unwrap() = mnemonic_phrase;
+        self.mark_unlocked();
 
-        Ok(WalletResponse {
-            success: true,
-            address: Some(keypair.pubkey().to_string()),
+        Ok(WalletAccount {
+            address: keypair.pubkey().to_string(),
             mnemonic: None,
-            balance: None,
-            private_key: None,
-            has_wallet: None,
-            is_unlocked: None,
-            error: None,
+            passphrase_used: None,
+            legacy_address: None,
         })
     }
 
-    /// Lock wallet
+    /// Remember this device: save the keys derived from `password` to the OS
+    /// keyring so `unlock_from_keyring` can unlock after a restart. Strictly
+    /// opt-in; the encrypted wallet in storage is left as it is.
+    pub fn remember_device(&self, password: &str, storage: &crate::storage::Storage) -> Result<()> {
+        if self.is_ephemeral() {
+            return Err(anyhow!("An in-memory wallet can't be remembered"));
+        }
+        let address = self
+            .stored_address(storage)
+            .ok_or_else(|| anyhow!("No wallet found"))?;
+        let encrypted = self
+            .stored_blob(&self.storage_key, storage)?
+            .ok_or_else(|| anyhow!("No wallet found"))?;
+        let wallet_key = self
+            .blob_key(&encrypted, password)
+            .context("Wrong password")?;
+        let mnemonic_key = match self.stored_blob(&self.mnemonic_storage_key, storage)? {
+            Some(encrypted) => Some(self.blob_key(&encrypted, password)?),
+            None => None,
+        };
+
+        keychain::save_device_keys(
+            &address,
+            &keychain::DeviceKeys {
+                wallet_key,
+                mnemonic_key,
+            },
+        )?;
+        storage.set(
+            REMEMBERED_DEVICE_STORAGE_KEY.to_string(),
+            serde_json::Value::String(address),
+        )
+    }
+
+    /// Unlock with the keys saved by `remember_device`. Returns `None` when
+    /// this device isn't remembered. An entry that no longer opens the
+    /// wallet, e.g. after it was replaced, is forgotten.
+    pub fn unlock_from_keyring(
+        &self,
+        storage: &crate::storage::Storage,
+    ) -> Result<Option<WalletAccount>> {
+        let Some(address) = self.remembered_address(storage) else {
+            return Ok(None);
+        };
+        let Some(keys) = keychain::load_device_keys(&address)? else {
+            storage.remove(REMEMBERED_DEVICE_STORAGE_KEY)?;
+            return Ok(None);
+        };
+
+        let unlocked = self
+            .open_with_keys(&keys, storage)
+            .and_then(|(keypair, mnemonic)| {
+                if keypair.pubkey().to_string() != address {
+                    return Err(anyhow!("Keyring entry is for a different wallet"));
+                }
+                Ok((keypair, mnemonic))
+            });
+        let (keypair, mnemonic) = match unlocked {
+            Ok(unlocked) => unlocked,
+            Err(e) => {
+                self.forget_device(storage)?;
+                return Err(e.context("Remembered device no longer matches the wallet"));
+            }
+        };
+
+        let address = keypair.pubkey().to_string();
+        *self.keypair.lock().unwrap() = Some(keypair);
+        *self.mnemonic.lock().unwrap() = mnemonic;
+        self.mark_unlocked();
+        Ok(Some(WalletAccount {
+            address,
+            mnemonic: None,
+            passphrase_used: None,
+            legacy_address: None,
+        }))
+    }
+
+    /// Remove this device's keyring entry so the next start needs the password
+    pub fn forget_device(&self, storage: &crate::storage::Storage) -> Result<()> {
+        let Some(address) = self.remembered_address(storage) else {
+            return Ok(());
+        };
+        // Clear the flag first so a keyring failure can't leave it in use
+        storage.remove(REMEMBERED_DEVICE_STORAGE_KEY)?;
+        keychain::delete_device_keys(&address)
+    }
+
+    pub fn is_device_remembered(&self, storage: &crate::storage::Storage) -> bool {
+        self.remembered_address(storage).is_some()
+    }
+
+    fn remembered_address(&self, storage: &crate::storage::Storage) -> Option<String> {
+        storage
+            .get(REMEMBERED_DEVICE_STORAGE_KEY)
+            .ok()
+            .flatten()
+            .and_then(|value| value.as_str().map(String::from))
+    }
+
+    /// Decrypt the stored wallet, and recovery phrase if any, with keyring keys
+    fn open_with_keys(
+        &self,
+        keys: &keychain::DeviceKeys,
+        storage: &crate::storage::Storage,
+    ) -> Result<(Keypair, Option<String>)> {
+        let encrypted = self
+            .stored_blob(&self.storage_key, storage)?
+            .ok_or_else(|| anyhow!("No wallet found"))?;
+        let secret_key = self.decrypt_with_key(&encrypted, &keys.wallet_key)?;
+        let keypair = Keypair::from_bytes(&secret_key)?;
+
+        let mnemonic = match (
+            self.stored_blob(&self.mnemonic_storage_key, storage)?,
+            &keys.mnemonic_key,
+        ) {
+            (Some(encrypted), Some(key)) => {
+                let phrase = self.decrypt_with_key(&encrypted, key)?;
+                Some(String::from_utf8(phrase).context("Invalid recovery phrase data")?)
+            }
+            _ => None,
+        };
+        Ok((keypair, mnemonic))
+    }
+
+    /// Lock wallet. The keypair zeroes its secret when dropped and the
+    /// phrase is overwritten, so an in-memory wallet is gone for good.
     pub fn lock(&self) {
         let mut kp = self.keypair.lock().unwrap();
         *kp = None;
+        if let Some(mut phrase) = self.mnemonic.lock().unwrap().take() {
+            phrase.zeroize();
+        }
+        self.ephemeral.store(false, Ordering::Relaxed);
+        *self.unlocked_at.lock().unwrap() = None;
+    }
+
+    /// Lock a saved wallet once it has been unlocked for `after`. In-memory
+    /// wallets are left alone, since locking would lose them for good.
+    /// Returns whether it locked.
+    pub fn lock_if_unlocked_for(&self, after: Duration) -> bool {
+        if self.is_ephemeral() {
+            return false;
+        }
+        let expired = self
+            .unlocked_at
+            .lock()
+            .unwrap()
+            .is_some_and(|unlocked_at| unlocked_at.elapsed() >= after);
+        if expired {
+            self.lock();
+        }
+        expired
+    }
+
+    /// Note that a saved wallet was just unlocked, for auto-lock
+    fn mark_unlocked(&self) {
+        self.ephemeral.store(false, Ordering::Relaxed);
+        *self.unlocked_at.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Lock the wallet and drop cached prices, custom RPC endpoints and
+    /// the priority fee
+    pub fn reset(&self) {
+        self.lock();
+        *self.price_cache.lock().unwrap() = None;
+        *self.priority_fee.lock().unwrap() = None;
+        *self.kdf_params.lock().unwrap() = KdfParams::default();
+        if let Err(e) = self.rpc.set_endpoints(vec![self.default_rpc_url.clone()]) {
+            println!("Failed to restore default RPC endpoint: {}", e);
+        }
     }
 
     /// Get wallet address
@@ -216,109 +1186,2201 @@ impl SolanaWallet {
         }
     }
 
-    /// Get balance in SOL
-    pub fn get_balance(&self) -> Result<f64> {
+    /// Public key of the unlocked wallet
+    fn get_pubkey(&self) -> Result<Pubkey> {
         let kp = self.keypair.lock().unwrap();
         match &*kp {
-            Some(keypair) => {
-                let lamports = self.rpc_client.get_balance(&keypair.pubkey())?;
-                Ok(lamports as f64 / LAMPORTS_PER_SOL as f64)
-            }
+            Some(keypair) => Ok(keypair.pubkey()),
             None => Err(anyhow!("Wallet not unlocked")),
         }
     }
 
-    /// Export private key (base58)
-    pub fn export_private_key(&self) -> Result<String> {
-        let kp = self.keypair.lock().unwrap();
-        match &*kp {
-            Some(keypair) => Ok(bs58::encode(keypair.to_bytes()).into_string()),
-            None => Err(anyhow!("Wallet not unlocked")),
-        }
+    /// Get balance in SOL
+    pub async fn get_balance(&self) -> Result<f64> {
+        let pubkey = self.get_pubkey()?;
+        let lamports = self
+            .rpc
+            .call_async(move |rpc| rpc.get_balance(&pubkey))
+            .await?;
+        Ok(lamports as f64 / LAMPORTS_PER_SOL as f64)
     }
 
-    /// Check if wallet is unlocked
-    pub fn is_unlocked(&self) -> bool {
-        let kp = self.keypair.lock().unwrap();
+    /// Addresses of every account the wallet controls. Only the primary
+    /// account is derived today.
+    fn account_pubkeys(&self) -> Result<Vec<Pubkey>> {
+        Ok(vec![self.get_pubkey()?])
+    }
+
+    /// Balances of every wallet account, looked up a few at a time. Accounts
+    /// whose lookup fails are still listed, with the error instead of a balance.
+    pub async fn get_all_balances(&self) -> Result<Vec<AccountBalance>> {
+        let pubkeys = self.account_pubkeys()?;
+        let mut balances = Vec::with_capacity(pubkeys.len());
+
+        for batch in pubkeys.chunks(MAX_CONCURRENT_BALANCE_LOOKUPS) {
+            let lookups: Vec<_> = batch
+                .iter()
+                .map(|&pubkey| {
+                    let rpc = Arc::clone(&self.rpc);
+                    tokio::spawn(async move {
+                        rpc.call_async(move |client| client.get_balance(&pubkey))
+                            .await
+                    })
+                })
+                .collect();
+
+            for (pubkey, lookup) in batch.iter().zip(lookups) {
+                let result = match lookup.await {
+                    Ok(result) => result.map_err(anyhow::Error::from),
+                    Err(e) => Err(anyhow!("Balance lookup failed: {}", e)),
+                };
+                balances.push(match result {
+                    Ok(lamports) => AccountBalance {
+                        address: pubkey.to_string(),
+                        balance: Some(lamports as f64 / LAMPORTS_PER_SOL as f64),
+                        error: None,
+                    },
+                    Err(e) => AccountBalance {
+                        address: pubkey.to_string(),
+                        balance: None,
+                        error: Some(e.to_string()),
+                    },
+                });
+            }
+        }
+
+        Ok(balances)
+    }
+
+    /// SOL balance plus its USD value. The value is `None` when the price
+    /// can't be fetched, so the balance is still shown.
+    pub async fn get_balance_usd(&self) -> Result<(f64, Option<UsdValue>)> {
+        let balance = self.get_balance().await?;
+
+        let value = match self.sol_price().await {
+            Ok(price) => Some(UsdValue {
+                usd: balance * price.usd,
+                price_usd: price.usd,
+                price_timestamp: price.fetched_at,
+            }),
+            Err(e) => {
+                println!("SOL price unavailable: {}", e);
+                None
+            }
+        };
+
+        Ok((balance, value))
+    }
+
+    /// Current SOL price in USD, cached for `PRICE_CACHE_TTL`
+    async fn sol_price(&self) -> Result<SolPrice> {
+        let cached = *self.price_cache.lock().unwrap();
+        if let Some((price, fetched)) = cached {
+            if fetched.elapsed() < PRICE_CACHE_TTL {
+                return Ok(price);
+            }
+        }
+
+        let client = crate::config::client_builder()?
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        let body: serde_json::Value = client
+            .get(&self.price_api_url)
+            .header("Accept", "application/json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let usd = parse_sol_price(&body).ok_or_else(|| anyhow!("Unexpected price API response"))?;
+        let price = SolPrice {
+            usd,
+            fetched_at: unix_now(),
+        };
+        *self.price_cache.lock().unwrap() = Some((price, Instant::now()));
+
+        Ok(price)
+    }
+
+    /// Get the confirmation state of a transaction signature
+    pub async fn get_signature_status(&self, signature: &str) -> Result<ConfirmationState> {
+        let signature =
+            Signature::from_str(signature).map_err(|_| anyhow!("Invalid transaction signature"))?;
+
+        let response = self
+            .rpc
+            .call_async(move |rpc| rpc.get_signature_statuses_with_history(&[signature]))
+            .await?;
+
+        let state = match response.value.into_iter().next().flatten() {
+            None => ConfirmationState {
+                status: ConfirmationStatus::Pending,
+                slot: None,
+                error: None,
+            },
+            Some(tx_status) => {
+                let status = if tx_status.err.is_some() {
+                    ConfirmationStatus::Failed
+                } else if tx_status.satisfies_commitment(CommitmentConfig::finalized()) {
+                    ConfirmationStatus::Finalized
+                } else if tx_status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                    ConfirmationStatus::Confirmed
+                } else {
+                    ConfirmationStatus::Processed
+                };
+
+                ConfirmationState {
+                    status,
+                    slot: Some(tx_status.slot),
+                    error: tx_status.err.map(|e| e.to_string()),
+                }
+            }
+        };
+
+        Ok(state)
+    }
+
+    /// Poll a signature until it is finalized or failed, or the timeout passes.
+    /// Returns the last observed state either way.
+    pub async fn wait_for_signature(
+        &self,
+        signature: &str,
+        timeout: Duration,
+    ) -> Result<ConfirmationState> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let state = self.get_signature_status(signature).await?;
+            if state.is_final() || Instant::now() >= deadline {
+                return Ok(state);
+            }
+            tokio::time::sleep(SIGNATURE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Details of a confirmed transaction: status, fee, block time, accounts
+    /// and instructions, with system transfers decoded into amounts
+    pub async fn get_transaction_details(&self, signature: &str) -> Result<serde_json::Value> {
+        let signature =
+            Signature::from_str(signature).map_err(|_| anyhow!("Invalid transaction signature"))?;
+
+        let params = serde_json::json!([
+            signature.to_string(),
+            {
+                "encoding": "jsonParsed",
+                "commitment": "confirmed",
+                "maxSupportedTransactionVersion": 0,
+            }
+        ]);
+        let transaction: serde_json::Value = self
+            .rpc
+            .call_async(move |rpc| rpc.send(RpcRequest::GetTransaction, params.clone()))
+            .await?;
+        if transaction.is_null() {
+            return Err(anyhow!(
+                "Transaction {} not found; it may be unconfirmed or too old for this RPC node",
+                signature
+            ));
+        }
+
+        Ok(summarize_transaction(&signature.to_string(), &transaction))
+    }
+
+    /// Build a transfer of `amount_sol` to `to` for external co-signing. The
+    /// wallet signs as the sender; `fee_payer` defaults to the wallet and,
+    /// when it's another account, has to sign before the transfer is submitted.
+    /// `priority_fee` overrides the saved compute unit price for this transfer.
+    /// With `versioned` set the transfer is a v0 transaction that can pull
+    /// accounts from the address `lookup_tables`; otherwise it's legacy.
+    /// A `memo`, which exchanges often require, goes in a Memo instruction
+    /// ahead of the transfer.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn build_unsigned_transfer(
+        &self,
+        to: &str,
+        amount_sol: f64,
+        fee_payer: Option<&str>,
+        priority_fee: Option<u64>,
+        versioned: bool,
+        lookup_tables: &[String],
+        memo: Option<&str>,
+    ) -> Result<UnsignedTransfer> {
+        if !versioned && !lookup_tables.is_empty() {
+            return Err(anyhow!("Address lookup tables need a versioned transaction"));
+        }
+        let to = Pubkey::from_str(to).map_err(|_| anyhow!("Invalid recipient address"))?;
+        let fee_payer = fee_payer
+            .map(|address| {
+                Pubkey::from_str(address).map_err(|_| anyhow!("Invalid fee payer address"))
+            })
+            .transpose()?;
+        let lamports = sol_to_lamports(amount_sol)?;
+        let priority_fee = match priority_fee {
+            Some(fee) => Some(fee).filter(|fee| *fee > 0),
+            None => self.priority_fee(),
+        };
+        if let Some(fee) = priority_fee {
+            validate_priority_fee(fee)?;
+        }
+
+        // Fail fast while locked, then fetch the blockhash and lookup tables
+        // before taking the keypair lock, which can't be held across an await
+        self.get_pubkey()?;
+        let lookup_tables = self.get_lookup_tables(lookup_tables).await?;
+        let blockhash = self
+            .rpc
+            .call_async(|rpc| rpc.get_latest_blockhash())
+            .await?;
+
+        let kp = self.keypair.lock().unwrap();
+        let keypair = kp.as_ref().ok_or_else(|| anyhow!("Wallet not unlocked"))?;
+        let fee_payer = fee_payer.unwrap_or_else(|| keypair.pubkey());
+
+        let mut instructions = Vec::new();
+        if let Some(fee) = priority_fee {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(fee));
+        }
+        if let Some(memo) = memo.filter(|memo| !memo.is_empty()) {
+            instructions.push(memo_instruction(memo, &keypair.pubkey())?);
+        }
+        instructions.push(system_instruction::transfer(
+            &keypair.pubkey(),
+            &to,
+            lamports,
+        ));
+        let transaction = if versioned {
+            let message =
+                v0::Message::try_compile(&fee_payer, &instructions, &lookup_tables, blockhash)
+                    .context("Failed to compile transfer")?;
+            partially_sign_versioned(VersionedMessage::V0(message), keypair)?
+        } else {
+            let mut transaction = Transaction::new_with_payer(&instructions, Some(&fee_payer));
+            transaction
+                .try_partial_sign(&[keypair], blockhash)
+                .context("Failed to sign transfer")?;
+            VersionedTransaction::from(transaction)
+        };
+
+        // Only a long memo can push a transfer past the packet size
+        let size = bincode::serialized_size(&transaction)
+            .context("Failed to serialize transaction")? as usize;
+        if size > PACKET_DATA_SIZE {
+            return Err(anyhow!(
+                "Memo is too long: the transfer would be {} bytes, over the {} byte limit",
+                size,
+                PACKET_DATA_SIZE
+            ));
+        }
+
+        Ok(UnsignedTransfer {
+            transaction: encode_transaction(&transaction)?,
+            fee_payer: fee_payer.to_string(),
+            recent_blockhash: blockhash.to_string(),
+            missing_signers: missing_signers(&transaction),
+            priority_fee,
+            versioned,
+        })
+    }
+
+    /// Fetch and parse the address lookup tables at `addresses`
+    async fn get_lookup_tables(
+        &self,
+        addresses: &[String],
+    ) -> Result<Vec<AddressLookupTableAccount>> {
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys = addresses
+            .iter()
+            .map(|address| {
+                Pubkey::from_str(address.trim())
+                    .map_err(|_| anyhow!("Invalid lookup table address: {}", address))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let lookup_keys = keys.clone();
+        let accounts = self
+            .rpc
+            .call_async(move |rpc| rpc.get_multiple_accounts(&lookup_keys))
+            .await?;
+
+        let program = Pubkey::from_str(ADDRESS_LOOKUP_TABLE_PROGRAM_ID)?;
+        keys.into_iter()
+            .zip(accounts)
+            .map(|(key, account)| {
+                let account = account.ok_or_else(|| anyhow!("Lookup table {} not found", key))?;
+                if account.owner != program {
+                    return Err(anyhow!("{} is not an address lookup table", key));
+                }
+                Ok(AddressLookupTableAccount {
+                    key,
+                    addresses: lookup_table_addresses(&account.data)
+                        .ok_or_else(|| anyhow!("Lookup table {} is malformed", key))?,
+                })
+            })
+            .collect()
+    }
+
+    /// Broadcast a fully signed transaction, e.g. one from
+    /// `build_unsigned_transfer` after the other signers have added theirs.
+    /// Returns the transaction signature.
+    pub async fn submit_signed_transaction(&self, transaction: &str) -> Result<String> {
+        let transaction = decode_transaction(transaction)?;
+
+        let missing = missing_signers(&transaction);
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Transaction is missing signatures from {}",
+                missing.join(", ")
+            ));
+        }
+        if !transaction.verify_with_results().into_iter().all(|valid| valid) {
+            return Err(anyhow!("Transaction has an invalid signature"));
+        }
+
+        let signature = self
+            .rpc
+            .call_async(move |rpc| rpc.send_transaction(&transaction))
+            .await?;
+        Ok(signature.to_string())
+    }
+
+    /// Fetch the transaction a Solana Pay transaction-request `url` builds for
+    /// this wallet and check it only pays. Nothing is signed until the user
+    /// approves and the transaction goes to `approve_solana_pay`.
+    pub async fn solana_pay_request(&self, url: &str) -> Result<SolanaPayRequest> {
+        let link = solana_pay::parse_transaction_request(url)?;
+        let account = self.get_pubkey()?;
+        solana_pay::fetch_request(&link, &account).await
+    }
+
+    /// Sign and submit a transaction from `solana_pay_request`, checking it
+    /// again since it's come back through the frontend. Returns the signature.
+    pub async fn approve_solana_pay(&self, transaction: &str) -> Result<String> {
+        let mut transaction = decode_transaction(transaction)?;
+        let signed = {
+            let kp = self.keypair.lock().unwrap();
+            let keypair = kp.as_ref().ok_or_else(|| anyhow!("Wallet not unlocked"))?;
+            solana_pay::check_transaction(&transaction, &keypair.pubkey())?;
+            solana_pay::add_signature(&mut transaction, keypair)?;
+            encode_transaction(&transaction)?
+        };
+        self.submit_signed_transaction(&signed).await
+    }
+
+    /// Make sure the wallet has an associated token account for `mint`,
+    /// creating it if needed. An existing account is returned as is.
+    pub async fn create_token_account(&self, mint: &str) -> Result<TokenAccount> {
+        let mint = Pubkey::from_str(mint.trim()).map_err(|_| anyhow!("Invalid mint address"))?;
+        let owner = self.get_pubkey()?;
+
+        // The mint's owner says whether it belongs to SPL Token or Token-2022
+        let mint_account = self
+            .rpc
+            .call_async(move |rpc| rpc.get_multiple_accounts(&[mint]))
+            .await?
+            .into_iter()
+            .next()
+            .flatten()
+            .ok_or_else(|| anyhow!("Mint {} not found", mint))?;
+        let token_program = mint_account.owner;
+        let is_token_program = [SPL_TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID]
+            .iter()
+            .any(|id| Pubkey::from_str(id).is_ok_and(|id| id == token_program));
+        if !is_token_program {
+            return Err(anyhow!("{} is not a token mint", mint));
+        }
+
+        let address = associated_token_address(&owner, &mint, &token_program)?;
+        let existing = self
+            .rpc
+            .call_async(move |rpc| rpc.get_multiple_accounts(&[address]))
+            .await?;
+        if existing.into_iter().next().flatten().is_some() {
+            return Ok(TokenAccount {
+                address: address.to_string(),
+                mint: mint.to_string(),
+                created: false,
+                signature: None,
+            });
+        }
+
+        let mut instructions = Vec::new();
+        if let Some(fee) = self.priority_fee() {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(fee));
+        }
+        instructions.push(create_associated_token_account(
+            &owner,
+            &address,
+            &mint,
+            &token_program,
+        )?);
+
+        let blockhash = self
+            .rpc
+            .call_async(|rpc| rpc.get_latest_blockhash())
+            .await?;
+        // The wallet may have been locked or replaced while we were waiting
+        let transaction = {
+            let kp = self.keypair.lock().unwrap();
+            let keypair = kp.as_ref().ok_or_else(|| anyhow!("Wallet not unlocked"))?;
+            if keypair.pubkey() != owner {
+                return Err(anyhow!("Wallet changed while creating the token account"));
+            }
+            let mut transaction = Transaction::new_with_payer(&instructions, Some(&owner));
+            transaction
+                .try_sign(&[keypair], blockhash)
+                .context("Failed to sign transaction")?;
+            transaction
+        };
+        let signature = self
+            .rpc
+            .call_async(move |rpc| rpc.send_transaction(&transaction))
+            .await?;
+
+        println!("Created token account {} for mint {}", address, mint);
+        Ok(TokenAccount {
+            address: address.to_string(),
+            mint: mint.to_string(),
+            created: true,
+            signature: Some(signature.to_string()),
+        })
+    }
+
+    /// List NFTs held by the wallet: token accounts with amount 1 and 0 decimals,
+    /// enriched with Metaplex metadata where available
+    pub async fn get_nfts(&self) -> Result<Vec<NftInfo>> {
+        let owner = self.get_pubkey()?;
+        let token_program = Pubkey::from_str(SPL_TOKEN_PROGRAM_ID)?;
+
+        let accounts = self
+            .rpc
+            .call_async(move |rpc| {
+                rpc.get_token_accounts_by_owner(
+                    &owner,
+                    TokenAccountsFilter::ProgramId(token_program),
+                )
+            })
+            .await?;
+
+        let mut nfts: Vec<NftInfo> = accounts
+            .into_iter()
+            .filter_map(|keyed| {
+                let data = serde_json::to_value(&keyed.account.data).ok()?;
+                let info = data.get("parsed")?.get("info")?;
+                let amount = info.get("tokenAmount")?;
+
+                let is_nft = amount.get("amount")?.as_str()? == "1"
+                    && amount.get("decimals")?.as_u64()? == 0;
+                if !is_nft {
+                    return None;
+                }
+
+                Some(NftInfo {
+                    mint: info.get("mint")?.as_str()?.to_string(),
+                    token_account: keyed.pubkey,
+                    name: None,
+                    symbol: None,
+                    uri: None,
+                    image: None,
+                    error: None,
+                })
+            })
+            .collect();
+
+        if nfts.is_empty() {
+            return Ok(nfts);
+        }
+
+        // Fetch all metadata accounts in one round trip
+        let metadata_addresses: Vec<Pubkey> = nfts
+            .iter()
+            .map(|nft| metadata_address(&nft.mint))
+            .collect::<Result<_>>()?;
+        let metadata_accounts = self
+            .rpc
+            .call_async(move |rpc| rpc.get_multiple_accounts(&metadata_addresses))
+            .await?;
+
+        let client = crate::config::client_builder()?
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        for (nft, account) in nfts.iter_mut().zip(metadata_accounts) {
+            let account = match account {
+                Some(account) => account,
+                None => {
+                    nft.error = Some("No metadata account".to_string());
+                    continue;
+                }
+            };
+
+            match parse_metadata(&account.data) {
+                Ok((name, symbol, uri)) => {
+                    nft.name = Some(name);
+                    nft.symbol = Some(symbol);
+                    if !uri.is_empty() {
+                        match fetch_image_uri(&client, &uri).await {
+                            Ok(image) => nft.image = image,
+                            Err(e) => nft.error = Some(e.to_string()),
+                        }
+                        nft.uri = Some(uri);
+                    }
+                }
+                Err(e) => nft.error = Some(e.to_string()),
+            }
+        }
+
+        Ok(nfts)
+    }
+
+    /// NFTs cached for the unlocked wallet, if still fresh
+    pub fn cached_nfts(&self, storage: &crate::storage::Storage) -> Option<Vec<NftInfo>> {
+        let owner = self.get_address().ok()?;
+        let cache: NftCache = serde_json::from_value(storage.get(NFT_CACHE_KEY).ok()??).ok()?;
+
+        let fresh = unix_now().saturating_sub(cache.fetched_at) < NFT_CACHE_TTL_SECS;
+        (cache.owner == owner && fresh).then_some(cache.nfts)
+    }
+
+    /// Save the wallet's NFTs for `cached_nfts`; skipped for in-memory wallets
+    pub fn cache_nfts(&self, nfts: &[NftInfo], storage: &crate::storage::Storage) -> Result<()> {
+        if self.is_ephemeral() {
+            return Ok(());
+        }
+        let cache = NftCache {
+            owner: self.get_address()?,
+            fetched_at: unix_now(),
+            nfts: nfts.to_vec(),
+        };
+        storage.set(NFT_CACHE_KEY.to_string(), serde_json::to_value(cache)?)
+    }
+
+    /// Export private key (base58)
+    pub fn export_private_key(&self) -> Result<String> {
+        let kp = self.keypair.lock().unwrap();
+        match &*kp {
+            Some(keypair) => Ok(bs58::encode(keypair.to_bytes()).into_string()),
+            None => Err(anyhow!("Wallet not unlocked")),
+        }
+    }
+
+    /// Export the recovery phrase, if the user chose to store it
+    pub fn export_mnemonic(&self) -> Result<String> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Wallet not unlocked"));
+        }
+
+        let mnemonic = self.mnemonic.lock().unwrap();
+        mnemonic
+            .clone()
+            .ok_or_else(|| anyhow!("No recovery phrase was stored for this wallet"))
+    }
+
+    /// Export the unlocked wallet as a versioned backup, encrypted with
+    /// `password` (which need not be the wallet password)
+    pub fn export_encrypted_backup(&self, password: &str) -> Result<String> {
+        if password.is_empty() {
+            return Err(anyhow!("Backup password cannot be empty"));
+        }
+
+        let (secret_key, address) = {
+            let kp = self.keypair.lock().unwrap();
+            let keypair = kp.as_ref().ok_or_else(|| anyhow!("Wallet not unlocked"))?;
+            (keypair.to_bytes(), keypair.pubkey().to_string())
+        };
+
+        let secrets = BackupSecrets {
+            secret_key: bs58::encode(secret_key).into_string(),
+            mnemonic: self.mnemonic.lock().unwrap().clone(),
+        };
+
+        // The work factor goes in `kdf_iterations`, so the payload is bare
+        let iterations = self.kdf_params().iterations;
+        let sealed = seal(&serde_json::to_vec(&secrets)?, password, iterations)?;
+        let backup = WalletBackup {
+            version: BACKUP_VERSION,
+            kdf: BACKUP_KDF.to_string(),
+            kdf_iterations: iterations,
+            cipher: BACKUP_CIPHER.to_string(),
+            address,
+            created_at: unix_now(),
+            payload: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, sealed),
+        };
+
+        Ok(serde_json::to_string_pretty(&backup)?)
+    }
+
+    /// Restore a wallet from a backup, as written by `export_encrypted_backup`
+    /// on this or another install, and save it under `password`
+    pub fn import_encrypted_backup(
+        &self,
+        backup_json: &str,
+        backup_password: &str,
+        password: &str,
+        overwrite: bool,
+        storage: &crate::storage::Storage,
+    ) -> Result<WalletAccount> {
+        self.ensure_can_save(overwrite, storage)?;
+
+        let backup: WalletBackup =
+            serde_json::from_str(backup_json).context("Not a wallet backup file")?;
+
+        match backup.version {
+            1..=BACKUP_VERSION => {}
+            version if version > BACKUP_VERSION => {
+                return Err(anyhow!(
+                    "Backup version {} is newer than this app supports",
+                    version
+                ));
+            }
+            version => return Err(anyhow!("Unknown backup version {}", version)),
+        }
+        if backup.kdf != BACKUP_KDF
+            || !(1..=MAX_KDF_ITERATIONS).contains(&backup.kdf_iterations)
+            || backup.cipher != BACKUP_CIPHER
+        {
+            return Err(anyhow!(
+                "Unsupported backup encryption ({} x{}, {})",
+                backup.kdf,
+                backup.kdf_iterations,
+                backup.cipher
+            ));
+        }
+
+        let envelope = parse_sealed(&backup.payload, backup.kdf_iterations)?;
+        let key = derive_key(backup_password, &envelope.salt, envelope.iterations);
+        let plaintext = open(&envelope, &key).context("Wrong backup password")?;
+        let secrets: BackupSecrets =
+            serde_json::from_slice(&plaintext).context("Invalid backup contents")?;
+
+        let decoded = bs58::decode(&secrets.secret_key)
+            .into_vec()
+            .context("Invalid key in backup")?;
+        let keypair = Keypair::from_bytes(&decoded)?;
+        if keypair.pubkey().to_string() != backup.address {
+            return Err(anyhow!("Backup key doesn't match its recorded address"));
+        }
+
+        // Backups don't record how the key was derived from the phrase
+        let derivation = secrets
+            .mnemonic
+            .as_deref()
+            .map(|phrase| PhraseDerivation::detect(phrase, &keypair))
+            .transpose()?;
+        self.save_wallet(
+            &keypair,
+            secrets.mnemonic.as_deref(),
+            derivation.as_ref(),
+            password,
+            storage,
+        )?;
+
+        let mut kp = self.keypair.lock().unwrap();
+        *kp = Some(Keypair::from_bytes(&keypair.to_bytes())?);
+        *self.mnemonic.lock().unwrap() = secrets.mnemonic;
+        self.mark_unlocked();
+
+        Ok(WalletAccount {
+            address: keypair.pubkey().to_string(),
+            mnemonic: None,
+            passphrase_used: None,
+            legacy_address: None,
+        })
+    }
+
+    /// Check if wallet is unlocked
+    pub fn is_unlocked(&self) -> bool {
+        let kp = self.keypair.lock().unwrap();
         kp.is_some()
     }
 
-    /// Save wallet encrypted to storage
+    /// Refuse to replace an existing wallet unless the caller confirmed it
+    fn ensure_can_save(&self, overwrite: bool, storage: &crate::storage::Storage) -> Result<()> {
+        if !overwrite && self.has_wallet(storage) {
+            return Err(WalletAlreadyExists.into());
+        }
+        Ok(())
+    }
+
+    /// Save wallet encrypted to storage, along with the recovery phrase if
+    /// given and how the key was derived from it, if it was
     fn save_wallet(
         &self,
         keypair: &Keypair,
+        mnemonic_phrase: Option<&str>,
+        derivation: Option<&PhraseDerivation>,
         password: &str,
         storage: &crate::storage::Storage,
     ) -> Result<()> {
+        // Keys remembered for the old blobs won't open the new ones
+        if let Err(e) = self.forget_device(storage) {
+            println!("Failed to forget remembered device: {}", e);
+        }
+
         let encrypted = self.encrypt(&keypair.to_bytes(), password)?;
         storage.set(
             self.storage_key.clone(),
             serde_json::Value::String(encrypted),
         )?;
         storage.set(
-            "solana_wallet_address".to_string(),
+            ADDRESS_STORAGE_KEY.to_string(),
             serde_json::Value::String(keypair.pubkey().to_string()),
         )?;
+
+        // Never leave a previous wallet's phrase behind
+        match mnemonic_phrase {
+            Some(phrase) => {
+                let encrypted = self.encrypt(phrase.as_bytes(), password)?;
+                storage.set(
+                    self.mnemonic_storage_key.clone(),
+                    serde_json::Value::String(encrypted),
+                )?;
+            }
+            None => storage.remove(&self.mnemonic_storage_key)?,
+        }
+        match derivation {
+            Some(derivation) => storage.set(
+                DERIVATION_STORAGE_KEY.to_string(),
+                serde_json::to_value(derivation)?,
+            )?,
+            None => storage.remove(DERIVATION_STORAGE_KEY)?,
+        }
+
         Ok(())
     }
 
-    /// Encrypt data with password using AES-256-GCM
+    /// How the saved wallet's key was derived from its phrase. `None` for
+    /// keys imported without a phrase and for wallets saved before the
+    /// derivation was recorded.
+    fn phrase_derivation(&self, storage: &crate::storage::Storage) -> Option<PhraseDerivation> {
+        storage
+            .get(DERIVATION_STORAGE_KEY)
+            .ok()
+            .flatten()
+            .and_then(|value| serde_json::from_value(value).ok())
+    }
+
+    /// Decrypt the stored recovery phrase, if any
+    fn load_mnemonic(
+        &self,
+        password: &str,
+        storage: &crate::storage::Storage,
+    ) -> Result<Option<String>> {
+        let encrypted = match storage.get(&self.mnemonic_storage_key)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let encrypted_str = encrypted
+            .as_str()
+            .ok_or_else(|| anyhow!("Invalid recovery phrase data"))?;
+
+        let phrase = self.decrypt(encrypted_str, password)?;
+        Ok(Some(String::from_utf8(phrase).context("Invalid recovery phrase data")?))
+    }
+
+    /// Encrypt data with password using AES-256-GCM, recording the current
+    /// KDF work factor in the blob's header
     fn encrypt(&self, data: &[u8], password: &str) -> Result<String> {
-        // Generate salt and nonce
-        let mut rng = rand::thread_rng();
-        let salt: [u8; 16] = rng.gen();
-        let nonce_bytes: [u8; 12] = rng.gen();
-        let nonce = Nonce::from_slice(&nonce_bytes);
-
-        // Derive key from password using PBKDF2
-        let mut key = [0u8; 32];
-        pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, 100_000, &mut key);
-
-        // Encrypt using AES-256-GCM
-        let cipher = Aes256Gcm::new_from_slice(&key)
-            .context("Failed to create cipher")?;
-        let ciphertext = cipher
-            .encrypt(nonce, data)
-            .map_err(|_| anyhow!("Encryption failed"))?;
-
-        // Combine: salt (16) + nonce (12) + ciphertext (which includes auth tag)
-        let mut combined = Vec::new();
-        combined.extend_from_slice(&salt);
-        combined.extend_from_slice(&nonce_bytes);
-        combined.extend_from_slice(&ciphertext);
-
-        // Encode as base64
-        Ok(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, combined))
-    }
-
-    /// Decrypt data with password
+        let iterations = self.kdf_params().iterations;
+        let sealed = seal(data, password, iterations)?;
+
+        // Encode as base64 behind the header
+        Ok(format!(
+            "{}{}${}",
+            ENVELOPE_PREFIX,
+            iterations,
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, sealed)
+        ))
+    }
+
+    /// Decrypt data with password, using the work factor the blob records
     fn decrypt(&self, encrypted_data: &str, password: &str) -> Result<Vec<u8>> {
-        // Decode from base64
-        let combined = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encrypted_data)
-            .context("Invalid base64")?;
+        let envelope = split_encrypted(encrypted_data)?;
+        let key = derive_key(password, &envelope.salt, envelope.iterations);
+        open(&envelope, &key)
+    }
+
+    /// Decrypt data with a key already derived from its password and salt
+    fn decrypt_with_key(&self, encrypted_data: &str, key: &[u8; 32]) -> Result<Vec<u8>> {
+        open(&split_encrypted(encrypted_data)?, key)
+    }
+
+    /// Key `password` derives for an encrypted blob, checked by decrypting it
+    fn blob_key(&self, encrypted_data: &str, password: &str) -> Result<[u8; 32]> {
+        let envelope = split_encrypted(encrypted_data)?;
+        let key = derive_key(password, &envelope.salt, envelope.iterations);
+        open(&envelope, &key)?;
+        Ok(key)
+    }
+
+    /// Encrypted blob saved under `key`, if any
+    fn stored_blob(&self, key: &str, storage: &crate::storage::Storage) -> Result<Option<String>> {
+        match storage.get(key)? {
+            Some(value) => Ok(Some(
+                value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Invalid wallet data"))?
+                    .to_string(),
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Derive an AES key from a password and salt using PBKDF2
+fn derive_key(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+/// Encrypt with a fresh salt and nonce: salt (16) + nonce (12) + ciphertext
+/// (which includes the auth tag)
+fn seal(data: &[u8], password: &str, iterations: u32) -> Result<Vec<u8>> {
+    // Generate salt and nonce
+    let mut rng = rand::thread_rng();
+    let salt: [u8; 16] = rng.gen();
+    let nonce_bytes: [u8; 12] = rng.gen();
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    // Derive key from password using PBKDF2
+    let key = derive_key(password, &salt, iterations);
+
+    // Encrypt using AES-256-GCM
+    let cipher = Aes256Gcm::new_from_slice(&key).context("Failed to create cipher")?;
+    let ciphertext = cipher
+        .encrypt(nonce, data)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+
+    let mut combined = Vec::new();
+    combined.extend_from_slice(&salt);
+    combined.extend_from_slice(&nonce_bytes);
+    combined.extend_from_slice(&ciphertext);
+    Ok(combined)
+}
+
+/// Decrypt an envelope with its derived key
+fn open(envelope: &Envelope, key: &[u8; 32]) -> Result<Vec<u8>> {
+    let nonce = Nonce::from_slice(&envelope.nonce);
+
+    let cipher = Aes256Gcm::new_from_slice(key).context("Failed to create cipher")?;
+    let plaintext = cipher
+        .decrypt(nonce, envelope.ciphertext.as_slice())
+        .map_err(|_| anyhow!("Decryption failed (wrong password?)"))?;
+
+    Ok(plaintext)
+}
+
+/// Split an encrypted blob into its parts. Blobs from before the work factor
+/// was configurable have no header and used `DEFAULT_KDF_ITERATIONS`.
+fn split_encrypted(encrypted_data: &str) -> Result<Envelope> {
+    let Some(rest) = encrypted_data.strip_prefix(ENVELOPE_PREFIX) else {
+        return parse_sealed(encrypted_data, DEFAULT_KDF_ITERATIONS);
+    };
+
+    let (iterations, encoded) = rest
+        .split_once('$')
+        .ok_or_else(|| anyhow!("Invalid encrypted data"))?;
+    let iterations = iterations
+        .parse::<u32>()
+        .ok()
+        .filter(|iterations| (1..=MAX_KDF_ITERATIONS).contains(iterations))
+        .ok_or_else(|| anyhow!("Invalid KDF iterations in encrypted data"))?;
+    parse_sealed(encoded, iterations)
+}
+
+/// Decode base64 salt (16) + nonce (12) + ciphertext
+fn parse_sealed(encoded: &str, iterations: u32) -> Result<Envelope> {
+    let combined = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded)
+        .context("Invalid base64")?;
+
+    if combined.len() < 28 {
+        return Err(anyhow!("Invalid encrypted data"));
+    }
+
+    Ok(Envelope {
+        iterations,
+        salt: combined[0..16].to_vec(),
+        nonce: combined[16..28].to_vec(),
+        ciphertext: combined[28..].to_vec(),
+    })
+}
+
+/// Codes of the wordlists `import_from_mnemonic` accepts
+pub fn mnemonic_languages() -> Vec<String> {
+    MNEMONIC_LANGUAGES
+        .iter()
+        .map(|(code, _)| code.to_string())
+        .collect()
+}
+
+/// Parse `phrase` in the wordlist for `language`, or the first wordlist it's
+/// valid in when no language is given
+fn parse_mnemonic(phrase: &str, language: Option<&str>) -> Result<bip39::Mnemonic> {
+    if let Some(code) = language {
+        let language = MNEMONIC_LANGUAGES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(code.trim()))
+            .map(|(_, language)| *language)
+            .ok_or_else(|| anyhow!("Unsupported recovery phrase language: {}", code))?;
+        return bip39::Mnemonic::parse_in(language, phrase)
+            .map_err(|e| anyhow!("Invalid recovery phrase: {}", e));
+    }
+
+    let detected = MNEMONIC_LANGUAGES
+        .iter()
+        .find_map(|(_, language)| bip39::Mnemonic::parse_in(*language, phrase).ok());
+    match detected {
+        Some(mnemonic) => Ok(mnemonic),
+        // Report why it isn't valid English, the likeliest intent
+        None => bip39::Mnemonic::parse_in(bip39::Language::English, phrase)
+            .map_err(|e| anyhow!("Invalid recovery phrase: {}", e)),
+    }
+}
+
+/// Derive the wallet keypair from a BIP-39 mnemonic and passphrase at
+/// `path`, `MNEMONIC_DERIVATION` or `LEGACY_MNEMONIC_DERIVATION`; an empty
+/// passphrase means none
+fn keypair_from_mnemonic(
+    mnemonic: &bip39::Mnemonic,
+    passphrase: &str,
+    path: &str,
+) -> Result<Keypair> {
+    let seed = mnemonic.to_seed(passphrase);
+    match path {
+        MNEMONIC_DERIVATION => keypair_from_seed_and_derivation_path(
+            &seed,
+            Some(DerivationPath::new_bip44(Some(0), Some(0))),
+        ),
+        LEGACY_MNEMONIC_DERIVATION => keypair_from_seed(&seed[..32]),
+        _ => return Err(anyhow!("Unknown derivation path: {}", path)),
+    }
+    .map_err(|e| anyhow!("Failed to derive keypair: {}", e))
+}
+
+/// Address `mnemonic` had in earlier versions, for a phrase imported at
+/// another `path`
+fn legacy_address(
+    mnemonic: &bip39::Mnemonic,
+    passphrase: &str,
+    path: &str,
+) -> Result<Option<String>> {
+    if path == LEGACY_MNEMONIC_DERIVATION {
+        return Ok(None);
+    }
+    let keypair = keypair_from_mnemonic(mnemonic, passphrase, LEGACY_MNEMONIC_DERIVATION)?;
+    Ok(Some(keypair.pubkey().to_string()))
+}
+
+/// Parse a base58 private key: a full 64-byte keypair or a 32-byte seed.
+/// The JSON byte array of a Solana CLI keypair file is accepted too.
+fn keypair_from_private_key(private_key: &str) -> Result<Keypair> {
+    let private_key = private_key.trim();
+    if private_key.starts_with('[') {
+        return keypair_from_json(private_key);
+    }
+    let decoded = bs58::decode(private_key)
+        .into_vec()
+        .map_err(|_| anyhow!("Invalid private key: not valid base58"))?;
+
+    match decoded.len() {
+        64 => Keypair::from_bytes(&decoded)
+            .map_err(|_| anyhow!("Invalid private key: public half doesn't match the secret")),
+        32 => keypair_from_seed(&decoded).map_err(|e| anyhow!("Invalid private key seed: {}", e)),
+        len if len < 32 => Err(anyhow!(
+            "Invalid private key: too short ({} bytes, expected 64 or a 32-byte seed)",
+            len
+        )),
+        len if len > 64 => Err(anyhow!(
+            "Invalid private key: too long ({} bytes, expected 64 or a 32-byte seed)",
+            len
+        )),
+        len => Err(anyhow!(
+            "Invalid private key: wrong length ({} bytes, expected 64 or a 32-byte seed)",
+            len
+        )),
+    }
+}
+
+/// Address a private key belongs to, without importing it. Takes the same
+/// forms as `import_from_private_key`.
+pub fn pubkey_from_private_key(private_key: &str) -> Result<String> {
+    Ok(keypair_from_private_key(private_key)?.pubkey().to_string())
+}
+
+/// Parse a Solana CLI keypair file: a JSON array of the 64 keypair bytes
+fn keypair_from_json(json: &str) -> Result<Keypair> {
+    let bytes: Vec<u8> = serde_json::from_str(json)
+        .map_err(|_| anyhow!("Invalid keypair file: expected a JSON array of bytes"))?;
+    if bytes.len() != 64 {
+        return Err(anyhow!(
+            "Invalid keypair file: {} bytes, expected 64",
+            bytes.len()
+        ));
+    }
+
+    Keypair::from_bytes(&bytes)
+        .map_err(|_| anyhow!("Invalid keypair file: public half doesn't match the secret"))
+}
 
-        if combined.len() < 28 {
-            return Err(anyhow!("Invalid encrypted data"));
+pub fn validate_priority_fee(microlamports: u64) -> Result<()> {
+    if microlamports > MAX_PRIORITY_FEE_MICROLAMPORTS {
+        return Err(anyhow!(
+            "Priority fee must be at most {} micro-lamports per compute unit",
+            MAX_PRIORITY_FEE_MICROLAMPORTS
+        ));
+    }
+    Ok(())
+}
+
+/// Convert a positive SOL amount to lamports
+fn sol_to_lamports(amount_sol: f64) -> Result<u64> {
+    let lamports = (amount_sol * LAMPORTS_PER_SOL as f64).round();
+    if !lamports.is_finite() || lamports < 1.0 || lamports > u64::MAX as f64 {
+        return Err(anyhow!("Amount must be a positive number of SOL"));
+    }
+    Ok(lamports as u64)
+}
+
+fn encode_transaction(transaction: &VersionedTransaction) -> Result<String> {
+    let bytes = bincode::serialize(transaction).context("Failed to serialize transaction")?;
+    Ok(base64::Engine::encode(
+        &base64::engine::general_purpose::STANDARD,
+        bytes,
+    ))
+}
+
+/// Parse a base64, bincode-serialized transaction, legacy or versioned
+pub fn decode_transaction(encoded: &str) -> Result<VersionedTransaction> {
+    let bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded.trim())
+        .context("Transaction is not valid base64")?;
+    bincode::deserialize(&bytes).context("Failed to parse transaction")
+}
+
+/// Required signers whose signature slot is still empty
+fn missing_signers(transaction: &VersionedTransaction) -> Vec<String> {
+    let required = transaction.message.header().num_required_signatures as usize;
+    transaction
+        .message
+        .static_account_keys()
+        .iter()
+        .take(required)
+        .zip(&transaction.signatures)
+        .filter(|(_, signature)| **signature == Signature::default())
+        .map(|(key, _)| key.to_string())
+        .collect()
+}
+
+/// Sign `message` as the wallet, leaving the other signers' slots empty
+fn partially_sign_versioned(
+    message: VersionedMessage,
+    keypair: &Keypair,
+) -> Result<VersionedTransaction> {
+    let required = message.header().num_required_signatures as usize;
+    let position = message
+        .static_account_keys()
+        .iter()
+        .take(required)
+        .position(|key| *key == keypair.pubkey())
+        .ok_or_else(|| anyhow!("Wallet is not a signer of this transaction"))?;
+
+    let mut signatures = vec![Signature::default(); required];
+    signatures[position] = keypair.sign_message(&message.serialize());
+    Ok(VersionedTransaction {
+        signatures,
+        message,
+    })
+}
+
+/// Addresses stored in an address lookup table account, or `None` when the
+/// data isn't a lookup table
+fn lookup_table_addresses(data: &[u8]) -> Option<Vec<Pubkey>> {
+    let addresses = data.get(LOOKUP_TABLE_META_SIZE..)?;
+    if addresses.len() % 32 != 0 {
+        return None;
+    }
+    addresses
+        .chunks_exact(32)
+        .map(|bytes| Pubkey::try_from(bytes).ok())
+        .collect()
+}
+
+/// Flatten a jsonParsed `getTransaction` result into what the UI shows
+fn summarize_transaction(signature: &str, transaction: &serde_json::Value) -> serde_json::Value {
+    let meta = &transaction["meta"];
+    let message = &transaction["transaction"]["message"];
+    let error = meta.get("err").filter(|err| !err.is_null());
+    let fee = meta["fee"].as_u64();
+
+    let pre_balances = meta["preBalances"].as_array();
+    let post_balances = meta["postBalances"].as_array();
+    let balance_at = |balances: Option<&Vec<serde_json::Value>>, index: usize| {
+        balances.and_then(|b| b.get(index)).and_then(|v| v.as_u64())
+    };
+
+    let accounts: Vec<serde_json::Value> = message["accountKeys"]
+        .as_array()
+        .map(|keys| {
+            keys.iter()
+                .enumerate()
+                .map(|(index, key)| {
+                    let pre = balance_at(pre_balances, index);
+                    let post = balance_at(post_balances, index);
+                    serde_json::json!({
+                        "address": key["pubkey"],
+                        "signer": key["signer"].as_bool().unwrap_or(false),
+                        "writable": key["writable"].as_bool().unwrap_or(false),
+                        "pre_balance_sol": pre.map(lamports_to_sol),
+                        "post_balance_sol": post.map(lamports_to_sol),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let instructions: Vec<serde_json::Value> = message["instructions"]
+        .as_array()
+        .map(|instructions| instructions.iter().map(summarize_instruction).collect())
+        .unwrap_or_default();
+
+    serde_json::json!({
+        "signature": signature,
+        "slot": transaction["slot"],
+        "block_time": transaction["blockTime"],
+        "status": if error.is_some() { "failed" } else { "success" },
+        "error": error,
+        "fee_lamports": fee,
+        "fee_sol": fee.map(lamports_to_sol),
+        "accounts": accounts,
+        "instructions": instructions,
+    })
+}
+
+/// One instruction, with system-program transfers decoded into from/to/amount
+fn summarize_instruction(instruction: &serde_json::Value) -> serde_json::Value {
+    let parsed = &instruction["parsed"];
+    let kind = parsed["type"].as_str();
+
+    let transfer = match (instruction["program"].as_str(), kind) {
+        (Some("system"), Some("transfer" | "transferWithSeed")) => {
+            let info = &parsed["info"];
+            info["lamports"].as_u64().map(|lamports| {
+                serde_json::json!({
+                    "from": info["source"],
+                    "to": info["destination"],
+                    "lamports": lamports,
+                    "amount_sol": lamports_to_sol(lamports),
+                })
+            })
+        }
+        _ => None,
+    };
+
+    serde_json::json!({
+        "program": instruction["program"],
+        "program_id": instruction["programId"],
+        "type": kind,
+        "transfer": transfer,
+        // Kept as is for instructions the node couldn't decode
+        "accounts": instruction.get("accounts"),
+        "data": instruction.get("data"),
+        "parsed": (transfer.is_none() && !parsed.is_null()).then_some(parsed),
+    })
+}
+
+fn lamports_to_sol(lamports: u64) -> f64 {
+    lamports as f64 / LAMPORTS_PER_SOL as f64
+}
+
+/// Check a base58 address without needing an unlocked wallet
+pub fn validate_address(address: &str) -> AddressValidation {
+    let address = address.trim();
+    match Pubkey::from_str(address) {
+        Ok(pubkey) => AddressValidation {
+            valid: true,
+            on_curve: Some(pubkey.is_on_curve()),
+            reason: None,
+        },
+        Err(_) => AddressValidation {
+            valid: false,
+            on_curve: None,
+            reason: Some(invalid_address_reason(address)),
+        },
+    }
+}
+
+/// Short fingerprint of an address for checking it by eye, e.g. `3F9A-C21B`:
+/// the first four bytes of a SHA-256 over the pubkey. Saved and shared
+/// fingerprints have to keep matching, so the input and output must never
+/// change.
+pub fn address_fingerprint(address: &str) -> Result<String> {
+    let pubkey = Pubkey::from_str(address.trim()).map_err(|_| anyhow!("Invalid address"))?;
+    let mut hasher = Sha256::new();
+    hasher.update(ADDRESS_FINGERPRINT_DOMAIN);
+    hasher.update(pubkey.as_ref());
+    let digest = hex::encode_upper(&hasher.finalize()[..4]);
+    Ok(format!("{}-{}", &digest[..4], &digest[4..]))
+}
+
+/// Check an ed25519 signature of `message` by `pubkey`. A well-formed
+/// signature that doesn't match is `Ok(false)`; only malformed input errors.
+pub fn verify_message(pubkey: &str, message: &str, signature_base58: &str) -> Result<bool> {
+    let pubkey = Pubkey::from_str(pubkey.trim()).map_err(|_| anyhow!("Invalid public key"))?;
+    let signature = Signature::from_str(signature_base58.trim())
+        .map_err(|_| anyhow!("Invalid signature: expected 64 bytes of base58"))?;
+    Ok(signature.verify(pubkey.as_ref(), message.as_bytes()))
+}
+
+fn invalid_address_reason(address: &str) -> String {
+    if address.is_empty() {
+        return "Address is empty".to_string();
+    }
+
+    match bs58::decode(address).into_vec() {
+        Err(bs58::decode::Error::InvalidCharacter { character, index }) => format!(
+            "Not valid base58: '{}' at position {}",
+            character,
+            index + 1
+        ),
+        Err(_) => "Not valid base58".to_string(),
+        Ok(bytes) => format!("Wrong length: {} bytes, expected 32", bytes.len()),
+    }
+}
+
+/// Read the USD price from a CoinGecko-style `{"solana": {"usd": ..}}` body,
+/// or a flat `{"price": ..}` / `{"usd": ..}` one
+fn parse_sol_price(body: &serde_json::Value) -> Option<f64> {
+    body.pointer("/solana/usd")
+        .or_else(|| body.get("price"))
+        .or_else(|| body.get("usd"))
+        .and_then(|v| v.as_f64())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Metaplex metadata PDA for a mint
+fn metadata_address(mint: &str) -> Result<Pubkey> {
+    let mint = Pubkey::from_str(mint)?;
+    let program = Pubkey::from_str(METADATA_PROGRAM_ID)?;
+    let (address, _) = Pubkey::find_program_address(
+        &[b"metadata", program.as_ref(), mint.as_ref()],
+        &program,
+    );
+    Ok(address)
+}
+
+/// Associated token account of `owner` for `mint` under `token_program`
+fn associated_token_address(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Result<Pubkey> {
+    let program = Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID)?;
+    let (address, _) = Pubkey::find_program_address(
+        &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &program,
+    );
+    Ok(address)
+}
+
+/// Associated token program instruction creating `owner`'s account for
+/// `mint`, paid for by the owner. Succeeds if the account already exists.
+fn create_associated_token_account(
+    owner: &Pubkey,
+    address: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Result<Instruction> {
+    Ok(Instruction::new_with_bytes(
+        Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID)?,
+        &[CREATE_IDEMPOTENT_INSTRUCTION],
+        vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(*address, false),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*token_program, false),
+        ],
+    ))
+}
+
+/// Memo program instruction recording `memo`, signed by `signer`
+fn memo_instruction(memo: &str, signer: &Pubkey) -> Result<Instruction> {
+    Ok(Instruction::new_with_bytes(
+        Pubkey::from_str(MEMO_PROGRAM_ID)?,
+        memo.as_bytes(),
+        vec![AccountMeta::new_readonly(*signer, true)],
+    ))
+}
+
+/// Read name, symbol and uri from a Metaplex metadata account.
+/// Layout: key (1) + update authority (32) + mint (32), then three
+/// length-prefixed, NUL-padded strings.
+fn parse_metadata(data: &[u8]) -> Result<(String, String, String)> {
+    let mut offset = 1 + 32 + 32;
+    let mut read_string = || -> Result<String> {
+        let len_bytes = data
+            .get(offset..offset + 4)
+            .ok_or_else(|| anyhow!("Truncated metadata account"))?;
+        let len = u32::from_le_bytes(len_bytes.try_into()?) as usize;
+        offset += 4;
+        let bytes = data
+            .get(offset..offset + len)
+            .ok_or_else(|| anyhow!("Truncated metadata account"))?;
+        offset += len;
+        Ok(String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string())
+    };
+
+    let name = read_string()?;
+    let symbol = read_string()?;
+    let uri = read_string()?;
+    Ok((name, symbol, uri))
+}
+
+/// Fetch the off-chain metadata JSON and return its `image` field
+async fn fetch_image_uri(client: &reqwest::Client, uri: &str) -> Result<Option<String>> {
+    let json: serde_json::Value = client
+        .get(uri)
+        .send()
+        .await
+        .context("Failed to fetch NFT metadata")?
+        .json()
+        .await
+        .context("Failed to parse NFT metadata")?;
+
+    Ok(json.get("image").and_then(|i| i.as_str()).map(String::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    // BIP-39 test vector phrase
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const PASSWORD: &str = "correct horse battery staple";
+
+    fn stored_phrase() -> MnemonicImport<'static> {
+        MnemonicImport {
+            store_mnemonic: true,
+            ..Default::default()
         }
+    }
+
+    fn with_passphrase(passphrase: &str) -> MnemonicImport<'_> {
+        MnemonicImport {
+            passphrase: Some(passphrase),
+            ..stored_phrase()
+        }
+    }
+
+    fn wallet_and_storage() -> (SolanaWallet, Storage, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        (
+            SolanaWallet::with_rpc_url("http://127.0.0.1:8899".to_string()),
+            storage,
+            dir,
+        )
+    }
+
+    #[test]
+    fn passphrase_wallets_keep_their_key_on_unlock() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let account = wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, with_passphrase("extra"), &storage)
+            .unwrap();
+        wallet.lock();
+
+        let unlocked = wallet.unlock(PASSWORD, &storage).unwrap();
 
-        // Extract components
-        let salt = &combined[0..16];
-        let nonce_bytes = &combined[16..28];
-        let ciphertext = &combined[28..];
+        assert_eq!(unlocked.address, account.address);
+        let derivation = wallet.phrase_derivation(&storage).unwrap();
+        assert!(derivation.passphrase);
+        let verification = wallet.verify_address(None, &storage).unwrap();
+        assert!(verification.matches);
+        assert_eq!(verification.phrase_address, None);
+        assert_eq!(verification.warning, None);
+    }
+
+    #[test]
+    fn phrase_wallets_use_the_solana_bip44_path() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let account = wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, stored_phrase(), &storage)
+            .unwrap();
+
+        // Address other Solana wallets show for this phrase
+        assert_eq!(
+            account.address,
+            "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk"
+        );
+        let info = wallet.debug_info(&storage).unwrap();
+        assert_eq!(info.derivation_path, "m/44'/501'/0'/0'");
+    }
+
+    #[test]
+    fn legacy_phrase_wallets_keep_their_address() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let mnemonic = parse_mnemonic(PHRASE, None).unwrap();
+        let keypair = keypair_from_mnemonic(&mnemonic, "", LEGACY_MNEMONIC_DERIVATION).unwrap();
+        wallet
+            .save_wallet(&keypair, Some(PHRASE), None, PASSWORD, &storage)
+            .unwrap();
+
+        let unlocked = wallet.unlock(PASSWORD, &storage).unwrap();
+
+        assert_eq!(unlocked.address, keypair.pubkey().to_string());
+        let verification = wallet.verify_address(None, &storage).unwrap();
+        assert!(verification.matches);
+        assert_eq!(verification.phrase_address, Some(unlocked.address));
+        assert_eq!(verification.warning, None);
+    }
+
+    #[test]
+    fn phrases_from_earlier_versions_restore_the_same_address() {
+        let (old_install, old_storage, _old_dir) = wallet_and_storage();
+        let mnemonic = parse_mnemonic(PHRASE, None).unwrap();
+        let keypair = keypair_from_mnemonic(&mnemonic, "", LEGACY_MNEMONIC_DERIVATION).unwrap();
+        old_install
+            .save_wallet(&keypair, None, None, PASSWORD, &old_storage)
+            .unwrap();
+        let old_address = old_install.stored_address(&old_storage).unwrap();
+
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let standard = wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, stored_phrase(), &storage)
+            .unwrap();
+        // The default path gives another address, and points at the old one
+        assert_ne!(standard.address, old_address);
+        assert_eq!(standard.legacy_address, Some(old_address.clone()));
+
+        let legacy = MnemonicImport {
+            overwrite: true,
+            derivation: Some(LEGACY_MNEMONIC_DERIVATION),
+            ..stored_phrase()
+        };
+        let restored = wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, legacy, &storage)
+            .unwrap();
+
+        assert_eq!(restored.address, old_address);
+        assert_eq!(restored.legacy_address, None);
+        let derivation = wallet.phrase_derivation(&storage).unwrap();
+        assert_eq!(derivation.path, LEGACY_MNEMONIC_DERIVATION);
+        wallet.lock();
+        let unlocked = wallet.unlock(PASSWORD, &storage).unwrap();
+        assert_eq!(unlocked.address, old_address);
+        let verification = wallet.verify_address(None, &storage).unwrap();
+        assert_eq!(verification.phrase_address, Some(old_address));
+        assert_eq!(verification.warning, None);
+    }
 
-        let nonce = Nonce::from_slice(nonce_bytes);
+    #[test]
+    fn unknown_derivation_paths_are_refused() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let options = MnemonicImport {
+            derivation: Some("m/44'/60'/0'/0/0"),
+            ..stored_phrase()
+        };
+
+        assert!(wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, options, &storage)
+            .is_err());
+        assert!(!wallet.has_wallet(&storage));
+    }
+
+    #[test]
+    fn a_second_wallet_is_refused_without_overwrite() {
+        let (wallet, storage, dir) = wallet_and_storage();
+        let key = bs58::encode(Keypair::new().to_bytes()).into_string();
+        let first = wallet
+            .import_from_private_key(&key, PASSWORD, false, &storage)
+            .unwrap();
+        let saved = std::fs::read(dir.path().join("storage.json")).unwrap();
+
+        let other_key = bs58::encode(Keypair::new().to_bytes()).into_string();
+        let attempts = [
+            wallet.generate_new(PASSWORD, None, false, false, &storage),
+            wallet.import_from_mnemonic(PHRASE, PASSWORD, stored_phrase(), &storage),
+            wallet.import_from_private_key(&other_key, PASSWORD, false, &storage),
+        ];
+
+        for attempt in attempts {
+            assert!(attempt.unwrap_err().is::<WalletAlreadyExists>());
+        }
+        assert_eq!(
+            std::fs::read(dir.path().join("storage.json")).unwrap(),
+            saved
+        );
+        assert_eq!(wallet.get_address().unwrap(), first.address);
+        wallet.lock();
+        let unlocked = wallet.unlock(PASSWORD, &storage).unwrap();
+        assert_eq!(unlocked.address, first.address);
+    }
+
+    #[test]
+    fn verify_address_reports_a_changed_address() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let key = bs58::encode(Keypair::new().to_bytes()).into_string();
+        let account = wallet
+            .import_from_private_key(&key, PASSWORD, false, &storage)
+            .unwrap();
+        wallet.lock();
+        let other = Keypair::new().pubkey().to_string();
+        storage
+            .set(ADDRESS_STORAGE_KEY.to_string(), serde_json::json!(other))
+            .unwrap();
+
+        let verification = wallet.verify_address(Some(PASSWORD), &storage).unwrap();
+
+        assert!(!verification.matches);
+        assert_eq!(verification.stored_address, Some(other.clone()));
+        assert_eq!(verification.derived_address, account.address);
+        assert!(verification.warning.unwrap().contains(&other));
+    }
+
+    #[test]
+    fn backups_restore_the_phrase_and_how_it_derives_the_key() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let account = wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, stored_phrase(), &storage)
+            .unwrap();
+        let backup = wallet.export_encrypted_backup("backup password").unwrap();
+        assert!(!backup.contains(PHRASE));
+
+        let restored = wallet
+            .import_encrypted_backup(&backup, "backup password", PASSWORD, true, &storage)
+            .unwrap();
+
+        assert_eq!(restored.address, account.address);
+        assert_eq!(wallet.export_mnemonic().unwrap(), PHRASE);
+        let derivation = wallet.phrase_derivation(&storage).unwrap();
+        assert_eq!(derivation.path, MNEMONIC_DERIVATION);
+        assert!(!derivation.passphrase);
+        let verification = wallet.verify_address(Some(PASSWORD), &storage).unwrap();
+        assert_eq!(verification.warning, None);
+    }
+
+    #[test]
+    fn backups_need_their_own_password() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, with_passphrase("extra"), &storage)
+            .unwrap();
+        let backup = wallet.export_encrypted_backup("backup password").unwrap();
+
+        let error = wallet
+            .import_encrypted_backup(&backup, PASSWORD, PASSWORD, true, &storage)
+            .unwrap_err();
+        assert!(error.to_string().contains("Wrong backup password"));
+
+        // A key the phrase only derives with a passphrase keeps its key
+        wallet
+            .import_encrypted_backup(&backup, "backup password", PASSWORD, true, &storage)
+            .unwrap();
+        assert!(wallet.phrase_derivation(&storage).unwrap().passphrase);
+        wallet.lock();
+        assert!(wallet.unlock(PASSWORD, &storage).is_ok());
+    }
+
+    #[test]
+    fn backups_are_refused_while_locked_or_without_a_password() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        assert!(wallet.export_encrypted_backup("backup password").is_err());
+        let key = bs58::encode(Keypair::new().to_bytes()).into_string();
+        wallet
+            .import_from_private_key(&key, PASSWORD, false, &storage)
+            .unwrap();
+
+        assert!(wallet.export_encrypted_backup("").is_err());
+    }
+
+    #[test]
+    fn private_keys_of_the_wrong_length_are_refused() {
+        let keypair = Keypair::new();
+        let bytes = keypair.to_bytes();
+        let full = bs58::encode(bytes).into_string();
+        let seed = bs58::encode(&bytes[..32]).into_string();
+        assert_eq!(
+            keypair_from_private_key(&full).unwrap().pubkey(),
+            keypair.pubkey()
+        );
+        assert_eq!(
+            keypair_from_private_key(&seed).unwrap().pubkey(),
+            keypair.pubkey()
+        );
+
+        let mut mismatched = bytes;
+        mismatched[32..].copy_from_slice(Keypair::new().pubkey().as_ref());
+        let cases = [
+            ("0OIl".to_string(), "not valid base58"),
+            (bs58::encode([7u8; 16]).into_string(), "too short"),
+            (bs58::encode([7u8; 48]).into_string(), "wrong length"),
+            (bs58::encode([7u8; 65]).into_string(), "too long"),
+            (bs58::encode(mismatched).into_string(), "doesn't match"),
+        ];
+        for (key, reason) in cases {
+            let error = keypair_from_private_key(&key).unwrap_err().to_string();
+            assert!(error.contains(reason), "{}: {}", reason, error);
+        }
+    }
+
+    #[test]
+    fn blobs_open_with_the_work_factor_they_were_sealed_with() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let strong = KdfParams {
+            iterations: DEFAULT_KDF_ITERATIONS + 1,
+        };
+        wallet.set_kdf_params(strong, &storage).unwrap();
+
+        let blob = wallet.encrypt(b"secret", PASSWORD).unwrap();
+
+        assert!(blob.starts_with(&format!("{}{}$", ENVELOPE_PREFIX, strong.iterations)));
+        // A wallet still on the default factor reads it from the blob
+        let other = SolanaWallet::with_rpc_url("http://127.0.0.1:8899".to_string());
+        assert_eq!(other.kdf_params().iterations, DEFAULT_KDF_ITERATIONS);
+        assert_eq!(other.decrypt(&blob, PASSWORD).unwrap(), b"secret");
+        assert!(other.decrypt(&blob, "wrong password").is_err());
+        other.load_kdf_params(&storage);
+        assert_eq!(other.kdf_params().iterations, strong.iterations);
+    }
+
+    #[test]
+    fn blobs_without_a_header_use_the_default_work_factor() {
+        let sealed = seal(b"secret", PASSWORD, DEFAULT_KDF_ITERATIONS).unwrap();
+        let blob = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, sealed);
+
+        let envelope = split_encrypted(&blob).unwrap();
+
+        assert_eq!(envelope.iterations, DEFAULT_KDF_ITERATIONS);
+        let key = derive_key(PASSWORD, &envelope.salt, envelope.iterations);
+        assert_eq!(open(&envelope, &key).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn work_factors_out_of_range_are_refused() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        for iterations in [DEFAULT_KDF_ITERATIONS - 1, MAX_KDF_ITERATIONS + 1] {
+            assert!(wallet
+                .set_kdf_params(KdfParams { iterations }, &storage)
+                .is_err());
+        }
+        assert_eq!(wallet.kdf_params().iterations, DEFAULT_KDF_ITERATIONS);
+        assert!(split_encrypted(&format!("{}0$AAAA", ENVELOPE_PREFIX)).is_err());
+    }
+
+    #[test]
+    fn address_fingerprints_never_change() {
+        // Pinned: fingerprints users have noted down must keep matching
+        let cases = [
+            ("11111111111111111111111111111111", "B14F-BB6D"),
+            ("HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk", "648D-58C1"),
+        ];
+        for (address, fingerprint) in cases {
+            assert_eq!(address_fingerprint(address).unwrap(), fingerprint);
+            assert_eq!(
+                address_fingerprint(&format!(" {} ", address)).unwrap(),
+                fingerprint
+            );
+        }
+        assert!(address_fingerprint("not an address").is_err());
+    }
+
+    #[test]
+    fn backups_move_between_installs() {
+        let (source, source_storage, _source_dir) = wallet_and_storage();
+        let key = bs58::encode(Keypair::new().to_bytes()).into_string();
+        let account = source
+            .import_from_private_key(&key, PASSWORD, false, &source_storage)
+            .unwrap();
+        let backup = source.export_encrypted_backup("backup password").unwrap();
+
+        let (target, target_storage, _target_dir) = wallet_and_storage();
+        let restored = target
+            .import_encrypted_backup(
+                &format!("\n{}\n", backup),
+                "backup password",
+                "new password",
+                false,
+                &target_storage,
+            )
+            .unwrap();
+
+        assert_eq!(restored.address, account.address);
+        assert_eq!(target.export_private_key().unwrap(), key);
+        assert!(target.phrase_derivation(&target_storage).is_none());
+        target.lock();
+        assert!(target.unlock(PASSWORD, &target_storage).is_err());
+        let unlocked = target.unlock("new password", &target_storage).unwrap();
+        assert_eq!(unlocked.address, account.address);
+    }
+
+    #[test]
+    fn backups_of_unknown_versions_are_refused() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let key = bs58::encode(Keypair::new().to_bytes()).into_string();
+        wallet
+            .import_from_private_key(&key, PASSWORD, false, &storage)
+            .unwrap();
+        let backup: serde_json::Value =
+            serde_json::from_str(&wallet.export_encrypted_backup("backup password").unwrap())
+                .unwrap();
+
+        let cases = [
+            ("version", serde_json::json!(BACKUP_VERSION + 1), "newer"),
+            ("version", serde_json::json!(0), "Unknown backup version"),
+            ("kdf", serde_json::json!("argon2id"), "Unsupported"),
+        ];
+        for (field, value, reason) in cases {
+            let mut changed = backup.clone();
+            changed[field] = value;
+            let error = wallet
+                .import_encrypted_backup(
+                    &changed.to_string(),
+                    "backup password",
+                    PASSWORD,
+                    true,
+                    &storage,
+                )
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains(reason), "{}: {}", reason, error);
+        }
+        assert!(wallet
+            .import_encrypted_backup("not a backup", "backup password", PASSWORD, true, &storage)
+            .is_err());
+    }
+
+    #[test]
+    fn in_memory_wallets_write_nothing_and_are_gone_once_locked() {
+        let (wallet, storage, dir) = wallet_and_storage();
+
+        let account = wallet.import_ephemeral(PHRASE, None, None).unwrap();
+
+        assert_eq!(
+            account.address,
+            "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk"
+        );
+        assert!(wallet.is_unlocked());
+        assert!(wallet.is_ephemeral());
+        assert!(!wallet.has_wallet(&storage));
+        assert!(wallet.remember_device(PASSWORD, &storage).is_err());
+        assert!(wallet.verify_address(None, &storage).is_err());
+        // Auto-lock would lose the wallet, so it never applies
+        assert!(!wallet.lock_if_unlocked_for(Duration::ZERO));
+        assert!(!dir.path().join("storage.json").exists());
+
+        wallet.lock();
+
+        assert!(!wallet.is_unlocked());
+        assert!(!wallet.is_ephemeral());
+        assert!(wallet.export_mnemonic().is_err());
+        assert!(wallet.unlock(PASSWORD, &storage).is_err());
+    }
+
+    /// An unlocked wallet whose RPC node hands out a fixed blockhash
+    async fn wallet_with_node() -> (SolanaWallet, Storage, tempfile::TempDir) {
+        let blockhash = solana_sdk::hash::Hash::new_from_array([7; 32]);
+        let url = rpc::test_node::serve_rpc(rpc::test_node::latest_blockhash(blockhash)).await;
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("storage.json")).unwrap();
+        let wallet = SolanaWallet::with_rpc_url(url);
+        let key = bs58::encode(Keypair::new().to_bytes()).into_string();
+        wallet
+            .import_from_private_key(&key, PASSWORD, false, &storage)
+            .unwrap();
+        (wallet, storage, dir)
+    }
+
+    /// The instructions in an encoded transfer, with their accounts resolved
+    fn decoded_instructions(transfer: &UnsignedTransfer) -> Vec<Instruction> {
+        let transaction = decode_transaction(&transfer.transaction).unwrap();
+        let keys = transaction.message.static_account_keys();
+        transaction
+            .message
+            .instructions()
+            .iter()
+            .map(|instruction| Instruction {
+                program_id: keys[instruction.program_id_index as usize],
+                accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|&index| {
+                        let index = index as usize;
+                        AccountMeta {
+                            pubkey: keys[index],
+                            is_signer: transaction.message.is_signer(index),
+                            is_writable: transaction.message.is_maybe_writable(index, None),
+                        }
+                    })
+                    .collect(),
+                data: instruction.data.clone(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn unsigned_transfers_decode_to_a_system_transfer() {
+        let (wallet, _storage, _dir) = wallet_with_node().await;
+        let from = wallet.get_pubkey().unwrap();
+        let to = Keypair::new().pubkey();
+
+        let transfer = wallet
+            .build_unsigned_transfer(&to.to_string(), 0.25, None, None, false, &[], None)
+            .await
+            .unwrap();
+
+        let transaction = decode_transaction(&transfer.transaction).unwrap();
+        assert!(matches!(transaction.message, VersionedMessage::Legacy(_)));
+        assert_eq!(
+            transaction.message.recent_blockhash().to_string(),
+            transfer.recent_blockhash
+        );
+        assert_eq!(
+            decoded_instructions(&transfer),
+            vec![system_instruction::transfer(&from, &to, 250_000_000)]
+        );
+        assert!(transfer.missing_signers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn priority_fees_set_the_compute_unit_price_first() {
+        let (wallet, _storage, _dir) = wallet_with_node().await;
+        let to = Keypair::new().pubkey().to_string();
+
+        let transfer = wallet
+            .build_unsigned_transfer(&to, 0.25, None, Some(5_000), false, &[], None)
+            .await
+            .unwrap();
+
+        let instructions = decoded_instructions(&transfer);
+        assert_eq!(transfer.priority_fee, Some(5_000));
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(
+            instructions[0],
+            ComputeBudgetInstruction::set_compute_unit_price(5_000)
+        );
+        assert_eq!(instructions[1].program_id, system_program::id());
+    }
+
+    #[tokio::test]
+    async fn versioned_transfers_compile_to_a_v0_message() {
+        let (wallet, _storage, _dir) = wallet_with_node().await;
+        let from = wallet.get_pubkey().unwrap();
+        let to = Keypair::new().pubkey();
+
+        let transfer = wallet
+            .build_unsigned_transfer(&to.to_string(), 0.25, None, None, true, &[], None)
+            .await
+            .unwrap();
+
+        let transaction = decode_transaction(&transfer.transaction).unwrap();
+        let VersionedMessage::V0(message) = &transaction.message else {
+            panic!("expected a v0 message");
+        };
+        assert!(transfer.versioned);
+        assert!(message.address_table_lookups.is_empty());
+        assert_eq!(
+            decoded_instructions(&transfer),
+            vec![system_instruction::transfer(&from, &to, 250_000_000)]
+        );
+    }
+
+    #[tokio::test]
+    async fn memos_go_ahead_of_the_transfer() {
+        let (wallet, _storage, _dir) = wallet_with_node().await;
+        let from = wallet.get_pubkey().unwrap();
+        let to = Keypair::new().pubkey().to_string();
+
+        let transfer = wallet
+            .build_unsigned_transfer(&to, 0.25, None, None, false, &[], Some("invoice 42"))
+            .await
+            .unwrap();
+
+        let instructions = decoded_instructions(&transfer);
+        assert_eq!(instructions.len(), 2);
+        // The signer is writable in the message as it also pays for the transfer
+        assert_eq!(instructions[0].program_id.to_string(), MEMO_PROGRAM_ID);
+        assert_eq!(instructions[0].accounts.len(), 1);
+        assert_eq!(instructions[0].accounts[0].pubkey, from);
+        assert!(instructions[0].accounts[0].is_signer);
+        assert_eq!(instructions[0].data, b"invoice 42");
+        assert_eq!(instructions[1].program_id, system_program::id());
+    }
+
+    #[test]
+    fn a_factory_reset_leaves_no_wallet_behind() {
+        let (wallet, storage, dir) = wallet_and_storage();
+        let key = bs58::encode(Keypair::new().to_bytes()).into_string();
+        wallet
+            .import_from_private_key(&key, PASSWORD, false, &storage)
+            .unwrap();
+        wallet.set_priority_fee(Some(5_000), &storage).unwrap();
+
+        // What `factory_reset` does with the wallet and its storage
+        wallet.reset();
+        storage.clear().unwrap();
+
+        assert!(!wallet.is_unlocked());
+        assert!(!wallet.has_wallet(&storage));
+        assert_eq!(wallet.priority_fee(), None);
+        assert!(wallet.unlock(PASSWORD, &storage).is_err());
+        let file = std::fs::read_to_string(dir.path().join("storage.json")).unwrap();
+        assert_eq!(file, "{}");
+        let reopened = Storage::open(dir.path().join("storage.json")).unwrap();
+        assert!(!wallet.has_wallet(&reopened));
+    }
+
+    #[test]
+    fn debug_info_holds_no_secrets() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, stored_phrase(), &storage)
+            .unwrap();
+        let credential = storage.seal("api_credentials_key", b"api-token").unwrap();
+        let local_key = hex::encode(storage.local_key("api_credentials_key").unwrap());
+        let secret = wallet.keypair.lock().unwrap().as_ref().unwrap().to_bytes();
+
+        let info = serde_json::to_string(&wallet.debug_info(&storage).unwrap()).unwrap();
+
+        let stored = |key: &str| storage.get(key).unwrap().unwrap().to_string();
+        for leaked in [
+            bs58::encode(secret).into_string(),
+            hex::encode(secret),
+            hex::encode(&secret[..32]),
+            stored(&wallet.storage_key),
+            stored(&wallet.mnemonic_storage_key),
+            credential,
+            local_key,
+            PASSWORD.to_string(),
+            "api-token".to_string(),
+        ] {
+            assert!(!info.contains(leaked.trim_matches('"')), "{}", info);
+        }
+        for word in ["abandon", "about"] {
+            assert!(!info.contains(word), "{}", info);
+        }
+    }
+
+    #[test]
+    fn signed_messages_verify_against_their_signer() {
+        let keypair = Keypair::new();
+        let signature = keypair.sign_message(b"sign in to example.com").to_string();
+
+        let verified = verify_message(
+            &keypair.pubkey().to_string(),
+            "sign in to example.com",
+            &signature,
+        )
+        .unwrap();
+
+        assert!(verified);
+    }
+
+    #[test]
+    fn tampered_messages_do_not_verify() {
+        let keypair = Keypair::new();
+        let signature = keypair.sign_message(b"pay 1 SOL").to_string();
+
+        let verified =
+            verify_message(&keypair.pubkey().to_string(), "pay 9 SOL", &signature).unwrap();
+
+        assert!(!verified);
+    }
+
+    #[test]
+    fn signatures_from_another_key_do_not_verify() {
+        let signer = Keypair::new();
+        let signature = signer.sign_message(b"hello").to_string();
+        let other = Keypair::new().pubkey().to_string();
+
+        assert!(!verify_message(&other, "hello", &signature).unwrap());
+        assert!(verify_message(&other, "hello", "not-a-signature").is_err());
+    }
+
+    #[test]
+    fn preview_addresses_come_from_base58_keys() {
+        let keypair = Keypair::new();
+        let address = keypair.pubkey().to_string();
+        let full = bs58::encode(keypair.to_bytes()).into_string();
+        let seed = bs58::encode(keypair.secret().as_bytes()).into_string();
+
+        assert_eq!(pubkey_from_private_key(&full).unwrap(), address);
+        assert_eq!(
+            pubkey_from_private_key(&format!(" {}\n", seed)).unwrap(),
+            address
+        );
+    }
+
+    #[test]
+    fn preview_addresses_come_from_keypair_json_arrays() {
+        let keypair = Keypair::new();
+        let json = serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap();
+
+        assert_eq!(
+            pubkey_from_private_key(&json).unwrap(),
+            keypair.pubkey().to_string()
+        );
+    }
+
+    #[test]
+    fn preview_rejects_keys_that_cannot_be_parsed() {
+        let invalid = pubkey_from_private_key("0OIl").unwrap_err().to_string();
+        assert!(invalid.contains("not valid base58"), "{}", invalid);
+
+        let short = pubkey_from_private_key(&bs58::encode([1u8; 16]).into_string())
+            .unwrap_err()
+            .to_string();
+        assert!(short.contains("too short"), "{}", short);
+
+        let array = pubkey_from_private_key("[1, 2, 3]")
+            .unwrap_err()
+            .to_string();
+        assert!(array.contains("3 bytes, expected 64"), "{}", array);
+        assert!(pubkey_from_private_key("[1, 2,").is_err());
+    }
+
+    #[test]
+    fn valid_addresses_report_whether_they_are_on_the_curve() {
+        let wallet_address = Keypair::new().pubkey().to_string();
+        let (pda, _) = Pubkey::find_program_address(&[b"vault"], &system_program::id());
+
+        let account = validate_address(&format!(" {} ", wallet_address));
+        assert!(account.valid);
+        assert_eq!(account.on_curve, Some(true));
+        assert_eq!(account.reason, None);
+
+        let program_address = validate_address(&pda.to_string());
+        assert!(program_address.valid);
+        assert_eq!(program_address.on_curve, Some(false));
+    }
+
+    #[test]
+    fn invalid_base58_addresses_name_the_bad_character() {
+        let validation = validate_address("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFi0");
+
+        assert!(!validation.valid);
+        assert_eq!(validation.on_curve, None);
+        assert_eq!(
+            validation.reason.as_deref(),
+            Some("Not valid base58: '0' at position 44")
+        );
+    }
+
+    #[test]
+    fn addresses_of_the_wrong_length_say_how_long_they_are() {
+        let short = bs58::encode([1u8; 31]).into_string();
+
+        let validation = validate_address(&short);
+
+        assert!(!validation.valid);
+        assert_eq!(
+            validation.reason.as_deref(),
+            Some("Wrong length: 31 bytes, expected 32")
+        );
+        assert_eq!(
+            validate_address("").reason.as_deref(),
+            Some("Address is empty")
+        );
+    }
+
+    #[test]
+    fn solana_cli_keypair_files_import_with_their_address() {
+        let (wallet, storage, dir) = wallet_and_storage();
+        let keypair = Keypair::new();
+        let json = serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap();
+        let path = dir.path().join("id.json");
+        std::fs::write(&path, json).unwrap();
+
+        let account = wallet
+            .import_from_keypair_file(&path, PASSWORD, false, &storage)
+            .unwrap();
+
+        assert_eq!(account.address, keypair.pubkey().to_string());
+        wallet.lock();
+        let unlocked = wallet.unlock(PASSWORD, &storage).unwrap();
+        assert_eq!(unlocked.address, keypair.pubkey().to_string());
+    }
+
+    #[test]
+    fn malformed_keypair_files_are_refused() {
+        let (wallet, storage, dir) = wallet_and_storage();
+        let mut mismatched = Keypair::new().to_bytes();
+        mismatched[32..].copy_from_slice(&Keypair::new().pubkey().to_bytes());
+        let cases = [
+            ("not json", "expected a JSON array of bytes"),
+            ("[1, 2, 3]", "3 bytes, expected 64"),
+            (
+                &serde_json::to_string(&mismatched.to_vec()).unwrap(),
+                "public half doesn't match",
+            ),
+        ];
+
+        for (contents, expected) in cases {
+            let path = dir.path().join("id.json");
+            std::fs::write(&path, contents).unwrap();
+            let error = wallet
+                .import_from_keypair_file(&path, PASSWORD, false, &storage)
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains(expected), "{}", error);
+        }
+        assert!(!wallet.has_wallet(&storage));
+        let missing = dir.path().join("missing.json");
+        assert!(wallet
+            .import_from_keypair_file(&missing, PASSWORD, false, &storage)
+            .is_err());
+    }
+
+    #[test]
+    fn japanese_phrases_parse_with_or_without_their_language() {
+        // Same entropy as PHRASE, in the Japanese wordlist
+        let phrase = bip39::Mnemonic::from_entropy_in(bip39::Language::Japanese, &[0; 16])
+            .unwrap()
+            .to_string();
+        let english = parse_mnemonic(PHRASE, None).unwrap();
+
+        for language in [Some("japanese"), Some(" Japanese "), None] {
+            let mnemonic = parse_mnemonic(&phrase, language).unwrap();
+            assert_eq!(mnemonic.language(), bip39::Language::Japanese);
+            assert_eq!(mnemonic.to_entropy(), english.to_entropy());
+        }
+        assert!(parse_mnemonic(&phrase, Some("english")).is_err());
+        assert!(parse_mnemonic(&phrase, Some("klingon")).is_err());
+    }
 
-        // Derive key
-        let mut key = [0u8; 32];
-        pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, 100_000, &mut key);
+    // Uses the real OS keyring, which headless machines often lack:
+    // cargo test --features keyring-tests
+    #[cfg(feature = "keyring-tests")]
+    #[test]
+    fn remembered_devices_unlock_after_a_restart() {
+        let (wallet, storage, _dir) = wallet_and_storage();
+        let account = wallet
+            .import_from_mnemonic(PHRASE, PASSWORD, stored_phrase(), &storage)
+            .unwrap();
+        wallet.remember_device(PASSWORD, &storage).unwrap();
 
-        // Decrypt
-        let cipher = Aes256Gcm::new_from_slice(&key)
-            .context("Failed to create cipher")?;
-        let plaintext = cipher
-            .decrypt(nonce, ciphertext)
-            .map_err(|_| anyhow!("Decryption failed (wrong password?)"))?;
+        let restarted = SolanaWallet::with_rpc_url("http://127.0.0.1:8899".to_string());
+        let unlocked = restarted.unlock_from_keyring(&storage).unwrap().unwrap();
 
-        Ok(plaintext)
+        assert_eq!(unlocked.address, account.address);
+        assert_eq!(restarted.export_mnemonic().unwrap(), PHRASE);
+        restarted.forget_device(&storage).unwrap();
+        restarted.lock();
+        assert!(!restarted.is_device_remembered(&storage));
+        assert!(restarted.unlock_from_keyring(&storage).unwrap().is_none());
+        assert!(keychain::load_device_keys(&account.address)
+            .unwrap()
+            .is_none());
     }
 }
@@ -0,0 +1,371 @@
+// RPC endpoint pool
+// Ordered Solana RPC endpoints with failover on network-level errors
+
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use serde::Serialize;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::RpcError;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+// JSON-RPC error a node returns while it is behind or unhealthy
+const NODE_UNHEALTHY_CODE: i64 = -32005;
+/// How long one RPC request may take before the next endpoint is tried
+const RPC_REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Configured endpoints and the one currently in use
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcEndpointsInfo {
+    pub endpoints: Vec<String>,
+    pub current: String,
+}
+
+/// What probing an RPC endpoint found
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcHealth {
+    pub url: String,
+    /// solana-core version the node reports
+    pub version: String,
+    pub healthy: bool,
+    /// Why `getHealth` failed, e.g. the node is behind
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_error: Option<String>,
+    /// Round trip of the `getVersion` call
+    pub latency_ms: u64,
+}
+
+struct Endpoint {
+    url: String,
+    client: Arc<RpcClient>,
+}
+
+pub struct RpcPool {
+    endpoints: RwLock<Arc<Vec<Endpoint>>>,
+    /// Index of the endpoint that last answered; calls start there
+    current: AtomicUsize,
+}
+
+impl RpcPool {
+    pub fn new(urls: &[&str]) -> Self {
+        Self {
+            endpoints: RwLock::new(Arc::new(build_endpoints(urls.iter().map(|u| u.to_string())))),
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    /// Replace the endpoint list; the first one becomes current
+    pub fn set_endpoints(&self, urls: Vec<String>) -> Result<()> {
+        let urls = validate_endpoints(urls)?;
+        *self.endpoints.write().unwrap() = Arc::new(build_endpoints(urls.into_iter()));
+        self.current.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn info(&self) -> RpcEndpointsInfo {
+        let endpoints = self.endpoints.read().unwrap().clone();
+        let current = self.current.load(Ordering::Relaxed) % endpoints.len();
+        RpcEndpointsInfo {
+            endpoints: endpoints.iter().map(|e| e.url.clone()).collect(),
+            current: endpoints[current].url.clone(),
+        }
+    }
+
+    /// Run an RPC call, moving on to the next endpoint when one is unreachable,
+    /// rate limited or failing. Errors about the request itself are returned as is.
+    pub fn call<T>(&self, op: impl Fn(&RpcClient) -> ClientResult<T>) -> ClientResult<T> {
+        let endpoints = self.endpoints.read().unwrap().clone();
+        let start = self.current.load(Ordering::Relaxed) % endpoints.len();
+        let mut last_error = None;
+
+        for offset in 0..endpoints.len() {
+            let index = (start + offset) % endpoints.len();
+            let endpoint = &endpoints[index];
+
+            match op(&endpoint.client) {
+                Ok(value) => {
+                    if offset > 0 {
+                        println!("Switched Solana RPC to {}", endpoint.url);
+                        self.current.store(index, Ordering::Relaxed);
+                    }
+                    return Ok(value);
+                }
+                Err(e) if is_failover_error(&e) => {
+                    println!("Solana RPC {} failed: {}", endpoint.url, e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.expect("endpoint list is never empty"))
+    }
+
+    /// `call` on the blocking thread pool, so a slow node doesn't stall the
+    /// async runtime
+    pub async fn call_async<T, F>(self: &Arc<Self>, op: F) -> ClientResult<T>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> ClientResult<T> + Send + 'static,
+    {
+        let pool = Arc::clone(self);
+        tokio::task::spawn_blocking(move || pool.call(op))
+            .await
+            .unwrap_or_else(|e| {
+                Err(ClientErrorKind::Custom(format!("RPC task failed: {}", e)).into())
+            })
+    }
+}
+
+/// Check an endpoint list: at least one entry, each an http(s) URL, no duplicates
+pub fn validate_endpoints(urls: Vec<String>) -> Result<Vec<String>> {
+    let mut valid: Vec<String> = Vec::new();
+    for url in urls {
+        let url = url.trim().to_string();
+        let parsed = Url::parse(&url).map_err(|_| anyhow!("Invalid RPC URL: {}", url))?;
+        if parsed.scheme() != "http" && parsed.scheme() != "https" {
+            return Err(anyhow!("RPC URL must use http or https: {}", url));
+        }
+        if !valid.contains(&url) {
+            valid.push(url);
+        }
+    }
+
+    if valid.is_empty() {
+        return Err(anyhow!("At least one RPC endpoint is required"));
+    }
+    Ok(valid)
+}
+
+/// Call `getVersion` and `getHealth` on `url` with a client of its own, so
+/// the pool is left alone. Fails only when the node can't be reached.
+pub fn check_endpoint(url: &str) -> Result<RpcHealth> {
+    let url = validate_endpoints(vec![url.to_string()])?.remove(0);
+    let client = RpcClient::new_with_timeout_and_commitment(
+        url.clone(),
+        RPC_REQUEST_TIMEOUT,
+        CommitmentConfig::confirmed(),
+    );
+
+    let started = Instant::now();
+    let version = client
+        .get_version()
+        .map_err(|e| anyhow!("RPC {} did not answer getVersion: {}", url, e))?;
+    let latency = started.elapsed();
+    let health_error = client.get_health().err().map(|e| e.to_string());
+
+    Ok(RpcHealth {
+        url,
+        version: version.solana_core,
+        healthy: health_error.is_none(),
+        health_error,
+        latency_ms: latency.as_millis() as u64,
+    })
+}
+
+fn build_endpoints(urls: impl Iterator<Item = String>) -> Vec<Endpoint> {
+    urls.map(|url| Endpoint {
+        client: Arc::new(RpcClient::new_with_timeout_and_commitment(
+            url.clone(),
+            RPC_REQUEST_TIMEOUT,
+            CommitmentConfig::confirmed(),
+        )),
+        url,
+    })
+    .collect()
+}
+
+/// Whether another endpoint might succeed: connection failures, timeouts,
+/// 429/5xx responses and unhealthy nodes, but not errors about the request
+fn is_failover_error(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.status()
+                    .is_some_and(|status| status.as_u16() == 429 || status.is_server_error())
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == NODE_UNHEALTHY_CODE
+        }
+        _ => false,
+    }
+}
+
+/// Local JSON-RPC node for tests
+#[cfg(test)]
+pub(crate) mod test_node {
+    use serde_json::{json, Value};
+    use solana_sdk::hash::Hash;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// `getLatestBlockhash` result handing out `blockhash`
+    pub fn latest_blockhash(blockhash: Hash) -> Value {
+        json!({
+            "context": { "slot": 1 },
+            "value": {
+                "blockhash": blockhash.to_string(),
+                "lastValidBlockHeight": 100,
+            },
+        })
+    }
+
+    /// Serve a node answering every request with `result`, returning its URL
+    pub async fn serve_rpc(result: Value) -> String {
+        serve(move |_| Some(result.clone())).await
+    }
+
+    /// Serve a node answering each method in `results`; other methods get a
+    /// "method not found" error
+    pub async fn serve_rpc_methods(results: Vec<(&'static str, Value)>) -> String {
+        serve(move |method| {
+            results
+                .iter()
+                .find(|(name, _)| *name == method)
+                .map(|(_, result)| result.clone())
+        })
+        .await
+    }
+
+    async fn serve(answer: impl Fn(&str) -> Option<Value> + Send + Sync + 'static) -> String {
+        let answer = Arc::new(answer);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let answer = answer.clone();
+                tokio::spawn(async move {
+                    // Answering before the body is read would cut the client off
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    while !request_complete(&request) {
+                        match socket.read(&mut buf).await {
+                            Ok(read) if read > 0 => request.extend_from_slice(&buf[..read]),
+                            _ => return,
+                        }
+                    }
+                    let body = match answer(&request_method(&request)) {
+                        Some(result) => json!({ "jsonrpc": "2.0", "id": 1, "result": result }),
+                        None => json!({
+                            "jsonrpc": "2.0",
+                            "id": 1,
+                            "error": { "code": -32601, "message": "Method not found" },
+                        }),
+                    }
+                    .to_string();
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(body.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    /// JSON-RPC method named in the body of `request`
+    fn request_method(request: &[u8]) -> String {
+        let text = String::from_utf8_lossy(request);
+        let body = text.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+        serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|body| body.get("method")?.as_str().map(String::from))
+            .unwrap_or_default()
+    }
+
+    /// Whether `request` holds its headers and the body they announce
+    fn request_complete(request: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(request);
+        let Some((head, body)) = text.split_once("\r\n\r\n") else {
+            return false;
+        };
+        let length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse().ok())
+            .unwrap_or(0);
+        body.len() >= length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use solana_sdk::hash::Hash;
+
+    /// URL of a port nothing is listening on
+    fn unreachable_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    #[tokio::test]
+    async fn calls_fail_over_to_the_next_endpoint() {
+        let blockhash = Hash::new_from_array([7; 32]);
+        let down = unreachable_url();
+        let up = test_node::serve_rpc(test_node::latest_blockhash(blockhash)).await;
+        let pool = Arc::new(RpcPool::new(&[&down, &up]));
+
+        let answered = pool
+            .call_async(|rpc| rpc.get_latest_blockhash())
+            .await
+            .unwrap();
+
+        assert_eq!(answered, blockhash);
+        // Later calls start at the endpoint that answered
+        assert_eq!(pool.info().current, up);
+    }
+
+    /// `getVersion` result of a 2.2.7 node
+    fn version() -> Value {
+        json!({ "solana-core": "2.2.7", "feature-set": 1 })
+    }
+
+    #[tokio::test]
+    async fn checks_report_a_healthy_node_and_its_version() {
+        let url = test_node::serve_rpc_methods(vec![
+            ("getVersion", version()),
+            ("getHealth", json!("ok")),
+        ])
+        .await;
+
+        let health = tokio::task::spawn_blocking(move || check_endpoint(&url))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(health.version, "2.2.7");
+        assert!(health.healthy);
+        assert_eq!(health.health_error, None);
+    }
+
+    #[tokio::test]
+    async fn checks_report_why_a_node_is_unhealthy() {
+        let url = test_node::serve_rpc_methods(vec![("getVersion", version())]).await;
+
+        let health = tokio::task::spawn_blocking(move || check_endpoint(&url))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(!health.healthy);
+        assert!(health.health_error.unwrap().contains("Method not found"));
+    }
+
+    #[test]
+    fn checks_fail_when_the_node_is_unreachable() {
+        let error = check_endpoint(&unreachable_url()).unwrap_err().to_string();
+
+        assert!(error.contains("did not answer getVersion"), "{}", error);
+    }
+}
@@ -0,0 +1,492 @@
+// Solana Pay transaction requests
+// The merchant's server builds the transaction; the wallet only signs it
+// after checking every instruction is one a payment needs
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    compute_budget,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_program,
+    transaction::VersionedTransaction,
+};
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::{
+    decode_transaction, encode_transaction, validate_priority_fee, ASSOCIATED_TOKEN_PROGRAM_ID,
+    MEMO_PROGRAM_ID, SPL_TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID,
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+// Instruction discriminators of the few instructions a payment is allowed
+const SYSTEM_TRANSFER: u32 = 2;
+const TOKEN_TRANSFER: u8 = 3;
+const TOKEN_TRANSFER_CHECKED: u8 = 12;
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+// The most compute units a transaction can use
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// A transaction request fetched from the merchant, checked but not signed
+#[derive(Debug, Clone, Serialize)]
+pub struct SolanaPayRequest {
+    /// The https link the transaction came from
+    pub link: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// Shown with the transaction, e.g. "Thanks for your order"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Base64 transaction to hand back once the user approves
+    pub transaction: String,
+    pub fee_payer: String,
+    pub instructions: Vec<PayInstruction>,
+}
+
+/// What one instruction of a payment does, for the approval prompt
+#[derive(Debug, Clone, Serialize)]
+pub struct PayInstruction {
+    /// `system`, `spl-token`, `token-2022`, `associated-token`,
+    /// `compute-budget` or `memo`
+    pub program: String,
+    /// e.g. `transfer`, `transferChecked`, `createAccount`
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Lamports for SOL transfers, base units for token transfers,
+    /// micro-lamports for a compute unit price, units for a compute limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decimals: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+impl PayInstruction {
+    fn new(program: &str, action: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            action: action.to_string(),
+            destination: None,
+            amount: None,
+            decimals: None,
+            memo: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LinkMetadata {
+    label: Option<String>,
+    icon: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TransactionResponse {
+    transaction: String,
+    message: Option<String>,
+}
+
+/// The https link inside a `solana:` transaction-request URL. Links with a
+/// query string arrive percent-encoded.
+pub fn parse_transaction_request(url: &str) -> Result<Url> {
+    let rest = url
+        .trim()
+        .strip_prefix("solana:")
+        .ok_or_else(|| anyhow!("Not a Solana Pay URL"))?;
+    let link = if rest.contains("://") {
+        rest.to_string()
+    } else {
+        percent_decode(rest)?
+    };
+
+    if !link.contains("://") {
+        let recipient = link.split('?').next().unwrap_or_default();
+        if Pubkey::from_str(recipient).is_ok() {
+            return Err(anyhow!(
+                "This is a Solana Pay transfer request; only transaction requests are supported"
+            ));
+        }
+    }
+    let link = Url::parse(&link).context("Invalid Solana Pay link")?;
+    if link.scheme() != "https" {
+        return Err(anyhow!("Solana Pay links must use https"));
+    }
+    Ok(link)
+}
+
+/// Fetch the merchant's label and the transaction it builds for `account`,
+/// then check the transaction. The label is optional, so failing to get it
+/// is only logged.
+pub async fn fetch_request(link: &Url, account: &Pubkey) -> Result<SolanaPayRequest> {
+    let client = crate::config::client_builder()?
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+
+    let metadata = match fetch_metadata(&client, link).await {
+        Ok(metadata) => Some(metadata),
+        Err(e) => {
+            println!("Failed to fetch Solana Pay label from {}: {}", link, e);
+            None
+        }
+    };
+
+    let response = client
+        .post(link.clone())
+        .header("Accept", "application/json")
+        .json(&serde_json::json!({ "account": account.to_string() }))
+        .send()
+        .await
+        .context("Failed to reach the merchant")?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Merchant returned HTTP {}",
+            response.status().as_u16()
+        ));
+    }
+    let response: TransactionResponse = response
+        .json()
+        .await
+        .context("Invalid transaction response from the merchant")?;
+
+    let transaction = decode_transaction(&response.transaction)?;
+    let instructions = check_transaction(&transaction, account)?;
+    let fee_payer = transaction
+        .message
+        .static_account_keys()
+        .first()
+        .map(|key| key.to_string())
+        .unwrap_or_default();
+    let (label, icon) = metadata
+        .map(|metadata| (metadata.label, metadata.icon))
+        .unwrap_or_default();
+
+    Ok(SolanaPayRequest {
+        link: link.to_string(),
+        label,
+        icon,
+        message: response.message,
+        transaction: encode_transaction(&transaction)?,
+        fee_payer,
+        instructions,
+    })
+}
+
+async fn fetch_metadata(client: &reqwest::Client, link: &Url) -> Result<LinkMetadata> {
+    Ok(client
+        .get(link.clone())
+        .header("Accept", "application/json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+/// Make sure `wallet` is a required signer and every instruction is a
+/// transfer, token account creation, compute unit limit or price, or memo.
+/// Anything else could hand the merchant control of the wallet's accounts, so
+/// it's refused, as are destinations from lookup tables, which can't be shown.
+pub fn check_transaction(
+    transaction: &VersionedTransaction,
+    wallet: &Pubkey,
+) -> Result<Vec<PayInstruction>> {
+    let message = &transaction.message;
+    let keys = message.static_account_keys();
+    let required = message.header().num_required_signatures as usize;
+    if !keys.iter().take(required).any(|key| key == wallet) {
+        return Err(anyhow!("Wallet is not a signer of this transaction"));
+    }
+
+    let system = system_program::id();
+    let compute_budget = compute_budget::id();
+    let spl_token = Pubkey::from_str(SPL_TOKEN_PROGRAM_ID)?;
+    let token_2022 = Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?;
+    let associated_token = Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID)?;
+    let memo = Pubkey::from_str(MEMO_PROGRAM_ID)?;
+
+    message
+        .instructions()
+        .iter()
+        .map(|instruction| {
+            let program = keys
+                .get(instruction.program_id_index as usize)
+                .ok_or_else(|| anyhow!("Instruction refers to a missing program"))?;
+            // Accounts past the static keys come from lookup tables
+            let account = |position: usize| {
+                let index = *instruction
+                    .accounts
+                    .get(position)
+                    .ok_or_else(|| anyhow!("Instruction is missing an account"))?;
+                let key = keys.get(index as usize).ok_or_else(|| {
+                    anyhow!("Transaction uses a lookup table account, which can't be checked")
+                })?;
+                Ok::<_, anyhow::Error>(key.to_string())
+            };
+            let data = &instruction.data;
+            let unexpected =
+                || anyhow!("Transaction contains an unexpected {} instruction", program);
+
+            if *program == system {
+                let tag = data.get(..4).and_then(|tag| tag.try_into().ok());
+                if tag.map(u32::from_le_bytes) != Some(SYSTEM_TRANSFER) {
+                    return Err(unexpected());
+                }
+                Ok(PayInstruction {
+                    destination: Some(account(1)?),
+                    amount: read_u64(data, 4),
+                    ..PayInstruction::new("system", "transfer")
+                })
+            } else if *program == spl_token || *program == token_2022 {
+                let name = if *program == spl_token {
+                    "spl-token"
+                } else {
+                    "token-2022"
+                };
+                let amount = read_u64(data, 1);
+                match data.first() {
+                    Some(&TOKEN_TRANSFER) => Ok(PayInstruction {
+                        destination: Some(account(1)?),
+                        amount,
+                        ..PayInstruction::new(name, "transfer")
+                    }),
+                    Some(&TOKEN_TRANSFER_CHECKED) => Ok(PayInstruction {
+                        destination: Some(account(2)?),
+                        amount,
+                        decimals: data.get(9).copied(),
+                        ..PayInstruction::new(name, "transferChecked")
+                    }),
+                    _ => Err(unexpected()),
+                }
+            } else if *program == associated_token {
+                // Create (empty or 0) and create-idempotent (1)
+                if !matches!(data.first(), None | Some(0) | Some(1)) {
+                    return Err(unexpected());
+                }
+                Ok(PayInstruction {
+                    destination: Some(account(1)?),
+                    ..PayInstruction::new("associated-token", "createAccount")
+                })
+            } else if *program == compute_budget {
+                match data.first() {
+                    Some(&SET_COMPUTE_UNIT_LIMIT) if data.len() == 5 => {
+                        let limit = u32::from_le_bytes(data[1..5].try_into()?);
+                        if limit > MAX_COMPUTE_UNIT_LIMIT {
+                            return Err(anyhow!(
+                                "Compute unit limit must be at most {}",
+                                MAX_COMPUTE_UNIT_LIMIT
+                            ));
+                        }
+                        Ok(PayInstruction {
+                            amount: Some(limit.into()),
+                            ..PayInstruction::new("compute-budget", "setComputeUnitLimit")
+                        })
+                    }
+                    Some(&SET_COMPUTE_UNIT_PRICE) if data.len() == 9 => {
+                        let price = read_u64(data, 1).ok_or_else(unexpected)?;
+                        validate_priority_fee(price)?;
+                        Ok(PayInstruction {
+                            amount: Some(price),
+                            ..PayInstruction::new("compute-budget", "setComputeUnitPrice")
+                        })
+                    }
+                    _ => Err(unexpected()),
+                }
+            } else if *program == memo {
+                Ok(PayInstruction {
+                    memo: Some(String::from_utf8_lossy(data).into_owned()),
+                    ..PayInstruction::new("memo", "memo")
+                })
+            } else {
+                Err(anyhow!(
+                    "Transaction calls an unexpected program: {}",
+                    program
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Add the wallet's signature, keeping any the merchant already added
+pub fn add_signature(transaction: &mut VersionedTransaction, keypair: &Keypair) -> Result<()> {
+    let required = transaction.message.header().num_required_signatures as usize;
+    let position = transaction
+        .message
+        .static_account_keys()
+        .iter()
+        .take(required)
+        .position(|key| *key == keypair.pubkey())
+        .ok_or_else(|| anyhow!("Wallet is not a signer of this transaction"))?;
+
+    transaction
+        .signatures
+        .resize(required, Signature::default());
+    transaction.signatures[position] = keypair.sign_message(&transaction.message.serialize());
+    Ok(())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn percent_decode(text: &str) -> Result<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid percent-encoding in Solana Pay URL"))?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).context("Invalid percent-encoding in Solana Pay URL")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::MAX_PRIORITY_FEE_MICROLAMPORTS;
+    use solana_sdk::{
+        compute_budget::ComputeBudgetInstruction,
+        hash::Hash,
+        instruction::{CompiledInstruction, Instruction},
+        message::{v0, Message, MessageHeader, VersionedMessage},
+        system_instruction,
+        transaction::Transaction,
+    };
+
+    fn transaction(payer: &Pubkey, instructions: &[Instruction]) -> VersionedTransaction {
+        let message = Message::new(instructions, Some(payer));
+        Transaction::new_unsigned(message).into()
+    }
+
+    #[test]
+    fn parses_transaction_request_links() {
+        let link = parse_transaction_request("solana:https://pay.example.com/order").unwrap();
+        assert_eq!(link.as_str(), "https://pay.example.com/order");
+
+        let link =
+            parse_transaction_request("solana:https%3A%2F%2Fpay.example.com%2Forder%3Fid%3D42")
+                .unwrap();
+        assert_eq!(link.as_str(), "https://pay.example.com/order?id=42");
+
+        let recipient = Pubkey::new_unique();
+        let transfer = format!("solana:{}?amount=1", recipient);
+        assert!(parse_transaction_request(&transfer).is_err());
+        assert!(parse_transaction_request("solana:http://pay.example.com/order").is_err());
+        assert!(parse_transaction_request("https://pay.example.com/order").is_err());
+    }
+
+    #[test]
+    fn describes_a_payment() {
+        let wallet = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let memo = Pubkey::from_str(MEMO_PROGRAM_ID).unwrap();
+        let tx = transaction(
+            &wallet,
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+                ComputeBudgetInstruction::set_compute_unit_price(1_000),
+                system_instruction::transfer(&wallet, &merchant, 5_000),
+                Instruction::new_with_bytes(memo, b"order 42", vec![]),
+            ],
+        );
+
+        let instructions = check_transaction(&tx, &wallet).unwrap();
+        let actions: Vec<_> = instructions
+            .iter()
+            .map(|ix| (ix.program.as_str(), ix.action.as_str(), ix.amount))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                ("compute-budget", "setComputeUnitLimit", Some(200_000)),
+                ("compute-budget", "setComputeUnitPrice", Some(1_000)),
+                ("system", "transfer", Some(5_000)),
+                ("memo", "memo", None),
+            ]
+        );
+        assert_eq!(instructions[2].destination, Some(merchant.to_string()));
+        assert_eq!(instructions[3].memo.as_deref(), Some("order 42"));
+    }
+
+    #[test]
+    fn refuses_other_instructions() {
+        let wallet = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let refused = [
+            system_instruction::assign(&wallet, &merchant),
+            ComputeBudgetInstruction::request_heap_frame(64 * 1024),
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT + 1),
+            ComputeBudgetInstruction::set_compute_unit_price(MAX_PRIORITY_FEE_MICROLAMPORTS + 1),
+            Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]),
+        ];
+        for instruction in refused {
+            let tx = transaction(&wallet, std::slice::from_ref(&instruction));
+            assert!(
+                check_transaction(&tx, &wallet).is_err(),
+                "{:?}",
+                instruction
+            );
+        }
+
+        // Checked even when the merchant pays the fee
+        let tx = transaction(
+            &merchant,
+            &[
+                ComputeBudgetInstruction::set_compute_unit_price(u64::MAX),
+                system_instruction::transfer(&wallet, &merchant, 5_000),
+            ],
+        );
+        assert!(check_transaction(&tx, &wallet).is_err());
+
+        let tx = transaction(
+            &merchant,
+            &[system_instruction::transfer(&merchant, &wallet, 5_000)],
+        );
+        assert!(check_transaction(&tx, &wallet).is_err());
+    }
+
+    #[test]
+    fn refuses_destinations_from_lookup_tables() {
+        let wallet = Pubkey::new_unique();
+        let mut data = SYSTEM_TRANSFER.to_le_bytes().to_vec();
+        data.extend_from_slice(&5_000u64.to_le_bytes());
+        let message = v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![wallet, system_program::id()],
+            recent_blockhash: Hash::default(),
+            // Account 2 is the first one loaded from a lookup table
+            instructions: vec![CompiledInstruction::new_from_raw_parts(1, data, vec![0, 2])],
+            address_table_lookups: vec![v0::MessageAddressTableLookup {
+                account_key: Pubkey::new_unique(),
+                writable_indexes: vec![0],
+                readonly_indexes: vec![],
+            }],
+        };
+        let tx = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message: VersionedMessage::V0(message),
+        };
+
+        assert!(check_transaction(&tx, &wallet).is_err());
+    }
+}
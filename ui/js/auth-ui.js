@@ -34,9 +34,9 @@ async function checkAuthStatus() {
     if (state.accessToken) {
         try {
             const result = await api.auth.getUser(state.accessToken);
-            if (result.success && result.user) {
+            if (result.success && result.data) {
                 authState.isLoggedIn = true;
-                authState.user = result.user;
+                authState.user = result.data;
             }
         } catch (error) {
             console.warn('Failed to verify auth status:', error);
@@ -302,16 +302,16 @@ async function handleLogin(e) {
         const result = await api.auth.login(username, password);
 
         if (!result.success) {
-            throw new Error(result.error || 'Login failed');
+            throw new Error(result.error?.message || 'Login failed');
         }
 
-        await saveAuthTokens(result.access_token, result.refresh_token);
+        await saveAuthTokens(result.data.access_token, result.data.refresh_token);
 
         // Get user info
-        const userResult = await api.auth.getUser(result.access_token);
-        if (userResult.success && userResult.user) {
+        const userResult = await api.auth.getUser(result.data.access_token);
+        if (userResult.success && userResult.data) {
             authState.isLoggedIn = true;
-            authState.user = userResult.user;
+            authState.user = userResult.data;
         }
 
         await renderAuthUI();
@@ -344,7 +344,7 @@ async function handleRegister(e) {
         const result = await api.auth.register(username, email || null, password);
 
         if (!result.success) {
-            throw new Error(result.error || 'Registration failed');
+            throw new Error(result.error?.message || 'Registration failed');
         }
 
         ui.showToast('Account created! Please sign in.', 'success');
@@ -374,10 +374,10 @@ async function handleRefresh() {
         const result = await api.auth.refresh(state.refreshToken);
 
         if (!result.success) {
-            throw new Error(result.error || 'Failed to refresh session');
+            throw new Error(result.error?.message || 'Failed to refresh session');
         }
 
-        await saveAuthTokens(result.access_token, result.refresh_token);
+        await saveAuthTokens(result.data.access_token, result.data.refresh_token);
 
         ui.showToast('Session refreshed', 'success');
     } catch (error) {
@@ -16,6 +16,7 @@ const state = {
     historyIndex: -1,
     accessToken: null,
     refreshToken: null,
+    generationId: null,
 };
 
 // ============================================================================
@@ -35,12 +36,55 @@ async function init() {
     // Load saved auth tokens
     await loadAuthTokens();
 
+    // Advance the progress bar as discovery and generation move along
+    await api.socketAgent.onProgress(handleProgress);
+
+    // Drop everything in memory once the backend has erased its data
+    await api.settings.onAppReset(() => {
+        Object.assign(state, {
+            currentUrl: null,
+            descriptor: null,
+            history: [],
+            historyIndex: -1,
+            accessToken: null,
+            refreshToken: null,
+            generationId: null,
+        });
+        updateNavigationButtons();
+        ui.showWelcome();
+    });
+
+    // Reopen the last visited site when the backend offers it
+    await api.sites.onRestoreSite((url) => {
+        if (!state.currentUrl) {
+            navigateTo(url);
+        }
+    });
+
     // Show welcome screen
     ui.showWelcome();
 
     console.log('✅ Socket Browser ready');
 }
 
+// How far along the load is once each progress event arrives
+const PROGRESS_STEPS = {
+    'discovery-started': 0.1,
+    'descriptor-fetched': 0.3,
+    'validation-done': 0.4,
+    'generation-started': 0.5,
+    'generation-done': 1,
+};
+
+function handleProgress(event, payload) {
+    // A failed step ends the load; clear the bar for the next one
+    if (payload?.success === false) {
+        ui.setProgress(0);
+        return;
+    }
+    ui.setProgress(PROGRESS_STEPS[event] ?? 0);
+}
+
 function setupEventListeners() {
     // Navigation buttons
     ui.onClick('btn-back', handleBack);
@@ -72,6 +116,13 @@ function setupEventListeners() {
     // Error dismiss
     ui.onClick('btn-dismiss-error', ui.hideError);
 
+    // Cancel an in-flight generation
+    ui.onClick('btn-cancel-generation', () => {
+        if (state.generationId !== null) {
+            api.socketAgent.cancelGeneration(state.generationId);
+        }
+    });
+
     // Welcome screen actions
     ui.onClick('btn-setup-wallet', () => ui.openPanel('wallet-panel'));
     ui.onClick('btn-login', () => ui.openPanel('auth-panel'));
@@ -132,13 +183,22 @@ export async function clearAuthTokens() {
 // ============================================================================
 
 async function handleGo() {
-    const url = ui.getInputValue('url-input');
+    const input = ui.getInputValue('url-input');
 
-    if (!url) {
+    if (!input) {
         ui.showToast('Please enter a URL', 'warning');
         return;
     }
 
+    let url;
+    try {
+        url = await api.socketAgent.normalizeUrl(input);
+    } catch (error) {
+        ui.showToast(String(error), 'warning');
+        return;
+    }
+    ui.setInputValue('url-input', url);
+
     await navigateTo(url);
 }
 
@@ -150,17 +210,26 @@ async function navigateTo(url) {
         const result = await api.socketAgent.discover(url);
 
         if (!result.success) {
-            throw new Error(result.error || 'Failed to discover API');
+            throw new Error(result.error?.message || 'Failed to discover API');
+        }
+        const discovery = result.data;
+
+        if (discovery.warning) {
+            ui.showToast(discovery.warning, 'warning');
+        }
+        // Generated pages can't say why their calls fail, so say it up front
+        for (const { message } of discovery.reachability || []) {
+            ui.showToast(message, 'warning', 6000);
         }
 
-        state.descriptor = result.descriptor;
+        state.descriptor = discovery.descriptor;
         state.currentUrl = url;
 
         // Add to history
         if (state.historyIndex < state.history.length - 1) {
             state.history = state.history.slice(0, state.historyIndex + 1);
         }
-        state.history.push({ url, descriptor: result.descriptor });
+        state.history.push({ url, descriptor: discovery.descriptor });
         state.historyIndex = state.history.length - 1;
 
         updateNavigationButtons();
@@ -176,7 +245,7 @@ async function navigateTo(url) {
 
 async function generateUI(descriptor) {
     try {
-        ui.showLoading('Generating UI...');
+        ui.showLoading('Generating UI...', true);
 
         // Check if we have access token
         if (!state.accessToken) {
@@ -185,16 +254,28 @@ async function generateUI(descriptor) {
         }
 
         // Generate website using render service
-        const result = await api.socketAgent.generateWebsite(state.accessToken, descriptor);
+        const result = await runGeneration(descriptor);
+
+        if (result.cancelled) {
+            ui.hideLoading();
+            ui.showWelcome();
+            ui.showToast('Generation cancelled', 'warning');
+            return;
+        }
 
         if (!result.success) {
-            throw new Error(result.error || 'Failed to generate UI');
+            throw new Error(result.error?.message || 'Failed to generate UI');
         }
+        const render = result.data;
 
         // Inject generated HTML
-        ui.setGeneratedUI(result.html);
+        ui.setGeneratedUI(render.html);
 
-        ui.showToast(`UI generated (${result.credits_remaining} credits remaining)`, 'success');
+        if (render.from_cache) {
+            ui.showToast('Loaded cached UI', 'success');
+        } else {
+            ui.showToast(`UI generated (${render.credits_remaining} credits remaining)`, 'success');
+        }
 
     } catch (error) {
         console.error('UI generation error:', error);
@@ -206,10 +287,18 @@ async function generateUI(descriptor) {
                 try {
                     const refreshResult = await api.auth.refresh(state.refreshToken);
                     if (refreshResult.success) {
-                        await saveAuthTokens(refreshResult.access_token, refreshResult.refresh_token);
+                        await saveAuthTokens(
+                            refreshResult.data.access_token,
+                            refreshResult.data.refresh_token
+                        );
                         // Retry generation
                         return await generateUI(descriptor);
                     }
+                    // Signing in again won't help until the clock is fixed
+                    if (refreshResult.error?.code === 'clock_skew') {
+                        ui.showError(refreshResult.error.message);
+                        return;
+                    }
                 } catch (refreshError) {
                     await clearAuthTokens();
                     ui.showError('Session expired. Please sign in again.');
@@ -224,6 +313,38 @@ async function generateUI(descriptor) {
     }
 }
 
+/**
+ * Start a background generation and wait for its render-complete or
+ * render-cancelled event. Resolves to the render response, or
+ * `{ cancelled: true }`.
+ */
+async function runGeneration(descriptor) {
+    const outcomes = new Map();
+    let wake = null;
+    const record = (id, outcome) => {
+        outcomes.set(id, outcome);
+        wake?.();
+    };
+
+    // Listen before starting so a fast result isn't missed
+    const unlisteners = await Promise.all([
+        api.socketAgent.onRenderComplete(({ id, response }) => record(id, response)),
+        api.socketAgent.onRenderCancelled(({ id }) => record(id, { cancelled: true })),
+    ]);
+
+    try {
+        const id = await api.socketAgent.startGeneration(state.accessToken, descriptor);
+        state.generationId = id;
+        while (!outcomes.has(id)) {
+            await new Promise((resolve) => { wake = resolve; });
+        }
+        return outcomes.get(id);
+    } finally {
+        state.generationId = null;
+        unlisteners.forEach((unlisten) => unlisten());
+    }
+}
+
 function handleBack() {
     if (state.historyIndex > 0) {
         state.historyIndex--;
@@ -4,6 +4,7 @@
  */
 
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { open } from '@tauri-apps/plugin-shell';
 
 // ============================================================================
@@ -29,6 +30,19 @@ export const auth = {
 
     async logout(refreshToken) {
         return await invoke('auth_logout', { refreshToken });
+    },
+
+    // Sign out on every device; refreshToken is the current session's
+    async logoutAll(accessToken, refreshToken) {
+        return await invoke('auth_logout_all', { accessToken, refreshToken });
+    },
+
+    async listSessions() {
+        return await invoke('list_sessions');
+    },
+
+    async revokeSession(id) {
+        return await invoke('revoke_session', { id });
     }
 };
 
@@ -37,16 +51,186 @@ export const auth = {
 // ============================================================================
 
 export const socketAgent = {
-    async discover(url) {
-        return await invoke('discover_socket_agent_cmd', { url });
+    // Adds https:// when missing and rejects hosts that can't be real
+    async normalizeUrl(input) {
+        return await invoke('normalize_url_cmd', { input });
+    },
+
+    // Quick check before navigating; doesn't fetch the descriptor
+    async probe(url) {
+        return await invoke('probe_site', { url });
+    },
+
+    // discoveryPath overrides where the descriptor is looked for; the
+    // result's data.discovery_path says where it was found
+    async discover(url, discoveryPath = null) {
+        return await invoke('discover_socket_agent_cmd', { url, discoveryPath });
+    },
+
+    async discoverWithDiff(url, discoveryPath = null) {
+        return await invoke('discover_with_diff', { url, discoveryPath });
+    },
+
+    // Discover several APIs as one descriptor; calls to its baseUrl are
+    // routed to the API each endpoint came from
+    async discoverMultiple(urls) {
+        return await invoke('discover_multiple', { urls });
+    },
+
+    async discoverFromFile(path, baseUrl) {
+        return await invoke('discover_from_file', { path, baseUrl });
+    },
+
+    async importOpenAPI(source) {
+        return await invoke('import_openapi_descriptor', { source });
+    },
+
+    // Cached descriptor as an OpenAPI 3.0 document
+    async exportOpenAPI(baseUrl) {
+        return await invoke('export_openapi', { baseUrl });
+    },
+
+    async listEndpoints(baseUrl) {
+        return await invoke('list_endpoints_cmd', { baseUrl });
+    },
+
+    async groupEndpoints(baseUrl) {
+        return await invoke('group_endpoints_by_tag_cmd', { baseUrl });
+    },
+
+    async searchEndpoints(baseUrl, query) {
+        return await invoke('search_endpoints_cmd', { baseUrl, query });
+    },
+
+    async getEndpointParams(baseUrl, endpointId) {
+        return await invoke('get_endpoint_params', { baseUrl, endpointId });
+    },
+
+    // Names of required params `provided` doesn't fill yet
+    async missingRequiredParams(baseUrl, endpointId, provided) {
+        return await invoke('missing_required_params_cmd', { baseUrl, endpointId, provided });
+    },
+
+    // Declared example params for "try it" forms; each may carry warnings
+    async getEndpointExamples(baseUrl, endpointId) {
+        return await invoke('get_endpoint_examples', { baseUrl, endpointId });
+    },
+
+    async getRateLimits() {
+        return await invoke('get_rate_limits');
+    },
+
+    async getCircuitBreakers() {
+        return await invoke('get_circuit_breakers');
+    },
+
+    // bodyEncoding ('json' or 'form') overrides what the endpoint declares
+    async callAPI(baseUrl, endpointId, params, validateResponse = false, bodyEncoding = null) {
+        return await invoke('call_api_cmd', { baseUrl, endpointId, params, validateResponse, bodyEncoding });
+    },
+
+    // Resolves to an id; the result arrives via onCallComplete
+    async startCall(baseUrl, endpointId, params, validateResponse = false, bodyEncoding = null) {
+        return await invoke('call_api_start', { baseUrl, endpointId, params, validateResponse, bodyEncoding });
+    },
+
+    async cancelCall(id) {
+        return await invoke('call_api_cancel', { id });
+    },
+
+    async onCallComplete(handler) {
+        return await listen('call-complete', (event) => handler(event.payload));
+    },
+
+    async onCallCancelled(handler) {
+        return await listen('call-cancelled', (event) => handler(event.payload));
+    },
+
+    // Resolves to an id; events arrive via onSSEMessage
+    async subscribeSSE(baseUrl, endpointId, params) {
+        return await invoke('subscribe_sse', { baseUrl, endpointId, params });
+    },
+
+    async unsubscribeSSE(id) {
+        return await invoke('unsubscribe_sse', { id });
+    },
+
+    async onSSEMessage(handler) {
+        return await listen('sse-message', (event) => handler(event.payload));
+    },
+
+    async onSSEClosed(handler) {
+        return await listen('sse-closed', (event) => handler(event.payload));
+    },
+
+    async callAPIBinary(baseUrl, endpointId, params) {
+        return await invoke('call_api_binary_cmd', { baseUrl, endpointId, params });
+    },
+
+    // Saves to a new file named fileName in the user's downloads folder
+    async callAPIDownload(baseUrl, endpointId, params, fileName) {
+        return await invoke('call_api_download_cmd', { baseUrl, endpointId, params, fileName });
+    },
+
+    async callAPIBatch(baseUrl, calls) {
+        return await invoke('call_api_batch_cmd', { baseUrl, calls });
+    },
+
+    async callAPIAll(baseUrl, endpointId, params, maxPages) {
+        return await invoke('call_api_all', { baseUrl, endpointId, params, maxPages });
+    },
+
+    // Only works with allow_raw_http set in config.json
+    async rawHttpCall(method, url, headers, body) {
+        return await invoke('raw_http_call', { method, url, headers, body });
+    },
+
+    // renderHints (theme, branding, layout) are merged over the descriptor's context
+    async generateWebsite(accessToken, descriptor, force = false, language = null, renderHints = null) {
+        return await invoke('generate_website', { accessToken, descriptor, force, language, renderHints });
+    },
+
+    async previewWebsite(accessToken, descriptor, language = null, renderHints = null) {
+        return await invoke('preview_website', { accessToken, descriptor, language, renderHints });
+    },
+
+    async startGeneration(accessToken, descriptor, force = false, language = null, renderHints = null) {
+        return await invoke('start_generation', { accessToken, descriptor, force, language, renderHints });
+    },
+
+    async cancelGeneration(id) {
+        return await invoke('cancel_generation', { id });
+    },
+
+    async onRenderComplete(handler) {
+        return await listen('render-complete', (event) => handler(event.payload));
+    },
+
+    async onRenderCancelled(handler) {
+        return await listen('render-cancelled', (event) => handler(event.payload));
     },
 
-    async callAPI(baseUrl, endpointId, params) {
-        return await invoke('call_api_cmd', { baseUrl, endpointId, params });
+    // handler(eventName, payload) for every discovery and generation step
+    async onProgress(handler) {
+        const events = [
+            'discovery-started',
+            'descriptor-fetched',
+            'validation-done',
+            'generation-started',
+            'generation-done',
+        ];
+        const unlisteners = await Promise.all(
+            events.map((name) => listen(name, (event) => handler(name, event.payload)))
+        );
+        return () => unlisteners.forEach((unlisten) => unlisten());
     },
 
-    async generateWebsite(accessToken, descriptor) {
-        return await invoke('generate_website', { accessToken, descriptor });
+    async estimateWebsiteCost(accessToken, descriptor) {
+        return await invoke('estimate_website_cost', { accessToken, descriptor });
+    },
+
+    async clearRenderCache() {
+        return await invoke('clear_render_cache');
     }
 };
 
@@ -55,16 +239,60 @@ export const socketAgent = {
 // ============================================================================
 
 export const wallet = {
-    async generateNew(password) {
-        return await invoke('wallet_generate_new', { password });
+    async generateNew(password, storeMnemonic = false, overwrite = false, passphrase = null) {
+        return await invoke('wallet_generate_new', {
+            password,
+            passphrase,
+            storeMnemonic,
+            overwrite,
+        });
+    },
+
+    // Derivation path phrases from earlier versions of Socket Browser need
+    LEGACY_DERIVATION: 'bip39-seed[0..32]',
+
+    async importMnemonic(
+        mnemonic,
+        password,
+        storeMnemonic = false,
+        overwrite = false,
+        passphrase = null,
+        language = null,
+        derivation = null
+    ) {
+        // Without a language, each wordlist is tried in turn; without a
+        // derivation, the standard BIP-44 path is used
+        return await invoke('wallet_import_mnemonic', {
+            mnemonic,
+            language,
+            password,
+            passphrase,
+            storeMnemonic,
+            overwrite,
+            derivation,
+        });
+    },
+
+    async listMnemonicLanguages() {
+        return await invoke('list_mnemonic_languages');
+    },
+
+    async importPrivateKey(privateKey, password, overwrite = false) {
+        return await invoke('wallet_import_private_key', { privateKey, password, overwrite });
     },
 
-    async importMnemonic(mnemonic, password) {
-        return await invoke('wallet_import_mnemonic', { mnemonic, password });
+    async importKeypairFile(path, password, overwrite = false) {
+        return await invoke('wallet_import_keypair_file', { path, password, overwrite });
     },
 
-    async importPrivateKey(privateKey, password) {
-        return await invoke('wallet_import_private_key', { privateKey, password });
+    // In-memory wallets are never saved and are gone once locked
+    async generateEphemeral(passphrase = null) {
+        return await invoke('wallet_generate_ephemeral', { passphrase });
+    },
+
+    // `secret` is a recovery phrase or a base58 private key
+    async importEphemeral(secret, passphrase = null, derivation = null) {
+        return await invoke('wallet_import_ephemeral', { secret, passphrase, derivation });
     },
 
     async unlock(password) {
@@ -75,6 +303,23 @@ export const wallet = {
         return await invoke('wallet_lock');
     },
 
+    // Wallet locked itself after `auto_lock_mins` in the config
+    async onLocked(handler) {
+        return await listen('wallet-locked', () => handler());
+    },
+
+    async rememberDevice(password) {
+        return await invoke('remember_device', { password });
+    },
+
+    async forgetDevice() {
+        return await invoke('forget_device');
+    },
+
+    async isDeviceRemembered() {
+        return await invoke('is_device_remembered');
+    },
+
     async getAddress() {
         return await invoke('wallet_get_address');
     },
@@ -83,16 +328,175 @@ export const wallet = {
         return await invoke('wallet_get_balance');
     },
 
+    async getBalanceUSD() {
+        return await invoke('wallet_get_balance_usd');
+    },
+
+    // Balance refreshed after coming back online
+    async onBalanceUpdated(handler) {
+        return await listen('balance-updated', (event) => handler(event.payload));
+    },
+
+    async getAllBalances() {
+        return await invoke('wallet_get_all_balances');
+    },
+
+    async confirmSignature(signature, timeoutSecs) {
+        return await invoke('wallet_confirm_signature', { signature, timeoutSecs });
+    },
+
+    async getTransaction(signature) {
+        return await invoke('wallet_get_transaction', { signature });
+    },
+
+    async buildUnsignedTransfer(
+        to,
+        amountSol,
+        feePayer = null,
+        priorityFee = null,
+        versioned = false,
+        lookupTables = null,
+        memo = null
+    ) {
+        return await invoke('wallet_build_unsigned_transfer', {
+            to,
+            amountSol,
+            feePayer,
+            priorityFee,
+            versioned,
+            lookupTables,
+            memo,
+        });
+    },
+
+    async submitSigned(transaction) {
+        return await invoke('wallet_submit_signed', { transaction });
+    },
+
+    // Returns the checked transaction and its instructions for the user to
+    // approve; pass `request.transaction` to approveSolanaPay to sign and send
+    async handleSolanaPay(url) {
+        return await invoke('wallet_handle_solana_pay', { url });
+    },
+
+    async approveSolanaPay(transaction) {
+        return await invoke('wallet_approve_solana_pay', { transaction });
+    },
+
+    async createTokenAccount(mint) {
+        return await invoke('wallet_create_token_account', { mint });
+    },
+
+    async getNFTs(refresh = false) {
+        return await invoke('wallet_get_nfts', { refresh });
+    },
+
+    // NFTs refreshed after coming back online
+    async onNftsUpdated(handler) {
+        return await listen('nfts-updated', (event) => handler(event.payload));
+    },
+
     async exportPrivateKey() {
         return await invoke('wallet_export_private_key');
     },
 
+    async exportMnemonic() {
+        return await invoke('wallet_export_mnemonic');
+    },
+
+    async exportBackup(path, backupPassword) {
+        return await invoke('wallet_export_backup', { path, backupPassword });
+    },
+
+    async importBackup(path, backupPassword, password, overwrite = false) {
+        return await invoke('wallet_import_backup', { path, backupPassword, password, overwrite });
+    },
+
+    async importBackupText(backup, backupPassword, password, overwrite = false) {
+        return await invoke('wallet_import_backup_text', { backup, backupPassword, password, overwrite });
+    },
+
     async hasWallet() {
         return await invoke('wallet_has_wallet');
     },
 
+    async getStoredAddress() {
+        return await invoke('wallet_get_stored_address');
+    },
+
+    async validateAddress(address) {
+        return await invoke('validate_address', { address });
+    },
+
+    async addressFingerprint(address) {
+        return await invoke('address_fingerprint', { address });
+    },
+
+    async getDebugInfo() {
+        return await invoke('wallet_debug_info');
+    },
+
+    // { stored_address, derived_address, matches, warning }; checks the
+    // unlocked wallet when no password is given
+    async verifyAddress(password) {
+        return await invoke('wallet_verify_address', { password });
+    },
+
+    async previewAddress(privateKey) {
+        return await invoke('wallet_preview_address', { privateKey });
+    },
+
+    async verifyMessage(pubkey, message, signature) {
+        return await invoke('wallet_verify_message', { pubkey, message, signature });
+    },
+
+    async getRpcEndpoints() {
+        return await invoke('wallet_get_rpc_endpoints');
+    },
+
+    async setRpcEndpoints(endpoints) {
+        return await invoke('wallet_set_rpc_endpoints', { endpoints });
+    },
+
+    async checkRpc(url) {
+        return await invoke('wallet_check_rpc', { url });
+    },
+
+    async getPriorityFee() {
+        return await invoke('wallet_get_priority_fee');
+    },
+
+    async setPriorityFee(microlamports) {
+        return await invoke('wallet_set_priority_fee', { microlamports });
+    },
+
+    async estimatePriorityFee() {
+        return await invoke('wallet_estimate_priority_fee');
+    },
+
+    async getKdfParams() {
+        return await invoke('wallet_get_kdf_params');
+    },
+
+    async setKdfParams(iterations) {
+        return await invoke('wallet_set_kdf_params', { params: { iterations } });
+    },
+
+    async getAuditLog(redact = false) {
+        return await invoke('wallet_get_audit_log', { redact });
+    },
+
+    // confirmation must be 'CLEAR'
+    async clearAuditLog(confirmation) {
+        return await invoke('wallet_clear_audit_log', { confirmation });
+    },
+
     async isUnlocked() {
         return await invoke('wallet_is_unlocked');
+    },
+
+    async isEphemeral() {
+        return await invoke('wallet_is_ephemeral');
     }
 };
 
@@ -107,6 +511,172 @@ export const storage = {
 
     async set(key, value) {
         return await invoke('set_storage', { key, value });
+    },
+
+    // One file write for all of `entries`; resolves to { saved, failed }
+    async setMany(entries) {
+        return await invoke('set_storage_many', { entries });
+    }
+};
+
+// ============================================================================
+// STATUS API
+// ============================================================================
+
+export const status = {
+    async systemStatus(baseUrl) {
+        return await invoke('system_status', { baseUrl });
+    },
+
+    // min_ms, max_ms, mean_ms and p95_ms over `samples` requests (default 10)
+    async measureLatency(baseUrl, samples) {
+        return await invoke('measure_api_latency', { baseUrl, samples });
+    },
+
+    // [{ issue, base_url, message }] for mixed content, scheme and CORS
+    // problems that would keep generated pages from calling the API
+    async checkReachability(baseUrl) {
+        return await invoke('check_api_reachability', { baseUrl });
+    },
+
+    async isOnline() {
+        return await invoke('is_online');
+    },
+
+    // handler(online) whenever connectivity changes
+    async onConnectivityChange(handler) {
+        const unlisteners = await Promise.all([
+            listen('online', () => handler(true)),
+            listen('offline', () => handler(false)),
+        ]);
+        return () => unlisteners.forEach((unlisten) => unlisten());
+    }
+};
+
+// ============================================================================
+// SETTINGS API
+// ============================================================================
+
+export const settings = {
+    async getTimeouts() {
+        return await invoke('get_timeouts');
+    },
+
+    async setTimeouts(timeouts) {
+        return await invoke('set_timeouts', { timeouts });
+    },
+
+    async getConfig() {
+        return await invoke('get_config');
+    },
+
+    // Service URLs take effect on the next start
+    async updateConfig(config) {
+        return await invoke('update_config', { config });
+    },
+
+    async getProxy() {
+        return await invoke('get_proxy');
+    },
+
+    async setProxy(proxy) {
+        return await invoke('set_proxy', { proxy });
+    },
+
+    async getMaxResponseSize() {
+        return await invoke('get_max_response_size');
+    },
+
+    async setMaxResponseSize(bytes) {
+        return await invoke('set_max_response_size', { bytes });
+    },
+
+    async getAuthRetries() {
+        return await invoke('get_auth_retries');
+    },
+
+    async setAuthRetries(retries) {
+        return await invoke('set_auth_retries', { retries });
+    },
+
+    async getHostRequestInterval() {
+        return await invoke('get_host_request_interval');
+    },
+
+    async setHostRequestInterval(ms) {
+        return await invoke('set_host_request_interval', { ms });
+    },
+
+    async setApiCredentials(host, scheme, value) {
+        return await invoke('set_api_credentials', { host, scheme, value });
+    },
+
+    async getCookiePolicy() {
+        return await invoke('get_cookie_policy');
+    },
+
+    async setCookiePolicy(policy) {
+        return await invoke('set_cookie_policy', { policy });
+    },
+
+    async clearCookies(baseUrl) {
+        return await invoke('clear_cookies', { baseUrl });
+    },
+
+    async rotateStorageKey() {
+        return await invoke('rotate_storage_key');
+    },
+
+    // confirmation must be 'RESET'
+    async factoryReset(confirmation) {
+        return await invoke('factory_reset', { confirmation });
+    },
+
+    async onAppReset(handler) {
+        return await listen('app-reset', () => handler());
+    }
+};
+
+// ============================================================================
+// SITES API
+// ============================================================================
+
+export const sites = {
+    async getLastSite() {
+        return await invoke('get_last_site');
+    },
+
+    async setLastSite(url) {
+        return await invoke('set_last_site', { url });
+    },
+
+    async addBookmark(url, name) {
+        return await invoke('add_bookmark', { url, name });
+    },
+
+    async listBookmarks() {
+        return await invoke('list_bookmarks');
+    },
+
+    async removeBookmark(url) {
+        return await invoke('remove_bookmark', { url });
+    },
+
+    async listHistory() {
+        return await invoke('list_history');
+    },
+
+    async clearHistory() {
+        return await invoke('clear_history');
+    },
+
+    // { content_type, base64, ... } or { placeholder: true }
+    async getSiteIcon(baseUrl) {
+        return await invoke('get_site_icon', { baseUrl });
+    },
+
+    async onRestoreSite(handler) {
+        return await listen('restore-site', (event) => handler(event.payload));
     }
 };
 
@@ -59,13 +59,17 @@ export function togglePanel(panelId) {
 // LOADING STATE
 // ============================================================================
 
-export function showLoading(message = 'Loading...') {
+export function showLoading(message = 'Loading...', cancellable = false) {
     const loading = document.getElementById('loading');
     const loadingText = loading.querySelector('p');
+    const cancelButton = document.getElementById('btn-cancel-generation');
 
     if (loadingText) {
         loadingText.textContent = message;
     }
+    if (cancelButton) {
+        cancelButton.style.display = cancellable ? 'inline-block' : 'none';
+    }
 
     loading.style.display = 'flex';
     hideWelcome();
@@ -77,6 +81,13 @@ export function hideLoading() {
     document.getElementById('loading').style.display = 'none';
 }
 
+export function setProgress(fraction) {
+    const fill = document.getElementById('loading-progress');
+    if (fill) {
+        fill.style.width = `${Math.round(Math.min(Math.max(fraction, 0), 1) * 100)}%`;
+    }
+}
+
 // ============================================================================
 // ERROR DISPLAY
 // ============================================================================
@@ -32,11 +32,11 @@ export async function initWalletUI() {
 async function checkWalletStatus() {
     try {
         const hasWalletResult = await api.wallet.hasWallet();
-        walletState.hasWallet = hasWalletResult.has_wallet || false;
+        walletState.hasWallet = hasWalletResult.data || false;
 
         if (walletState.hasWallet) {
             const isUnlockedResult = await api.wallet.isUnlocked();
-            walletState.isUnlocked = isUnlockedResult.is_unlocked || false;
+            walletState.isUnlocked = isUnlockedResult.data || false;
 
             if (walletState.isUnlocked) {
                 await updateWalletInfo();
@@ -51,12 +51,12 @@ async function updateWalletInfo() {
     try {
         const addressResult = await api.wallet.getAddress();
         if (addressResult.success) {
-            walletState.address = addressResult.address;
+            walletState.address = addressResult.data;
         }
 
         const balanceResult = await api.wallet.getBalance();
         if (balanceResult.success) {
-            walletState.balance = balanceResult.balance;
+            walletState.balance = balanceResult.data.balance;
         }
     } catch (error) {
         console.error('Failed to update wallet info:', error);
@@ -183,6 +183,12 @@ function attachWalletEventListeners() {
     document.getElementById('wallet-btn-lock')?.addEventListener('click', handleLock);
 }
 
+// Ask before replacing a wallet the backend refused to overwrite
+function confirmOverwrite(result) {
+    return result.error?.code === 'wallet_already_exists'
+        && confirm('A wallet already exists on this device. Replace it? This cannot be undone unless you have its recovery phrase or key.');
+}
+
 async function handleCreateWallet() {
     const password = prompt('Enter a password to encrypt your wallet:');
     if (!password) return;
@@ -194,17 +200,20 @@ async function handleCreateWallet() {
     }
 
     try {
-        const result = await api.wallet.generateNew(password);
+        let result = await api.wallet.generateNew(password);
+        if (confirmOverwrite(result)) {
+            result = await api.wallet.generateNew(password, false, true);
+        }
 
         if (!result.success) {
-            throw new Error(result.error || 'Failed to create wallet');
+            throw new Error(result.error?.message || 'Failed to create wallet');
         }
 
-        alert(`⚠️ SAVE YOUR RECOVERY PHRASE ⚠️\n\n${result.mnemonic}\n\nWrite this down and keep it safe. You will need it to recover your wallet.`);
+        alert(`⚠️ SAVE YOUR RECOVERY PHRASE ⚠️\n\n${result.data.mnemonic}\n\nWrite this down and keep it safe. You will need it to recover your wallet.`);
 
         walletState.hasWallet = true;
         walletState.isUnlocked = true;
-        walletState.address = result.address;
+        walletState.address = result.data.address;
 
         await updateWalletInfo();
         await renderWalletUI();
@@ -223,15 +232,29 @@ async function handleImportMnemonic() {
     if (!password) return;
 
     try {
-        const result = await api.wallet.importMnemonic(mnemonic, password);
+        let result = await api.wallet.importMnemonic(mnemonic, password);
+        if (confirmOverwrite(result)) {
+            result = await api.wallet.importMnemonic(mnemonic, password, false, true);
+        }
 
         if (!result.success) {
-            throw new Error(result.error || 'Failed to import wallet');
+            throw new Error(result.error?.message || 'Failed to import wallet');
+        }
+
+        // Earlier versions derived a different address from the same phrase
+        const legacyAddress = result.data.legacy_address;
+        if (legacyAddress && confirm(`This phrase gives ${result.data.address} in other Solana wallets, but earlier versions of Socket Browser used ${legacyAddress}.\n\nRestore ${legacyAddress} instead?`)) {
+            result = await api.wallet.importMnemonic(
+                mnemonic, password, false, true, null, null, api.wallet.LEGACY_DERIVATION
+            );
+            if (!result.success) {
+                throw new Error(result.error?.message || 'Failed to import wallet');
+            }
         }
 
         walletState.hasWallet = true;
         walletState.isUnlocked = true;
-        walletState.address = result.address;
+        walletState.address = result.data.address;
 
         await updateWalletInfo();
         await renderWalletUI();
@@ -250,15 +273,18 @@ async function handleImportKey() {
     if (!password) return;
 
     try {
-        const result = await api.wallet.importPrivateKey(privateKey, password);
+        let result = await api.wallet.importPrivateKey(privateKey, password);
+        if (confirmOverwrite(result)) {
+            result = await api.wallet.importPrivateKey(privateKey, password, true);
+        }
 
         if (!result.success) {
-            throw new Error(result.error || 'Failed to import wallet');
+            throw new Error(result.error?.message || 'Failed to import wallet');
         }
 
         walletState.hasWallet = true;
         walletState.isUnlocked = true;
-        walletState.address = result.address;
+        walletState.address = result.data.address;
 
         await updateWalletInfo();
         await renderWalletUI();
@@ -279,11 +305,11 @@ async function handleUnlock(e) {
         const result = await api.wallet.unlock(password);
 
         if (!result.success) {
-            throw new Error(result.error || 'Failed to unlock wallet');
+            throw new Error(result.error?.message || 'Failed to unlock wallet');
         }
 
         walletState.isUnlocked = true;
-        walletState.address = result.address;
+        walletState.address = result.data.address;
 
         await updateWalletInfo();
         await renderWalletUI();
@@ -313,10 +339,10 @@ async function handleExport() {
         const result = await api.wallet.exportPrivateKey();
 
         if (!result.success) {
-            throw new Error(result.error || 'Failed to export private key');
+            throw new Error(result.error?.message || 'Failed to export private key');
         }
 
-        alert(`Your Private Key:\n\n${result.private_key}\n\nKeep this safe and never share it.`);
+        alert(`Your Private Key:\n\n${result.data}\n\nKeep this safe and never share it.`);
     } catch (error) {
         ui.showToast(`Failed to export private key: ${error.message}`, 'error');
     }